@@ -0,0 +1,69 @@
+use axum::body::{Body, Bytes};
+use axum::extract::{Path, State};
+use axum::http::header;
+use axum::response::{IntoResponse, Redirect, Response};
+use futures::stream;
+use unified_domain::runs;
+use unified_domain::sample_outputs::{self, SampleCursor};
+use unified_shared::error::DomainError;
+use unified_shared::eval::{OutputConfig, SampleResultLocation};
+use uuid::Uuid;
+
+use crate::SharedState;
+
+/// Samples fetched per DB round trip; bounds the memory held per export.
+const EXPORT_BATCH_SIZE: i64 = 1000;
+/// Lifetime of the presigned URL handed out for object-store-backed runs.
+const PRESIGN_EXPIRY_SECS: u32 = 15 * 60;
+
+/// Streams a run's samples as NDJSON. Runs whose samples live in the object
+/// store are redirected to a presigned URL of the stored `samples.jsonl`.
+pub async fn export_samples(
+    State(state): State<SharedState>,
+    Path(run_id): Path<Uuid>,
+) -> Result<Response, DomainError> {
+    let run = runs::get(&state.db, &run_id).await?;
+    let in_object_store = matches!(run.output(), OutputConfig::ObjectStore { .. })
+        || matches!(
+            run.samples_location,
+            Some(SampleResultLocation::ObjectStore { .. })
+        );
+    if let (true, Some(store)) = (in_object_store, &state.stores.object_store) {
+        let url = store
+            .presign_samples(run_id, PRESIGN_EXPIRY_SECS)
+            .await
+            .map_err(|e| DomainError::Internal(e.to_string()))?;
+        return Ok(Redirect::temporary(&url).into_response());
+    }
+
+    let db = state.db.clone();
+    let batches = stream::try_unfold(Some(None), move |cursor: Option<Option<SampleCursor>>| {
+        let db = db.clone();
+        async move {
+            let Some(after) = cursor else {
+                return Ok(None);
+            };
+            let batch =
+                sample_outputs::list_after(&db, &run_id, after.as_ref(), EXPORT_BATCH_SIZE).await?;
+            if batch.is_empty() {
+                return Ok(None);
+            }
+            // A short batch is the last one.
+            let next = (batch.len() as i64 == EXPORT_BATCH_SIZE)
+                .then(|| batch.last().map(SampleCursor::from));
+            let mut body = Vec::new();
+            for sample in &batch {
+                serde_json::to_writer(&mut body, sample)
+                    .map_err(|e| DomainError::Internal(e.to_string()))?;
+                body.push(b'\n');
+            }
+            Ok::<_, DomainError>(Some((Bytes::from(body), next)))
+        }
+    });
+
+    Ok((
+        [(header::CONTENT_TYPE, "application/x-ndjson")],
+        Body::from_stream(batches),
+    )
+        .into_response())
+}
@@ -0,0 +1,185 @@
+use axum::http::HeaderMap;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use unified_domain::db::DbPool;
+use unified_domain::idempotency::{self, IdempotencyRecord, Reservation};
+use unified_shared::error::DomainError;
+use uuid::Uuid;
+
+use crate::AppState;
+
+pub const IDEMPOTENCY_HEADER: &str = "Idempotency-Key";
+
+/// A key claimed for this request. Dropping it without [`commit`], e.g.
+/// because creating the resource failed, releases the key so a retry can
+/// use it.
+pub struct IdempotencyKey {
+    db: DbPool,
+    scope: &'static str,
+    key: String,
+    committed: bool,
+}
+
+impl Drop for IdempotencyKey {
+    fn drop(&mut self) {
+        if self.committed {
+            return;
+        }
+        let db = self.db.clone();
+        let scope = self.scope;
+        let key = std::mem::take(&mut self.key);
+        tokio::spawn(async move {
+            if let Err(err) = idempotency::release(&db, scope, &key).await {
+                tracing::warn!("failed to release idempotency key {key}: {err}");
+            }
+        });
+    }
+}
+
+pub enum Outcome {
+    /// No previous request matched and the key (if any) is now claimed;
+    /// create the resource and `commit` the key.
+    Proceed(Option<IdempotencyKey>),
+    /// The same key and body were seen before; these are the ids it produced.
+    Replay(Vec<Uuid>),
+}
+
+pub async fn begin<T: Serialize>(
+    state: &AppState,
+    headers: &HeaderMap,
+    scope: &'static str,
+    payload: &T,
+) -> Result<Outcome, DomainError> {
+    let Some(value) = headers.get(IDEMPOTENCY_HEADER) else {
+        return Ok(Outcome::Proceed(None));
+    };
+    let key = value
+        .to_str()
+        .map_err(|_| DomainError::Validation("Idempotency-Key must be visible ASCII".into()))?
+        .trim();
+    if key.is_empty() {
+        return Err(DomainError::Validation(
+            "Idempotency-Key must not be empty".into(),
+        ));
+    }
+
+    let request_hash = request_hash(payload)?;
+
+    // A claim older than the request timeout belongs to a request that was
+    // cut off before it could release it.
+    match idempotency::reserve(
+        &state.db,
+        scope,
+        key,
+        &request_hash,
+        state.settings.idempotency.ttl_seconds,
+        state.settings.server.request_timeout_seconds,
+    )
+    .await?
+    {
+        Reservation::Acquired => Ok(Outcome::Proceed(Some(IdempotencyKey {
+            db: state.db.clone(),
+            scope,
+            key: key.to_string(),
+            committed: false,
+        }))),
+        Reservation::Existing(record) => replay(key, &request_hash, record).map(Outcome::Replay),
+    }
+}
+
+/// SHA-256 of the request body a key is bound to.
+fn request_hash<T: Serialize>(payload: &T) -> Result<String, DomainError> {
+    let body = serde_json::to_vec(payload).map_err(|e| DomainError::Internal(e.to_string()))?;
+    Ok(format!("{:x}", Sha256::digest(&body)))
+}
+
+/// The ids to answer with for a key another request already holds: those
+/// it created when the body matches, else a `Conflict`.
+fn replay(
+    key: &str,
+    request_hash: &str,
+    record: IdempotencyRecord,
+) -> Result<Vec<Uuid>, DomainError> {
+    if record.request_hash != request_hash {
+        return Err(DomainError::Conflict(format!(
+            "idempotency key {key} was already used with a different request body"
+        )));
+    }
+    record.resource_ids.ok_or_else(|| {
+        DomainError::Conflict(format!(
+            "a request with idempotency key {key} is still in progress"
+        ))
+    })
+}
+
+pub async fn commit(
+    state: &AppState,
+    key: Option<IdempotencyKey>,
+    resource_ids: &[Uuid],
+) -> Result<(), DomainError> {
+    match key {
+        Some(mut key) => {
+            idempotency::complete(&state.db, key.scope, &key.key, resource_ids).await?;
+            key.committed = true;
+            Ok(())
+        }
+        None => Ok(()),
+    }
+}
+
+pub fn single(resource_ids: &[Uuid]) -> Result<Uuid, DomainError> {
+    resource_ids
+        .first()
+        .copied()
+        .ok_or_else(|| DomainError::Internal("idempotency record has no resource id".into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn record(request_hash: &str, resource_ids: Option<Vec<Uuid>>) -> IdempotencyRecord {
+        IdempotencyRecord {
+            scope: "runs".into(),
+            key: "key-1".into(),
+            request_hash: request_hash.into(),
+            resource_ids,
+            created_at: chrono::Utc::now(),
+        }
+    }
+
+    #[test]
+    fn the_same_key_and_body_replay_the_created_ids() {
+        let body = json!({ "name": "nightly", "tasks": [1, 2] });
+        let hash = request_hash(&body).unwrap();
+        assert_eq!(hash, request_hash(&body).unwrap());
+
+        let ids = vec![Uuid::new_v4()];
+        let replayed = replay("key-1", &hash, record(&hash, Some(ids.clone()))).unwrap();
+        assert_eq!(replayed, ids);
+    }
+
+    #[test]
+    fn a_different_body_under_the_same_key_conflicts() {
+        let first = request_hash(&json!({ "name": "nightly" })).unwrap();
+        let second = request_hash(&json!({ "name": "weekly" })).unwrap();
+        assert_ne!(first, second);
+
+        let err = replay("key-1", &second, record(&first, Some(vec![Uuid::new_v4()]))).unwrap_err();
+        assert!(
+            matches!(&err, DomainError::Conflict(msg) if msg.contains("different request body")),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn a_key_still_in_progress_conflicts() {
+        let hash = request_hash(&json!({ "name": "nightly" })).unwrap();
+        let err = replay("key-1", &hash, record(&hash, None)).unwrap_err();
+        assert!(
+            matches!(&err, DomainError::Conflict(msg) if msg.contains("still in progress")),
+            "{err}"
+        );
+    }
+}
@@ -1,57 +1,99 @@
+mod export;
+mod idempotency;
+mod readiness;
+mod run_events;
+
 use axum::{
-    extract::{Path, Query, State},
-    http::StatusCode,
-    response::IntoResponse,
-    routing::{get, post},
+    extract::{DefaultBodyLimit, Path, Query, Request, State},
+    http::{header, HeaderMap, HeaderName, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, patch, post, put},
     Json, Router,
 };
+use chrono::{DateTime, Utc};
 use deadpool_redis::{Config as RedisConfig, Pool as RedisPool, Runtime};
 use redis::AsyncCommands;
+use schemars::schema::RootSchema;
+use schemars::schema_for;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
+use tower_http::compression::CompressionLayer;
+use tower_http::limit::RequestBodyLimitLayer;
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
+use tower_http::timeout::TimeoutLayer;
+use tower_http::trace::TraceLayer;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+use unified_domain::artifacts;
+use unified_domain::canary::{self, CanaryBaseline, CanaryDrift};
+use unified_domain::coverage::{self, CoverageWarning};
 use unified_domain::datasets::{self, Dataset, NewDataset};
-use unified_domain::experiments::{self, Experiment, NewExperiment};
+use unified_domain::experiments::{self, Experiment, ExperimentWithTasks, NewExperiment};
 use unified_domain::metrics;
 use unified_domain::models::{
-    self, Checkpoint, ModelFamily, ModelImplementation, NewCheckpoint, NewModelFamily,
-    NewModelImplementation,
+    self, Checkpoint, CheckpointFilter, ModelFamily, ModelImplUpdate, ModelImplementation,
+    NewCheckpoint, NewModelFamily, NewModelImplementation,
 };
-use unified_domain::projects::{self, NewProject, Project};
-use unified_domain::runs::{self, NewRun, Run};
+use unified_domain::projects::{self, NewProject, Project, ProjectUpdate};
+use unified_domain::result_store::{ClickHouseResultStore, MigratedResults, ResultStoreHandles};
+use unified_domain::runs::{self, NewRun, Run, RunFilter};
 use unified_domain::sample_outputs;
 use unified_domain::tasks::{self, NewTask, Task};
-use unified_shared::error::DomainError;
-use unified_shared::eval::{EvalConfig, RunStatus};
-use unified_shared::settings::Settings;
+use unified_domain::templating;
+use unified_domain::utils::{merge_json, JsonDiff};
+use unified_shared::error::{DomainError, FieldErrors};
+use unified_shared::eval::{
+    run_status_channel, EvalConfig, EvalEngine, EvalResult, MetricConfig, MetricDirection,
+    MetricRecord, OutputConfig, ResourceConfig, RunStatus, RunStatusEvent, SampleResultLocation,
+    TaskConfig, HIGHER_IS_BETTER, LOWER_IS_BETTER,
+};
+use unified_shared::queue;
+use unified_shared::settings::{
+    LogFormat, LoggingSettings, ProjectSettings, QueueStrategy, Settings,
+};
 use uuid::Uuid;
 
+use crate::idempotency::Outcome;
+
 #[derive(Clone)]
 struct AppState {
     db: unified_domain::db::DbPool,
     redis: RedisPool,
+    stores: ResultStoreHandles,
     settings: Settings,
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    init_tracing();
     let settings = Settings::load()?;
-    let db = unified_domain::db::init_pool(&settings.database.url).await?;
+    init_tracing(&settings.logging);
+    let db = unified_domain::db::init_pool(&settings.database).await?;
     let redis_cfg = RedisConfig::from_url(settings.redis.url.clone());
     let redis = redis_cfg.create_pool(Some(Runtime::Tokio1))?;
+    let stores = ResultStoreHandles::new(&settings, db.clone()).await?;
 
     let state = AppState {
         db,
         redis,
+        stores,
         settings: settings.clone(),
     };
 
     let app = Router::new()
         .route("/healthz", get(health_check))
+        .route("/schema/:name", get(get_schema))
+        .route("/readyz", get(readiness::readiness_check))
         .route("/projects", get(list_projects).post(create_project))
+        .route("/projects/:id", get(get_project).patch(update_project))
+        .route(
+            "/projects/:id/settings",
+            get(get_project_settings).put(put_project_settings),
+        )
+        .route("/projects/:id/canary-baseline", put(set_canary_baseline))
         .nest(
             "/models",
             Router::new()
@@ -60,10 +102,13 @@ async fn main() -> anyhow::Result<()> {
                     get(list_model_families).post(create_model_family),
                 )
                 .route("/impls", get(list_model_impls).post(create_model_impl))
+                .route("/impls/:id", patch(update_model_impl))
+                .route("/impls/:id/history", get(model_impl_history))
                 .route(
                     "/checkpoints",
                     get(list_checkpoints).post(create_checkpoint),
-                ),
+                )
+                .route("/checkpoints/batch", post(create_checkpoints_batch)),
         )
         .route("/datasets", get(list_datasets).post(create_dataset))
         .route("/tasks", get(list_tasks).post(create_task))
@@ -71,14 +116,55 @@ async fn main() -> anyhow::Result<()> {
             "/experiments",
             get(list_experiments).post(create_experiment),
         )
+        .route("/experiments/:id", get(get_experiment))
         .route("/experiments/:id/compile", post(compile_experiment))
+        .route("/experiments/:id/compile-sweep", post(compile_sweep))
+        .route("/experiments/:id/cancel", post(cancel_experiment))
         .route("/runs", get(list_runs))
+        .route("/admin/runs", get(list_all_runs))
+        .route("/admin/pause", get(pause_state).post(pause_workers))
+        .route("/admin/resume", post(resume_workers))
+        .route("/runs/compare", get(compare_runs))
+        .route("/runs/compare-config", get(compare_run_configs))
         .route("/runs/:id", get(get_run))
+        .route("/runs/:id/metrics", post(ingest_metrics))
+        .route("/runs/:id/metrics/by-subset", get(run_metrics_by_subset))
+        .route("/runs/:id/ws", get(run_events::run_status_ws))
+        .route("/runs/:id/usage", get(run_usage))
+        .route("/runs/:id/sample-errors/summary", get(sample_error_summary))
+        .route("/runs/:id/latency", get(run_latency))
+        .route("/runs/:id/history", get(run_history))
+        .route("/runs/:id/canary-drift", get(run_canary_drift))
+        .route("/runs/:id/reproducibility", get(run_reproducibility))
+        .route("/runs/:id/artifacts", get(run_artifacts))
+        .route("/runs/:id/raw-result", get(run_raw_result))
+        .route("/runs/:id/samples/:index", get(get_sample))
+        .route(
+            "/runs/:id/samples/export",
+            get(export::export_samples).layer(CompressionLayer::new()),
+        )
         .route("/runs/:id/enqueue", post(enqueue_run))
+        .route("/runs/enqueue-batch", post(enqueue_runs_batch))
+        .route("/runs/:id/migrate-store", post(migrate_run_store))
+        .route("/runs/:id/regression-check", post(regression_check))
         .route("/metrics", get(list_metrics))
+        .route("/metrics/series", get(metric_series))
+        .route("/metrics/rollup", get(metric_rollup))
+        .route("/metric-directions", get(list_metric_directions))
+        .route("/metric-directions/:name", put(set_metric_direction))
         .route("/samples", get(list_samples))
+        .route("/samples/search", get(search_samples))
+        .route("/samples/diff", get(diff_samples))
         .route("/tests/trigger", post(trigger_remote_test))
-        .with_state(Arc::new(state));
+        .with_state(Arc::new(state))
+        .layer(DefaultBodyLimit::disable())
+        .layer(RequestBodyLimitLayer::new(settings.server.max_body_bytes))
+        .layer(TimeoutLayer::new(Duration::from_secs(
+            settings.server.request_timeout_seconds,
+        )))
+        .layer(PropagateRequestIdLayer::new(REQUEST_ID_HEADER))
+        .layer(TraceLayer::new_for_http().make_span_with(request_span))
+        .layer(SetRequestIdLayer::new(REQUEST_ID_HEADER, MakeRequestUuid));
 
     let addr: SocketAddr = "0.0.0.0:8080".parse().unwrap();
     tracing::info!("listening on {}", addr);
@@ -88,13 +174,49 @@ async fn main() -> anyhow::Result<()> {
     Ok(())
 }
 
+/// JSON Schemas of the queue contract, so clients can validate configs
+/// before submitting them.
+async fn get_schema(Path(name): Path<String>) -> Result<Json<RootSchema>, DomainError> {
+    let schema = match name.as_str() {
+        "eval-config" => schema_for!(EvalConfig),
+        "eval-result" => schema_for!(EvalResult),
+        "output-config" => schema_for!(OutputConfig),
+        other => return Err(DomainError::NotFound(format!("unknown schema {other}"))),
+    };
+    Ok(Json(schema))
+}
+
 async fn health_check() -> &'static str {
     "ok"
 }
 
-fn init_tracing() {
-    let fmt_layer = tracing_subscriber::fmt::layer();
-    tracing_subscriber::registry().with(fmt_layer).init();
+const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+/// Set on paged list responses that filled the page; pass it back as
+/// `cursor` to fetch the next one.
+const NEXT_CURSOR_HEADER: HeaderName = HeaderName::from_static("x-next-cursor");
+
+fn request_span(request: &Request) -> tracing::Span {
+    let request_id = request
+        .headers()
+        .get(&REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    tracing::info_span!(
+        "request",
+        method = %request.method(),
+        uri = %request.uri(),
+        request_id = %request_id,
+    )
+}
+
+fn init_tracing(settings: &LoggingSettings) {
+    let registry = tracing_subscriber::registry();
+    match settings.format {
+        LogFormat::Json => registry
+            .with(tracing_subscriber::fmt::layer().json().with_span_list(true))
+            .init(),
+        LogFormat::Text => registry.with(tracing_subscriber::fmt::layer()).init(),
+    }
 }
 
 type SharedState = Arc<AppState>;
@@ -104,11 +226,6 @@ struct ProjectQuery {
     project_id: Uuid,
 }
 
-#[derive(Deserialize)]
-struct RunQuery {
-    run_id: Uuid,
-}
-
 async fn list_projects(
     State(state): State<SharedState>,
 ) -> Result<Json<Vec<Project>>, DomainError> {
@@ -116,7 +233,7 @@ async fn list_projects(
     Ok(Json(projects))
 }
 
-#[derive(Deserialize)]
+#[derive(Serialize, Deserialize)]
 struct CreateProjectRequest {
     name: String,
     description: Option<String>,
@@ -124,8 +241,16 @@ struct CreateProjectRequest {
 
 async fn create_project(
     State(state): State<SharedState>,
+    headers: HeaderMap,
     Json(payload): Json<CreateProjectRequest>,
 ) -> Result<Json<Project>, DomainError> {
+    let key = match idempotency::begin(&state, &headers, "create_project", &payload).await? {
+        Outcome::Replay(ids) => {
+            let project = projects::get(&state.db, &idempotency::single(&ids)?).await?;
+            return Ok(Json(project));
+        }
+        Outcome::Proceed(key) => key,
+    };
     let project = projects::create(
         &state.db,
         NewProject {
@@ -134,9 +259,74 @@ async fn create_project(
         },
     )
     .await?;
+    idempotency::commit(&state, key, &[project.id]).await?;
     Ok(Json(project))
 }
 
+async fn get_project(
+    State(state): State<SharedState>,
+    Path(project_id): Path<Uuid>,
+) -> Result<Json<Project>, DomainError> {
+    let project = projects::get(&state.db, &project_id).await?;
+    Ok(Json(project))
+}
+
+async fn update_project(
+    State(state): State<SharedState>,
+    Path(project_id): Path<Uuid>,
+    Json(payload): Json<ProjectUpdate>,
+) -> Result<Json<Project>, DomainError> {
+    let project = projects::update(&state.db, &project_id, payload).await?;
+    Ok(Json(project))
+}
+
+async fn get_project_settings(
+    State(state): State<SharedState>,
+    Path(project_id): Path<Uuid>,
+) -> Result<Json<ProjectSettings>, DomainError> {
+    projects::get(&state.db, &project_id).await?;
+    let overrides = projects::get_settings(&state.db, &project_id).await?;
+    Ok(Json(overrides))
+}
+
+async fn put_project_settings(
+    State(state): State<SharedState>,
+    Path(project_id): Path<Uuid>,
+    Json(payload): Json<ProjectSettings>,
+) -> Result<Json<ProjectSettings>, DomainError> {
+    let overrides =
+        projects::put_settings(&state.db, &project_id, payload, &state.settings).await?;
+    Ok(Json(overrides))
+}
+
+#[derive(Deserialize)]
+struct CanaryBaselineRequest {
+    run_id: Uuid,
+}
+
+async fn set_canary_baseline(
+    State(state): State<SharedState>,
+    Path(project_id): Path<Uuid>,
+    Json(payload): Json<CanaryBaselineRequest>,
+) -> Result<Json<CanaryBaseline>, DomainError> {
+    projects::get(&state.db, &project_id).await?;
+    let run = runs::get(&state.db, &payload.run_id).await?;
+    if run.project_id != project_id {
+        return Err(DomainError::field(
+            "run_id",
+            "run belongs to another project",
+        ));
+    }
+    if !matches!(run.status, RunStatus::Completed) {
+        return Err(DomainError::Conflict(format!(
+            "run is {:?}; only completed runs can be a canary baseline",
+            run.status
+        )));
+    }
+    let baseline = canary::set_baseline(&state.db, project_id, run.id).await?;
+    Ok(Json(baseline))
+}
+
 async fn list_model_families(
     State(state): State<SharedState>,
     Query(query): Query<ProjectQuery>,
@@ -178,6 +368,24 @@ async fn list_model_impls(
     Ok(Json(items))
 }
 
+async fn update_model_impl(
+    State(state): State<SharedState>,
+    Path(impl_id): Path<Uuid>,
+    Json(payload): Json<ModelImplUpdate>,
+) -> Result<Json<ModelImplementation>, DomainError> {
+    let item = models::update_impl(&state.db, &impl_id, payload).await?;
+    Ok(Json(item))
+}
+
+async fn model_impl_history(
+    State(state): State<SharedState>,
+    Path(impl_id): Path<Uuid>,
+) -> Result<Json<Vec<models::ReferenceChange>>, DomainError> {
+    models::get_impl(&state.db, &impl_id).await?;
+    let items = models::reference_history(&state.db, &impl_id).await?;
+    Ok(Json(items))
+}
+
 #[derive(Deserialize)]
 struct CreateModelImplRequest {
     project_id: Uuid,
@@ -253,6 +461,21 @@ async fn create_checkpoint(
     Ok(Json(item))
 }
 
+#[derive(Serialize)]
+struct CheckpointBatchResponse {
+    checkpoint_ids: Vec<Uuid>,
+}
+
+async fn create_checkpoints_batch(
+    State(state): State<SharedState>,
+    Json(payload): Json<Vec<NewCheckpoint>>,
+) -> Result<Json<CheckpointBatchResponse>, DomainError> {
+    let created = models::create_checkpoints_batch(&state.db, payload).await?;
+    Ok(Json(CheckpointBatchResponse {
+        checkpoint_ids: created.iter().map(|c| c.id).collect(),
+    }))
+}
+
 async fn list_datasets(
     State(state): State<SharedState>,
     Query(query): Query<ProjectQuery>,
@@ -261,7 +484,7 @@ async fn list_datasets(
     Ok(Json(items))
 }
 
-#[derive(Deserialize)]
+#[derive(Serialize, Deserialize)]
 struct CreateDatasetRequest {
     project_id: Uuid,
     name: String,
@@ -273,8 +496,16 @@ struct CreateDatasetRequest {
 
 async fn create_dataset(
     State(state): State<SharedState>,
+    headers: HeaderMap,
     Json(payload): Json<CreateDatasetRequest>,
 ) -> Result<Json<Dataset>, DomainError> {
+    let key = match idempotency::begin(&state, &headers, "create_dataset", &payload).await? {
+        Outcome::Replay(ids) => {
+            let item = datasets::get(&state.db, &idempotency::single(&ids)?).await?;
+            return Ok(Json(item));
+        }
+        Outcome::Proceed(key) => key,
+    };
     let item = datasets::create(
         &state.db,
         NewDataset {
@@ -287,6 +518,7 @@ async fn create_dataset(
         },
     )
     .await?;
+    idempotency::commit(&state, key, &[item.id]).await?;
     Ok(Json(item))
 }
 
@@ -298,7 +530,7 @@ async fn list_tasks(
     Ok(Json(items))
 }
 
-#[derive(Deserialize)]
+#[derive(Serialize, Deserialize)]
 struct CreateTaskRequest {
     project_id: Uuid,
     dataset_id: Uuid,
@@ -311,8 +543,27 @@ struct CreateTaskRequest {
 
 async fn create_task(
     State(state): State<SharedState>,
+    headers: HeaderMap,
     Json(payload): Json<CreateTaskRequest>,
 ) -> Result<Json<Task>, DomainError> {
+    let key = match idempotency::begin(&state, &headers, "create_task", &payload).await? {
+        Outcome::Replay(ids) => {
+            let task = tasks::get(&state.db, &idempotency::single(&ids)?).await?;
+            return Ok(Json(task));
+        }
+        Outcome::Proceed(key) => key,
+    };
+    state
+        .stores
+        .post_processors
+        .check_config(&payload.eval_config)?;
+    check_harness_args(
+        &state.settings.integrations.harness_arg_allowlist,
+        &payload.eval_config,
+        Some(&payload.eval_engine),
+    )?;
+    let dataset = datasets::get(&state.db, &payload.dataset_id).await?;
+    datasets::check_rows(&dataset, state.stores.object_store.as_deref()).await?;
     let task = tasks::create(
         &state.db,
         NewTask {
@@ -326,6 +577,7 @@ async fn create_task(
         },
     )
     .await?;
+    idempotency::commit(&state, key, &[task.id]).await?;
     Ok(Json(task))
 }
 
@@ -338,6 +590,44 @@ async fn list_experiments(
 }
 
 #[derive(Deserialize)]
+struct ExperimentQuery {
+    expand: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+enum ExperimentResponse {
+    Plain(Experiment),
+    Expanded(ExperimentWithTasks),
+}
+
+async fn get_experiment(
+    State(state): State<SharedState>,
+    Path(experiment_id): Path<Uuid>,
+    Query(query): Query<ExperimentQuery>,
+) -> Result<Json<ExperimentResponse>, DomainError> {
+    let mut expand_tasks = false;
+    for field in query.expand.iter().flat_map(|expand| expand.split(',')) {
+        match field.trim() {
+            "tasks" => expand_tasks = true,
+            "" => {}
+            other => {
+                return Err(DomainError::Validation(format!(
+                    "unsupported expand field: {other}"
+                )))
+            }
+        }
+    }
+
+    let response = if expand_tasks {
+        ExperimentResponse::Expanded(experiments::get_with_tasks(&state.db, &experiment_id).await?)
+    } else {
+        ExperimentResponse::Plain(experiments::get(&state.db, &experiment_id).await?)
+    };
+    Ok(Json(response))
+}
+
+#[derive(Serialize, Deserialize)]
 struct CreateExperimentRequest {
     project_id: Uuid,
     name: String,
@@ -349,8 +639,24 @@ struct CreateExperimentRequest {
 
 async fn create_experiment(
     State(state): State<SharedState>,
+    headers: HeaderMap,
     Json(payload): Json<CreateExperimentRequest>,
 ) -> Result<Json<Experiment>, DomainError> {
+    let key = match idempotency::begin(&state, &headers, "create_experiment", &payload).await? {
+        Outcome::Replay(ids) => {
+            let experiment = experiments::get(&state.db, &idempotency::single(&ids)?).await?;
+            return Ok(Json(experiment));
+        }
+        Outcome::Proceed(key) => key,
+    };
+    if let Some(global_config) = &payload.global_config {
+        state.stores.post_processors.check_config(global_config)?;
+        check_harness_args(
+            &state.settings.integrations.harness_arg_allowlist,
+            global_config,
+            None,
+        )?;
+    }
     let experiment = experiments::create(
         &state.db,
         NewExperiment {
@@ -363,36 +669,130 @@ async fn create_experiment(
         },
     )
     .await?;
+    idempotency::commit(&state, key, &[experiment.id]).await?;
     Ok(Json(experiment))
 }
 
-#[derive(Deserialize)]
+#[derive(Serialize, Deserialize)]
 struct CompileExperimentRequest {
     runs: Vec<CompileRunRequest>,
+    /// Re-create runs whose earlier attempt failed, timed out or was
+    /// cancelled instead of skipping them.
+    #[serde(default)]
+    force: bool,
 }
 
-#[derive(Deserialize)]
+#[derive(Serialize, Deserialize)]
 struct CompileRunRequest {
     model_impl_id: Uuid,
     checkpoint_id: Uuid,
     task_id: Uuid,
     run_type: Option<String>,
     eval_config: Value,
+    /// Existing runs that must complete before this one is queued.
+    #[serde(default)]
+    depends_on: Vec<Uuid>,
 }
 
 #[derive(Serialize)]
 struct CompileExperimentResponse {
+    /// Runs created by this call.
     run_ids: Vec<Uuid>,
+    /// Existing runs that matched a request and were kept instead.
+    skipped_run_ids: Vec<Uuid>,
 }
 
 async fn compile_experiment(
     State(state): State<SharedState>,
     Path(experiment_id): Path<Uuid>,
+    headers: HeaderMap,
     Json(payload): Json<CompileExperimentRequest>,
 ) -> Result<Json<CompileExperimentResponse>, DomainError> {
+    let key = match idempotency::begin(
+        &state,
+        &headers,
+        "compile_experiment",
+        &(experiment_id, &payload),
+    )
+    .await?
+    {
+        Outcome::Replay(run_ids) => {
+            return Ok(Json(CompileExperimentResponse {
+                run_ids,
+                skipped_run_ids: Vec::new(),
+            }))
+        }
+        Outcome::Proceed(key) => key,
+    };
     let experiment = experiments::get(&state.db, &experiment_id).await?;
+    let response = compile_runs(&state, &experiment, payload.runs, payload.force).await?;
+    idempotency::commit(&state, key, &response.run_ids).await?;
+    Ok(Json(response))
+}
+
+/// Creates a run per request unless an equivalent one exists; see
+/// `POST /experiments/{id}/compile`. The params of the requested configs'
+/// post-processed metrics, their harness args and the datasets of the
+/// requested tasks are checked first, so nothing is queued when one of them
+/// is invalid.
+async fn compile_runs(
+    state: &AppState,
+    experiment: &Experiment,
+    requests: Vec<CompileRunRequest>,
+    force: bool,
+) -> Result<CompileExperimentResponse, DomainError> {
+    let experiment_id = experiment.id;
+    for run_req in &requests {
+        state
+            .stores
+            .post_processors
+            .check_config(&run_req.eval_config)?;
+        check_harness_args(
+            &state.settings.integrations.harness_arg_allowlist,
+            &run_req.eval_config,
+            None,
+        )?;
+    }
+    let task_ids: BTreeSet<Uuid> = requests.iter().map(|run_req| run_req.task_id).collect();
+    let task_ids: Vec<Uuid> = task_ids.into_iter().collect();
+    let dataset_ids: BTreeSet<Uuid> = tasks::list_by_ids(&state.db, &task_ids)
+        .await?
+        .iter()
+        .map(|task| task.dataset_id)
+        .collect();
+    for dataset_id in &dataset_ids {
+        let dataset = datasets::get(&state.db, dataset_id).await?;
+        datasets::check_rows(&dataset, state.stores.object_store.as_deref()).await?;
+    }
+    let settings =
+        projects::effective_settings(&state.db, &experiment.project_id, &state.settings).await?;
+    let existing = runs::latest_by_compile_hash(&state.db, &experiment_id).await?;
+    // Repeats of a request within this call share the run created for it.
+    let mut fresh: HashMap<String, Uuid> = HashMap::new();
     let mut created = Vec::new();
-    for run_req in payload.runs {
+    let mut skipped = Vec::new();
+    for run_req in requests {
+        let run_type = run_req.run_type.unwrap_or_else(|| "offline_eval".into());
+        let hash = runs::compile_hash(
+            &run_req.model_impl_id,
+            &run_req.checkpoint_id,
+            &run_req.task_id,
+            &run_type,
+            &run_req.eval_config,
+        );
+        if let Some(run_id) = fresh.get(&hash) {
+            skipped.push(*run_id);
+            continue;
+        }
+        // Queued, running and completed runs are always kept; failed ones
+        // only without `force`.
+        if let Some(run) = existing.get(&hash) {
+            let failed = run.status.is_terminal() && !matches!(run.status, RunStatus::Completed);
+            if !(failed && force) {
+                skipped.push(run.id);
+                continue;
+            }
+        }
         let mut config = run_req.eval_config;
         if let Some(obj) = config.as_object_mut() {
             obj.insert(
@@ -404,37 +804,847 @@ async fn compile_experiment(
                 Value::String(experiment.project_id.to_string()),
             );
         }
+        apply_defaults(&settings, &mut config)?;
         let new_run = NewRun {
             experiment_id,
             project_id: experiment.project_id,
             model_impl_id: run_req.model_impl_id,
             checkpoint_id: run_req.checkpoint_id,
             task_id: run_req.task_id,
-            run_type: run_req.run_type.unwrap_or_else(|| "offline_eval".into()),
+            run_type,
             status: RunStatus::Queued,
             eval_config: config,
+            compile_hash: Some(hash.clone()),
+            depends_on: run_req.depends_on,
         };
-        let run = runs::create(&state.db, new_run).await?;
+        let run = runs::create_with_store(&state.db, new_run, state.stores.object_store.as_deref())
+            .await?;
+        fresh.insert(hash, run.id);
         created.push(run.id);
     }
 
-    Ok(Json(CompileExperimentResponse { run_ids: created }))
+    Ok(CompileExperimentResponse {
+        run_ids: created,
+        skipped_run_ids: skipped,
+    })
+}
+
+#[derive(Serialize, Deserialize)]
+struct CompileSweepRequest {
+    model_impl_id: Uuid,
+    task_ids: Vec<Uuid>,
+    #[serde(default)]
+    checkpoint_filter: CheckpointFilter,
+    run_type: Option<String>,
+    #[serde(default)]
+    force: bool,
+    /// Values per template variable; a run is compiled for every combination.
+    #[serde(default)]
+    variables: BTreeMap<String, Vec<Value>>,
+}
+
+/// Template variables set for every run of a sweep; `variables` can't
+/// redefine them.
+const SWEEP_VARIABLES: &[&str] = &[
+    "checkpoint_id",
+    "checkpoint_name",
+    "checkpoint_step",
+    "weights_uri",
+    "task_id",
+    "task_name",
+];
+
+/// Parses a rendered sweep config as a complete [`EvalConfig`] and returns
+/// it in its canonical JSON form.
+fn concrete_config(rendered: Value) -> Result<Value, serde_json::Error> {
+    let config: EvalConfig = serde_json::from_value(rendered)?;
+    serde_json::to_value(config)
+}
+
+/// Compiles a run for every checkpoint of a model impl matching
+/// `checkpoint_filter` on every task and every combination of `variables`.
+/// Each run's `eval_config` is the experiment's `global_config` with the
+/// task's `eval_config` merged over it, rendered as a template (see
+/// [`templating::render`]) with the combination and [`SWEEP_VARIABLES`], and
+/// the checkpoint recorded under `model.extra.checkpoint`. The rendered
+/// config must be a complete [`EvalConfig`] once the defaults are applied.
+/// Sweeps expanding to more than `server.max_sweep_runs` runs are refused
+/// before any is compiled.
+async fn compile_sweep(
+    State(state): State<SharedState>,
+    Path(experiment_id): Path<Uuid>,
+    headers: HeaderMap,
+    Json(payload): Json<CompileSweepRequest>,
+) -> Result<Json<CompileExperimentResponse>, DomainError> {
+    payload.checkpoint_filter.validate()?;
+    if payload.task_ids.is_empty() {
+        return Err(DomainError::field("task_ids", "must not be empty"));
+    }
+    let mut errors = FieldErrors::new();
+    for (name, values) in &payload.variables {
+        if SWEEP_VARIABLES.contains(&name.as_str()) {
+            errors.push(format!("variables.{name}"), "is set by the sweep");
+        } else if values.is_empty() {
+            errors.push(format!("variables.{name}"), "must list at least one value");
+        }
+    }
+    errors.finish()?;
+    let key = match idempotency::begin(
+        &state,
+        &headers,
+        "compile_sweep",
+        &(experiment_id, &payload),
+    )
+    .await?
+    {
+        Outcome::Replay(run_ids) => {
+            return Ok(Json(CompileExperimentResponse {
+                run_ids,
+                skipped_run_ids: Vec::new(),
+            }))
+        }
+        Outcome::Proceed(key) => key,
+    };
+    let experiment = experiments::get(&state.db, &experiment_id).await?;
+    let model_impl = models::get_impl(&state.db, &payload.model_impl_id).await?;
+    if model_impl.project_id != experiment.project_id {
+        return Err(DomainError::field(
+            "model_impl_id",
+            "belongs to a different project than the experiment",
+        ));
+    }
+    let mut tasks = Vec::with_capacity(payload.task_ids.len());
+    for (i, task_id) in payload.task_ids.iter().enumerate() {
+        let task = tasks::get(&state.db, task_id).await?;
+        if task.project_id != experiment.project_id {
+            return Err(DomainError::field(
+                format!("task_ids[{i}]"),
+                "belongs to a different project than the experiment",
+            ));
+        }
+        tasks.push(task);
+    }
+    let checkpoints = payload
+        .checkpoint_filter
+        .apply(models::list_checkpoints(&state.db, &model_impl.id).await?);
+
+    let base = experiment
+        .global_config
+        .clone()
+        .unwrap_or_else(|| Value::Object(Default::default()));
+    let max_runs = state.settings.server.max_sweep_runs;
+    let combination_count = templating::grid_len(&payload.variables);
+    let total = checkpoints
+        .len()
+        .saturating_mul(tasks.len())
+        .saturating_mul(combination_count);
+    if total > max_runs {
+        return Err(DomainError::Validation(format!(
+            "the sweep expands to {total} runs ({} checkpoints × {} tasks × {combination_count} combinations), more than the limit of {max_runs}",
+            checkpoints.len(),
+            tasks.len(),
+        )));
+    }
+    let combinations = templating::grid(&payload.variables);
+    let settings =
+        projects::effective_settings(&state.db, &experiment.project_id, &state.settings).await?;
+    let mut requests = Vec::with_capacity(total);
+    for checkpoint in &checkpoints {
+        for task in &tasks {
+            let mut template = base.clone();
+            merge_json(&mut template, &task.eval_config);
+            let builtins = [
+                ("checkpoint_id", serde_json::json!(checkpoint.id)),
+                ("checkpoint_name", serde_json::json!(checkpoint.name)),
+                ("checkpoint_step", serde_json::json!(checkpoint.step)),
+                ("weights_uri", serde_json::json!(checkpoint.weights_uri)),
+                ("task_id", serde_json::json!(task.id)),
+                ("task_name", serde_json::json!(task.name)),
+            ];
+            for combination in &combinations {
+                let mut vars = combination.clone();
+                vars.extend(
+                    builtins
+                        .iter()
+                        .map(|(name, value)| (name.to_string(), value.clone())),
+                );
+                let mut eval_config = templating::render(&template, &vars)?;
+                merge_json(
+                    &mut eval_config,
+                    &serde_json::json!({
+                        // The run's own id replaces the placeholder when it
+                        // is created.
+                        "run_id": Uuid::nil(),
+                        "project_id": experiment.project_id,
+                        "model": { "extra": { "checkpoint": {
+                            "id": checkpoint.id,
+                            "name": checkpoint.name,
+                            "step": checkpoint.step,
+                            "weights_uri": checkpoint.weights_uri,
+                        } } }
+                    }),
+                );
+                apply_defaults(&settings, &mut eval_config)?;
+                let eval_config = concrete_config(eval_config).map_err(|e| {
+                    DomainError::Validation(format!(
+                        "config of task {} for checkpoint {} is invalid once rendered: {e}",
+                        task.name, checkpoint.name
+                    ))
+                })?;
+                requests.push(CompileRunRequest {
+                    model_impl_id: model_impl.id,
+                    checkpoint_id: checkpoint.id,
+                    task_id: task.id,
+                    run_type: payload.run_type.clone(),
+                    eval_config,
+                    depends_on: Vec::new(),
+                });
+            }
+        }
+    }
+
+    let response = compile_runs(&state, &experiment, requests, payload.force).await?;
+    idempotency::commit(&state, key, &response.run_ids).await?;
+    Ok(Json(response))
+}
+
+/// Failing to get a Redis connection means Redis is down or saturated.
+fn redis_pool_error(err: deadpool_redis::PoolError) -> DomainError {
+    DomainError::Unavailable(format!("redis: {err}"))
+}
+
+/// Connection-level Redis failures are `Unavailable`; anything else (such as
+/// a wrong-type reply) is an internal error.
+fn redis_error(err: redis::RedisError) -> DomainError {
+    if err.is_io_error()
+        || err.is_connection_refusal()
+        || err.is_connection_dropped()
+        || err.is_timeout()
+    {
+        DomainError::Unavailable(format!("redis: {err}"))
+    } else {
+        DomainError::Internal(err.to_string())
+    }
+}
+
+/// Checks a config's `task.harness_args` the way the lm-eval runner will,
+/// so a run isn't queued only to fail as `harness_args_rejected`. The engine
+/// is the config's own `engine`, else `engine` (a task's `eval_engine`);
+/// args for any engine but the lm-eval harness are rejected, since no other
+/// runner passes them on.
+fn check_harness_args(
+    allowlist: &[String],
+    config: &Value,
+    engine: Option<&str>,
+) -> Result<(), DomainError> {
+    let Some(args) = config.pointer("/task/harness_args") else {
+        return Ok(());
+    };
+    let args: Vec<String> = serde_json::from_value(args.clone())
+        .map_err(|_| DomainError::field("task.harness_args", "must be a list of strings"))?;
+    if args.is_empty() {
+        return Ok(());
+    }
+    let engine = config
+        .get("engine")
+        .cloned()
+        .or_else(|| engine.map(|engine| Value::String(engine.into())))
+        .and_then(|engine| serde_json::from_value::<EvalEngine>(engine).ok());
+    if let Some(engine) = engine.filter(|engine| !matches!(engine, EvalEngine::LmEvalHarness)) {
+        return Err(DomainError::field(
+            "task.harness_args",
+            format!(
+                "only the lm-eval harness takes harness args, not {}",
+                engine.label()
+            ),
+        ));
+    }
+    TaskConfig::check_harness_args(&args, allowlist)
+        .map_err(|message| DomainError::field("task.harness_args", message))
+}
+
+/// Sets `default_output` on a config without an `output`, and fills resource
+/// fields the config leaves unset from the engine's entry in
+/// `default_resources`.
+fn apply_defaults(settings: &Settings, config: &mut Value) -> Result<(), DomainError> {
+    if let (Some(output), Some(obj)) = (&settings.default_output, config.as_object_mut()) {
+        if !obj.contains_key("output") {
+            let output =
+                serde_json::to_value(output).map_err(|e| DomainError::Internal(e.to_string()))?;
+            obj.insert("output".into(), output);
+        }
+    }
+    let Some(engine) = config
+        .get("engine")
+        .and_then(|engine| serde_json::from_value::<EvalEngine>(engine.clone()).ok())
+    else {
+        return Ok(());
+    };
+    let Some(defaults) = settings.default_resources.get(&engine) else {
+        return Ok(());
+    };
+    let Some(obj) = config.as_object_mut() else {
+        return Ok(());
+    };
+    let resources: ResourceConfig = match obj.get("resources") {
+        Some(resources) => serde_json::from_value(resources.clone())
+            .map_err(|e| DomainError::Validation(format!("invalid resources: {e}")))?,
+        None => ResourceConfig::default(),
+    };
+    let resources = serde_json::to_value(resources.with_defaults(defaults))
+        .map_err(|e| DomainError::Internal(e.to_string()))?;
+    obj.insert("resources".into(), resources);
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct CancelExperimentResponse {
+    cancelled: usize,
+    already_terminal: usize,
+    /// Cancelled runs whose job was still in the Redis queue and got removed.
+    dequeued: usize,
+}
+
+/// Cancels all queued and running runs of an experiment. Jobs still waiting
+/// in Redis are removed by payload; a job that slips through anyway is
+/// dropped by the worker, which can't claim a cancelled run. Running runs
+/// are marked cancelled; the worker notices within a poll interval, stops
+/// their engine and discards any result.
+async fn cancel_experiment(
+    State(state): State<SharedState>,
+    Path(experiment_id): Path<Uuid>,
+) -> Result<Json<CancelExperimentResponse>, DomainError> {
+    experiments::get(&state.db, &experiment_id).await?;
+    let outcome = runs::cancel_experiment(&state.db, &experiment_id).await?;
+
+    let mut redis_conn = state.redis.get().await.map_err(redis_pool_error)?;
+    let mut dequeued = 0;
+    for run in &outcome.cancelled {
+        if matches!(run.status, RunStatus::Queued) {
+            let payload = run.queue_payload()?;
+            let mut removed = 0;
+            for key in queue::queue_keys(&state.settings, &run.project_id) {
+                let count: usize = redis_conn
+                    .lrem(key, 0, &payload)
+                    .await
+                    .map_err(redis_error)?;
+                removed += count;
+            }
+            dequeued += removed.min(1);
+        }
+        let event = RunStatusEvent {
+            run_id: run.id,
+            status: RunStatus::Cancelled,
+            error: Some(runs::experiment_cancelled_error()),
+            at: Utc::now(),
+        };
+        let channel = run_status_channel(&state.settings.redis.status_channel_prefix, &run.id);
+        if let Ok(payload) = serde_json::to_string(&event) {
+            if let Err(err) = redis_conn.publish::<_, _, ()>(channel, payload).await {
+                tracing::warn!("failed to publish cancellation of run {}: {err}", run.id);
+            }
+        }
+    }
+    for (run_id, error) in outcome.dependents.skipped {
+        let event = RunStatusEvent {
+            run_id,
+            status: RunStatus::Cancelled,
+            error: Some(error),
+            at: Utc::now(),
+        };
+        let channel = run_status_channel(&state.settings.redis.status_channel_prefix, &run_id);
+        if let Ok(payload) = serde_json::to_string(&event) {
+            if let Err(err) = redis_conn.publish::<_, _, ()>(channel, payload).await {
+                tracing::warn!("failed to publish cancellation of run {run_id}: {err}");
+            }
+        }
+    }
+
+    Ok(Json(CancelExperimentResponse {
+        cancelled: outcome.cancelled.len(),
+        already_terminal: outcome.already_terminal,
+        dequeued,
+    }))
+}
+
+#[derive(Deserialize)]
+struct AdminRunListQuery {
+    /// Comma-separated statuses, e.g. `running,failed_engine`.
+    status: Option<String>,
+    engine: Option<String>,
+    created_after: Option<DateTime<Utc>>,
+    created_before: Option<DateTime<Utc>>,
+    limit: Option<i64>,
+    cursor: Option<String>,
+}
+
+/// Runs of every project, for operator dashboards. Always paged; the next
+/// page's cursor is returned in `X-Next-Cursor`.
+async fn list_all_runs(
+    State(state): State<SharedState>,
+    Query(query): Query<AdminRunListQuery>,
+) -> Result<Response, DomainError> {
+    let filter = runs::GlobalRunFilter {
+        statuses: query
+            .status
+            .as_deref()
+            .map(|raw| {
+                raw.split(',')
+                    .map(str::trim)
+                    .filter(|status| !status.is_empty())
+                    .map(runs::parse_status)
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .transpose()?
+            .unwrap_or_default(),
+        engine: query
+            .engine
+            .map(|engine| match engine.as_str() {
+                // Matches every custom engine, whatever its command.
+                "Custom" | "custom" => Ok(EvalEngine::Custom(Default::default())),
+                _ => serde_json::from_value::<EvalEngine>(Value::String(engine))
+                    .map_err(|e| DomainError::field("engine", e.to_string())),
+            })
+            .transpose()?,
+        created_after: query.created_after,
+        created_before: query.created_before,
+        limit: query.limit,
+        cursor: query.cursor.as_deref().map(str::parse).transpose()?,
+    };
+    let items = runs::search_global(&state.db, &filter).await?;
+    Ok(with_next_cursor(items, Some(filter.page_size())))
+}
+
+#[derive(Serialize)]
+struct PauseState {
+    paused: bool,
+}
+
+/// Whether `redis.pause_key` is set, i.e. workers pop no new jobs.
+async fn pause_state(State(state): State<SharedState>) -> Result<Json<PauseState>, DomainError> {
+    let mut redis_conn = state.redis.get().await.map_err(redis_pool_error)?;
+    let paused: bool = redis_conn
+        .exists(&state.settings.redis.pause_key)
+        .await
+        .map_err(redis_error)?;
+    Ok(Json(PauseState { paused }))
+}
+
+/// Sets `redis.pause_key`: workers finish their running jobs but pop no new
+/// ones until `POST /admin/resume`. Queued jobs stay in Redis.
+async fn pause_workers(State(state): State<SharedState>) -> Result<Json<PauseState>, DomainError> {
+    let mut redis_conn = state.redis.get().await.map_err(redis_pool_error)?;
+    redis_conn
+        .set::<_, _, ()>(&state.settings.redis.pause_key, Utc::now().to_rfc3339())
+        .await
+        .map_err(redis_error)?;
+    tracing::info!("workers paused");
+    Ok(Json(PauseState { paused: true }))
+}
+
+async fn resume_workers(State(state): State<SharedState>) -> Result<Json<PauseState>, DomainError> {
+    let mut redis_conn = state.redis.get().await.map_err(redis_pool_error)?;
+    redis_conn
+        .del::<_, ()>(&state.settings.redis.pause_key)
+        .await
+        .map_err(redis_error)?;
+    tracing::info!("workers resumed");
+    Ok(Json(PauseState { paused: false }))
+}
+
+/// Responds with `items`, adding `X-Next-Cursor` when the page is full.
+fn with_next_cursor(items: Vec<Run>, page_size: Option<i64>) -> Response {
+    let mut headers = HeaderMap::new();
+    if let Some(last) = items
+        .last()
+        .filter(|_| page_size == Some(items.len() as i64))
+    {
+        let cursor = runs::RunCursor::from(last).to_string();
+        if let Ok(value) = cursor.parse() {
+            headers.insert(NEXT_CURSOR_HEADER, value);
+        }
+    }
+    (headers, Json(items)).into_response()
+}
+
+#[derive(Deserialize)]
+struct RunListQuery {
+    project_id: Uuid,
+    status: Option<String>,
+    model_impl_id: Option<Uuid>,
+    checkpoint_id: Option<Uuid>,
+    task_id: Option<Uuid>,
+    created_after: Option<DateTime<Utc>>,
+    created_before: Option<DateTime<Utc>>,
+    limit: Option<i64>,
+    cursor: Option<String>,
 }
 
 async fn list_runs(
     State(state): State<SharedState>,
-    Query(query): Query<ProjectQuery>,
-) -> Result<Json<Vec<Run>>, DomainError> {
-    let items = runs::list(&state.db, &query.project_id).await?;
-    Ok(Json(items))
+    Query(query): Query<RunListQuery>,
+) -> Result<Response, DomainError> {
+    let filter = RunFilter {
+        project_id: query.project_id,
+        status: query
+            .status
+            .as_deref()
+            .map(runs::parse_status)
+            .transpose()?,
+        model_impl_id: query.model_impl_id,
+        checkpoint_id: query.checkpoint_id,
+        task_id: query.task_id,
+        created_after: query.created_after,
+        created_before: query.created_before,
+        limit: query.limit,
+        cursor: query.cursor.as_deref().map(str::parse).transpose()?,
+    };
+    let page_size = filter.page_size();
+    let items = runs::search(&state.db, filter).await?;
+    Ok(with_next_cursor(items, page_size))
+}
+
+/// A run with its result checks, as returned by `GET /runs/:id`.
+#[derive(Serialize)]
+struct RunDetail {
+    #[serde(flatten)]
+    run: Run,
+    coverage_warning: Option<CoverageWarning>,
 }
 
 async fn get_run(
     State(state): State<SharedState>,
     Path(run_id): Path<Uuid>,
-) -> Result<Json<Run>, DomainError> {
+) -> Result<Json<RunDetail>, DomainError> {
     let run = runs::get(&state.db, &run_id).await?;
-    Ok(Json(run))
+    let coverage_warning = coverage_warning(&state, &run).await?;
+    Ok(Json(RunDetail {
+        run,
+        coverage_warning,
+    }))
+}
+
+/// Checks the run's metrics, read from the store they were written to,
+/// against its task's dataset. A store that cannot read metrics back leaves
+/// the warning unset rather than failing the read of the run.
+async fn coverage_warning(
+    state: &AppState,
+    run: &Run,
+) -> Result<Option<CoverageWarning>, DomainError> {
+    let task = tasks::get(&state.db, &run.task_id).await?;
+    let dataset = datasets::get(&state.db, &task.dataset_id).await?;
+    if dataset.num_samples.is_none() {
+        return Ok(None);
+    }
+    match state
+        .stores
+        .for_output(&run.output())
+        .read_metrics(run.id)
+        .await
+    {
+        Ok(metrics) => Ok(coverage::check(
+            &dataset,
+            &run.member_names(),
+            &metrics,
+            &state.settings.coverage,
+        )),
+        Err(err) => {
+            tracing::warn!("failed to read metrics of run {}: {err:?}", run.id);
+            Ok(None)
+        }
+    }
+}
+
+/// The canary metrics of a completed run that moved beyond
+/// `canary.max_drift` from its project's canary baseline. Empty while the
+/// run is unfinished, without a baseline, or for the baseline run itself.
+/// Served apart from `GET /runs/:id` since it reads two runs' metrics.
+async fn run_canary_drift(
+    State(state): State<SharedState>,
+    Path(run_id): Path<Uuid>,
+) -> Result<Json<Vec<CanaryDrift>>, DomainError> {
+    let run = runs::get(&state.db, &run_id).await?;
+    if !matches!(run.status, RunStatus::Completed) {
+        return Ok(Json(Vec::new()));
+    }
+    let Some(baseline) = canary::get_baseline(&state.db, run.project_id).await? else {
+        return Ok(Json(Vec::new()));
+    };
+    if baseline.run_id == run.id {
+        return Ok(Json(Vec::new()));
+    }
+    let baseline_run = runs::get(&state.db, &baseline.run_id).await?;
+    let read = async {
+        let current = state
+            .stores
+            .for_output(&run.output())
+            .read_metrics(run.id)
+            .await?;
+        let reference = state
+            .stores
+            .for_output(&baseline_run.output())
+            .read_metrics(baseline_run.id)
+            .await?;
+        anyhow::Ok((current, reference))
+    };
+    let (current, reference) = read
+        .await
+        .map_err(|e| DomainError::Internal(format!("failed to read canary metrics: {e:#}")))?;
+    Ok(Json(canary::drift(
+        baseline.run_id,
+        &reference,
+        &current,
+        state.settings.canary.max_drift,
+    )))
+}
+
+async fn run_history(
+    State(state): State<SharedState>,
+    Path(run_id): Path<Uuid>,
+) -> Result<Json<Vec<runs::StatusTransition>>, DomainError> {
+    runs::get(&state.db, &run_id).await?;
+    let items = runs::history(&state.db, &run_id).await?;
+    Ok(Json(items))
+}
+
+async fn run_reproducibility(
+    State(state): State<SharedState>,
+    Path(run_id): Path<Uuid>,
+) -> Result<Json<runs::Reproducibility>, DomainError> {
+    let run = runs::get(&state.db, &run_id).await?;
+    let eval_config = full_eval_config(&state, &run).await?;
+    Ok(Json(runs::reproducibility(&run, &eval_config)))
+}
+
+/// The run's whole `eval_config`, fetched from the object store when the row
+/// only holds a stub.
+async fn full_eval_config(state: &AppState, run: &Run) -> Result<Value, DomainError> {
+    match (&run.config_uri, &state.stores.object_store) {
+        (None, _) => Ok(run.eval_config().clone()),
+        (Some(uri), Some(store)) => {
+            let data = store
+                .get_uri(uri)
+                .await
+                .map_err(|e| DomainError::Internal(format!("failed to load run config: {e}")))?;
+            serde_json::from_slice(&data).map_err(|e| DomainError::Internal(e.to_string()))
+        }
+        (Some(uri), None) => Err(DomainError::Internal(format!(
+            "run config is stored at {uri} but the object store is not configured"
+        ))),
+    }
+}
+
+/// The run's downloadable artifacts. Local ones are looked up under
+/// `integrations.work_dir`, so they are only listed when the API shares the
+/// worker's filesystem.
+async fn run_artifacts(
+    State(state): State<SharedState>,
+    Path(run_id): Path<Uuid>,
+) -> Result<Json<Vec<artifacts::Artifact>>, DomainError> {
+    let run = runs::get(&state.db, &run_id).await?;
+    let run_dir = std::path::Path::new(&state.settings.integrations.work_dir)
+        .join("runs")
+        .join(run_id.to_string());
+    let items = artifacts::manifest(&run, &run_dir, state.stores.object_store.as_deref()).await?;
+    Ok(Json(items))
+}
+
+/// Serves the engine's `result.json` verbatim, as kept in the object store by
+/// the worker. Runs finished without an object store have none.
+async fn run_raw_result(
+    State(state): State<SharedState>,
+    Path(run_id): Path<Uuid>,
+) -> Result<Response, DomainError> {
+    runs::get(&state.db, &run_id).await?;
+    let Some(store) = &state.stores.object_store else {
+        return Err(DomainError::NotFound(
+            "raw results are only kept when an object store is configured".into(),
+        ));
+    };
+    let data = store
+        .get_raw_result(run_id)
+        .await
+        .map_err(|e| DomainError::Internal(format!("failed to read raw result: {e}")))?
+        .ok_or_else(|| {
+            DomainError::NotFound(format!(
+                "run {run_id} has no stored raw result; its engine may not keep one or it has not finished"
+            ))
+        })?;
+    Ok(([(header::CONTENT_TYPE, "application/json")], data).into_response())
+}
+
+/// Where the sample queries of a run go.
+enum SampleSource {
+    Db,
+    ClickHouse(Arc<ClickHouseResultStore>),
+}
+
+/// Routes a run's sample queries by the location its samples were recorded
+/// at, or by its output config while none is recorded. Samples kept as an
+/// object-store blob have no index to query, so those runs get a 501
+/// pointing at the export endpoint.
+fn sample_source(state: &AppState, run: &Run) -> Result<SampleSource, DomainError> {
+    let not_queryable = || {
+        DomainError::NotImplemented(format!(
+            "samples of run {} are stored in the object store; \
+             fetch them via /runs/{}/samples/export",
+            run.id, run.id
+        ))
+    };
+    match &run.samples_location {
+        Some(SampleResultLocation::ObjectStore { .. }) => Err(not_queryable()),
+        Some(SampleResultLocation::ClickHouse { .. }) => match &state.stores.clickhouse {
+            Some(ch) => Ok(SampleSource::ClickHouse(ch.clone())),
+            None => Err(DomainError::Unavailable(format!(
+                "samples of run {} are in ClickHouse, which is not configured",
+                run.id
+            ))),
+        },
+        Some(SampleResultLocation::Inline { .. }) => Ok(SampleSource::Db),
+        None | Some(SampleResultLocation::None) => {
+            match (
+                run.output(),
+                &state.stores.clickhouse,
+                &state.stores.object_store,
+            ) {
+                (OutputConfig::ObjectStore { .. }, _, Some(_)) => Err(not_queryable()),
+                (OutputConfig::ClickHouse { .. } | OutputConfig::Hybrid { .. }, Some(ch), _) => {
+                    Ok(SampleSource::ClickHouse(ch.clone()))
+                }
+                _ => Ok(SampleSource::Db),
+            }
+        }
+    }
+}
+
+async fn sample_error_summary(
+    State(state): State<SharedState>,
+    Path(run_id): Path<Uuid>,
+) -> Result<Json<sample_outputs::SampleErrorSummary>, DomainError> {
+    let run = runs::get(&state.db, &run_id).await?;
+    let summary = match sample_source(&state, &run)? {
+        SampleSource::ClickHouse(ch) => ch
+            .error_summary(run_id)
+            .await
+            .map_err(|e| DomainError::Internal(e.to_string()))?,
+        SampleSource::Db => sample_outputs::error_summary(&state.db, &run_id).await?,
+    };
+    Ok(Json(summary))
+}
+
+async fn run_latency(
+    State(state): State<SharedState>,
+    Path(run_id): Path<Uuid>,
+) -> Result<Json<sample_outputs::LatencyPercentiles>, DomainError> {
+    let run = runs::get(&state.db, &run_id).await?;
+    let latency = match sample_source(&state, &run)? {
+        SampleSource::ClickHouse(ch) => ch
+            .latency_percentiles(run_id)
+            .await
+            .map_err(|e| DomainError::Internal(e.to_string()))?,
+        SampleSource::Db => sample_outputs::latency_percentiles(&state.db, &run_id).await?,
+    };
+    Ok(Json(latency))
+}
+
+#[derive(Serialize)]
+struct RunUsage {
+    run_id: Uuid,
+    model_name: Option<String>,
+    tokens: sample_outputs::TokenSummary,
+    /// `None` when no price is configured for the model.
+    estimated_cost: Option<f64>,
+}
+
+async fn run_usage(
+    State(state): State<SharedState>,
+    Path(run_id): Path<Uuid>,
+) -> Result<Json<RunUsage>, DomainError> {
+    let run = runs::get(&state.db, &run_id).await?;
+    let tokens = match sample_source(&state, &run)? {
+        SampleSource::ClickHouse(ch) => ch
+            .token_summary(run_id)
+            .await
+            .map_err(|e| DomainError::Internal(e.to_string()))?,
+        SampleSource::Db => sample_outputs::token_summary(&state.db, &run_id).await?,
+    };
+    let model_name = run
+        .eval_config()
+        .pointer("/model/model_name")
+        .and_then(Value::as_str)
+        .map(str::to_string);
+    let estimated_cost = model_name
+        .as_deref()
+        .and_then(|model| state.settings.pricing.price_for(model))
+        .map(|price| price.cost(tokens.prompt_tokens, tokens.completion_tokens));
+
+    Ok(Json(RunUsage {
+        run_id,
+        model_name,
+        tokens,
+        estimated_cost,
+    }))
+}
+
+#[derive(Deserialize)]
+struct CompareRunsQuery {
+    left: Uuid,
+    right: Uuid,
+}
+
+async fn compare_runs(
+    State(state): State<SharedState>,
+    Query(query): Query<CompareRunsQuery>,
+) -> Result<Json<Vec<metrics::MetricComparison>>, DomainError> {
+    let items = metrics::compare(&state.db, &query.left, &query.right).await?;
+    Ok(Json(items))
+}
+
+#[derive(Serialize)]
+struct ConfigComparison {
+    left: Uuid,
+    right: Uuid,
+    identical: bool,
+    differences: Vec<JsonDiff>,
+}
+
+async fn compare_run_configs(
+    State(state): State<SharedState>,
+    Query(query): Query<CompareRunsQuery>,
+) -> Result<Json<ConfigComparison>, DomainError> {
+    let left = runs::get(&state.db, &query.left).await?;
+    let right = runs::get(&state.db, &query.right).await?;
+    let differences = runs::config_diff(
+        &full_eval_config(&state, &left).await?,
+        &full_eval_config(&state, &right).await?,
+    );
+    Ok(Json(ConfigComparison {
+        left: left.id,
+        right: right.id,
+        identical: differences.is_empty(),
+        differences,
+    }))
+}
+
+#[derive(Deserialize)]
+struct RegressionCheckRequest {
+    baseline_run_id: Uuid,
+    thresholds: HashMap<String, f64>,
+}
+
+async fn regression_check(
+    State(state): State<SharedState>,
+    Path(run_id): Path<Uuid>,
+    Json(payload): Json<RegressionCheckRequest>,
+) -> Result<Json<metrics::RegressionReport>, DomainError> {
+    let report = metrics::regression_check(
+        &state.db,
+        &run_id,
+        &payload.baseline_run_id,
+        &payload.thresholds,
+    )
+    .await?;
+    Ok(Json(report))
 }
 
 #[derive(Serialize)]
@@ -447,37 +1657,562 @@ async fn enqueue_run(
     Path(run_id): Path<Uuid>,
 ) -> Result<Json<EnqueueResponse>, DomainError> {
     let run = runs::get(&state.db, &run_id).await?;
-    let payload = serde_json::to_string(&run.eval_config)
-        .map_err(|e| DomainError::Internal(e.to_string()))?;
-    let mut redis_conn = state
-        .redis
-        .get()
+    if matches!(run.status, RunStatus::Blocked) {
+        return Err(DomainError::Conflict(
+            "run is blocked on its dependencies; it is queued once they complete".into(),
+        ));
+    }
+    projects::effective_settings(&state.db, &run.project_id, &state.settings)
+        .await?
+        .check_output(&run.output())
+        .map_err(DomainError::Validation)?;
+    let payload = run.queue_payload()?;
+    let mut redis_conn = state.redis.get().await.map_err(redis_pool_error)?;
+    // One MULTI, so the worker's registry pruning never sees the project
+    // registered with an empty list that is about to be pushed to.
+    let mut pipe = redis::pipe();
+    pipe.atomic()
+        .rpush(queue::queue_key(&state.settings, &run.project_id), payload)
+        .ignore();
+    if state.settings.queues.strategy != QueueStrategy::Single {
+        pipe.sadd(
+            queue::registry_key(&state.settings),
+            run.project_id.to_string(),
+        )
+        .ignore();
+    }
+    pipe.query_async::<_, ()>(&mut redis_conn)
         .await
-        .map_err(|e| DomainError::Internal(e.to_string()))?;
-    redis_conn
-        .rpush(&state.settings.redis.queue_key, payload)
+        .map_err(redis_error)?;
+
+    Ok(Json(EnqueueResponse { accepted: true }))
+}
+
+/// Most run ids `POST /runs/enqueue-batch` takes at once.
+const MAX_ENQUEUE_BATCH: usize = 1000;
+
+#[derive(Serialize)]
+struct BatchEnqueueResponse {
+    accepted: usize,
+    rejected: usize,
+    /// One entry per requested id, in request order.
+    results: Vec<BatchEnqueueResult>,
+}
+
+#[derive(Serialize)]
+struct BatchEnqueueResult {
+    run_id: Uuid,
+    accepted: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+}
+
+/// Enqueues many queued runs with one pipelined Redis round-trip. Runs that
+/// don't exist, aren't `Queued` or need an unconfigured store are rejected
+/// individually; the others are pushed even if some are rejected.
+async fn enqueue_runs_batch(
+    State(state): State<SharedState>,
+    Json(run_ids): Json<Vec<Uuid>>,
+) -> Result<Json<BatchEnqueueResponse>, DomainError> {
+    if run_ids.is_empty() {
+        return Err(DomainError::Validation("no run ids given".into()));
+    }
+    if run_ids.len() > MAX_ENQUEUE_BATCH {
+        return Err(DomainError::Validation(format!(
+            "at most {MAX_ENQUEUE_BATCH} runs can be enqueued at once"
+        )));
+    }
+    let found: HashMap<Uuid, Run> = runs::list_by_ids(&state.db, &run_ids)
+        .await?
+        .into_iter()
+        .map(|run| (run.id, run))
+        .collect();
+    let mut settings: HashMap<Uuid, Result<Settings, String>> = HashMap::new();
+    for run in found.values() {
+        if let Entry::Vacant(entry) = settings.entry(run.project_id) {
+            let effective =
+                projects::effective_settings(&state.db, &run.project_id, &state.settings)
+                    .await
+                    .map_err(|e| e.to_string());
+            entry.insert(effective);
+        }
+    }
+
+    let mut results = Vec::with_capacity(run_ids.len());
+    let mut pipe = redis::pipe();
+    pipe.atomic();
+    let mut projects = std::collections::BTreeSet::new();
+    let mut seen = std::collections::HashSet::new();
+    for run_id in run_ids {
+        let checked = match found.get(&run_id) {
+            None => Err("run not found".to_string()),
+            Some(_) if !seen.insert(run_id) => Err("listed more than once".to_string()),
+            Some(run) if !matches!(run.status, RunStatus::Queued) => {
+                Err(format!("run is {:?}, not Queued", run.status))
+            }
+            Some(run) => settings[&run.project_id]
+                .as_ref()
+                .map_err(Clone::clone)
+                .and_then(|settings| settings.check_output(&run.output()))
+                .and_then(|()| run.queue_payload().map_err(|e| e.to_string()))
+                .map(|payload| (run, payload)),
+        };
+        match checked {
+            Ok((run, payload)) => {
+                pipe.rpush(queue::queue_key(&state.settings, &run.project_id), payload)
+                    .ignore();
+                projects.insert(run.project_id);
+                results.push(BatchEnqueueResult {
+                    run_id,
+                    accepted: true,
+                    reason: None,
+                });
+            }
+            Err(reason) => results.push(BatchEnqueueResult {
+                run_id,
+                accepted: false,
+                reason: Some(reason),
+            }),
+        }
+    }
+
+    if !projects.is_empty() {
+        if state.settings.queues.strategy != QueueStrategy::Single {
+            let project_ids: Vec<String> = projects.iter().map(Uuid::to_string).collect();
+            pipe.sadd(queue::registry_key(&state.settings), project_ids)
+                .ignore();
+        }
+        let mut redis_conn = state.redis.get().await.map_err(redis_pool_error)?;
+        pipe.query_async::<_, ()>(&mut redis_conn)
+            .await
+            .map_err(redis_error)?;
+    }
+
+    let accepted = results.iter().filter(|result| result.accepted).count();
+    Ok(Json(BatchEnqueueResponse {
+        accepted,
+        rejected: results.len() - accepted,
+        results,
+    }))
+}
+
+#[derive(Deserialize)]
+struct MigrateStoreRequest {
+    output: OutputConfig,
+}
+
+#[derive(Serialize)]
+struct MigrateStoreResponse {
+    migrated: MigratedResults,
+    run: Run,
+}
+
+/// Copies a completed run's metrics and samples into the stores of `output`
+/// and makes it the run's output, e.g. to backfill ClickHouse for runs that
+/// were written DB-only.
+async fn migrate_run_store(
+    State(state): State<SharedState>,
+    Path(run_id): Path<Uuid>,
+    Json(payload): Json<MigrateStoreRequest>,
+) -> Result<Json<MigrateStoreResponse>, DomainError> {
+    let run = runs::get(&state.db, &run_id).await?;
+    if !matches!(run.status, RunStatus::Completed) {
+        return Err(DomainError::Conflict(format!(
+            "run is {:?}; only completed runs can be migrated",
+            run.status
+        )));
+    }
+    if let Some(SampleResultLocation::ObjectStore { .. }) = run.samples_location {
+        return Err(DomainError::Unprocessable(
+            "the run's samples are stored as an object-store blob and can't be read back".into(),
+        ));
+    }
+    state
+        .settings
+        .check_output(&payload.output)
+        .map_err(DomainError::Validation)?;
+    let migrated = state
+        .stores
+        .migrate_run(&run, &payload.output)
         .await
         .map_err(|e| DomainError::Internal(e.to_string()))?;
+    runs::set_output(&state.db, &run_id, &payload.output).await?;
+    let run = runs::get(&state.db, &run_id).await?;
+    Ok(Json(MigrateStoreResponse { migrated, run }))
+}
 
-    Ok(Json(EnqueueResponse { accepted: true }))
+#[derive(Deserialize)]
+struct MetricsQuery {
+    run_id: Uuid,
+    dataset: Option<String>,
+    subset: Option<String>,
+    split: Option<String>,
+    metric_name: Option<String>,
+    limit: Option<i64>,
+    offset: Option<i64>,
 }
 
 async fn list_metrics(
     State(state): State<SharedState>,
-    Query(query): Query<RunQuery>,
+    Query(query): Query<MetricsQuery>,
 ) -> Result<Json<Vec<metrics::Metric>>, DomainError> {
-    let items = metrics::list_by_run(&state.db, &query.run_id).await?;
+    let filter = metrics::MetricFilter {
+        run_id: query.run_id,
+        dataset: query.dataset,
+        subset: query.subset,
+        split: query.split,
+        metric_name: query.metric_name,
+        limit: query.limit,
+        offset: query.offset,
+    };
+    let items = metrics::list_by_run(&state.db, &filter).await?;
     Ok(Json(items))
 }
 
+#[derive(Deserialize)]
+struct SubsetBreakdownQuery {
+    metric_name: String,
+}
+
+/// `metric_name` of a run per subset, worst first. The direction comes from
+/// the stored metrics, else the run config, else the direction registry,
+/// else the default `higher_better`.
+async fn run_metrics_by_subset(
+    State(state): State<SharedState>,
+    Path(run_id): Path<Uuid>,
+    Query(query): Query<SubsetBreakdownQuery>,
+) -> Result<Json<metrics::SubsetBreakdown>, DomainError> {
+    let run = runs::get(&state.db, &run_id).await?;
+    let records = state
+        .stores
+        .for_output(&run.output())
+        .read_metrics(run.id)
+        .await
+        .map_err(|e| DomainError::Internal(e.to_string()))?;
+    let stored = records
+        .iter()
+        .filter(|record| record.metric_name == query.metric_name)
+        .find_map(|record| record.direction);
+    let configured = || {
+        run.parsed_config().ok().and_then(|config| {
+            config
+                .metrics
+                .iter()
+                .find(|metric| metric.name == query.metric_name)
+                .and_then(MetricConfig::configured_direction)
+        })
+    };
+    let direction = match stored.or_else(configured) {
+        Some(direction) => direction,
+        None => metrics::direction_registry(&state.db)
+            .await?
+            .resolve(&query.metric_name)
+            .unwrap_or_default(),
+    };
+    Ok(Json(metrics::subset_breakdown(
+        &query.metric_name,
+        &records,
+        direction,
+    )))
+}
+
+#[derive(Deserialize)]
+struct MetricSeriesQuery {
+    model_impl_id: Uuid,
+    metric_name: String,
+    dataset: String,
+    #[serde(default)]
+    all_runs: bool,
+}
+
+#[derive(Deserialize)]
+struct IngestMetricRequest {
+    dataset: String,
+    subset: Option<String>,
+    split: Option<String>,
+    metric_name: String,
+    value: f64,
+    n_samples: Option<i64>,
+    ci_low: Option<f64>,
+    ci_high: Option<f64>,
+    extra: Option<Value>,
+    #[serde(default)]
+    direction: Option<MetricDirection>,
+}
+
+#[derive(Serialize)]
+struct IngestMetricsResponse {
+    ingested: usize,
+    /// Records the post-processors derived from the run's results with the
+    /// ingested ones, e.g. composites and aggregates of per-sample scores.
+    derived: usize,
+}
+
+async fn ingest_metrics(
+    State(state): State<SharedState>,
+    Path(run_id): Path<Uuid>,
+    Json(payload): Json<Vec<IngestMetricRequest>>,
+) -> Result<Json<IngestMetricsResponse>, DomainError> {
+    let run = runs::get(&state.db, &run_id).await?;
+    if !run.status.is_terminal() {
+        return Err(DomainError::Conflict(format!(
+            "run is {:?}; metrics can only be attached to finished runs",
+            run.status
+        )));
+    }
+    if let Some(metric) = payload.iter().find(|metric| !metric.value.is_finite()) {
+        return Err(DomainError::Validation(format!(
+            "metric {} has a non-finite value",
+            metric.metric_name
+        )));
+    }
+
+    let mut records: Vec<MetricRecord> = payload
+        .into_iter()
+        .map(|metric| MetricRecord {
+            run_id,
+            dataset: metric.dataset,
+            subset: metric.subset,
+            split: metric.split,
+            metric_name: metric.metric_name,
+            value: metric.value,
+            n_samples: metric.n_samples,
+            ci_low: metric.ci_low,
+            ci_high: metric.ci_high,
+            extra: metric.extra,
+            direction: metric.direction,
+        })
+        .collect();
+    let configs = run
+        .parsed_config()
+        .map(|config| config.metrics.as_slice())
+        .unwrap_or_default();
+    let ingested = records.len();
+    match state.stores.derive_ingested(&run, configs, &records).await {
+        Ok(derived) => records.extend(derived),
+        Err(err) => tracing::warn!("failed to post-process metrics of run {run_id}: {err:#}"),
+    }
+    metrics::direction_registry(&state.db)
+        .await?
+        .fill(&mut records, configs);
+    state
+        .stores
+        .for_output(&run.output())
+        .upsert_metrics(&records)
+        .await
+        .map_err(|e| DomainError::Internal(e.to_string()))?;
+
+    Ok(Json(IngestMetricsResponse {
+        ingested,
+        derived: records.len() - ingested,
+    }))
+}
+
+#[derive(Serialize)]
+struct MetricDirections {
+    /// Names, or whole tokens of names, marking a metric as
+    /// lower-is-better.
+    builtin_lower_is_better: &'static [&'static str],
+    /// Likewise for higher-is-better; names in neither list have no
+    /// direction unless overridden.
+    builtin_higher_is_better: &'static [&'static str],
+    overrides: Vec<metrics::DirectionOverride>,
+}
+
+async fn list_metric_directions(
+    State(state): State<SharedState>,
+) -> Result<Json<MetricDirections>, DomainError> {
+    Ok(Json(MetricDirections {
+        builtin_lower_is_better: LOWER_IS_BETTER,
+        builtin_higher_is_better: HIGHER_IS_BETTER,
+        overrides: metrics::list_direction_overrides(&state.db).await?,
+    }))
+}
+
+#[derive(Deserialize)]
+struct MetricDirectionRequest {
+    /// `null` removes the override, restoring the built-in direction.
+    direction: Option<MetricDirection>,
+}
+
+#[derive(Serialize)]
+struct MetricDirectionResponse {
+    metric_name: String,
+    /// `None` once the override is cleared from a name without a built-in
+    /// direction.
+    direction: Option<MetricDirection>,
+    overridden: bool,
+}
+
+/// Overrides the registry direction of a metric name for metrics persisted
+/// and compared from now on. Directions already stored with metrics stay.
+async fn set_metric_direction(
+    State(state): State<SharedState>,
+    Path(metric_name): Path<String>,
+    Json(payload): Json<MetricDirectionRequest>,
+) -> Result<Json<MetricDirectionResponse>, DomainError> {
+    let direction = match payload.direction {
+        Some(direction) => {
+            let stored =
+                metrics::set_direction_override(&state.db, &metric_name, direction).await?;
+            Some(stored.direction)
+        }
+        None => {
+            metrics::clear_direction_override(&state.db, &metric_name).await?;
+            MetricDirection::for_name(&metric_name)
+        }
+    };
+    Ok(Json(MetricDirectionResponse {
+        overridden: payload.direction.is_some(),
+        metric_name,
+        direction,
+    }))
+}
+
+async fn metric_series(
+    State(state): State<SharedState>,
+    Query(query): Query<MetricSeriesQuery>,
+) -> Result<Json<Vec<metrics::SeriesPoint>>, DomainError> {
+    let items = metrics::series(
+        &state.db,
+        &metrics::SeriesQuery {
+            model_impl_id: query.model_impl_id,
+            metric_name: query.metric_name,
+            dataset: query.dataset,
+            all_runs: query.all_runs,
+        },
+    )
+    .await?;
+    Ok(Json(items))
+}
+
+#[derive(Deserialize)]
+struct MetricRollupQuery {
+    project_id: Uuid,
+    metric_name: String,
+}
+
+async fn metric_rollup(
+    State(state): State<SharedState>,
+    Query(query): Query<MetricRollupQuery>,
+) -> Result<Json<Vec<metrics::MetricRollup>>, DomainError> {
+    projects::get(&state.db, &query.project_id).await?;
+    let items = metrics::rollup(&state.db, &query.project_id, &query.metric_name).await?;
+    Ok(Json(items))
+}
+
+#[derive(Deserialize)]
+struct SamplesQuery {
+    run_id: Uuid,
+    dataset: Option<String>,
+    split: Option<String>,
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+/// Lists a run's samples from whichever store its output config wrote them
+/// to, ordered by `sample_index`.
 async fn list_samples(
     State(state): State<SharedState>,
-    Query(query): Query<RunQuery>,
+    Query(query): Query<SamplesQuery>,
 ) -> Result<Json<Vec<sample_outputs::SampleOutput>>, DomainError> {
-    let items = sample_outputs::list_by_run(&state.db, &query.run_id).await?;
+    let filter = sample_outputs::SampleFilter {
+        run_id: query.run_id,
+        dataset: query.dataset,
+        split: query.split,
+        limit: query.limit,
+        offset: query.offset,
+    };
+    let run = runs::get(&state.db, &filter.run_id).await?;
+    let items = state
+        .stores
+        .read_samples(&run, &filter)
+        .await
+        .map_err(|e| DomainError::Internal(e.to_string()))?;
     Ok(Json(items))
 }
 
+/// Looks up one sample by index. Samples written to the object store are a
+/// single jsonl blob without an index, so those runs get a 501 pointing at
+/// the export endpoint instead.
+async fn get_sample(
+    State(state): State<SharedState>,
+    Path((run_id, sample_index)): Path<(Uuid, i64)>,
+) -> Result<Json<sample_outputs::SampleOutput>, DomainError> {
+    let run = runs::get(&state.db, &run_id).await?;
+    let sample = match sample_source(&state, &run)? {
+        SampleSource::ClickHouse(ch) => ch
+            .get_sample(run_id, sample_index)
+            .await
+            .map_err(|e| DomainError::Internal(e.to_string()))?
+            .ok_or_else(|| {
+                DomainError::NotFound(format!("sample {sample_index} of run {run_id}"))
+            })?,
+        SampleSource::Db => sample_outputs::get_one(&state.db, &run_id, sample_index).await?,
+    };
+    Ok(Json(sample))
+}
+
+#[derive(Deserialize)]
+struct SampleSearchQuery {
+    run_id: Uuid,
+    q: Option<String>,
+    metric: Option<String>,
+    lt: Option<f64>,
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+async fn search_samples(
+    State(state): State<SharedState>,
+    Query(query): Query<SampleSearchQuery>,
+) -> Result<Json<sample_outputs::SamplePage>, DomainError> {
+    let run = runs::get(&state.db, &query.run_id).await?;
+    let search = sample_outputs::SampleSearch {
+        run_id: query.run_id,
+        q: query.q,
+        metric: query.metric,
+        lt: query.lt,
+        limit: query.limit.unwrap_or(sample_outputs::DEFAULT_PAGE_SIZE),
+        offset: query.offset.unwrap_or(0),
+    };
+    search.metric_threshold()?;
+    let page = match sample_source(&state, &run)? {
+        SampleSource::ClickHouse(ch) => ch
+            .search_samples(&search)
+            .await
+            .map_err(|e| DomainError::Internal(e.to_string()))?,
+        SampleSource::Db => sample_outputs::search(&state.db, &search).await?,
+    };
+    Ok(Json(page))
+}
+
+#[derive(Deserialize)]
+struct SampleDiffQuery {
+    left: Uuid,
+    right: Uuid,
+}
+
+async fn diff_samples(
+    State(state): State<SharedState>,
+    Query(query): Query<SampleDiffQuery>,
+) -> Result<Json<sample_outputs::SampleDiff>, DomainError> {
+    for run_id in [&query.left, &query.right] {
+        let run = runs::get(&state.db, run_id).await?;
+        let spilled = matches!(
+            run.samples_location,
+            Some(SampleResultLocation::ObjectStore { .. })
+        );
+        if spilled || !state.stores.samples_in_db(&run.output()) {
+            return Err(DomainError::Validation(format!(
+                "samples of run {run_id} are not stored in MySQL and can't be diffed"
+            )));
+        }
+    }
+    let diff = sample_outputs::diff(&state.db, &query.left, &query.right).await?;
+    Ok(Json(diff))
+}
+
 #[derive(Deserialize)]
 struct RemoteTestRequest {
     project_id: Uuid,
@@ -0,0 +1,89 @@
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::Json;
+use serde::Serialize;
+
+use crate::SharedState;
+
+#[derive(Serialize)]
+pub struct DependencyStatus {
+    name: &'static str,
+    required: bool,
+    healthy: bool,
+    error: Option<String>,
+}
+
+impl DependencyStatus {
+    fn from_probe(name: &'static str, required: bool, probe: Result<(), String>) -> Self {
+        Self {
+            name,
+            required,
+            healthy: probe.is_ok(),
+            error: probe.err(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct ReadinessResponse {
+    ready: bool,
+    dependencies: Vec<DependencyStatus>,
+}
+
+/// Ready only when every required dependency is healthy; optional ones are
+/// reported but never fail the probe.
+pub fn summarize(dependencies: Vec<DependencyStatus>) -> (StatusCode, Json<ReadinessResponse>) {
+    let ready = dependencies.iter().all(|dep| dep.healthy || !dep.required);
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (
+        status,
+        Json(ReadinessResponse {
+            ready,
+            dependencies,
+        }),
+    )
+}
+
+async fn probe_redis(state: &SharedState) -> Result<(), String> {
+    let mut conn = state.redis.get().await.map_err(|e| e.to_string())?;
+    redis::cmd("PING")
+        .query_async::<_, String>(&mut conn)
+        .await
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+pub async fn readiness_check(
+    State(state): State<SharedState>,
+) -> (StatusCode, Json<ReadinessResponse>) {
+    let readiness = &state.settings.readiness;
+    let mut dependencies = vec![
+        DependencyStatus::from_probe(
+            "database",
+            true,
+            unified_domain::db::ping(&state.db)
+                .await
+                .map_err(|e| e.to_string()),
+        ),
+        DependencyStatus::from_probe("redis", true, probe_redis(&state).await),
+    ];
+    if let Some(clickhouse) = &state.stores.clickhouse {
+        dependencies.push(DependencyStatus::from_probe(
+            "clickhouse",
+            readiness.require_clickhouse,
+            clickhouse.ping().await.map_err(|e| e.to_string()),
+        ));
+    }
+    if let Some(object_store) = &state.stores.object_store {
+        dependencies.push(DependencyStatus::from_probe(
+            "object_store",
+            readiness.require_object_store,
+            object_store.ping().await.map_err(|e| e.to_string()),
+        ));
+    }
+    summarize(dependencies)
+}
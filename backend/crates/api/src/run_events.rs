@@ -0,0 +1,89 @@
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Path, State};
+use axum::response::Response;
+use futures::StreamExt;
+use unified_domain::runs::{self, Run};
+use unified_shared::error::DomainError;
+use unified_shared::eval::{run_status_channel, RunStatusEvent};
+use uuid::Uuid;
+
+use crate::SharedState;
+
+/// Upgrades to a WebSocket that sends the run's current status, then every
+/// status change published by the worker, and closes once the run is terminal.
+pub async fn run_status_ws(
+    State(state): State<SharedState>,
+    Path(run_id): Path<Uuid>,
+    ws: WebSocketUpgrade,
+) -> Result<Response, DomainError> {
+    runs::get(&state.db, &run_id).await?;
+    Ok(ws.on_upgrade(move |socket| async move {
+        if let Err(err) = forward_status(state, run_id, socket).await {
+            tracing::warn!("status stream for run {run_id} ended: {err:?}");
+        }
+    }))
+}
+
+async fn forward_status(
+    state: SharedState,
+    run_id: Uuid,
+    mut socket: WebSocket,
+) -> anyhow::Result<()> {
+    // Subscribe before reading the current status so no transition in
+    // between is lost.
+    let client = redis::Client::open(state.settings.redis.url.as_str())?;
+    let mut pubsub = client.get_async_connection().await?.into_pubsub();
+    pubsub
+        .subscribe(run_status_channel(
+            &state.settings.redis.status_channel_prefix,
+            &run_id,
+        ))
+        .await?;
+
+    let run = runs::get(&state.db, &run_id).await?;
+    let current = current_event(&run);
+    socket
+        .send(Message::Text(serde_json::to_string(&current)?))
+        .await?;
+    if current.status.is_terminal() {
+        socket.send(Message::Close(None)).await?;
+        return Ok(());
+    }
+
+    let mut messages = pubsub.on_message();
+    loop {
+        tokio::select! {
+            message = messages.next() => {
+                let Some(message) = message else {
+                    break;
+                };
+                let payload: String = message.get_payload()?;
+                let event: RunStatusEvent = serde_json::from_str(&payload)?;
+                socket.send(Message::Text(payload)).await?;
+                if event.status.is_terminal() {
+                    socket.send(Message::Close(None)).await?;
+                    break;
+                }
+            }
+            incoming = socket.recv() => match incoming {
+                Some(Ok(Message::Close(_))) | None => break,
+                Some(Ok(_)) => {}
+                Some(Err(err)) => return Err(err.into()),
+            },
+        }
+    }
+
+    Ok(())
+}
+
+fn current_event(run: &Run) -> RunStatusEvent {
+    RunStatusEvent {
+        run_id: run.id,
+        status: run.status,
+        error: run.error.clone(),
+        at: run
+            .finished_at
+            .or(run.started_at)
+            .unwrap_or_else(chrono::Utc::now),
+    }
+}
@@ -0,0 +1,128 @@
+use std::collections::HashSet;
+use std::path::Path;
+
+use serde::Serialize;
+use unified_shared::error::DomainError;
+use unified_shared::eval::SampleResultLocation;
+
+use crate::result_store::ObjectStoreResultStore;
+use crate::runs::Run;
+
+/// Files a run may leave in its run directory and under its object-store
+/// prefix.
+pub const KNOWN_ARTIFACTS: &[&str] = &["config.json", "result.json", "samples.jsonl", "logs.txt"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ArtifactLocation {
+    /// The worker's run directory (`integrations.work_dir/runs/{run_id}`).
+    Local,
+    ObjectStore,
+    /// Samples stored in MySQL or ClickHouse; downloadable through
+    /// `GET /runs/{id}/samples/export`.
+    Database,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Artifact {
+    pub name: String,
+    pub location: ArtifactLocation,
+    /// Local path or `s3://` URI; `None` for samples in the database.
+    pub uri: Option<String>,
+    /// `None` when the store didn't report it.
+    pub size: Option<u64>,
+    pub content_type: String,
+}
+
+/// Lists the run's artifacts: the [`KNOWN_ARTIFACTS`] present in `run_dir`
+/// and under the run's object-store prefix, the stored config and whatever
+/// `samples_location` points to. Object-store artifacts are HEADed for their
+/// size, so only existing objects are listed.
+pub async fn manifest(
+    run: &Run,
+    run_dir: &Path,
+    object_store: Option<&ObjectStoreResultStore>,
+) -> Result<Vec<Artifact>, DomainError> {
+    let mut artifacts = Vec::new();
+
+    for name in KNOWN_ARTIFACTS {
+        let path = run_dir.join(name);
+        if let Ok(metadata) = tokio::fs::metadata(&path).await {
+            if metadata.is_file() {
+                artifacts.push(Artifact {
+                    name: name.to_string(),
+                    location: ArtifactLocation::Local,
+                    uri: Some(path.display().to_string()),
+                    size: Some(metadata.len()),
+                    content_type: content_type_for(name).into(),
+                });
+            }
+        }
+    }
+
+    let mut objects: Vec<(String, String)> = Vec::new();
+    if let Some(config_uri) = &run.config_uri {
+        objects.push(("config.json".into(), config_uri.clone()));
+    }
+    match &run.samples_location {
+        Some(SampleResultLocation::ObjectStore { uri, .. }) => {
+            objects.push(("samples.jsonl".into(), uri.clone()))
+        }
+        Some(SampleResultLocation::Inline { .. } | SampleResultLocation::ClickHouse { .. }) => {
+            artifacts.push(Artifact {
+                name: "samples.jsonl".into(),
+                location: ArtifactLocation::Database,
+                uri: None,
+                size: None,
+                content_type: content_type_for("samples.jsonl").into(),
+            })
+        }
+        Some(SampleResultLocation::None) | None => {}
+    }
+
+    if let Some(store) = object_store {
+        for name in KNOWN_ARTIFACTS {
+            objects.push((name.to_string(), store.run_object_uri(run.id, name)));
+        }
+        let mut seen = HashSet::new();
+        for (name, uri) in objects {
+            let Some((bucket, key)) = store.locate(&uri) else {
+                tracing::warn!("run {} artifact {uri} is not in the object store", run.id);
+                continue;
+            };
+            if !seen.insert((bucket.to_string(), key.to_string())) {
+                continue;
+            }
+            let head = store.head_object(bucket, key).await.map_err(|e| {
+                DomainError::Internal(format!("failed to read artifact {uri}: {e}"))
+            })?;
+            if let Some(head) = head {
+                artifacts.push(Artifact {
+                    content_type: head
+                        .content_type
+                        .unwrap_or_else(|| content_type_for(&name).into()),
+                    name,
+                    location: ArtifactLocation::ObjectStore,
+                    uri: Some(format!("s3://{bucket}/{key}")),
+                    size: head.size,
+                });
+            }
+        }
+    } else if !objects.is_empty() {
+        tracing::warn!(
+            "run {} has object-store artifacts but the object store is not configured",
+            run.id
+        );
+    }
+
+    Ok(artifacts)
+}
+
+fn content_type_for(name: &str) -> &'static str {
+    match Path::new(name).extension().and_then(|ext| ext.to_str()) {
+        Some("json") => "application/json",
+        Some("jsonl") => "application/x-ndjson",
+        Some("txt") => "text/plain",
+        _ => "application/octet-stream",
+    }
+}
@@ -0,0 +1,163 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::Row;
+use unified_shared::error::DomainError;
+use unified_shared::eval::{is_canary_subset, MetricRecord};
+use uuid::Uuid;
+
+use crate::db::{db_error, DbPool};
+use crate::utils::parse_uuid;
+
+/// The run whose canary metrics a project's runs are compared against.
+#[derive(Debug, Clone, Serialize)]
+pub struct CanaryBaseline {
+    pub project_id: Uuid,
+    pub run_id: Uuid,
+    pub set_at: DateTime<Utc>,
+}
+
+/// A canary metric that moved further from the baseline than
+/// `canary.max_drift`.
+#[derive(Debug, Clone, Serialize)]
+pub struct CanaryDrift {
+    pub dataset: String,
+    /// `canary`, or `canary/<subset>` for a subset of the canary dataset.
+    pub subset: String,
+    pub split: Option<String>,
+    pub metric_name: String,
+    pub baseline_run_id: Uuid,
+    pub baseline: f64,
+    pub value: f64,
+    pub delta: f64,
+}
+
+/// Makes `run_id` the project's canary baseline, replacing any earlier one.
+pub async fn set_baseline(
+    pool: &DbPool,
+    project_id: Uuid,
+    run_id: Uuid,
+) -> Result<CanaryBaseline, DomainError> {
+    let baseline = CanaryBaseline {
+        project_id,
+        run_id,
+        set_at: Utc::now(),
+    };
+    sqlx::query(
+        "INSERT INTO canary_baselines (project_id, run_id, set_at) VALUES (?, ?, ?) \
+         ON DUPLICATE KEY UPDATE run_id = VALUES(run_id), set_at = VALUES(set_at)",
+    )
+    .bind(project_id.to_string())
+    .bind(run_id.to_string())
+    .bind(baseline.set_at)
+    .execute(pool)
+    .await
+    .map_err(db_error)?;
+    Ok(baseline)
+}
+
+pub async fn get_baseline(
+    pool: &DbPool,
+    project_id: Uuid,
+) -> Result<Option<CanaryBaseline>, DomainError> {
+    let row =
+        sqlx::query("SELECT project_id, run_id, set_at FROM canary_baselines WHERE project_id = ?")
+            .bind(project_id.to_string())
+            .fetch_optional(pool)
+            .await
+            .map_err(db_error)?;
+    row.map(|row| {
+        let project_id: String = row.try_get("project_id")?;
+        let run_id: String = row.try_get("run_id")?;
+        Ok(CanaryBaseline {
+            project_id: parse_uuid(&project_id)?,
+            run_id: parse_uuid(&run_id)?,
+            set_at: row.try_get("set_at")?,
+        })
+    })
+    .transpose()
+}
+
+/// Compares the canary metrics (see [`is_canary_subset`]) of a run against
+/// the baseline run's, matched by dataset, subset, split and metric name.
+/// Metrics missing on either side are skipped; a metric drifts when the
+/// absolute difference exceeds `max_drift`.
+pub fn drift(
+    baseline_run_id: Uuid,
+    baseline: &[MetricRecord],
+    current: &[MetricRecord],
+    max_drift: f64,
+) -> Vec<CanaryDrift> {
+    let canary_subset = |metric: &MetricRecord| {
+        metric
+            .subset
+            .as_deref()
+            .filter(|subset| is_canary_subset(subset))
+            .map(str::to_string)
+    };
+    current
+        .iter()
+        .filter_map(|metric| {
+            let subset = canary_subset(metric)?;
+            let reference = baseline.iter().find(|reference| {
+                reference.subset.as_deref() == Some(subset.as_str())
+                    && reference.dataset == metric.dataset
+                    && reference.split == metric.split
+                    && reference.metric_name == metric.metric_name
+            })?;
+            let delta = metric.value - reference.value;
+            (delta.abs() > max_drift).then(|| CanaryDrift {
+                dataset: metric.dataset.clone(),
+                subset,
+                split: metric.split.clone(),
+                metric_name: metric.metric_name.clone(),
+                baseline_run_id,
+                baseline: reference.value,
+                value: metric.value,
+                delta,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use unified_shared::eval::canary_subset;
+
+    fn metric(subset: Option<String>, value: f64) -> MetricRecord {
+        MetricRecord {
+            run_id: Uuid::nil(),
+            dataset: "canary_set".into(),
+            subset,
+            split: None,
+            metric_name: "accuracy".into(),
+            value,
+            n_samples: None,
+            ci_low: None,
+            ci_high: None,
+            extra: None,
+            direction: None,
+        }
+    }
+
+    #[test]
+    fn drift_matches_canary_subsets() {
+        let baseline_run_id = Uuid::new_v4();
+        let baseline = [
+            metric(Some(canary_subset(Some("easy"))), 0.9),
+            metric(Some(canary_subset(Some("hard"))), 0.4),
+            metric(None, 0.1),
+        ];
+        let current = [
+            metric(Some(canary_subset(Some("easy"))), 0.88),
+            metric(Some(canary_subset(Some("hard"))), 0.7),
+            // Not a canary metric, however far it moved.
+            metric(None, 0.9),
+        ];
+        let drift = drift(baseline_run_id, &baseline, &current, 0.05);
+        assert_eq!(drift.len(), 1);
+        assert_eq!(drift[0].subset, "canary/hard");
+        assert_eq!(drift[0].baseline, 0.4);
+        assert!((drift[0].delta - 0.3).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,263 @@
+use crate::post_processors::MetricPostProcessor;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use unified_shared::eval::{EvalResult, MetricConfig, MetricRecord};
+
+/// `metric_type` of metrics computed from other metrics after the runner
+/// returns, rather than by the engine.
+pub const COMPOSITE_METRIC_TYPE: &str = "composite";
+
+/// `metric_type: "composite"`: the weighted mean of the engine's metrics in
+/// `params.components`, per dataset/subset/split; see [`CompositeParams`].
+pub struct Composite;
+
+/// `params` of a composite metric.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CompositeParams {
+    pub components: Vec<CompositeComponent>,
+    #[serde(default)]
+    pub on_missing: MissingSource,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CompositeComponent {
+    pub source_metric: String,
+    pub weight: f64,
+}
+
+/// What to do when a source metric wasn't reported for a dataset/subset/split.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MissingSource {
+    /// Combine the sources that are present, renormalizing their weights.
+    #[default]
+    Skip,
+    /// Fail the run.
+    Fail,
+}
+
+fn parse_params(config: &MetricConfig) -> Result<CompositeParams, String> {
+    let params: CompositeParams =
+        serde_json::from_value(config.params.clone().unwrap_or(Value::Null))
+            .map_err(|e| format!("composite metric {}: invalid params: {e}", config.name))?;
+    if params.components.is_empty() {
+        return Err(format!(
+            "composite metric {}: needs at least one component",
+            config.name
+        ));
+    }
+    if let Some(bad) = params
+        .components
+        .iter()
+        .find(|c| !c.weight.is_finite() || c.weight < 0.0)
+    {
+        return Err(format!(
+            "composite metric {}: weight of {} must be a non-negative number",
+            config.name, bad.source_metric
+        ));
+    }
+    if params.components.iter().map(|c| c.weight).sum::<f64>() <= 0.0 {
+        return Err(format!(
+            "composite metric {}: weights must not all be zero",
+            config.name
+        ));
+    }
+    Ok(params)
+}
+
+type GroupKey = (String, Option<String>, Option<String>);
+
+impl MetricPostProcessor for Composite {
+    fn name(&self) -> &'static str {
+        COMPOSITE_METRIC_TYPE
+    }
+
+    fn check(&self, config: &MetricConfig) -> Result<(), String> {
+        parse_params(config).map(|_| ())
+    }
+
+    /// A record per `(dataset, subset, split)` that reported any of the
+    /// sources. The value is the weighted mean of the sources, weights
+    /// normalized to sum to 1 over the sources present; `extra.composite`
+    /// records the weights and inputs used. With `on_missing: fail`, a
+    /// missing source is an error naming the composite.
+    fn process(
+        &self,
+        config: &MetricConfig,
+        result: &EvalResult,
+    ) -> Result<Vec<MetricRecord>, String> {
+        let params = parse_params(config)?;
+        let mut groups: BTreeMap<GroupKey, Vec<&MetricRecord>> = BTreeMap::new();
+        for record in &result.metrics {
+            groups
+                .entry((
+                    record.dataset.clone(),
+                    record.subset.clone(),
+                    record.split.clone(),
+                ))
+                .or_default()
+                .push(record);
+        }
+
+        let mut computed = Vec::new();
+        for ((dataset, subset, split), group) in groups {
+            let mut used = Vec::new();
+            let mut missing = Vec::new();
+            for component in &params.components {
+                match group
+                    .iter()
+                    .find(|record| record.metric_name == component.source_metric)
+                {
+                    Some(record) => used.push((component, *record)),
+                    None => missing.push(component.source_metric.clone()),
+                }
+            }
+            if used.is_empty() {
+                continue;
+            }
+            if !missing.is_empty() && params.on_missing == MissingSource::Fail {
+                return Err(format!(
+                    "composite metric {}: {dataset} is missing {}",
+                    config.name,
+                    missing.join(", ")
+                ));
+            }
+            let total: f64 = used.iter().map(|(component, _)| component.weight).sum();
+            if total <= 0.0 {
+                continue;
+            }
+            let value = used
+                .iter()
+                .map(|(component, record)| component.weight / total * record.value)
+                .sum();
+            let inputs: Vec<Value> = used
+                .iter()
+                .map(|(component, record)| {
+                    json!({
+                        "source_metric": component.source_metric,
+                        "weight": component.weight / total,
+                        "value": record.value,
+                    })
+                })
+                .collect();
+            computed.push(MetricRecord {
+                run_id: result.run_id,
+                dataset,
+                subset,
+                split,
+                metric_name: config.name.clone(),
+                value,
+                n_samples: None,
+                ci_low: None,
+                ci_high: None,
+                extra: Some(json!({ "composite": { "inputs": inputs, "missing": missing } })),
+                direction: config.configured_direction(),
+            });
+        }
+        Ok(computed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use unified_shared::eval::{RunStatus, SampleResultLocation};
+    use uuid::Uuid;
+
+    fn record(metric_name: &str, value: f64) -> MetricRecord {
+        MetricRecord {
+            run_id: Uuid::nil(),
+            dataset: "mmlu".into(),
+            subset: None,
+            split: None,
+            metric_name: metric_name.into(),
+            value,
+            n_samples: None,
+            ci_low: None,
+            ci_high: None,
+            extra: None,
+            direction: None,
+        }
+    }
+
+    fn result(metrics: Vec<MetricRecord>) -> EvalResult {
+        EvalResult {
+            run_id: Uuid::nil(),
+            status: RunStatus::Completed,
+            started_at: Utc::now(),
+            completed_at: Utc::now(),
+            metrics,
+            samples: SampleResultLocation::None,
+            error: None,
+            metadata: None,
+        }
+    }
+
+    fn composite(params: Value) -> MetricConfig {
+        MetricConfig {
+            name: "headline".into(),
+            metric_type: COMPOSITE_METRIC_TYPE.into(),
+            params: Some(params),
+            direction: None,
+            reference_free: None,
+            aggregation: None,
+        }
+    }
+
+    #[test]
+    fn weights_are_normalized() {
+        let config = composite(json!({ "components": [
+            { "source_metric": "accuracy", "weight": 3.0 },
+            { "source_metric": "f1", "weight": 1.0 },
+        ] }));
+        let records = Composite
+            .process(
+                &config,
+                &result(vec![record("accuracy", 0.8), record("f1", 0.4)]),
+            )
+            .unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].metric_name, "headline");
+        assert!((records[0].value - 0.7).abs() < 1e-9);
+    }
+
+    #[test]
+    fn missing_sources_are_skipped_or_fail() {
+        let components = json!([
+            { "source_metric": "accuracy", "weight": 1.0 },
+            { "source_metric": "f1", "weight": 1.0 },
+        ]);
+        let reported = result(vec![record("accuracy", 0.8)]);
+        let records = Composite
+            .process(&composite(json!({ "components": components })), &reported)
+            .unwrap();
+        assert_eq!(records[0].value, 0.8);
+
+        let err = Composite
+            .process(
+                &composite(json!({ "components": components, "on_missing": "fail" })),
+                &reported,
+            )
+            .unwrap_err();
+        assert!(err.contains("missing f1"), "{err}");
+    }
+
+    #[test]
+    fn invalid_params_fail_the_check() {
+        assert!(Composite
+            .check(&composite(json!({ "components": [
+                { "source_metric": "accuracy", "weight": 1.0 },
+            ] })))
+            .is_ok());
+        for params in [
+            json!({ "components": [] }),
+            json!({ "components": [{ "source_metric": "accuracy", "weight": -1.0 }] }),
+            json!({ "components": [{ "source_metric": "accuracy", "weight": 0.0 }] }),
+            json!({ "weights": { "accuracy": 1.0 } }),
+        ] {
+            assert!(Composite.check(&composite(params)).is_err());
+        }
+    }
+}
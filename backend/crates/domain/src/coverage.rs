@@ -0,0 +1,190 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use unified_shared::eval::{is_canary_subset, MetricRecord};
+use unified_shared::settings::CoverageSettings;
+
+use crate::datasets::Dataset;
+use crate::ensemble;
+
+/// A run whose metrics cover less of its dataset than
+/// `coverage.min_fraction`, e.g. because samples were filtered out or the
+/// engine stopped early.
+#[derive(Debug, Clone, Serialize)]
+pub struct CoverageWarning {
+    /// Name of the task's dataset.
+    pub dataset: String,
+    /// The dataset's `num_samples`.
+    pub num_samples: i64,
+    /// Samples the run's metrics report, from `MetricRecord.n_samples`.
+    pub evaluated: i64,
+    pub coverage: f64,
+    pub min_fraction: f64,
+}
+
+/// Compares the samples the run's metrics on `dataset` cover against its
+/// `num_samples`. Metrics without a subset are taken as the whole dataset;
+/// otherwise the subsets are summed, each counting its largest `n_samples`.
+/// Canary metrics and the per-member metrics of a multi-model run (whose
+/// members are named by `members`, see [`Run::member_names`]) are left out,
+/// since they don't add to the dataset's coverage.
+///
+/// [`Run::member_names`]: crate::runs::Run::member_names
+///
+/// Subsets in `coverage.exclude_subsets` (matched as `subset` or
+/// `dataset/subset`) are expected to be partial, so what they evaluated is
+/// taken out of both sides and the rest of the dataset is checked on its
+/// own. No warning when the dataset has no `num_samples` or no metric
+/// reports `n_samples`.
+pub fn check(
+    dataset: &Dataset,
+    members: &[String],
+    metrics: &[MetricRecord],
+    settings: &CoverageSettings,
+) -> Option<CoverageWarning> {
+    let num_samples = dataset.num_samples.filter(|n| *n > 0)?;
+    let mut whole: Option<i64> = None;
+    let mut subsets: BTreeMap<&str, i64> = BTreeMap::new();
+    for metric in metrics {
+        let Some(n_samples) = metric.n_samples else {
+            continue;
+        };
+        if metric.dataset != dataset.name {
+            continue;
+        }
+        match metric.subset.as_deref() {
+            Some(subset)
+                if is_canary_subset(subset) || ensemble::is_member_subset(subset, members) =>
+            {
+                continue
+            }
+            Some(subset) => {
+                let evaluated = subsets.entry(subset).or_default();
+                *evaluated = (*evaluated).max(n_samples);
+            }
+            None => whole = Some(whole.unwrap_or_default().max(n_samples)),
+        }
+    }
+    if whole.is_none() && subsets.is_empty() {
+        return None;
+    }
+
+    let excluded: i64 = subsets
+        .iter()
+        .filter(|(subset, _)| settings.excludes(&dataset.name, subset))
+        .map(|(_, evaluated)| evaluated)
+        .sum();
+    let total = whole.unwrap_or_else(|| subsets.values().sum());
+    let expected = num_samples - excluded;
+    if expected <= 0 {
+        return None;
+    }
+    let evaluated = (total - excluded).max(0);
+    let coverage = evaluated as f64 / expected as f64;
+    if coverage >= settings.min_fraction {
+        return None;
+    }
+    Some(CoverageWarning {
+        dataset: dataset.name.clone(),
+        num_samples: expected,
+        evaluated,
+        coverage,
+        min_fraction: settings.min_fraction,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use unified_shared::eval::CANARY_SUBSET;
+    use uuid::Uuid;
+
+    fn dataset(num_samples: i64) -> Dataset {
+        Dataset {
+            id: Uuid::new_v4(),
+            project_id: Uuid::new_v4(),
+            name: "mmlu".into(),
+            version: None,
+            storage_uri: None,
+            schema: None,
+            num_samples: Some(num_samples),
+            created_at: Utc::now(),
+        }
+    }
+
+    fn metric(dataset: &str, subset: Option<&str>, n_samples: i64) -> MetricRecord {
+        MetricRecord {
+            run_id: Uuid::nil(),
+            dataset: dataset.into(),
+            subset: subset.map(Into::into),
+            split: None,
+            metric_name: "accuracy".into(),
+            value: 0.5,
+            n_samples: Some(n_samples),
+            ci_low: None,
+            ci_high: None,
+            extra: None,
+            direction: None,
+        }
+    }
+
+    fn settings(exclude_subsets: &[&str]) -> CoverageSettings {
+        CoverageSettings {
+            min_fraction: 0.95,
+            exclude_subsets: exclude_subsets.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn full_coverage_has_no_warning() {
+        let metrics = [
+            metric("mmlu", Some("algebra"), 500),
+            metric("mmlu", Some("biology"), 500),
+        ];
+        assert!(check(&dataset(1000), &[], &metrics, &settings(&[])).is_none());
+        let whole = [metric("mmlu", None, 1000)];
+        assert!(check(&dataset(1000), &[], &whole, &settings(&[])).is_none());
+    }
+
+    #[test]
+    fn partial_coverage_warns() {
+        let metrics = [
+            metric("mmlu", Some("algebra"), 500),
+            metric("mmlu", Some("biology"), 200),
+        ];
+        let warning = check(&dataset(1000), &[], &metrics, &settings(&[])).unwrap();
+        assert_eq!(warning.evaluated, 700);
+        assert_eq!(warning.num_samples, 1000);
+        assert!((warning.coverage - 0.7).abs() < 1e-9);
+    }
+
+    #[test]
+    fn canary_and_member_subsets_do_not_add_coverage() {
+        let members = vec!["a".to_string(), "b".to_string()];
+        let metrics = [
+            metric("mmlu", Some("algebra"), 400),
+            metric("mmlu", Some(CANARY_SUBSET), 600),
+            metric("mmlu", Some("canary/algebra"), 600),
+            metric("mmlu", Some("a"), 600),
+            metric("mmlu", Some("b/algebra"), 600),
+            // Another dataset, e.g. the canary's.
+            metric("canary_set", None, 600),
+        ];
+        let warning = check(&dataset(1000), &members, &metrics, &settings(&[])).unwrap();
+        assert_eq!(warning.evaluated, 400);
+    }
+
+    #[test]
+    fn excluded_subsets_are_taken_out_of_the_check() {
+        let metrics = [
+            metric("mmlu", Some("algebra"), 500),
+            metric("mmlu", Some("biology"), 100),
+        ];
+        // The rest of the dataset is fully covered.
+        assert!(check(&dataset(600), &[], &metrics, &settings(&["biology"])).is_none());
+        // ...or not: excluding one subset doesn't exempt the others.
+        let warning = check(&dataset(1100), &[], &metrics, &settings(&["mmlu/biology"])).unwrap();
+        assert_eq!(warning.evaluated, 500);
+        assert_eq!(warning.num_samples, 1000);
+    }
+}
@@ -1,11 +1,18 @@
-use crate::db::DbPool;
+use crate::db::{db_error, DbPool};
+use crate::result_store::ObjectStoreResultStore;
 use crate::utils::parse_uuid;
+use anyhow::{bail, Context};
 use chrono::{DateTime, Utc};
+use jsonschema::JSONSchema;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use sha2::{Digest, Sha256};
 use sqlx::mysql::MySqlRow;
 use sqlx::Row;
-use unified_shared::error::DomainError;
+use std::path::{Path, PathBuf};
+use tokio::io::AsyncWriteExt;
+use unified_shared::error::{DomainError, FieldErrors};
+use unified_shared::eval::{DatasetConfig, DatasetSource};
 use uuid::Uuid;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -30,6 +37,112 @@ pub struct NewDataset {
     pub num_samples: Option<i64>,
 }
 
+impl NewDataset {
+    pub fn validate(&self) -> Result<(), DomainError> {
+        let mut errors = FieldErrors::new();
+        errors.require_non_empty("name", &self.name);
+        errors.finish()
+    }
+}
+
+/// Checks one sample against a dataset's JSON schema. Each error names the
+/// offending field by its JSON pointer.
+pub fn validate_sample(schema: &Value, sample: &Value) -> Result<(), Vec<String>> {
+    let compiled =
+        JSONSchema::compile(schema).map_err(|e| vec![format!("invalid dataset schema: {e}")])?;
+    check(&compiled, sample)
+}
+
+/// Checks every `(sample_index, sample)` pair, compiling the schema once.
+/// Errors are prefixed with the sample index.
+pub fn validate_samples<'a>(
+    schema: &Value,
+    samples: impl IntoIterator<Item = (i64, &'a Value)>,
+) -> Result<(), Vec<String>> {
+    let compiled =
+        JSONSchema::compile(schema).map_err(|e| vec![format!("invalid dataset schema: {e}")])?;
+    let errors: Vec<String> = samples
+        .into_iter()
+        .filter_map(|(index, sample)| check(&compiled, sample).err().map(|e| (index, e)))
+        .flat_map(|(index, errors)| {
+            errors
+                .into_iter()
+                .map(move |error| format!("sample {index}: {error}"))
+        })
+        .collect();
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// Maximum number of row errors named when a dataset is rejected.
+const MAX_SCHEMA_ERRORS: usize = 20;
+
+/// Checks an uploaded dataset's rows against its stored schema, so rows that
+/// don't match what its tasks expect are rejected before any run is queued.
+/// The rows are read as JSON lines from `storage_uri`; datasets without a
+/// schema or a `storage_uri` pass. Mismatches fail with `Unprocessable`,
+/// naming the first few by sample index and field.
+pub async fn check_rows(
+    dataset: &Dataset,
+    object_store: Option<&ObjectStoreResultStore>,
+) -> Result<(), DomainError> {
+    let (Some(schema), Some(uri)) = (&dataset.schema, dataset.storage_uri.as_deref()) else {
+        return Ok(());
+    };
+    let data = read_uri(uri, object_store).await.map_err(|err| {
+        DomainError::Unprocessable(format!("failed to read dataset {}: {err:#}", dataset.name))
+    })?;
+    let Err(mut errors) = validate_jsonl(schema, &String::from_utf8_lossy(&data)) else {
+        return Ok(());
+    };
+    let total = errors.len();
+    errors.truncate(MAX_SCHEMA_ERRORS);
+    Err(DomainError::Unprocessable(format!(
+        "rows of dataset {} do not match its schema ({total} errors): {}",
+        dataset.name,
+        errors.join("; ")
+    )))
+}
+
+/// Checks every non-blank line of a JSON lines document, indexing samples by
+/// line. Lines that aren't JSON are errors too.
+fn validate_jsonl(schema: &Value, text: &str) -> Result<(), Vec<String>> {
+    let mut errors = Vec::new();
+    let mut rows = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<Value>(line) {
+            Ok(row) => rows.push((index as i64, row)),
+            Err(err) => errors.push(format!("sample {index}: not valid JSON: {err}")),
+        }
+    }
+    if let Err(found) = validate_samples(schema, rows.iter().map(|(index, row)| (*index, row))) {
+        errors.extend(found);
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+fn check(schema: &JSONSchema, sample: &Value) -> Result<(), Vec<String>> {
+    schema.validate(sample).map_err(|errors| {
+        errors
+            .map(|error| {
+                let path = error.instance_path.to_string();
+                let field = if path.is_empty() { "/" } else { &path };
+                format!("field `{field}`: {error}")
+            })
+            .collect()
+    })
+}
+
 fn row_to_dataset(row: &MySqlRow) -> Result<Dataset, DomainError> {
     let schema = row
         .try_get::<Option<String>, _>("schema_json")?
@@ -46,19 +159,134 @@ fn row_to_dataset(row: &MySqlRow) -> Result<Dataset, DomainError> {
     })
 }
 
+fn sha256_hex(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+/// Makes an external or uploaded dataset available on local disk and
+/// returns its path. `http(s)://` and `s3://` URIs are downloaded into
+/// `cache_dir`, which stores content under its SHA-256 (`objects/{hash}`)
+/// and maps each URI to the hash it last resolved to (`index/{sha256(uri)}`).
+/// A URI whose cached content still matches its recorded hash is not
+/// downloaded again. Other URIs are treated as local paths and returned
+/// unchanged; built-in datasets and configs without a URI yield `None`.
+pub async fn ensure_local(
+    config: &DatasetConfig,
+    cache_dir: &Path,
+    object_store: Option<&ObjectStoreResultStore>,
+) -> anyhow::Result<Option<PathBuf>> {
+    if matches!(config.source, DatasetSource::BuiltIn) {
+        return Ok(None);
+    }
+    let Some(uri) = config.uri.as_deref() else {
+        return Ok(None);
+    };
+    let remote =
+        uri.starts_with("http://") || uri.starts_with("https://") || uri.starts_with("s3://");
+    if !remote {
+        return Ok(Some(PathBuf::from(
+            uri.strip_prefix("file://").unwrap_or(uri),
+        )));
+    }
+
+    let index_path = cache_dir.join("index").join(sha256_hex(uri.as_bytes()));
+    if let Ok(hash) = tokio::fs::read_to_string(&index_path).await {
+        let object_path = cache_dir.join("objects").join(hash.trim());
+        if let Ok(data) = tokio::fs::read(&object_path).await {
+            if sha256_hex(&data) == hash.trim() {
+                tracing::debug!("dataset {uri} served from cache");
+                return Ok(Some(object_path));
+            }
+            tracing::warn!("cached copy of {uri} is corrupt; downloading again");
+        }
+    }
+
+    let data = read_uri(uri, object_store).await?;
+    let hash = sha256_hex(&data);
+    let object_path = cache_dir.join("objects").join(&hash);
+    write_atomically(&object_path, &data).await?;
+    write_atomically(&index_path, hash.as_bytes()).await?;
+    Ok(Some(object_path))
+}
+
+/// Reads a dataset's content from an `s3://` or `http(s)://` URI, or from a
+/// local path.
+async fn read_uri(
+    uri: &str,
+    object_store: Option<&ObjectStoreResultStore>,
+) -> anyhow::Result<Vec<u8>> {
+    if uri.starts_with("s3://") {
+        let Some(store) = object_store else {
+            bail!("s3 dataset URIs need the object store to be configured");
+        };
+        store.get_uri(uri).await
+    } else if uri.starts_with("http://") || uri.starts_with("https://") {
+        download_http(uri).await
+    } else {
+        let path = uri.strip_prefix("file://").unwrap_or(uri);
+        tokio::fs::read(path)
+            .await
+            .with_context(|| format!("failed to read {path}"))
+    }
+}
+
+async fn download_http(uri: &str) -> anyhow::Result<Vec<u8>> {
+    let response = reqwest::get(uri)
+        .await
+        .with_context(|| format!("failed to fetch {uri}"))?
+        .error_for_status()
+        .with_context(|| format!("failed to fetch {uri}"))?;
+    let data = response
+        .bytes()
+        .await
+        .with_context(|| format!("failed to read {uri}"))?;
+    Ok(data.to_vec())
+}
+
+/// Writes via a temporary file and a rename, so concurrent runs fetching the
+/// same dataset never observe a partial file.
+async fn write_atomically(path: &Path, data: &[u8]) -> anyhow::Result<()> {
+    let dir = path.parent().context("cache path has no parent")?;
+    tokio::fs::create_dir_all(dir).await?;
+    let tmp = dir.join(format!(".{}.tmp", Uuid::new_v4()));
+    let mut file = tokio::fs::File::create(&tmp).await?;
+    file.write_all(data).await?;
+    file.sync_all().await?;
+    tokio::fs::rename(&tmp, path)
+        .await
+        .with_context(|| format!("failed to move dataset into {}", path.display()))?;
+    Ok(())
+}
+
 pub async fn list(pool: &DbPool, project_id: &Uuid) -> Result<Vec<Dataset>, DomainError> {
     let rows = sqlx::query(
-        "SELECT id, project_id, name, version, storage_uri, schema_json, num_samples, created_at FROM datasets WHERE project_id = ? ORDER BY created_at DESC",
+        "SELECT id, project_id, name, version, storage_uri, schema_json, num_samples, created_at FROM datasets WHERE project_id = ? ORDER BY created_at DESC, id DESC",
     )
     .bind(project_id.to_string())
     .fetch_all(pool)
     .await
-    .map_err(|e| DomainError::Internal(e.to_string()))?;
+    .map_err(db_error)?;
 
     rows.iter().map(row_to_dataset).collect()
 }
 
+pub async fn get(pool: &DbPool, id: &Uuid) -> Result<Dataset, DomainError> {
+    let row = sqlx::query(
+        "SELECT id, project_id, name, version, storage_uri, schema_json, num_samples, created_at FROM datasets WHERE id = ?",
+    )
+    .bind(id.to_string())
+    .fetch_optional(pool)
+    .await
+    .map_err(db_error)?;
+
+    match row {
+        Some(row) => row_to_dataset(&row),
+        None => Err(DomainError::NotFound("dataset not found".into())),
+    }
+}
+
 pub async fn create(pool: &DbPool, payload: NewDataset) -> Result<Dataset, DomainError> {
+    payload.validate()?;
     let id = Uuid::new_v4();
     let now = Utc::now();
     let schema_str = match payload.schema {
@@ -79,7 +307,7 @@ pub async fn create(pool: &DbPool, payload: NewDataset) -> Result<Dataset, Domai
         .bind(now)
         .execute(pool)
         .await
-        .map_err(|e| DomainError::Internal(e.to_string()))?;
+        .map_err(db_error)?;
 
     Ok(Dataset {
         id,
@@ -92,3 +320,72 @@ pub async fn create(pool: &DbPool, payload: NewDataset) -> Result<Dataset, Domai
         created_at: now,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn schema() -> Value {
+        json!({
+            "type": "object",
+            "required": ["prompt", "reference"],
+            "properties": {
+                "prompt": { "type": "string" },
+                "reference": { "type": "string" },
+                "weight": { "type": "number" }
+            }
+        })
+    }
+
+    #[test]
+    fn conforming_sample_passes() {
+        let sample = json!({ "prompt": "2 + 2", "reference": "4", "weight": 1.5 });
+        assert!(validate_sample(&schema(), &sample).is_ok());
+    }
+
+    #[test]
+    fn violations_name_the_field() {
+        let errors =
+            validate_sample(&schema(), &json!({ "prompt": 4, "reference": "4" })).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with("field `/prompt`"), "{errors:?}");
+
+        let errors = validate_sample(&schema(), &json!({ "prompt": "2 + 2" })).unwrap_err();
+        assert!(errors[0].contains("reference"), "{errors:?}");
+    }
+
+    #[test]
+    fn violations_name_the_sample_index() {
+        let good = json!({ "prompt": "a", "reference": "b" });
+        let bad = json!({ "prompt": "a", "reference": "b", "weight": "heavy" });
+        let errors = validate_samples(&schema(), [(0, &good), (7, &bad)]).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(
+            errors[0].starts_with("sample 7: field `/weight`"),
+            "{errors:?}"
+        );
+    }
+
+    #[test]
+    fn invalid_schema_is_reported() {
+        let errors = validate_sample(&json!({ "type": 12 }), &json!({})).unwrap_err();
+        assert!(
+            errors[0].starts_with("invalid dataset schema"),
+            "{errors:?}"
+        );
+    }
+
+    #[test]
+    fn jsonl_rows_are_indexed_by_line() {
+        let text = "{\"prompt\": \"a\", \"reference\": \"b\"}\n\nnot json\n{\"prompt\": \"a\"}\n";
+        let errors = validate_jsonl(&schema(), text).unwrap_err();
+        assert_eq!(errors.len(), 2, "{errors:?}");
+        assert!(
+            errors[0].starts_with("sample 2: not valid JSON"),
+            "{errors:?}"
+        );
+        assert!(errors[1].starts_with("sample 3: field `/`"), "{errors:?}");
+        assert!(validate_jsonl(&schema(), "").is_ok());
+    }
+}
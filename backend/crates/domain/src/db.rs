@@ -1,11 +1,37 @@
+use std::time::Duration;
+
 use sqlx::mysql::MySqlPoolOptions;
 use sqlx::MySqlPool;
+use unified_shared::error::DomainError;
+use unified_shared::settings::DatabaseSettings;
 
 pub type DbPool = MySqlPool;
 
-pub async fn init_pool(database_url: &str) -> Result<DbPool, sqlx::Error> {
+pub fn pool_options(settings: &DatabaseSettings) -> MySqlPoolOptions {
     MySqlPoolOptions::new()
-        .max_connections(5)
-        .connect(database_url)
-        .await
+        .max_connections(settings.max_connections)
+        .min_connections(settings.min_connections)
+        .acquire_timeout(Duration::from_secs(settings.acquire_timeout_seconds))
+        .idle_timeout(settings.idle_timeout_seconds.map(Duration::from_secs))
+}
+
+pub async fn init_pool(settings: &DatabaseSettings) -> Result<DbPool, sqlx::Error> {
+    pool_options(settings).connect(&settings.url).await
+}
+
+/// Maps a query failure to `Unavailable` when the database couldn't be
+/// reached (connection, pool or TLS trouble) and to `Internal` otherwise.
+pub fn db_error(err: sqlx::Error) -> DomainError {
+    match err {
+        sqlx::Error::Io(_)
+        | sqlx::Error::Tls(_)
+        | sqlx::Error::PoolTimedOut
+        | sqlx::Error::PoolClosed
+        | sqlx::Error::WorkerCrashed => DomainError::Unavailable(format!("database: {err}")),
+        other => DomainError::Internal(other.to_string()),
+    }
+}
+
+pub async fn ping(pool: &DbPool) -> Result<(), sqlx::Error> {
+    sqlx::query("SELECT 1").execute(pool).await.map(|_| ())
 }
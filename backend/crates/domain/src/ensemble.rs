@@ -0,0 +1,275 @@
+use crate::metrics;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use unified_shared::eval::{MetricConfig, MetricRecord, SampleRecord};
+use uuid::Uuid;
+
+/// `subset` of a member's metric in a multi-model run: the member's
+/// `logical_name`, followed by the engine's subset if it reported one.
+pub fn member_subset(logical_name: &str, subset: Option<&str>) -> String {
+    match subset {
+        Some(subset) => format!("{logical_name}/{subset}"),
+        None => logical_name.to_string(),
+    }
+}
+
+/// Whether `subset` belongs to one of `members`, per [`member_subset`].
+pub fn is_member_subset(subset: &str, members: &[String]) -> bool {
+    members.iter().any(|member| {
+        subset == member
+            || subset
+                .strip_prefix(member.as_str())
+                .is_some_and(|rest| rest.starts_with('/'))
+    })
+}
+
+/// What one member of a multi-model run reported, with its metrics and
+/// samples already under the run's id and the engine's own subsets.
+#[derive(Debug, Clone)]
+pub struct MemberResult {
+    pub logical_name: String,
+    pub metrics: Vec<MetricRecord>,
+    pub samples: Vec<SampleRecord>,
+}
+
+/// Results of a multi-model run as stored: see [`combine`].
+#[derive(Debug, Clone, Default)]
+pub struct Combined {
+    pub metrics: Vec<MetricRecord>,
+    pub samples: Vec<SampleRecord>,
+}
+
+type SampleKey = (String, Option<String>, Option<String>, i64);
+
+/// Merges the results of the members of run `run_id`, in member order.
+/// Every member metric and sample is kept, its subset prefixed per
+/// [`member_subset`].
+///
+/// The ensemble's own results, under the engine's subsets, come from a
+/// per-sample majority vote: for each `(dataset, subset, split,
+/// sample_index)` the members' outputs (trimmed) are counted, samples with
+/// an error abstaining, and the most common one wins, ties going to the
+/// earliest member. The winning member's sample is kept as the ensemble's,
+/// and the metrics in `configs` are aggregated over the winners' per-sample
+/// scores as in [`metrics::aggregate_samples`], with `extra.ensemble`
+/// naming the method and members. A metric the members reported only as a
+/// run-level value has no per-sample scores to vote on and gets no
+/// ensemble record.
+pub fn combine<'a>(
+    run_id: Uuid,
+    configs: impl IntoIterator<Item = &'a MetricConfig>,
+    members: Vec<MemberResult>,
+) -> Combined {
+    let names: Vec<String> = members
+        .iter()
+        .map(|member| member.logical_name.clone())
+        .collect();
+    let mut ballots: BTreeMap<SampleKey, Vec<SampleRecord>> = BTreeMap::new();
+    let mut combined = Combined::default();
+    for member in members {
+        for sample in member.samples {
+            if sample.error.is_none() {
+                ballots
+                    .entry((
+                        sample.dataset.clone(),
+                        sample.subset.clone(),
+                        sample.split.clone(),
+                        sample.sample_index,
+                    ))
+                    .or_default()
+                    .push(SampleRecord {
+                        run_id,
+                        ..sample.clone()
+                    });
+            }
+            combined.samples.push(SampleRecord {
+                run_id,
+                subset: Some(member_subset(
+                    &member.logical_name,
+                    sample.subset.as_deref(),
+                )),
+                ..sample
+            });
+        }
+        combined
+            .metrics
+            .extend(member.metrics.into_iter().map(|metric| MetricRecord {
+                run_id,
+                subset: Some(member_subset(
+                    &member.logical_name,
+                    metric.subset.as_deref(),
+                )),
+                ..metric
+            }));
+    }
+
+    let winners: Vec<SampleRecord> = ballots.into_values().filter_map(vote).collect();
+    let method = json!({ "method": "majority_vote", "members": names });
+    for mut record in metrics::aggregate_samples(run_id, configs, &[], &winners) {
+        if let Some(Value::Object(extra)) = &mut record.extra {
+            extra.insert("ensemble".into(), method.clone());
+        }
+        combined.metrics.push(record);
+    }
+    combined.samples.extend(winners);
+    combined
+}
+
+/// The sample whose output most of `ballots` agree on; the earliest one
+/// among equally common outputs.
+fn vote(ballots: Vec<SampleRecord>) -> Option<SampleRecord> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for ballot in &ballots {
+        *counts.entry(ballot.output.trim()).or_default() += 1;
+    }
+    let most = counts.values().copied().max()?;
+    let winner = ballots
+        .iter()
+        .position(|ballot| counts[ballot.output.trim()] == most)?;
+    ballots.into_iter().nth(winner)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use unified_shared::eval::SampleError;
+
+    fn sample(index: i64, output: &str, correct: bool) -> SampleRecord {
+        SampleRecord {
+            run_id: Uuid::nil(),
+            dataset: "mmlu".into(),
+            subset: None,
+            split: Some("test".into()),
+            sample_index: index,
+            input: format!("question {index}"),
+            reference: Some("A".into()),
+            output: output.into(),
+            metrics: Some(json!({ "accuracy": correct })),
+            latency_ms: None,
+            token_counts: None,
+            error: None,
+        }
+    }
+
+    fn member(logical_name: &str, value: f64, samples: Vec<SampleRecord>) -> MemberResult {
+        MemberResult {
+            logical_name: logical_name.into(),
+            metrics: vec![MetricRecord {
+                run_id: Uuid::nil(),
+                dataset: "mmlu".into(),
+                subset: None,
+                split: Some("test".into()),
+                metric_name: "accuracy".into(),
+                value,
+                n_samples: Some(samples.len() as i64),
+                ci_low: None,
+                ci_high: None,
+                extra: None,
+                direction: None,
+            }],
+            samples,
+        }
+    }
+
+    fn accuracy() -> MetricConfig {
+        MetricConfig {
+            name: "accuracy".into(),
+            metric_type: "builtin".into(),
+            params: None,
+            direction: None,
+            reference_free: None,
+            aggregation: None,
+        }
+    }
+
+    #[test]
+    fn two_models_keep_their_rows_and_vote_per_sample() {
+        let run_id = Uuid::new_v4();
+        let combined = combine(
+            run_id,
+            &[accuracy()],
+            vec![
+                member(
+                    "small",
+                    0.5,
+                    vec![sample(0, "A", true), sample(1, "B", false)],
+                ),
+                member(
+                    "large",
+                    1.0,
+                    vec![sample(0, " A ", true), sample(1, "A", true)],
+                ),
+            ],
+        );
+
+        let subsets: Vec<_> = combined
+            .metrics
+            .iter()
+            .map(|metric| (metric.subset.as_deref(), metric.value))
+            .collect();
+        assert_eq!(
+            subsets,
+            [(Some("small"), 0.5), (Some("large"), 1.0), (None, 0.5)]
+        );
+        let ensemble = &combined.metrics[2];
+        assert_eq!(ensemble.run_id, run_id);
+        assert_eq!(ensemble.n_samples, Some(2));
+        assert_eq!(
+            ensemble.extra.as_ref().unwrap()["ensemble"],
+            json!({ "method": "majority_vote", "members": ["small", "large"] })
+        );
+        // Sample 0 agrees; sample 1 is a tie won by the earlier member.
+        let winners: Vec<_> = combined
+            .samples
+            .iter()
+            .filter(|sample| sample.subset.is_none())
+            .map(|sample| sample.output.as_str())
+            .collect();
+        assert_eq!(winners, ["A", "B"]);
+        assert_eq!(combined.samples.len(), 6);
+        assert!(combined
+            .samples
+            .iter()
+            .all(|sample| sample.run_id == run_id));
+    }
+
+    #[test]
+    fn majority_wins_and_failed_samples_abstain() {
+        let mut failed = sample(0, "C", false);
+        failed.error = Some(SampleError {
+            message: "timeout".into(),
+            code: None,
+            kind: Default::default(),
+        });
+        let combined = combine(
+            Uuid::nil(),
+            &[accuracy()],
+            vec![
+                member("a", 0.0, vec![failed]),
+                member("b", 0.0, vec![sample(0, "B", false)]),
+                member("c", 0.0, vec![sample(0, "A", true)]),
+                member("d", 0.0, vec![sample(0, "A", true)]),
+            ],
+        );
+        let ensemble = combined
+            .metrics
+            .iter()
+            .find(|metric| metric.subset.is_none())
+            .unwrap();
+        assert_eq!(ensemble.value, 1.0);
+        assert_eq!(ensemble.n_samples, Some(1));
+    }
+
+    #[test]
+    fn run_level_metrics_get_no_ensemble_record() {
+        let mut a = member("a", 1.0, vec![sample(0, "A", true)]);
+        let mut b = member("b", 1.0, vec![sample(0, "A", true)]);
+        a.samples.clear();
+        b.samples.clear();
+        let combined = combine(Uuid::nil(), &[accuracy()], vec![a, b]);
+        assert!(combined
+            .metrics
+            .iter()
+            .all(|metric| metric.subset.is_some()));
+    }
+}
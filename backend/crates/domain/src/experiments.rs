@@ -1,11 +1,13 @@
-use crate::db::DbPool;
-use crate::utils::parse_uuid;
+use crate::db::{db_error, DbPool};
+use crate::tasks::{self, Task};
+use crate::utils::{optional_json_column, parse_uuid};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use sqlx::mysql::MySqlRow;
+use sqlx::types::Json;
 use sqlx::Row;
-use unified_shared::error::DomainError;
+use unified_shared::error::{DomainError, FieldErrors};
 use uuid::Uuid;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -20,6 +22,16 @@ pub struct Experiment {
     pub created_at: DateTime<Utc>,
 }
 
+/// An experiment with its task ids resolved. Tasks that no longer exist are
+/// listed in `missing_task_ids` instead.
+#[derive(Debug, Clone, Serialize)]
+pub struct ExperimentWithTasks {
+    #[serde(flatten)]
+    pub experiment: Experiment,
+    pub resolved_tasks: Vec<Task>,
+    pub missing_task_ids: Vec<Uuid>,
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct NewExperiment {
     pub project_id: Uuid,
@@ -30,6 +42,14 @@ pub struct NewExperiment {
     pub global_config: Option<Value>,
 }
 
+impl NewExperiment {
+    pub fn validate(&self) -> Result<(), DomainError> {
+        let mut errors = FieldErrors::new();
+        errors.require_non_empty("name", &self.name);
+        errors.finish()
+    }
+}
+
 fn row_to_experiment(row: &MySqlRow) -> Result<Experiment, DomainError> {
     let tasks_raw: String = row.try_get("tasks_json")?;
     let tasks_vec: Vec<String> =
@@ -39,9 +59,7 @@ fn row_to_experiment(row: &MySqlRow) -> Result<Experiment, DomainError> {
         .map(|id| parse_uuid(id.as_str()))
         .collect::<Result<Vec<_>, _>>()?;
 
-    let global_config = row
-        .try_get::<Option<String>, _>("global_config_json")?
-        .map(|raw| serde_json::from_str(&raw).unwrap_or(Value::Null));
+    let global_config = optional_json_column(row, "global_config_json")?;
 
     Ok(Experiment {
         id: parse_uuid(row.try_get::<String, _>("id")?.as_str())?,
@@ -56,11 +74,11 @@ fn row_to_experiment(row: &MySqlRow) -> Result<Experiment, DomainError> {
 }
 
 pub async fn list(pool: &DbPool, project_id: &Uuid) -> Result<Vec<Experiment>, DomainError> {
-    let rows = sqlx::query("SELECT id, project_id, name, description, scenario_type, tasks_json, global_config_json, created_at FROM experiments WHERE project_id = ? ORDER BY created_at DESC")
+    let rows = sqlx::query("SELECT id, project_id, name, description, scenario_type, tasks_json, global_config_json, created_at FROM experiments WHERE project_id = ? ORDER BY created_at DESC, id DESC")
         .bind(project_id.to_string())
         .fetch_all(pool)
         .await
-        .map_err(|e| DomainError::Internal(e.to_string()))?;
+        .map_err(db_error)?;
 
     rows.iter().map(row_to_experiment).collect()
 }
@@ -70,7 +88,7 @@ pub async fn get(pool: &DbPool, id: &Uuid) -> Result<Experiment, DomainError> {
         .bind(id.to_string())
         .fetch_optional(pool)
         .await
-        .map_err(|e| DomainError::Internal(e.to_string()))?;
+        .map_err(db_error)?;
 
     match row {
         Some(row) => row_to_experiment(&row),
@@ -78,7 +96,25 @@ pub async fn get(pool: &DbPool, id: &Uuid) -> Result<Experiment, DomainError> {
     }
 }
 
+pub async fn get_with_tasks(pool: &DbPool, id: &Uuid) -> Result<ExperimentWithTasks, DomainError> {
+    let experiment = get(pool, id).await?;
+    let resolved_tasks = tasks::list_by_ids(pool, &experiment.tasks).await?;
+    let missing_task_ids = experiment
+        .tasks
+        .iter()
+        .filter(|id| !resolved_tasks.iter().any(|task| task.id == **id))
+        .copied()
+        .collect();
+
+    Ok(ExperimentWithTasks {
+        experiment,
+        resolved_tasks,
+        missing_task_ids,
+    })
+}
+
 pub async fn create(pool: &DbPool, payload: NewExperiment) -> Result<Experiment, DomainError> {
+    payload.validate()?;
     let id = Uuid::new_v4();
     let now = Utc::now();
     let tasks_str = serde_json::to_string(
@@ -89,12 +125,6 @@ pub async fn create(pool: &DbPool, payload: NewExperiment) -> Result<Experiment,
             .collect::<Vec<_>>(),
     )
     .map_err(|e| DomainError::Internal(e.to_string()))?;
-    let global_config_str = match payload.global_config {
-        Some(ref value) => {
-            Some(serde_json::to_string(value).map_err(|e| DomainError::Internal(e.to_string()))?)
-        }
-        None => None,
-    };
 
     sqlx::query("INSERT INTO experiments (id, project_id, name, description, scenario_type, tasks_json, global_config_json, created_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?)")
         .bind(id.to_string())
@@ -103,11 +133,11 @@ pub async fn create(pool: &DbPool, payload: NewExperiment) -> Result<Experiment,
         .bind(&payload.description)
         .bind(&payload.scenario_type)
         .bind(tasks_str)
-        .bind(global_config_str)
+        .bind(payload.global_config.as_ref().map(Json))
         .bind(now)
         .execute(pool)
         .await
-        .map_err(|e| DomainError::Internal(e.to_string()))?;
+        .map_err(db_error)?;
 
     Ok(Experiment {
         id,
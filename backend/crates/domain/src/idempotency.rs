@@ -0,0 +1,144 @@
+use crate::db::{db_error, DbPool};
+use crate::utils::parse_uuid;
+use chrono::{DateTime, Utc};
+use sqlx::mysql::MySqlRow;
+use sqlx::Row;
+use unified_shared::error::DomainError;
+use uuid::Uuid;
+
+#[derive(Debug, Clone)]
+pub struct IdempotencyRecord {
+    pub scope: String,
+    pub key: String,
+    pub request_hash: String,
+    /// Ids created by the request; `None` while it is still in progress.
+    pub resource_ids: Option<Vec<Uuid>>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Result of [`reserve`].
+#[derive(Debug, Clone)]
+pub enum Reservation {
+    /// The key is now held by the caller, which creates the resources and
+    /// then calls [`complete`], or [`release`] if that fails.
+    Acquired,
+    /// Another request holds or used the key; this is its record.
+    Existing(IdempotencyRecord),
+}
+
+fn row_to_record(row: &MySqlRow) -> Result<IdempotencyRecord, DomainError> {
+    let resource_ids = row
+        .try_get::<Option<String>, _>("resource_ids_json")?
+        .map(|raw| {
+            let ids: Vec<String> =
+                serde_json::from_str(&raw).map_err(|e| DomainError::Internal(e.to_string()))?;
+            ids.iter()
+                .map(|id| parse_uuid(id.as_str()))
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .transpose()?;
+
+    Ok(IdempotencyRecord {
+        scope: row.try_get("scope")?,
+        key: row.try_get("idempotency_key")?,
+        request_hash: row.try_get("request_hash")?,
+        resource_ids,
+        created_at: row.try_get("created_at")?,
+    })
+}
+
+/// Claims `key` for `scope` by inserting an in-progress record, so that of
+/// concurrent requests with the same key only one creates anything. Keys
+/// older than `ttl_seconds`, and in-progress ones older than
+/// `abandoned_seconds` (their request can no longer be running), are purged
+/// first so they behave exactly like an unseen key. When the insert loses,
+/// the winning record is read back.
+pub async fn reserve(
+    pool: &DbPool,
+    scope: &str,
+    key: &str,
+    request_hash: &str,
+    ttl_seconds: u64,
+    abandoned_seconds: u64,
+) -> Result<Reservation, DomainError> {
+    sqlx::query(
+        "DELETE FROM idempotency_keys WHERE scope = ? AND idempotency_key = ? \
+         AND (created_at <= NOW() - INTERVAL ? SECOND \
+         OR (resource_ids_json IS NULL AND created_at <= NOW() - INTERVAL ? SECOND))",
+    )
+    .bind(scope)
+    .bind(key)
+    .bind(ttl_seconds)
+    .bind(abandoned_seconds)
+    .execute(pool)
+    .await
+    .map_err(db_error)?;
+
+    let inserted = sqlx::query("INSERT IGNORE INTO idempotency_keys (scope, idempotency_key, request_hash, resource_ids_json, created_at) VALUES (?, ?, ?, NULL, ?)")
+        .bind(scope)
+        .bind(key)
+        .bind(request_hash)
+        .bind(Utc::now())
+        .execute(pool)
+        .await
+        .map_err(db_error)?;
+    if inserted.rows_affected() == 1 {
+        return Ok(Reservation::Acquired);
+    }
+
+    let row = sqlx::query("SELECT scope, idempotency_key, request_hash, resource_ids_json, created_at FROM idempotency_keys WHERE scope = ? AND idempotency_key = ?")
+        .bind(scope)
+        .bind(key)
+        .fetch_optional(pool)
+        .await
+        .map_err(db_error)?;
+    match row {
+        Some(row) => Ok(Reservation::Existing(row_to_record(&row)?)),
+        // The winner released the key between the insert and the read.
+        None => Err(DomainError::Conflict(format!(
+            "idempotency key {key} is in use by a concurrent request"
+        ))),
+    }
+}
+
+/// Records the ids created under a key claimed with [`reserve`].
+pub async fn complete(
+    pool: &DbPool,
+    scope: &str,
+    key: &str,
+    resource_ids: &[Uuid],
+) -> Result<(), DomainError> {
+    let ids_str = serde_json::to_string(
+        &resource_ids
+            .iter()
+            .map(|id| id.to_string())
+            .collect::<Vec<_>>(),
+    )
+    .map_err(|e| DomainError::Internal(e.to_string()))?;
+
+    let updated = sqlx::query("UPDATE idempotency_keys SET resource_ids_json = ? WHERE scope = ? AND idempotency_key = ? AND resource_ids_json IS NULL")
+        .bind(ids_str)
+        .bind(scope)
+        .bind(key)
+        .execute(pool)
+        .await
+        .map_err(db_error)?;
+    if updated.rows_affected() == 0 {
+        return Err(DomainError::Conflict(format!(
+            "idempotency key {key} is no longer held by this request"
+        )));
+    }
+    Ok(())
+}
+
+/// Gives up a key claimed with [`reserve`] whose request failed, so a retry
+/// can use it straight away.
+pub async fn release(pool: &DbPool, scope: &str, key: &str) -> Result<(), DomainError> {
+    sqlx::query("DELETE FROM idempotency_keys WHERE scope = ? AND idempotency_key = ? AND resource_ids_json IS NULL")
+        .bind(scope)
+        .bind(key)
+        .execute(pool)
+        .await
+        .map_err(db_error)?;
+    Ok(())
+}
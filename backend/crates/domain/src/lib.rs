@@ -1,11 +1,21 @@
+pub mod artifacts;
+pub mod canary;
+pub mod composite;
+pub mod coverage;
 pub mod datasets;
 pub mod db;
+pub mod ensemble;
 pub mod experiments;
+pub mod idempotency;
 pub mod metrics;
+#[cfg(any(test, feature = "test-util"))]
+pub mod mock_store;
 pub mod models;
+pub mod post_processors;
 pub mod projects;
 pub mod result_store;
 pub mod runs;
 pub mod sample_outputs;
 pub mod tasks;
+pub mod templating;
 pub mod utils;
@@ -1,12 +1,200 @@
-use crate::db::DbPool;
+use crate::db::{db_error, DbPool};
+use crate::utils::{page_bounds, parse_uuid};
 use chrono::{DateTime, Utc};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use sqlx::Row;
-use unified_shared::error::DomainError;
-use unified_shared::eval::MetricRecord;
+use sqlx::mysql::MySqlArguments;
+use sqlx::query::Query;
+use sqlx::{MySql, QueryBuilder, Row};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use unified_shared::error::{DomainError, FieldErrors};
+use unified_shared::eval::{
+    to_canonical_json, MetricConfig, MetricDirection, MetricRecord, SampleAggregation,
+    SampleRecord, CANARY_SUBSET,
+};
 use uuid::Uuid;
 
+/// Fixed so that recomputing a run's intervals gives the same result.
+const BOOTSTRAP_SEED: u64 = 0x5eed;
+
+/// Percentile bootstrap interval of the mean of `samples`: resamples with
+/// replacement `iterations` times and returns the `(1 - confidence) / 2` and
+/// `(1 + confidence) / 2` quantiles of the resampled means. An empty slice
+/// yields `(NaN, NaN)`.
+pub fn bootstrap_ci(samples: &[f64], confidence: f64, iterations: usize) -> (f64, f64) {
+    if samples.is_empty() || iterations == 0 {
+        return (f64::NAN, f64::NAN);
+    }
+    let mut rng = StdRng::seed_from_u64(BOOTSTRAP_SEED);
+    let n = samples.len();
+    let mut means: Vec<f64> = (0..iterations)
+        .map(|_| (0..n).map(|_| samples[rng.gen_range(0..n)]).sum::<f64>() / n as f64)
+        .collect();
+    means.sort_by(f64::total_cmp);
+
+    let alpha = (1.0 - confidence.clamp(0.0, 1.0)) / 2.0;
+    let quantile =
+        |q: f64| means[((q * (iterations - 1) as f64).round() as usize).min(iterations - 1)];
+    (quantile(alpha), quantile(1.0 - alpha))
+}
+
+/// Fills `ci_low`/`ci_high` of records that have neither, using the numeric
+/// per-sample values of the same metric in the matching dataset/subset/split.
+/// Metrics with fewer than two such values are left alone.
+pub fn fill_bootstrap_cis(
+    records: &mut [MetricRecord],
+    samples: &[SampleRecord],
+    confidence: f64,
+    iterations: usize,
+) {
+    for record in records
+        .iter_mut()
+        .filter(|record| record.ci_low.is_none() && record.ci_high.is_none())
+    {
+        let values: Vec<f64> = samples
+            .iter()
+            .filter(|sample| {
+                sample.dataset == record.dataset
+                    && sample.subset == record.subset
+                    && sample.split == record.split
+            })
+            .filter_map(|sample| sample.metrics.as_ref()?.get(&record.metric_name)?.as_f64())
+            .collect();
+        if values.len() < 2 {
+            continue;
+        }
+        let (low, high) = bootstrap_ci(&values, confidence, iterations);
+        record.ci_low = Some(low);
+        record.ci_high = Some(high);
+    }
+}
+
+/// Run-level records for the metrics in `configs` that the engine reported
+/// only per sample: for each dataset/subset/split without a record of the
+/// metric, the numeric (or boolean, as 1/0) values under its name in the
+/// samples' `metrics` are combined by its `aggregation`. `n_samples` is the
+/// number of values and `extra.sample_aggregate` records the aggregation,
+/// count and sum.
+pub fn aggregate_samples<'a>(
+    run_id: Uuid,
+    configs: impl IntoIterator<Item = &'a MetricConfig>,
+    records: &[MetricRecord],
+    samples: &[SampleRecord],
+) -> Vec<MetricRecord> {
+    type GroupKey = (String, Option<String>, Option<String>);
+    let reported: BTreeSet<(&str, Option<&str>, Option<&str>, &str)> = records
+        .iter()
+        .map(|record| {
+            (
+                record.dataset.as_str(),
+                record.subset.as_deref(),
+                record.split.as_deref(),
+                record.metric_name.as_str(),
+            )
+        })
+        .collect();
+
+    let mut aggregated = Vec::new();
+    for config in configs {
+        let mut groups: BTreeMap<GroupKey, Vec<f64>> = BTreeMap::new();
+        for sample in samples {
+            let value = match sample.metrics.as_ref().and_then(|m| m.get(&config.name)) {
+                Some(Value::Number(value)) => value.as_f64(),
+                Some(Value::Bool(passed)) => Some(f64::from(u8::from(*passed))),
+                _ => None,
+            };
+            let Some(value) = value.filter(|value| value.is_finite()) else {
+                continue;
+            };
+            let key = (
+                sample.dataset.as_str(),
+                sample.subset.as_deref(),
+                sample.split.as_deref(),
+                config.name.as_str(),
+            );
+            if reported.contains(&key) {
+                continue;
+            }
+            groups
+                .entry((
+                    sample.dataset.clone(),
+                    sample.subset.clone(),
+                    sample.split.clone(),
+                ))
+                .or_default()
+                .push(value);
+        }
+
+        let aggregation = config.aggregation.unwrap_or_default();
+        for ((dataset, subset, split), mut values) in groups {
+            let sum: f64 = values.iter().sum();
+            let count = values.len();
+            let value = match aggregation {
+                SampleAggregation::Mean => sum / count as f64,
+                SampleAggregation::Sum => sum,
+                SampleAggregation::Median => {
+                    values.sort_by(f64::total_cmp);
+                    let mid = count / 2;
+                    if count % 2 == 0 {
+                        (values[mid - 1] + values[mid]) / 2.0
+                    } else {
+                        values[mid]
+                    }
+                }
+            };
+            aggregated.push(MetricRecord {
+                run_id,
+                dataset,
+                subset,
+                split,
+                metric_name: config.name.clone(),
+                value,
+                n_samples: Some(count as i64),
+                ci_low: None,
+                ci_high: None,
+                extra: Some(serde_json::json!({
+                    "sample_aggregate": {
+                        "aggregation": aggregation.as_str(),
+                        "count": count,
+                        "sum": sum,
+                    }
+                })),
+                direction: config.configured_direction(),
+            });
+        }
+    }
+    aggregated
+}
+
+/// Optional filters and paging for [`list_by_run`]; without `limit` or
+/// `offset` every matching metric is returned.
+#[derive(Debug, Clone)]
+pub struct MetricFilter {
+    pub run_id: Uuid,
+    pub dataset: Option<String>,
+    pub subset: Option<String>,
+    pub split: Option<String>,
+    pub metric_name: Option<String>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+impl MetricFilter {
+    pub fn for_run(run_id: Uuid) -> Self {
+        Self {
+            run_id,
+            dataset: None,
+            subset: None,
+            split: None,
+            metric_name: None,
+            limit: None,
+            offset: None,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Metric {
     pub id: Uuid,
@@ -21,14 +209,611 @@ pub struct Metric {
     pub ci_high: Option<f64>,
     pub extra: Option<Value>,
     pub timestamp: DateTime<Utc>,
+    /// Reported by the runner while the run was still in progress; cleared
+    /// once the final result includes the metric.
+    pub partial: bool,
+    /// Resolved when the metric was persisted; `None` when nothing knew it,
+    /// and for metrics stored before directions were recorded.
+    pub direction: Option<MetricDirection>,
 }
 
-pub async fn list_by_run(pool: &DbPool, run_id: &Uuid) -> Result<Vec<Metric>, DomainError> {
-    let rows = sqlx::query("SELECT id, run_id, dataset, subset, split, metric_name, value, n_samples, ci_low, ci_high, extra_json, timestamp FROM metrics WHERE run_id = ? ORDER BY timestamp ASC")
-        .bind(run_id.to_string())
+impl Metric {
+    pub fn into_record(self) -> MetricRecord {
+        MetricRecord {
+            run_id: self.run_id,
+            dataset: self.dataset,
+            subset: self.subset,
+            split: self.split,
+            metric_name: self.metric_name,
+            value: self.value,
+            n_samples: self.n_samples,
+            ci_low: self.ci_low,
+            ci_high: self.ci_high,
+            extra: self.extra,
+            direction: self.direction,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ComparisonWinner {
+    Left,
+    Right,
+    Tie,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetricComparison {
+    pub dataset: String,
+    pub subset: Option<String>,
+    pub split: Option<String>,
+    pub metric_name: String,
+    pub direction: MetricDirection,
+    pub left: Option<f64>,
+    pub right: Option<f64>,
+    /// `right - left`, present only when both sides reported the metric.
+    pub delta: Option<f64>,
+    pub winner: Option<ComparisonWinner>,
+}
+
+/// Outcome of one thresholded metric in a regression check. `drop` is how
+/// much worse the candidate is than the baseline in the metric's direction
+/// (negative when it improved); a missing candidate value fails.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetricRegression {
+    pub dataset: String,
+    pub subset: Option<String>,
+    pub split: Option<String>,
+    pub metric_name: String,
+    pub direction: MetricDirection,
+    pub baseline: f64,
+    pub candidate: Option<f64>,
+    pub drop: Option<f64>,
+    pub max_drop: f64,
+    pub passed: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegressionReport {
+    pub baseline_run_id: Uuid,
+    pub run_id: Uuid,
+    /// `true` when every check passed.
+    pub passed: bool,
+    pub checks: Vec<MetricRegression>,
+}
+
+/// One value of a metric at a checkpoint of a model implementation.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SeriesPoint {
+    pub step: Option<i64>,
+    pub checkpoint_id: Uuid,
+    pub run_id: Uuid,
+    pub value: f64,
+    pub ci_low: Option<f64>,
+    pub ci_high: Option<f64>,
+}
+
+/// Aggregate of one metric over the models of a project, per
+/// `(dataset, subset, split)`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetricRollup {
+    pub dataset: String,
+    pub subset: Option<String>,
+    pub split: Option<String>,
+    pub mean: f64,
+    pub min: f64,
+    pub max: f64,
+    /// Number of `(model_impl, checkpoint)` pairs that reported the metric.
+    pub count: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct SeriesQuery {
+    pub model_impl_id: Uuid,
+    pub metric_name: String,
+    pub dataset: String,
+    /// Return every completed run per checkpoint instead of only the latest.
+    pub all_runs: bool,
+}
+
+/// One value of a metric in a [`SubsetBreakdown`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubsetValue {
+    pub dataset: String,
+    pub subset: Option<String>,
+    pub split: Option<String>,
+    pub value: f64,
+    pub n_samples: Option<i64>,
+    /// Set on an aggregate the engine didn't report, computed from the
+    /// subsets instead.
+    #[serde(default)]
+    pub computed: bool,
+}
+
+/// A metric of one run per subset, worst first, with the aggregate per
+/// `(dataset, split)`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubsetBreakdown {
+    pub metric_name: String,
+    pub direction: MetricDirection,
+    pub aggregates: Vec<SubsetValue>,
+    pub subsets: Vec<SubsetValue>,
+}
+
+/// Splits a run's `metric_name` records into the per-subset values and the
+/// aggregates (records without a subset). Subsets are ordered worst first
+/// according to `direction`, ties by name. Where the engine reported
+/// subsets but no aggregate, one is computed as their mean weighted by
+/// `n_samples`, or unweighted unless every subset has it. Canary metrics
+/// are left out. A run without subsets only has aggregates.
+pub fn subset_breakdown(
+    metric_name: &str,
+    records: &[MetricRecord],
+    direction: MetricDirection,
+) -> SubsetBreakdown {
+    let value = |record: &MetricRecord| SubsetValue {
+        dataset: record.dataset.clone(),
+        subset: record.subset.clone(),
+        split: record.split.clone(),
+        value: record.value,
+        n_samples: record.n_samples,
+        computed: false,
+    };
+    let records = records.iter().filter(|record| {
+        record.metric_name == metric_name && record.subset.as_deref() != Some(CANARY_SUBSET)
+    });
+    let (mut aggregates, mut subsets): (Vec<_>, Vec<_>) =
+        records.map(value).partition(|value| value.subset.is_none());
+
+    let mut missing: BTreeMap<(String, Option<String>), Vec<&SubsetValue>> = BTreeMap::new();
+    for subset in &subsets {
+        let has_aggregate = aggregates.iter().any(|aggregate| {
+            aggregate.dataset == subset.dataset && aggregate.split == subset.split
+        });
+        if !has_aggregate {
+            missing
+                .entry((subset.dataset.clone(), subset.split.clone()))
+                .or_default()
+                .push(subset);
+        }
+    }
+    for ((dataset, split), group) in missing {
+        let weights: Option<Vec<f64>> = group
+            .iter()
+            .map(|subset| subset.n_samples.filter(|n| *n > 0).map(|n| n as f64))
+            .collect();
+        let weights = weights.unwrap_or_else(|| vec![1.0; group.len()]);
+        let total: f64 = weights.iter().sum();
+        aggregates.push(SubsetValue {
+            dataset,
+            subset: None,
+            split,
+            value: group
+                .iter()
+                .zip(&weights)
+                .map(|(subset, weight)| subset.value * weight / total)
+                .sum(),
+            n_samples: group
+                .iter()
+                .map(|subset| subset.n_samples)
+                .sum::<Option<i64>>(),
+            computed: true,
+        });
+    }
+
+    subsets.sort_by(|a, b| {
+        let by_value = match direction {
+            MetricDirection::HigherBetter => a.value.total_cmp(&b.value),
+            MetricDirection::LowerBetter => b.value.total_cmp(&a.value),
+        };
+        by_value.then_with(|| a.subset.cmp(&b.subset))
+    });
+    aggregates.sort_by(|a, b| (&a.dataset, &a.split).cmp(&(&b.dataset, &b.split)));
+    SubsetBreakdown {
+        metric_name: metric_name.to_string(),
+        direction,
+        aggregates,
+        subsets,
+    }
+}
+
+/// A metric name whose direction was set through
+/// `PUT /metric-directions/{name}`, overriding the built-in guess.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DirectionOverride {
+    pub metric_name: String,
+    pub direction: MetricDirection,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Directions of metrics the run config says nothing about: overrides by
+/// exact name, then [`MetricDirection::for_name`].
+#[derive(Debug, Clone, Default)]
+pub struct DirectionRegistry {
+    pub overrides: HashMap<String, MetricDirection>,
+}
+
+impl DirectionRegistry {
+    /// `None` for a metric name neither overridden nor known.
+    pub fn resolve(&self, metric_name: &str) -> Option<MetricDirection> {
+        self.overrides
+            .get(metric_name)
+            .copied()
+            .or_else(|| MetricDirection::for_name(metric_name))
+    }
+
+    /// Sets the direction of every record that doesn't carry one, from the
+    /// run's metric config or else the registry. Records of unknown
+    /// direction keep `None`.
+    pub fn fill(&self, records: &mut [MetricRecord], configs: &[MetricConfig]) {
+        for record in records
+            .iter_mut()
+            .filter(|record| record.direction.is_none())
+        {
+            let configured = configs
+                .iter()
+                .find(|config| config.name == record.metric_name)
+                .and_then(MetricConfig::configured_direction);
+            record.direction = configured.or_else(|| self.resolve(&record.metric_name));
+        }
+    }
+}
+
+pub async fn list_direction_overrides(
+    pool: &DbPool,
+) -> Result<Vec<DirectionOverride>, DomainError> {
+    let rows = sqlx::query(
+        "SELECT metric_name, direction, updated_at FROM metric_directions ORDER BY metric_name",
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(db_error)?;
+    rows.iter()
+        .map(|row| {
+            Ok(DirectionOverride {
+                metric_name: row.try_get("metric_name")?,
+                direction: parse_direction(&row.try_get::<String, _>("direction")?)?,
+                updated_at: row.try_get("updated_at")?,
+            })
+        })
+        .collect()
+}
+
+pub async fn direction_registry(pool: &DbPool) -> Result<DirectionRegistry, DomainError> {
+    Ok(DirectionRegistry {
+        overrides: list_direction_overrides(pool)
+            .await?
+            .into_iter()
+            .map(|entry| (entry.metric_name, entry.direction))
+            .collect(),
+    })
+}
+
+pub async fn set_direction_override(
+    pool: &DbPool,
+    metric_name: &str,
+    direction: MetricDirection,
+) -> Result<DirectionOverride, DomainError> {
+    let mut errors = FieldErrors::new();
+    errors.require_non_empty("metric_name", metric_name);
+    errors.finish()?;
+    let entry = DirectionOverride {
+        metric_name: metric_name.to_string(),
+        direction,
+        updated_at: Utc::now(),
+    };
+    sqlx::query(
+        "INSERT INTO metric_directions (metric_name, direction, updated_at) VALUES (?, ?, ?) \
+         ON DUPLICATE KEY UPDATE direction = VALUES(direction), updated_at = VALUES(updated_at)",
+    )
+    .bind(&entry.metric_name)
+    .bind(direction.as_str())
+    .bind(entry.updated_at)
+    .execute(pool)
+    .await
+    .map_err(db_error)?;
+    Ok(entry)
+}
+
+/// Removes an override, returning whether the metric had one.
+pub async fn clear_direction_override(
+    pool: &DbPool,
+    metric_name: &str,
+) -> Result<bool, DomainError> {
+    let result = sqlx::query("DELETE FROM metric_directions WHERE metric_name = ?")
+        .bind(metric_name)
+        .execute(pool)
+        .await
+        .map_err(db_error)?;
+    Ok(result.rows_affected() > 0)
+}
+
+fn parse_direction(value: &str) -> Result<MetricDirection, DomainError> {
+    value
+        .parse()
+        .map_err(|err: String| DomainError::Internal(err))
+}
+
+/// Directions the `metrics` entries of a stored eval config state.
+fn configured_directions(eval_config: &Value) -> HashMap<String, MetricDirection> {
+    eval_config
+        .get("metrics")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|metric| {
+            let config: MetricConfig = serde_json::from_value(metric.clone()).ok()?;
+            Some((config.name.clone(), config.configured_direction()?))
+        })
+        .collect()
+}
+
+fn pick_winner(direction: MetricDirection, left: f64, right: f64) -> ComparisonWinner {
+    if left == right {
+        return ComparisonWinner::Tie;
+    }
+    let right_higher = right > left;
+    match (direction, right_higher) {
+        (MetricDirection::HigherBetter, true) | (MetricDirection::LowerBetter, false) => {
+            ComparisonWinner::Right
+        }
+        _ => ComparisonWinner::Left,
+    }
+}
+
+type MetricKey = (String, Option<String>, Option<String>, String);
+
+fn metric_key(metric: &Metric) -> MetricKey {
+    (
+        metric.dataset.clone(),
+        metric.subset.clone(),
+        metric.split.clone(),
+        metric.metric_name.clone(),
+    )
+}
+
+/// Pairs up the metrics of two runs by `(dataset, subset, split, metric_name)`.
+/// Rows are ordered by that key; metrics reported by only one run keep the
+/// other side empty. A metric's direction is the one stored with it (the
+/// left run's first), else what either run's config states, else the
+/// [`DirectionRegistry`], else the default `higher_better`.
+pub async fn compare(
+    pool: &DbPool,
+    left: &Uuid,
+    right: &Uuid,
+) -> Result<Vec<MetricComparison>, DomainError> {
+    let left_run = crate::runs::get(pool, left).await?;
+    let right_run = crate::runs::get(pool, right).await?;
+    let mut directions = configured_directions(right_run.eval_config());
+    directions.extend(configured_directions(left_run.eval_config()));
+    let registry = direction_registry(pool).await?;
+
+    let mut pairs: BTreeMap<MetricKey, (Option<f64>, Option<f64>)> = BTreeMap::new();
+    let mut stored: HashMap<MetricKey, MetricDirection> = HashMap::new();
+    for metric in list_by_run(pool, &MetricFilter::for_run(*right)).await? {
+        if let Some(direction) = metric.direction {
+            stored.insert(metric_key(&metric), direction);
+        }
+        pairs.entry(metric_key(&metric)).or_default().1 = Some(metric.value);
+    }
+    for metric in list_by_run(pool, &MetricFilter::for_run(*left)).await? {
+        if let Some(direction) = metric.direction {
+            stored.insert(metric_key(&metric), direction);
+        }
+        pairs.entry(metric_key(&metric)).or_default().0 = Some(metric.value);
+    }
+
+    Ok(pairs
+        .into_iter()
+        .map(|(key, (left, right))| {
+            let direction = stored
+                .get(&key)
+                .or_else(|| directions.get(&key.3))
+                .copied()
+                .or_else(|| registry.resolve(&key.3))
+                .unwrap_or_default();
+            let (dataset, subset, split, metric_name) = key;
+            let (delta, winner) = match (left, right) {
+                (Some(l), Some(r)) => (Some(r - l), Some(pick_winner(direction, l, r))),
+                _ => (None, None),
+            };
+            MetricComparison {
+                dataset,
+                subset,
+                split,
+                metric_name,
+                direction,
+                left,
+                right,
+                delta,
+                winner,
+            }
+        })
+        .collect())
+}
+
+/// Compares `run_id` against `baseline_run_id` on the metrics named in
+/// `thresholds` (metric name to the largest allowed drop), per
+/// `(dataset, subset, split)`. Both runs must have reported metrics for the
+/// same datasets, otherwise the comparison is meaningless and `Unprocessable`
+/// is returned.
+pub async fn regression_check(
+    pool: &DbPool,
+    run_id: &Uuid,
+    baseline_run_id: &Uuid,
+    thresholds: &HashMap<String, f64>,
+) -> Result<RegressionReport, DomainError> {
+    let mut errors = FieldErrors::new();
+    if thresholds.is_empty() {
+        errors.push("thresholds", "must name at least one metric");
+    }
+    for (name, max_drop) in thresholds {
+        if !max_drop.is_finite() || *max_drop < 0.0 {
+            errors.push(
+                format!("thresholds.{name}"),
+                "must be a non-negative number",
+            );
+        }
+    }
+    errors.finish()?;
+
+    let comparisons = compare(pool, baseline_run_id, run_id).await?;
+    let datasets = |side: fn(&MetricComparison) -> Option<f64>| {
+        comparisons
+            .iter()
+            .filter(|c| side(c).is_some())
+            .map(|c| c.dataset.as_str())
+            .collect::<BTreeSet<_>>()
+    };
+    let (baseline_datasets, run_datasets) = (datasets(|c| c.left), datasets(|c| c.right));
+    if baseline_datasets != run_datasets {
+        return Err(DomainError::Unprocessable(format!(
+            "runs evaluated different datasets: baseline {baseline_datasets:?}, run {run_datasets:?}"
+        )));
+    }
+
+    let mut errors = FieldErrors::new();
+    for name in thresholds.keys() {
+        if !comparisons
+            .iter()
+            .any(|c| &c.metric_name == name && c.left.is_some())
+        {
+            errors.push(
+                format!("thresholds.{name}"),
+                "the baseline run did not report this metric",
+            );
+        }
+    }
+    errors.finish()?;
+
+    let checks: Vec<MetricRegression> = comparisons
+        .into_iter()
+        .filter_map(|c| {
+            let max_drop = *thresholds.get(&c.metric_name)?;
+            let baseline = c.left?;
+            let drop = c.right.map(|candidate| match c.direction {
+                MetricDirection::HigherBetter => baseline - candidate,
+                MetricDirection::LowerBetter => candidate - baseline,
+            });
+            Some(MetricRegression {
+                dataset: c.dataset,
+                subset: c.subset,
+                split: c.split,
+                metric_name: c.metric_name,
+                direction: c.direction,
+                baseline,
+                candidate: c.right,
+                drop,
+                max_drop,
+                passed: drop.is_some_and(|drop| drop <= max_drop),
+            })
+        })
+        .collect();
+
+    Ok(RegressionReport {
+        baseline_run_id: *baseline_run_id,
+        run_id: *run_id,
+        passed: checks.iter().all(|check| check.passed),
+        checks,
+    })
+}
+
+/// A metric across the checkpoints of a model implementation, ordered by
+/// checkpoint `step` (checkpoints without a step last). Only completed runs
+/// count; unless `all_runs` is set, the most recently finished run of each
+/// checkpoint wins.
+pub async fn series(pool: &DbPool, query: &SeriesQuery) -> Result<Vec<SeriesPoint>, DomainError> {
+    let rows = sqlx::query(
+        "SELECT c.step, c.id AS checkpoint_id, r.id AS run_id, m.value, m.ci_low, m.ci_high \
+         FROM metrics m \
+         JOIN runs r ON r.id = m.run_id \
+         JOIN checkpoints c ON c.id = r.checkpoint_id \
+         WHERE r.model_impl_id = ? AND r.status = 'completed' AND m.metric_name = ? AND m.dataset = ? \
+         ORDER BY c.step IS NULL, c.step ASC, c.created_at ASC, c.id ASC, r.finished_at DESC, r.id ASC",
+    )
+    .bind(query.model_impl_id.to_string())
+    .bind(&query.metric_name)
+    .bind(&query.dataset)
+    .fetch_all(pool)
+    .await
+    .map_err(db_error)?;
+
+    let mut points: Vec<SeriesPoint> = Vec::with_capacity(rows.len());
+    for row in rows {
+        let point = SeriesPoint {
+            step: row.try_get("step")?,
+            checkpoint_id: parse_uuid(row.try_get::<String, _>("checkpoint_id")?.as_str())?,
+            run_id: parse_uuid(row.try_get::<String, _>("run_id")?.as_str())?,
+            value: row.try_get("value")?,
+            ci_low: row.try_get("ci_low")?,
+            ci_high: row.try_get("ci_high")?,
+        };
+        let seen = points
+            .last()
+            .is_some_and(|last| last.checkpoint_id == point.checkpoint_id);
+        if query.all_runs || !seen {
+            points.push(point);
+        }
+    }
+
+    Ok(points)
+}
+
+/// Mean/min/max of `metric_name` across a project. Each `(model_impl,
+/// checkpoint)` contributes the value from its most recently finished
+/// completed run that reported the metric for that dataset/subset/split, so a
+/// newer run without the metric doesn't hide an older value, and pairs that
+/// never reported it are simply not counted.
+pub async fn rollup(
+    pool: &DbPool,
+    project_id: &Uuid,
+    metric_name: &str,
+) -> Result<Vec<MetricRollup>, DomainError> {
+    let rows = sqlx::query(
+        "SELECT dataset, subset, split, AVG(value) AS mean, MIN(value) AS min_value, \
+         MAX(value) AS max_value, COUNT(*) AS n \
+         FROM ( \
+           SELECT m.dataset, m.subset, m.split, m.value, \
+             ROW_NUMBER() OVER ( \
+               PARTITION BY r.model_impl_id, r.checkpoint_id, m.dataset, m.subset_key, m.split_key \
+               ORDER BY r.finished_at DESC, r.id DESC \
+             ) AS rn \
+           FROM metrics m \
+           JOIN runs r ON r.id = m.run_id \
+           WHERE r.project_id = ? AND r.status = 'completed' AND m.metric_name = ? \
+         ) latest \
+         WHERE rn = 1 \
+         GROUP BY dataset, subset, split \
+         ORDER BY dataset ASC, subset ASC, split ASC",
+    )
+    .bind(project_id.to_string())
+    .bind(metric_name)
+    .fetch_all(pool)
+    .await
+    .map_err(db_error)?;
+
+    rows.iter()
+        .map(|row| {
+            Ok(MetricRollup {
+                dataset: row.try_get("dataset")?,
+                subset: row.try_get("subset")?,
+                split: row.try_get("split")?,
+                mean: row.try_get("mean")?,
+                min: row.try_get("min_value")?,
+                max: row.try_get("max_value")?,
+                count: row.try_get("n")?,
+            })
+        })
+        .collect()
+}
+
+pub async fn list_by_run(pool: &DbPool, filter: &MetricFilter) -> Result<Vec<Metric>, DomainError> {
+    let rows = list_query(filter)
+        .build()
         .fetch_all(pool)
         .await
-        .map_err(|e| DomainError::Internal(e.to_string()))?;
+        .map_err(db_error)?;
 
     let mut metrics = Vec::new();
     for row in rows {
@@ -52,30 +837,265 @@ pub async fn list_by_run(pool: &DbPool, run_id: &Uuid) -> Result<Vec<Metric>, Do
             ci_high: row.try_get("ci_high")?,
             extra,
             timestamp: row.try_get("timestamp")?,
+            partial: row.try_get("partial")?,
+            direction: row
+                .try_get::<Option<String>, _>("direction")?
+                .as_deref()
+                .map(parse_direction)
+                .transpose()?,
         });
     }
 
     Ok(metrics)
 }
 
+/// The query behind [`list_by_run`]: each set filter adds one bound
+/// condition.
+fn list_query(filter: &MetricFilter) -> QueryBuilder<'static, MySql> {
+    let mut query: QueryBuilder<MySql> = QueryBuilder::new(
+        "SELECT id, run_id, dataset, subset, split, metric_name, value, n_samples, ci_low, ci_high, extra_json, timestamp, partial, direction FROM metrics WHERE run_id = ",
+    );
+    query.push_bind(filter.run_id.to_string());
+    if let Some(dataset) = &filter.dataset {
+        query.push(" AND dataset = ").push_bind(dataset.clone());
+    }
+    if let Some(subset) = &filter.subset {
+        query.push(" AND subset = ").push_bind(subset.clone());
+    }
+    if let Some(split) = &filter.split {
+        query.push(" AND split = ").push_bind(split.clone());
+    }
+    if let Some(metric_name) = &filter.metric_name {
+        query
+            .push(" AND metric_name = ")
+            .push_bind(metric_name.clone());
+    }
+    query.push(" ORDER BY timestamp ASC, id ASC");
+    if let Some((limit, offset)) = page_bounds(filter.limit, filter.offset) {
+        query
+            .push(" LIMIT ")
+            .push_bind(limit)
+            .push(" OFFSET ")
+            .push_bind(offset);
+    }
+    query
+}
+
+fn upsert_query(record: &MetricRecord, partial: bool) -> Query<'_, MySql, MySqlArguments> {
+    sqlx::query("INSERT INTO metrics (id, run_id, dataset, subset, split, metric_name, value, n_samples, ci_low, ci_high, extra_json, timestamp, partial, direction) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?) ON DUPLICATE KEY UPDATE value = VALUES(value), n_samples = VALUES(n_samples), ci_low = VALUES(ci_low), ci_high = VALUES(ci_high), extra_json = VALUES(extra_json), timestamp = VALUES(timestamp), partial = VALUES(partial), direction = VALUES(direction)")
+        .bind(Uuid::new_v4().to_string())
+        .bind(record.run_id.to_string())
+        .bind(&record.dataset)
+        .bind(&record.subset)
+        .bind(&record.split)
+        .bind(&record.metric_name)
+        .bind(record.value)
+        .bind(record.n_samples)
+        .bind(record.ci_low)
+        .bind(record.ci_high)
+        .bind(record.extra.as_ref().map(|v| to_canonical_json(v).unwrap_or_else(|_| "{}".into())))
+        .bind(Utc::now())
+        .bind(partial)
+        .bind(record.direction.map(MetricDirection::as_str))
+}
+
+/// Like [`save_records`], but all-or-nothing: runs in one transaction.
+pub async fn upsert_records(pool: &DbPool, records: &[MetricRecord]) -> Result<(), DomainError> {
+    upsert_records_flagged(pool, records, false).await
+}
+
+/// Writes `records`, replacing any metric of the same run with the same
+/// `(dataset, subset, split, metric_name)`; within `records`, the last
+/// duplicate wins. Metrics differing only in subset or split coexist.
 pub async fn save_records(pool: &DbPool, records: &[MetricRecord]) -> Result<(), DomainError> {
     for record in records {
-        sqlx::query("INSERT INTO metrics (id, run_id, dataset, subset, split, metric_name, value, n_samples, ci_low, ci_high, extra_json, timestamp) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)")
-            .bind(Uuid::new_v4().to_string())
-            .bind(record.run_id.to_string())
-            .bind(&record.dataset)
-            .bind(&record.subset)
-            .bind(&record.split)
-            .bind(&record.metric_name)
-            .bind(record.value)
-            .bind(record.n_samples)
-            .bind(record.ci_low)
-            .bind(record.ci_high)
-            .bind(record.extra.as_ref().map(|v| serde_json::to_string(v).unwrap_or_else(|_| "{}".into())))
-            .bind(Utc::now())
+        upsert_query(record, false)
             .execute(pool)
             .await
-            .map_err(|e| DomainError::Internal(e.to_string()))?;
+            .map_err(db_error)?;
     }
     Ok(())
 }
+
+/// Writes metrics of a run in progress flagged `partial`, replacing earlier
+/// partial values of the same key; see [`save_records`].
+pub async fn save_partial_records(
+    pool: &DbPool,
+    records: &[MetricRecord],
+) -> Result<(), DomainError> {
+    upsert_records_flagged(pool, records, true).await
+}
+
+async fn upsert_records_flagged(
+    pool: &DbPool,
+    records: &[MetricRecord],
+    partial: bool,
+) -> Result<(), DomainError> {
+    let mut tx = pool.begin().await.map_err(db_error)?;
+    for record in records {
+        upsert_query(record, partial)
+            .execute(&mut *tx)
+            .await
+            .map_err(db_error)?;
+    }
+    tx.commit().await.map_err(db_error)?;
+    Ok(())
+}
+
+/// Drops the run's metrics still flagged partial, i.e. those the final
+/// result didn't report again. Returns how many were dropped.
+pub async fn drop_partial(pool: &DbPool, run_id: &Uuid) -> Result<u64, DomainError> {
+    let result = sqlx::query("DELETE FROM metrics WHERE run_id = ? AND partial = TRUE")
+        .bind(run_id.to_string())
+        .execute(pool)
+        .await
+        .map_err(db_error)?;
+    Ok(result.rows_affected())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scores() -> Vec<f64> {
+        (0..200)
+            .map(|i| f64::from(i % 7 == 0 || i % 3 == 0))
+            .collect()
+    }
+
+    #[test]
+    fn bootstrap_intervals_bracket_the_mean() {
+        let samples = scores();
+        let mean = samples.iter().sum::<f64>() / samples.len() as f64;
+        let (low, high) = bootstrap_ci(&samples, 0.95, 1000);
+        assert!(low < mean && mean < high, "{low} < {mean} < {high}");
+        assert!(high - low < 0.2, "interval {low}..{high} is too wide");
+    }
+
+    #[test]
+    fn bootstrap_intervals_are_deterministic() {
+        let samples = scores();
+        assert_eq!(
+            bootstrap_ci(&samples, 0.95, 500),
+            bootstrap_ci(&samples, 0.95, 500)
+        );
+    }
+
+    #[test]
+    fn bootstrap_intervals_of_nothing_are_nan() {
+        let (low, high) = bootstrap_ci(&[], 0.95, 1000);
+        assert!(low.is_nan() && high.is_nan());
+        let (low, high) = bootstrap_ci(&[1.0, 0.0], 0.95, 0);
+        assert!(low.is_nan() && high.is_nan());
+    }
+
+    #[test]
+    fn upserts_refresh_every_column_but_the_metric_key() {
+        use sqlx::Execute;
+
+        let record = MetricRecord {
+            run_id: Uuid::new_v4(),
+            dataset: "mmlu".into(),
+            subset: None,
+            split: Some("test".into()),
+            metric_name: "accuracy".into(),
+            value: 0.5,
+            n_samples: Some(10),
+            ci_low: None,
+            ci_high: None,
+            extra: None,
+            direction: None,
+        };
+        let query = upsert_query(&record, false);
+        let (insert, update) = query.sql().split_once(" ON DUPLICATE KEY UPDATE ").unwrap();
+        let columns = insert
+            .split_once('(')
+            .and_then(|(_, rest)| rest.split_once(')'))
+            .unwrap()
+            .0
+            .split(", ");
+        // `id` is fresh per insert; the rest is the unique key.
+        let key = ["id", "run_id", "dataset", "subset", "split", "metric_name"];
+        for column in columns {
+            let refreshed = update.contains(&format!("{column} = VALUES({column})"));
+            assert_eq!(refreshed, !key.contains(&column), "{column}");
+        }
+    }
+
+    /// What follows `FROM metrics WHERE` in the query for `filter`.
+    fn conditions(filter: &MetricFilter) -> String {
+        let query = list_query(filter);
+        let (_, conditions) = query.sql().split_once(" FROM metrics WHERE ").unwrap();
+        conditions.to_string()
+    }
+
+    #[test]
+    fn each_metric_filter_adds_one_bound_condition() {
+        let all = MetricFilter::for_run(Uuid::new_v4());
+        assert_eq!(
+            conditions(&all),
+            "run_id = ? ORDER BY timestamp ASC, id ASC"
+        );
+        let cases = [
+            (
+                MetricFilter {
+                    dataset: Some("mmlu".into()),
+                    ..all.clone()
+                },
+                "dataset = ?",
+            ),
+            (
+                MetricFilter {
+                    subset: Some("anatomy".into()),
+                    ..all.clone()
+                },
+                "subset = ?",
+            ),
+            (
+                MetricFilter {
+                    split: Some("test".into()),
+                    ..all.clone()
+                },
+                "split = ?",
+            ),
+            (
+                MetricFilter {
+                    metric_name: Some("accuracy".into()),
+                    ..all.clone()
+                },
+                "metric_name = ?",
+            ),
+        ];
+        for (filter, condition) in cases {
+            assert_eq!(
+                conditions(&filter),
+                format!("run_id = ? AND {condition} ORDER BY timestamp ASC, id ASC")
+            );
+        }
+    }
+
+    #[test]
+    fn metric_filters_combine_and_page() {
+        let filter = MetricFilter {
+            dataset: Some("mmlu".into()),
+            subset: Some("anatomy".into()),
+            split: Some("test".into()),
+            metric_name: Some("accuracy' OR 1 = 1".into()),
+            limit: Some(10),
+            offset: Some(20),
+            ..MetricFilter::for_run(Uuid::new_v4())
+        };
+        assert_eq!(
+            conditions(&filter),
+            "run_id = ? AND dataset = ? AND subset = ? AND split = ? AND metric_name = ? \
+             ORDER BY timestamp ASC, id ASC LIMIT ? OFFSET ?"
+        );
+
+        // An offset alone pages with the default size.
+        let filter = MetricFilter {
+            offset: Some(50),
+            ..MetricFilter::for_run(Uuid::new_v4())
+        };
+        assert!(conditions(&filter).ends_with(" LIMIT ? OFFSET ?"));
+    }
+}
@@ -0,0 +1,164 @@
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use unified_shared::eval::{MetricRecord, SampleRecord, SampleResultLocation};
+use uuid::Uuid;
+
+use crate::result_store::{ResultStore, StoreRoutes};
+
+/// One call made to a [`MockResultStore`], with its arguments.
+#[derive(Debug, Clone)]
+pub enum StoreCall {
+    SaveMetrics(Vec<MetricRecord>),
+    UpsertMetrics(Vec<MetricRecord>),
+    SavePartialMetrics(Vec<MetricRecord>),
+    FinalizeMetrics(Uuid),
+    SaveSamplesInline(Vec<SampleRecord>),
+    SaveSamplesLocation(Uuid, SampleResultLocation),
+}
+
+/// An in-memory [`ResultStore`] that records what it is asked to write, so
+/// result routing can be exercised without MySQL, ClickHouse or S3.
+#[derive(Debug, Default)]
+pub struct MockResultStore {
+    /// Identifies the store in assertions, e.g. `"db"` or `"clickhouse"`.
+    pub name: &'static str,
+    calls: Mutex<Vec<StoreCall>>,
+}
+
+impl MockResultStore {
+    pub fn new(name: &'static str) -> Arc<Self> {
+        Arc::new(Self {
+            name,
+            calls: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// The calls made so far, oldest first.
+    pub fn calls(&self) -> Vec<StoreCall> {
+        self.lock().clone()
+    }
+
+    /// Metrics written through any of the metric calls.
+    pub fn metrics(&self) -> Vec<MetricRecord> {
+        self.lock()
+            .iter()
+            .flat_map(|call| match call {
+                StoreCall::SaveMetrics(records)
+                | StoreCall::UpsertMetrics(records)
+                | StoreCall::SavePartialMetrics(records) => records.clone(),
+                _ => Vec::new(),
+            })
+            .collect()
+    }
+
+    /// Samples written through `save_samples_inline`.
+    pub fn samples(&self) -> Vec<SampleRecord> {
+        self.lock()
+            .iter()
+            .flat_map(|call| match call {
+                StoreCall::SaveSamplesInline(records) => records.clone(),
+                _ => Vec::new(),
+            })
+            .collect()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<StoreCall>> {
+        // A panicking test thread must not hide the calls from the others.
+        self.calls.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn record(&self, call: StoreCall) {
+        self.lock().push(call);
+    }
+}
+
+#[async_trait]
+impl ResultStore for MockResultStore {
+    async fn save_metrics(&self, records: &[MetricRecord]) -> anyhow::Result<()> {
+        self.record(StoreCall::SaveMetrics(records.to_vec()));
+        Ok(())
+    }
+
+    async fn upsert_metrics(&self, records: &[MetricRecord]) -> anyhow::Result<()> {
+        self.record(StoreCall::UpsertMetrics(records.to_vec()));
+        Ok(())
+    }
+
+    async fn save_samples_inline(
+        &self,
+        records: &[SampleRecord],
+    ) -> anyhow::Result<SampleResultLocation> {
+        self.record(StoreCall::SaveSamplesInline(records.to_vec()));
+        // Report the location the real store of this name would.
+        Ok(match (self.name, records.first()) {
+            ("object_store", Some(record)) => SampleResultLocation::ObjectStore {
+                uri: format!("s3://mock/runs/{}/samples.jsonl", record.run_id),
+                format: "jsonl".into(),
+            },
+            _ => SampleResultLocation::Inline {
+                samples: records.to_vec(),
+            },
+        })
+    }
+
+    async fn save_samples_location(
+        &self,
+        run_id: Uuid,
+        location: &SampleResultLocation,
+    ) -> anyhow::Result<()> {
+        self.record(StoreCall::SaveSamplesLocation(run_id, location.clone()));
+        Ok(())
+    }
+
+    async fn read_metrics(&self, run_id: Uuid) -> anyhow::Result<Vec<MetricRecord>> {
+        Ok(self
+            .metrics()
+            .into_iter()
+            .filter(|metric| metric.run_id == run_id)
+            .collect())
+    }
+
+    async fn save_partial_metrics(&self, records: &[MetricRecord]) -> anyhow::Result<()> {
+        self.record(StoreCall::SavePartialMetrics(records.to_vec()));
+        Ok(())
+    }
+
+    async fn finalize_metrics(&self, run_id: Uuid) -> anyhow::Result<()> {
+        self.record(StoreCall::FinalizeMetrics(run_id));
+        Ok(())
+    }
+}
+
+/// Mock stores standing in for all three backends, and the routes using
+/// them. Pass `routes` to
+/// [`crate::result_store::ResultStoreHandles::from_routes`].
+pub struct MockStores {
+    pub db: Arc<MockResultStore>,
+    pub clickhouse: Arc<MockResultStore>,
+    pub object_store: Arc<MockResultStore>,
+    pub routes: StoreRoutes,
+}
+
+impl MockStores {
+    /// `clickhouse` and `object_store` say whether those stores count as
+    /// configured; unconfigured ones are left out of the routes and must see
+    /// no calls.
+    pub fn new(clickhouse: bool, object_store: bool) -> Self {
+        let db = MockResultStore::new("db");
+        let ch = MockResultStore::new("clickhouse");
+        let obj = MockResultStore::new("object_store");
+        let routes = StoreRoutes {
+            db: db.clone(),
+            clickhouse: clickhouse.then(|| ch.clone() as Arc<dyn ResultStore>),
+            object_store: object_store.then(|| obj.clone() as Arc<dyn ResultStore>),
+            max_inline_samples: None,
+        };
+        Self {
+            db,
+            clickhouse: ch,
+            object_store: obj,
+            routes,
+        }
+    }
+}
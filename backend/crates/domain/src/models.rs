@@ -1,15 +1,20 @@
-use crate::db::DbPool;
+use crate::db::{db_error, DbPool};
 use crate::utils::parse_uuid;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use sqlx::mysql::MySqlRow;
-use sqlx::Row;
-use unified_shared::error::DomainError;
+use sqlx::{MySql, QueryBuilder, Row};
+use std::collections::HashSet;
+use unified_shared::error::{DomainError, FieldErrors};
+use unified_shared::eval::TaskType;
 use uuid::Uuid;
 
 const INTERNAL_ERR: &str = "internal error";
 
+/// Largest number of checkpoints accepted by [`create_checkpoints_batch`].
+pub const MAX_CHECKPOINT_BATCH: usize = 1000;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ModelFamily {
     pub id: Uuid,
@@ -29,6 +34,14 @@ pub struct NewModelFamily {
     pub description: Option<String>,
 }
 
+impl NewModelFamily {
+    pub fn validate(&self) -> Result<(), DomainError> {
+        let mut errors = FieldErrors::new();
+        errors.require_non_empty("name", &self.name);
+        errors.finish()
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ModelImplementation {
     pub id: Uuid,
@@ -56,6 +69,50 @@ pub struct NewModelImplementation {
     pub default_task_types: Vec<String>,
 }
 
+impl NewModelImplementation {
+    /// Checks the payload and returns the canonical default task types.
+    pub fn validate(&self) -> Result<Vec<String>, DomainError> {
+        let mut errors = FieldErrors::new();
+        errors.require_non_empty("name", &self.name);
+        let task_types = canonical_task_types(&self.default_task_types, &mut errors);
+        errors.finish()?;
+        Ok(task_types)
+    }
+}
+
+/// Fields of a model implementation to change; `None` leaves the field as it
+/// is. The name, family and runtime are fixed once created.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ModelImplUpdate {
+    pub repo_url: Option<String>,
+    pub repo_reference: Option<String>,
+    pub config_path: Option<String>,
+    pub default_task_types: Option<Vec<String>>,
+    /// Whether a `repo_reference` change is appended to the implementation's
+    /// reference history; defaults to `true`.
+    pub record_history: Option<bool>,
+}
+
+/// One `repo_reference` change of a model implementation.
+#[derive(Debug, Clone, Serialize)]
+pub struct ReferenceChange {
+    pub repo_url: Option<String>,
+    pub from_reference: Option<String>,
+    pub to_reference: Option<String>,
+    pub changed_at: DateTime<Utc>,
+}
+
+fn canonical_task_types(values: &[String], errors: &mut FieldErrors) -> Vec<String> {
+    let mut task_types = Vec::with_capacity(values.len());
+    for (index, task_type) in values.iter().enumerate() {
+        match task_type.parse::<TaskType>() {
+            Ok(task_type) => task_types.push(task_type.to_string()),
+            Err(err) => errors.push(format!("default_task_types[{index}]"), err.to_string()),
+        }
+    }
+    task_types
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Checkpoint {
     pub id: Uuid,
@@ -78,6 +135,18 @@ pub struct NewCheckpoint {
     pub training_summary: Option<Value>,
 }
 
+impl NewCheckpoint {
+    pub fn validate(&self) -> Result<(), DomainError> {
+        let mut errors = FieldErrors::new();
+        self.check(&mut errors, "");
+        errors.finish()
+    }
+
+    fn check(&self, errors: &mut FieldErrors, prefix: &str) {
+        errors.require_non_empty(&format!("{prefix}name"), &self.name);
+    }
+}
+
 fn row_to_family(row: &MySqlRow) -> Result<ModelFamily, DomainError> {
     Ok(ModelFamily {
         id: parse_uuid(row.try_get::<String, _>("id")?.as_str())?,
@@ -133,12 +202,12 @@ pub async fn list_families(
     project_id: &Uuid,
 ) -> Result<Vec<ModelFamily>, DomainError> {
     let rows = sqlx::query(
-        "SELECT id, project_id, name, model_type, description, created_at, updated_at FROM model_families WHERE project_id = ? ORDER BY created_at DESC",
+        "SELECT id, project_id, name, model_type, description, created_at, updated_at FROM model_families WHERE project_id = ? ORDER BY created_at DESC, id DESC",
     )
     .bind(project_id.to_string())
     .fetch_all(pool)
     .await
-    .map_err(|e| DomainError::Internal(e.to_string()))?;
+    .map_err(db_error)?;
 
     rows.iter().map(row_to_family).collect()
 }
@@ -147,6 +216,7 @@ pub async fn create_family(
     pool: &DbPool,
     payload: NewModelFamily,
 ) -> Result<ModelFamily, DomainError> {
+    payload.validate()?;
     let id = Uuid::new_v4();
     let now = Utc::now();
     sqlx::query("INSERT INTO model_families (id, project_id, name, model_type, description, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?, ?)")
@@ -159,7 +229,7 @@ pub async fn create_family(
         .bind(now)
         .execute(pool)
         .await
-        .map_err(|e| DomainError::Internal(e.to_string()))?;
+        .map_err(db_error)?;
 
     Ok(ModelFamily {
         id,
@@ -177,24 +247,42 @@ pub async fn list_impls(
     project_id: &Uuid,
 ) -> Result<Vec<ModelImplementation>, DomainError> {
     let rows = sqlx::query(
-        "SELECT id, project_id, family_id, name, repo_url, repo_reference, runtime_type, config_path, default_task_types, created_at, updated_at FROM model_impls WHERE project_id = ? ORDER BY created_at DESC",
+        "SELECT id, project_id, family_id, name, repo_url, repo_reference, runtime_type, config_path, default_task_types, created_at, updated_at FROM model_impls WHERE project_id = ? ORDER BY created_at DESC, id DESC",
     )
     .bind(project_id.to_string())
     .fetch_all(pool)
     .await
-    .map_err(|e| DomainError::Internal(e.to_string()))?;
+    .map_err(db_error)?;
 
     rows.iter().map(row_to_impl).collect()
 }
 
+pub async fn get_impl(pool: &DbPool, id: &Uuid) -> Result<ModelImplementation, DomainError> {
+    let row = sqlx::query(
+        "SELECT id, project_id, family_id, name, repo_url, repo_reference, runtime_type, config_path, default_task_types, created_at, updated_at FROM model_impls WHERE id = ?",
+    )
+    .bind(id.to_string())
+    .fetch_optional(pool)
+    .await
+    .map_err(db_error)?;
+
+    match row {
+        Some(row) => row_to_impl(&row),
+        None => Err(DomainError::NotFound(
+            "model implementation not found".into(),
+        )),
+    }
+}
+
 pub async fn create_impl(
     pool: &DbPool,
     payload: NewModelImplementation,
 ) -> Result<ModelImplementation, DomainError> {
     let id = Uuid::new_v4();
     let now = Utc::now();
-    let default_task_types = serde_json::to_string(&payload.default_task_types)
-        .map_err(|e| DomainError::Internal(e.to_string()))?;
+    let task_types = payload.validate()?;
+    let default_task_types =
+        serde_json::to_string(&task_types).map_err(|e| DomainError::Internal(e.to_string()))?;
 
     sqlx::query("INSERT INTO model_impls (id, project_id, family_id, name, repo_url, repo_reference, runtime_type, config_path, default_task_types, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)")
         .bind(id.to_string())
@@ -210,7 +298,7 @@ pub async fn create_impl(
         .bind(now)
         .execute(pool)
         .await
-        .map_err(|e| DomainError::Internal(e.to_string()))?;
+        .map_err(db_error)?;
 
     Ok(ModelImplementation {
         id,
@@ -221,23 +309,172 @@ pub async fn create_impl(
         repo_reference: payload.repo_reference,
         runtime_type: payload.runtime_type,
         config_path: payload.config_path,
-        default_task_types: payload.default_task_types,
+        default_task_types: task_types,
         created_at: now,
         updated_at: now,
     })
 }
 
+/// Applies `changes` and bumps `updated_at`. When `repo_reference` changes
+/// and `record_history` isn't `false`, the old and new references are
+/// appended to `model_impl_reference_history` in the same transaction.
+pub async fn update_impl(
+    pool: &DbPool,
+    id: &Uuid,
+    changes: ModelImplUpdate,
+) -> Result<ModelImplementation, DomainError> {
+    let mut errors = FieldErrors::new();
+    let task_types = changes
+        .default_task_types
+        .as_deref()
+        .map(|values| canonical_task_types(values, &mut errors));
+    errors.finish()?;
+
+    let mut tx = pool.begin().await.map_err(db_error)?;
+    let row = sqlx::query(
+        "SELECT id, project_id, family_id, name, repo_url, repo_reference, runtime_type, config_path, default_task_types, created_at, updated_at FROM model_impls WHERE id = ? FOR UPDATE",
+    )
+    .bind(id.to_string())
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(db_error)?;
+    let Some(row) = row else {
+        return Err(DomainError::NotFound(
+            "model implementation not found".into(),
+        ));
+    };
+    let mut model_impl = row_to_impl(&row)?;
+    let previous_reference = model_impl.repo_reference.clone();
+
+    if let Some(repo_url) = changes.repo_url {
+        model_impl.repo_url = Some(repo_url);
+    }
+    if let Some(repo_reference) = changes.repo_reference {
+        model_impl.repo_reference = Some(repo_reference);
+    }
+    if let Some(config_path) = changes.config_path {
+        model_impl.config_path = Some(config_path);
+    }
+    if let Some(task_types) = task_types {
+        model_impl.default_task_types = task_types;
+    }
+    model_impl.updated_at = Utc::now();
+    let default_task_types = serde_json::to_string(&model_impl.default_task_types)
+        .map_err(|e| DomainError::Internal(e.to_string()))?;
+
+    sqlx::query("UPDATE model_impls SET repo_url = ?, repo_reference = ?, config_path = ?, default_task_types = ?, updated_at = ? WHERE id = ?")
+        .bind(&model_impl.repo_url)
+        .bind(&model_impl.repo_reference)
+        .bind(&model_impl.config_path)
+        .bind(default_task_types)
+        .bind(model_impl.updated_at)
+        .bind(id.to_string())
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+
+    if changes.record_history.unwrap_or(true) && model_impl.repo_reference != previous_reference {
+        sqlx::query("INSERT INTO model_impl_reference_history (model_impl_id, repo_url, from_reference, to_reference, changed_at) VALUES (?, ?, ?, ?, ?)")
+            .bind(id.to_string())
+            .bind(&model_impl.repo_url)
+            .bind(&previous_reference)
+            .bind(&model_impl.repo_reference)
+            .bind(model_impl.updated_at)
+            .execute(&mut *tx)
+            .await
+            .map_err(db_error)?;
+    }
+
+    tx.commit().await.map_err(db_error)?;
+    Ok(model_impl)
+}
+
+/// Recorded `repo_reference` changes of a model implementation, oldest first.
+pub async fn reference_history(
+    pool: &DbPool,
+    id: &Uuid,
+) -> Result<Vec<ReferenceChange>, DomainError> {
+    let rows = sqlx::query(
+        "SELECT repo_url, from_reference, to_reference, changed_at FROM model_impl_reference_history WHERE model_impl_id = ? ORDER BY id ASC",
+    )
+    .bind(id.to_string())
+    .fetch_all(pool)
+    .await
+    .map_err(db_error)?;
+
+    rows.iter()
+        .map(|row| {
+            Ok(ReferenceChange {
+                repo_url: row.try_get("repo_url")?,
+                from_reference: row.try_get("from_reference")?,
+                to_reference: row.try_get("to_reference")?,
+                changed_at: row.try_get("changed_at")?,
+            })
+        })
+        .collect()
+}
+
+/// Selects checkpoints of a model impl, e.g. for a compile sweep. Step bounds
+/// are inclusive and exclude checkpoints without a `step`; `latest` keeps the
+/// N highest steps (then newest) among those left.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CheckpointFilter {
+    pub min_step: Option<i64>,
+    pub max_step: Option<i64>,
+    pub latest: Option<usize>,
+}
+
+impl CheckpointFilter {
+    pub fn validate(&self) -> Result<(), DomainError> {
+        let mut errors = FieldErrors::new();
+        if let (Some(min), Some(max)) = (self.min_step, self.max_step) {
+            if min > max {
+                errors.push(
+                    "checkpoint_filter.min_step",
+                    format!("must not exceed max_step ({max})"),
+                );
+            }
+        }
+        if self.latest == Some(0) {
+            errors.push("checkpoint_filter.latest", "must be at least 1");
+        }
+        errors.finish()
+    }
+
+    /// The matching checkpoints, highest step first.
+    pub fn apply(&self, mut checkpoints: Vec<Checkpoint>) -> Vec<Checkpoint> {
+        let bounded = self.min_step.is_some() || self.max_step.is_some();
+        checkpoints.retain(|checkpoint| match checkpoint.step {
+            Some(step) => {
+                !matches!(self.min_step, Some(min) if step < min)
+                    && !matches!(self.max_step, Some(max) if step > max)
+            }
+            None => !bounded,
+        });
+        // Checkpoints without a step sort last.
+        checkpoints.sort_by(|a, b| {
+            b.step
+                .cmp(&a.step)
+                .then_with(|| b.created_at.cmp(&a.created_at))
+        });
+        if let Some(latest) = self.latest {
+            checkpoints.truncate(latest);
+        }
+        checkpoints
+    }
+}
+
 pub async fn list_checkpoints(
     pool: &DbPool,
     model_impl_id: &Uuid,
 ) -> Result<Vec<Checkpoint>, DomainError> {
     let rows = sqlx::query(
-        "SELECT id, project_id, model_impl_id, name, weights_uri, step, training_summary, created_at FROM checkpoints WHERE model_impl_id = ? ORDER BY created_at DESC",
+        "SELECT id, project_id, model_impl_id, name, weights_uri, step, training_summary, created_at FROM checkpoints WHERE model_impl_id = ? ORDER BY created_at DESC, id DESC",
     )
     .bind(model_impl_id.to_string())
     .fetch_all(pool)
     .await
-    .map_err(|e| DomainError::Internal(e.to_string()))?;
+    .map_err(db_error)?;
 
     rows.iter().map(row_to_checkpoint).collect()
 }
@@ -246,6 +483,7 @@ pub async fn create_checkpoint(
     pool: &DbPool,
     payload: NewCheckpoint,
 ) -> Result<Checkpoint, DomainError> {
+    payload.validate()?;
     let id = Uuid::new_v4();
     let now = Utc::now();
     let summary_str = match payload.training_summary {
@@ -266,7 +504,7 @@ pub async fn create_checkpoint(
         .bind(now)
         .execute(pool)
         .await
-        .map_err(|e| DomainError::Internal(e.to_string()))?;
+        .map_err(db_error)?;
 
     Ok(Checkpoint {
         id,
@@ -279,3 +517,105 @@ pub async fn create_checkpoint(
         created_at: now,
     })
 }
+
+/// Creates checkpoints of one model implementation in a single transaction.
+/// All entries must share `project_id` and `model_impl_id`, and their steps
+/// must be unique within the batch and among the implementation's existing
+/// checkpoints; otherwise nothing is inserted.
+pub async fn create_checkpoints_batch(
+    pool: &DbPool,
+    payload: Vec<NewCheckpoint>,
+) -> Result<Vec<Checkpoint>, DomainError> {
+    let Some(first) = payload.first() else {
+        return Err(DomainError::Validation("batch must not be empty".into()));
+    };
+    if payload.len() > MAX_CHECKPOINT_BATCH {
+        return Err(DomainError::Validation(format!(
+            "batch has {} checkpoints; at most {MAX_CHECKPOINT_BATCH} are allowed",
+            payload.len()
+        )));
+    }
+    let (project_id, model_impl_id) = (first.project_id, first.model_impl_id);
+    if payload
+        .iter()
+        .any(|c| c.project_id != project_id || c.model_impl_id != model_impl_id)
+    {
+        return Err(DomainError::Validation(
+            "all checkpoints in a batch must share project_id and model_impl_id".into(),
+        ));
+    }
+    let mut errors = FieldErrors::new();
+    for (index, checkpoint) in payload.iter().enumerate() {
+        checkpoint.check(&mut errors, &format!("[{index}]."));
+    }
+    errors.finish()?;
+    let mut steps = HashSet::new();
+    for step in payload.iter().filter_map(|c| c.step) {
+        if !steps.insert(step) {
+            return Err(DomainError::Validation(format!(
+                "step {step} appears more than once in the batch"
+            )));
+        }
+    }
+
+    let now = Utc::now();
+    let checkpoints = payload
+        .into_iter()
+        .map(|c| Checkpoint {
+            id: Uuid::new_v4(),
+            project_id: c.project_id,
+            model_impl_id: c.model_impl_id,
+            name: c.name,
+            weights_uri: c.weights_uri,
+            step: c.step,
+            training_summary: c.training_summary,
+            created_at: now,
+        })
+        .collect::<Vec<_>>();
+
+    let mut tx = pool.begin().await.map_err(db_error)?;
+    if !steps.is_empty() {
+        let mut query: QueryBuilder<MySql> =
+            QueryBuilder::new("SELECT step FROM checkpoints WHERE model_impl_id = ");
+        query
+            .push_bind(model_impl_id.to_string())
+            .push(" AND step IN (");
+        let mut separated = query.separated(", ");
+        for step in &steps {
+            separated.push_bind(*step);
+        }
+        separated.push_unseparated(") FOR UPDATE");
+        let existing: Vec<i64> = query
+            .build_query_scalar()
+            .fetch_all(&mut *tx)
+            .await
+            .map_err(db_error)?;
+        if let Some(step) = existing.first() {
+            return Err(DomainError::Conflict(format!(
+                "model implementation {model_impl_id} already has a checkpoint at step {step}"
+            )));
+        }
+    }
+
+    let mut insert: QueryBuilder<MySql> = QueryBuilder::new(
+        "INSERT INTO checkpoints (id, project_id, model_impl_id, name, weights_uri, step, training_summary, created_at) ",
+    );
+    insert.push_values(&checkpoints, |mut row, c| {
+        row.push_bind(c.id.to_string())
+            .push_bind(c.project_id.to_string())
+            .push_bind(c.model_impl_id.to_string())
+            .push_bind(c.name.clone())
+            .push_bind(c.weights_uri.clone())
+            .push_bind(c.step)
+            .push_bind(
+                c.training_summary
+                    .as_ref()
+                    .map(|v| serde_json::to_string(v).unwrap_or_else(|_| "{}".into())),
+            )
+            .push_bind(c.created_at);
+    });
+    insert.build().execute(&mut *tx).await.map_err(db_error)?;
+    tx.commit().await.map_err(db_error)?;
+
+    Ok(checkpoints)
+}
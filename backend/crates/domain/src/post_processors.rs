@@ -0,0 +1,586 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+use unified_shared::error::DomainError;
+use unified_shared::eval::{
+    EvalResult, MetricConfig, MetricRecord, SampleRecord, SampleResultLocation,
+};
+
+use crate::composite::Composite;
+use crate::metrics;
+
+/// Derives metrics the engine doesn't emit from a run's [`EvalResult`].
+///
+/// Processors are selected by `MetricConfig.metric_type`, or run for the
+/// engine's own metrics (see [`MetricPostProcessors::set_engine_metrics`]),
+/// and must be pure: each sees only the engine's result, never another
+/// processor's output, so the order they run in doesn't matter. The one
+/// exception is what the engine metrics' processor derives, which counts as
+/// the engine's.
+pub trait MetricPostProcessor: Send + Sync {
+    /// The `metric_type` this processor handles.
+    fn name(&self) -> &'static str;
+
+    /// Checks `config.params` without a result, so an invalid config is
+    /// refused when it is saved rather than when the run's results are.
+    fn check(&self, config: &MetricConfig) -> Result<(), String>;
+
+    /// Records to add for `config`, named `config.name`. Errors describe
+    /// invalid `params` or a result the metric can't be derived from.
+    fn process(
+        &self,
+        config: &MetricConfig,
+        result: &EvalResult,
+    ) -> Result<Vec<MetricRecord>, String>;
+}
+
+/// A post-processor failed on a run's result; the run's config is at fault
+/// rather than the stores.
+#[derive(Debug, thiserror::Error)]
+#[error("{0}")]
+pub struct PostProcessError(pub String);
+
+/// Post-processors keyed by [`MetricPostProcessor::name`], plus the one run
+/// for the metrics none of them handles, i.e. those the engine computes.
+#[derive(Clone, Default)]
+pub struct MetricPostProcessors {
+    processors: HashMap<&'static str, Arc<dyn MetricPostProcessor>>,
+    engine_metrics: Option<Arc<dyn MetricPostProcessor>>,
+}
+
+impl MetricPostProcessors {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The built-in processors: [`Composite`], [`MacroAverage`] and
+    /// [`PassAtK`], with [`SampleAggregate`] for engine metrics.
+    pub fn with_builtins() -> Self {
+        let mut registry = Self::new();
+        registry.register(Arc::new(Composite));
+        registry.register(Arc::new(MacroAverage));
+        registry.register(Arc::new(PassAtK));
+        registry.set_engine_metrics(Arc::new(SampleAggregate));
+        registry
+    }
+
+    /// Adds `processor`, replacing any registered under the same name.
+    pub fn register(&mut self, processor: Arc<dyn MetricPostProcessor>) {
+        self.processors.insert(processor.name(), processor);
+    }
+
+    /// Runs `processor` for every metric no registered processor handles,
+    /// replacing any set before.
+    pub fn set_engine_metrics(&mut self, processor: Arc<dyn MetricPostProcessor>) {
+        self.engine_metrics = Some(processor);
+    }
+
+    /// Whether `config` is computed by a registered processor rather than the
+    /// engine.
+    pub fn handles(&self, config: &MetricConfig) -> bool {
+        self.processors.contains_key(config.metric_type.as_str())
+    }
+
+    /// Checks the params of the metrics among the `metrics` of a stored eval
+    /// config, whole or partial, that a registered processor handles.
+    /// `Unprocessable` names the metric and what is wrong with it.
+    pub fn check_config(&self, eval_config: &Value) -> Result<(), DomainError> {
+        for metric in eval_config
+            .get("metrics")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+        {
+            let Ok(config) = serde_json::from_value::<MetricConfig>(metric.clone()) else {
+                continue;
+            };
+            if let Some(processor) = self.processors.get(config.metric_type.as_str()) {
+                processor
+                    .check(&config)
+                    .map_err(DomainError::Unprocessable)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs the processor of every metric in `configs` against `result` and
+    /// returns the derived records. The engine metrics' processor runs first
+    /// and what it derives stands in for the engine's own records, so the
+    /// others (e.g. composites) see aggregates of per-sample scores.
+    pub fn apply(
+        &self,
+        configs: &[MetricConfig],
+        result: &EvalResult,
+    ) -> Result<Vec<MetricRecord>, PostProcessError> {
+        let mut derived = Vec::new();
+        if let Some(processor) = &self.engine_metrics {
+            for config in configs.iter().filter(|config| !self.handles(config)) {
+                derived.extend(
+                    processor
+                        .process(config, result)
+                        .map_err(PostProcessError)?,
+                );
+            }
+        }
+        let extended;
+        let result = if derived.is_empty() {
+            result
+        } else {
+            let mut metrics = result.metrics.clone();
+            metrics.extend(derived.iter().cloned());
+            extended = EvalResult {
+                metrics,
+                ..result.clone()
+            };
+            &extended
+        };
+        for config in configs {
+            if let Some(processor) = self.processors.get(config.metric_type.as_str()) {
+                derived.extend(
+                    processor
+                        .process(config, result)
+                        .map_err(PostProcessError)?,
+                );
+            }
+        }
+        Ok(derived)
+    }
+}
+
+fn parse_params<T: for<'de> Deserialize<'de>>(config: &MetricConfig) -> Result<T, String> {
+    serde_json::from_value(config.params.clone().unwrap_or_else(|| json!({}))).map_err(|e| {
+        format!(
+            "{} metric {}: invalid params: {e}",
+            config.metric_type, config.name
+        )
+    })
+}
+
+type GroupKey = (String, Option<String>, Option<String>);
+
+fn group_key(dataset: &str, subset: &Option<String>, split: &Option<String>) -> GroupKey {
+    (dataset.to_string(), subset.clone(), split.clone())
+}
+
+fn derived_record(
+    result: &EvalResult,
+    config: &MetricConfig,
+    (dataset, subset, split): GroupKey,
+    value: f64,
+    n_samples: Option<i64>,
+    extra: Value,
+) -> MetricRecord {
+    MetricRecord {
+        run_id: result.run_id,
+        dataset,
+        subset,
+        split,
+        metric_name: config.name.clone(),
+        value,
+        n_samples,
+        ci_low: None,
+        ci_high: None,
+        extra: Some(extra),
+        direction: config.configured_direction(),
+    }
+}
+
+/// Run-level records for an engine metric reported only per sample, from
+/// the inline samples; see [`metrics::aggregate_samples`]. Metrics stored
+/// with their samples (canary and ensemble member subsets included) are
+/// aggregated per subset like any other.
+pub struct SampleAggregate;
+
+impl MetricPostProcessor for SampleAggregate {
+    fn name(&self) -> &'static str {
+        "sample_aggregate"
+    }
+
+    fn check(&self, _config: &MetricConfig) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn process(
+        &self,
+        config: &MetricConfig,
+        result: &EvalResult,
+    ) -> Result<Vec<MetricRecord>, String> {
+        let SampleResultLocation::Inline { samples } = &result.samples else {
+            return Ok(Vec::new());
+        };
+        Ok(metrics::aggregate_samples(
+            result.run_id,
+            [config],
+            &result.metrics,
+            samples,
+        ))
+    }
+}
+
+/// `metric_type: "macro_average"`: the unweighted mean of the engine's
+/// metrics named in `params.source_metrics` or starting with `params.prefix`
+/// (e.g. per-class F1), per dataset/subset/split.
+pub struct MacroAverage;
+
+#[derive(Deserialize)]
+struct MacroAverageParams {
+    #[serde(default)]
+    source_metrics: Vec<String>,
+    prefix: Option<String>,
+}
+
+impl MacroAverageParams {
+    fn parse(config: &MetricConfig) -> Result<Self, String> {
+        let params: Self = parse_params(config)?;
+        if params.source_metrics.is_empty() && params.prefix.is_none() {
+            return Err(format!(
+                "macro_average metric {}: needs source_metrics or prefix",
+                config.name
+            ));
+        }
+        Ok(params)
+    }
+}
+
+impl MetricPostProcessor for MacroAverage {
+    fn name(&self) -> &'static str {
+        "macro_average"
+    }
+
+    fn check(&self, config: &MetricConfig) -> Result<(), String> {
+        MacroAverageParams::parse(config).map(|_| ())
+    }
+
+    fn process(
+        &self,
+        config: &MetricConfig,
+        result: &EvalResult,
+    ) -> Result<Vec<MetricRecord>, String> {
+        let params = MacroAverageParams::parse(config)?;
+        let selected = |name: &str| {
+            name != config.name
+                && (params.source_metrics.iter().any(|source| source == name)
+                    || params
+                        .prefix
+                        .as_deref()
+                        .is_some_and(|prefix| name.starts_with(prefix)))
+        };
+
+        let mut groups: BTreeMap<GroupKey, Vec<f64>> = BTreeMap::new();
+        for record in result
+            .metrics
+            .iter()
+            .filter(|record| selected(&record.metric_name) && record.value.is_finite())
+        {
+            groups
+                .entry(group_key(&record.dataset, &record.subset, &record.split))
+                .or_default()
+                .push(record.value);
+        }
+        Ok(groups
+            .into_iter()
+            .map(|(key, values)| {
+                let mean = values.iter().sum::<f64>() / values.len() as f64;
+                let extra = json!({ "macro_average": { "sources": values.len() } });
+                derived_record(result, config, key, mean, None, extra)
+            })
+            .collect())
+    }
+}
+
+/// `metric_type: "pass_at_k"`: the unbiased pass@k estimate from inline
+/// samples, per dataset/subset/split. A sample passed when
+/// `metrics[params.pass_field]` (default `"passed"`) is `true` or non-zero;
+/// samples sharing `metrics[params.problem_field]` (default `"problem_id"`)
+/// are generations for one problem, and a sample without one is its own
+/// problem. Problems with fewer than `k` generations are skipped.
+pub struct PassAtK;
+
+#[derive(Deserialize)]
+struct PassAtKParams {
+    k: u64,
+    #[serde(default = "default_pass_field")]
+    pass_field: String,
+    #[serde(default = "default_problem_field")]
+    problem_field: String,
+}
+
+fn default_pass_field() -> String {
+    "passed".into()
+}
+
+fn default_problem_field() -> String {
+    "problem_id".into()
+}
+
+impl PassAtKParams {
+    fn parse(config: &MetricConfig) -> Result<Self, String> {
+        let params: Self = parse_params(config)?;
+        if params.k == 0 {
+            return Err(format!(
+                "pass_at_k metric {}: k must be at least 1",
+                config.name
+            ));
+        }
+        Ok(params)
+    }
+}
+
+impl MetricPostProcessor for PassAtK {
+    fn name(&self) -> &'static str {
+        "pass_at_k"
+    }
+
+    fn check(&self, config: &MetricConfig) -> Result<(), String> {
+        PassAtKParams::parse(config).map(|_| ())
+    }
+
+    fn process(
+        &self,
+        config: &MetricConfig,
+        result: &EvalResult,
+    ) -> Result<Vec<MetricRecord>, String> {
+        let params = PassAtKParams::parse(config)?;
+        // Samples stored elsewhere aren't visible here.
+        let SampleResultLocation::Inline { samples } = &result.samples else {
+            return Ok(Vec::new());
+        };
+
+        // Per group, per problem: (generations, passed).
+        let mut groups: BTreeMap<GroupKey, BTreeMap<String, (u64, u64)>> = BTreeMap::new();
+        for sample in samples {
+            let Some(passed) = sample_passed(sample, &params.pass_field) else {
+                continue;
+            };
+            let problem = sample
+                .metrics
+                .as_ref()
+                .and_then(|metrics| metrics.get(&params.problem_field))
+                .map(|id| match id {
+                    Value::String(id) => id.clone(),
+                    other => other.to_string(),
+                })
+                .unwrap_or_else(|| format!("#{}", sample.sample_index));
+            let counts = groups
+                .entry(group_key(&sample.dataset, &sample.subset, &sample.split))
+                .or_default()
+                .entry(problem)
+                .or_default();
+            counts.0 += 1;
+            counts.1 += u64::from(passed);
+        }
+
+        Ok(groups
+            .into_iter()
+            .filter_map(|(key, problems)| {
+                let estimates: Vec<f64> = problems
+                    .values()
+                    .filter(|(n, _)| *n >= params.k)
+                    .map(|&(n, c)| pass_at_k(n, c, params.k))
+                    .collect();
+                if estimates.is_empty() {
+                    return None;
+                }
+                let value = estimates.iter().sum::<f64>() / estimates.len() as f64;
+                let extra = json!({
+                    "pass_at_k": { "k": params.k, "problems": estimates.len() }
+                });
+                let n_samples = problems
+                    .values()
+                    .filter(|(n, _)| *n >= params.k)
+                    .map(|(n, _)| *n as i64)
+                    .sum();
+                Some(derived_record(
+                    result,
+                    config,
+                    key,
+                    value,
+                    Some(n_samples),
+                    extra,
+                ))
+            })
+            .collect())
+    }
+}
+
+fn sample_passed(sample: &SampleRecord, field: &str) -> Option<bool> {
+    match sample.metrics.as_ref()?.get(field)? {
+        Value::Bool(passed) => Some(*passed),
+        Value::Number(value) => value.as_f64().map(|value| value != 0.0),
+        _ => None,
+    }
+}
+
+/// `1 - C(n - c, k) / C(n, k)`, computed as a running product to stay finite
+/// for large `n`.
+fn pass_at_k(n: u64, c: u64, k: u64) -> f64 {
+    if n - c < k {
+        return 1.0;
+    }
+    let failing: f64 = ((n - c + 1)..=n)
+        .map(|i| 1.0 - k as f64 / i as f64)
+        .product();
+    1.0 - failing
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ensemble;
+    use chrono::Utc;
+    use unified_shared::eval::{canary_subset, RunStatus};
+    use uuid::Uuid;
+
+    fn config(metrics: Value) -> Value {
+        json!({ "engine": "lm_eval_harness", "metrics": metrics })
+    }
+
+    #[test]
+    fn saved_configs_with_invalid_params_are_refused() {
+        let registry = MetricPostProcessors::with_builtins();
+        assert!(registry
+            .check_config(&config(json!([
+                { "name": "accuracy", "metric_type": "builtin" },
+                { "name": "pass@1", "metric_type": "pass_at_k", "params": { "k": 1 } },
+                { "name": "macro_f1", "metric_type": "macro_average", "params": { "prefix": "f1_" } },
+                { "name": "headline", "metric_type": "composite", "params": {
+                    "components": [{ "source_metric": "accuracy", "weight": 1.0 }]
+                } },
+            ])))
+            .is_ok());
+        for metric in [
+            json!({ "name": "pass@0", "metric_type": "pass_at_k", "params": { "k": 0 } }),
+            json!({ "name": "macro_f1", "metric_type": "macro_average" }),
+            json!({ "name": "headline", "metric_type": "composite", "params": { "components": [] } }),
+        ] {
+            assert!(matches!(
+                registry.check_config(&config(json!([metric]))),
+                Err(DomainError::Unprocessable(_))
+            ));
+        }
+        assert!(registry.check_config(&json!({})).is_ok());
+    }
+
+    fn sample(subset: Option<&str>, index: i64, correct: bool) -> SampleRecord {
+        SampleRecord {
+            run_id: Uuid::nil(),
+            dataset: "mmlu".into(),
+            subset: subset.map(Into::into),
+            split: None,
+            sample_index: index,
+            input: String::new(),
+            reference: None,
+            output: String::new(),
+            metrics: Some(json!({ "accuracy": correct })),
+            latency_ms: None,
+            token_counts: None,
+            error: None,
+        }
+    }
+
+    fn metric_config(name: &str, metric_type: &str, params: Option<Value>) -> MetricConfig {
+        MetricConfig {
+            name: name.into(),
+            metric_type: metric_type.into(),
+            params,
+            direction: None,
+            reference_free: None,
+            aggregation: None,
+        }
+    }
+
+    fn result(metrics: Vec<MetricRecord>, samples: Vec<SampleRecord>) -> EvalResult {
+        EvalResult {
+            run_id: Uuid::nil(),
+            status: RunStatus::Completed,
+            started_at: Utc::now(),
+            completed_at: Utc::now(),
+            metrics,
+            samples: SampleResultLocation::Inline { samples },
+            error: None,
+            metadata: None,
+        }
+    }
+
+    #[test]
+    fn per_sample_scores_aggregate_per_canary_and_member_subset() {
+        let canary = canary_subset(Some("algebra"));
+        let member = ensemble::member_subset("small", Some("algebra"));
+        let samples = vec![
+            sample(Some(&canary), 0, true),
+            sample(Some(&canary), 1, false),
+            sample(Some(&member), 0, true),
+            sample(Some(&member), 1, true),
+            sample(Some("large/algebra"), 0, false),
+            sample(Some("algebra"), 0, true),
+        ];
+        // The engine's own record for the plain subset wins over aggregating.
+        let reported = MetricRecord {
+            run_id: Uuid::nil(),
+            dataset: "mmlu".into(),
+            subset: Some("algebra".into()),
+            split: None,
+            metric_name: "accuracy".into(),
+            value: 0.25,
+            n_samples: None,
+            ci_low: None,
+            ci_high: None,
+            extra: None,
+            direction: None,
+        };
+        let derived = MetricPostProcessors::with_builtins()
+            .apply(
+                &[metric_config("accuracy", "builtin", None)],
+                &result(vec![reported], samples),
+            )
+            .unwrap();
+        let values: BTreeMap<_, _> = derived
+            .iter()
+            .map(|record| (record.subset.clone().unwrap(), record.value))
+            .collect();
+        assert_eq!(
+            values,
+            BTreeMap::from([
+                ("canary/algebra".to_string(), 0.5),
+                ("large/algebra".to_string(), 0.0),
+                ("small/algebra".to_string(), 1.0),
+            ])
+        );
+    }
+
+    #[test]
+    fn composites_see_aggregated_scores() {
+        let configs = [
+            metric_config("accuracy", "builtin", None),
+            metric_config(
+                "headline",
+                "composite",
+                Some(json!({ "components": [{ "source_metric": "accuracy", "weight": 1.0 }] })),
+            ),
+        ];
+        let derived = MetricPostProcessors::with_builtins()
+            .apply(
+                &configs,
+                &result(
+                    Vec::new(),
+                    vec![sample(None, 0, true), sample(None, 1, false)],
+                ),
+            )
+            .unwrap();
+        let headline = derived
+            .iter()
+            .find(|record| record.metric_name == "headline")
+            .unwrap();
+        assert_eq!(headline.value, 0.5);
+    }
+
+    #[test]
+    fn pass_at_k_is_exact_for_small_counts() {
+        assert_eq!(pass_at_k(4, 0, 1), 0.0);
+        assert_eq!(pass_at_k(4, 4, 2), 1.0);
+        assert!((pass_at_k(4, 1, 1) - 0.25).abs() < 1e-12);
+        assert!((pass_at_k(4, 1, 2) - 0.5).abs() < 1e-12);
+    }
+}
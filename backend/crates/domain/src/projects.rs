@@ -1,10 +1,12 @@
-use crate::db::DbPool;
-use crate::utils::parse_uuid;
+use crate::db::{db_error, DbPool};
+use crate::utils::{json_column, parse_uuid};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::mysql::MySqlRow;
+use sqlx::types::Json;
 use sqlx::Row;
-use unified_shared::error::DomainError;
+use unified_shared::error::{DomainError, FieldErrors};
+use unified_shared::settings::{ProjectSettings, Settings};
 use uuid::Uuid;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -22,6 +24,30 @@ pub struct NewProject {
     pub description: Option<String>,
 }
 
+impl NewProject {
+    pub fn validate(&self) -> Result<(), DomainError> {
+        let mut errors = FieldErrors::new();
+        errors.require_non_empty("name", &self.name);
+        errors.finish()
+    }
+}
+
+/// Fields of a project to change; `None` leaves the field as it is.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ProjectUpdate {
+    pub name: Option<String>,
+    pub description: Option<String>,
+}
+
+fn map_write_error(err: sqlx::Error, name: &str) -> DomainError {
+    match err {
+        sqlx::Error::Database(db_err) if db_err.is_unique_violation() => {
+            DomainError::Conflict(format!("a project named {name:?} already exists"))
+        }
+        other => db_error(other),
+    }
+}
+
 fn row_to_project(row: &MySqlRow) -> Result<Project, DomainError> {
     Ok(Project {
         id: parse_uuid(row.try_get::<String, _>("id")?.as_str())?,
@@ -33,10 +59,10 @@ fn row_to_project(row: &MySqlRow) -> Result<Project, DomainError> {
 }
 
 pub async fn list(pool: &DbPool) -> Result<Vec<Project>, DomainError> {
-    let rows = sqlx::query("SELECT id, name, description, created_at, updated_at FROM projects ORDER BY created_at DESC")
+    let rows = sqlx::query("SELECT id, name, description, created_at, updated_at FROM projects ORDER BY created_at DESC, id DESC")
         .fetch_all(pool)
         .await
-        .map_err(|e| DomainError::Internal(e.to_string()))?;
+        .map_err(db_error)?;
 
     rows.iter().map(row_to_project).collect()
 }
@@ -48,7 +74,7 @@ pub async fn get(pool: &DbPool, id: &Uuid) -> Result<Project, DomainError> {
     .bind(id.to_string())
     .fetch_optional(pool)
     .await
-    .map_err(|e| DomainError::Internal(e.to_string()))?;
+    .map_err(db_error)?;
 
     match row {
         Some(row) => row_to_project(&row),
@@ -57,6 +83,7 @@ pub async fn get(pool: &DbPool, id: &Uuid) -> Result<Project, DomainError> {
 }
 
 pub async fn create(pool: &DbPool, payload: NewProject) -> Result<Project, DomainError> {
+    payload.validate()?;
     let id = Uuid::new_v4();
     let now = Utc::now();
     sqlx::query(
@@ -69,7 +96,7 @@ pub async fn create(pool: &DbPool, payload: NewProject) -> Result<Project, Domai
     .bind(now)
     .execute(pool)
     .await
-    .map_err(|e| DomainError::Internal(e.to_string()))?;
+    .map_err(|e| map_write_error(e, &payload.name))?;
 
     Ok(Project {
         id,
@@ -79,3 +106,111 @@ pub async fn create(pool: &DbPool, payload: NewProject) -> Result<Project, Domai
         updated_at: now,
     })
 }
+
+pub async fn update(
+    pool: &DbPool,
+    id: &Uuid,
+    changes: ProjectUpdate,
+) -> Result<Project, DomainError> {
+    let mut project = get(pool, id).await?;
+    if let Some(name) = changes.name {
+        if name.trim().is_empty() {
+            return Err(DomainError::field("name", "must not be empty"));
+        }
+        project.name = name;
+    }
+    if let Some(description) = changes.description {
+        project.description = Some(description);
+    }
+    project.updated_at = Utc::now();
+
+    sqlx::query("UPDATE projects SET name = ?, description = ?, updated_at = ? WHERE id = ?")
+        .bind(&project.name)
+        .bind(&project.description)
+        .bind(project.updated_at)
+        .bind(id.to_string())
+        .execute(pool)
+        .await
+        .map_err(|e| map_write_error(e, &project.name))?;
+
+    Ok(project)
+}
+
+fn parse_overrides(row: &MySqlRow) -> Result<ProjectSettings, DomainError> {
+    serde_json::from_value(json_column(row, "settings_json")?)
+        .map_err(|e| DomainError::Internal(format!("invalid stored project settings: {e}")))
+}
+
+/// The project's overrides of the global settings; empty when it has none.
+pub async fn get_settings(pool: &DbPool, id: &Uuid) -> Result<ProjectSettings, DomainError> {
+    let row = sqlx::query("SELECT settings_json FROM project_settings WHERE project_id = ?")
+        .bind(id.to_string())
+        .fetch_optional(pool)
+        .await
+        .map_err(db_error)?;
+    row.map(|row| parse_overrides(&row))
+        .transpose()
+        .map(Option::unwrap_or_default)
+}
+
+/// Every project with stored overrides.
+pub async fn list_settings(pool: &DbPool) -> Result<Vec<(Uuid, ProjectSettings)>, DomainError> {
+    let rows = sqlx::query("SELECT project_id, settings_json FROM project_settings")
+        .fetch_all(pool)
+        .await
+        .map_err(db_error)?;
+    rows.iter()
+        .map(|row| {
+            let project_id = parse_uuid(row.try_get::<String, _>("project_id")?.as_str())?;
+            Ok((project_id, parse_overrides(row)?))
+        })
+        .collect()
+}
+
+/// Checks the settings `overrides` produce over `global` with
+/// [`Settings::validate`], as the global settings are checked at startup.
+fn check_overrides(
+    overrides: &ProjectSettings,
+    global: &Settings,
+) -> Result<Settings, DomainError> {
+    let settings = overrides.apply(global);
+    settings
+        .validate()
+        .map_err(|problems| DomainError::Validation(problems.join("; ")))?;
+    Ok(settings)
+}
+
+/// Replaces the project's overrides; fields left unset fall back to the
+/// global settings. Rejected when the resulting settings are invalid.
+pub async fn put_settings(
+    pool: &DbPool,
+    id: &Uuid,
+    overrides: ProjectSettings,
+    global: &Settings,
+) -> Result<ProjectSettings, DomainError> {
+    get(pool, id).await?;
+    check_overrides(&overrides, global)?;
+    sqlx::query(
+        "INSERT INTO project_settings (project_id, settings_json, updated_at) VALUES (?, ?, ?) \
+         ON DUPLICATE KEY UPDATE settings_json = VALUES(settings_json), updated_at = VALUES(updated_at)",
+    )
+    .bind(id.to_string())
+    .bind(Json(&overrides))
+    .bind(Utc::now())
+    .execute(pool)
+    .await
+    .map_err(db_error)?;
+    Ok(overrides)
+}
+
+/// `global` with the project's overrides applied. Overrides that no longer
+/// validate, e.g. naming a result store since removed from the global
+/// settings, fail with `Validation`.
+pub async fn effective_settings(
+    pool: &DbPool,
+    project_id: &Uuid,
+    global: &Settings,
+) -> Result<Settings, DomainError> {
+    let overrides = get_settings(pool, project_id).await?;
+    check_overrides(&overrides, global)
+}
@@ -1,21 +1,43 @@
+use std::future::Future;
 use std::io::Write;
 use std::sync::Arc;
+use std::time::Duration;
 
+use crate::metrics::{Metric, MetricFilter};
+use crate::post_processors::MetricPostProcessors;
+use crate::sample_outputs::{
+    like_pattern, LatencyPercentiles, SampleErrorSummary, SampleFilter, SampleOutput, SamplePage,
+    SampleSearch, TokenSummary, LATENCY_PERCENTILES,
+};
+use crate::utils::page_bounds;
 use anyhow::bail;
 use anyhow::bail;
+use anyhow::Context;
 use async_trait::async_trait;
 use clickhouse::{Client as ClickHouseClient, Row};
+use flate2::write::GzDecoder;
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::client::legacy::Client as HyperClient;
+use hyper_util::rt::TokioExecutor;
 use s3::creds::Credentials;
 use s3::{Bucket, Region};
 use unified_shared::eval::{
-    EvalConfig, EvalResult, MetricRecord, OutputConfig, SampleRecord, SampleResultLocation,
+    is_canary_subset, sort_metrics, to_canonical_json, EvalConfig, EvalResult, MetricConfig,
+    MetricRecord, OutputConfig, ResultStoreKind, SampleRecord, SampleResultLocation,
 };
-use unified_shared::settings::{ClickhouseSettings, ObjectStoreSettings};
+use unified_shared::retry::{retry_with_backoff, Backoff, RetryPolicy};
+use unified_shared::settings::{BootstrapSettings, ClickhouseSettings, ObjectStoreSettings};
 use uuid::Uuid;
 
 #[async_trait]
 pub trait ResultStore: Send + Sync {
+    /// Saves metrics, replacing any of the run's metrics that share
+    /// `(dataset, subset, split, metric_name)` with a record, so re-persisting
+    /// a result never duplicates.
     async fn save_metrics(&self, records: &[MetricRecord]) -> anyhow::Result<()>;
+    /// Like `save_metrics`, but applies the whole batch atomically where the
+    /// store supports it.
+    async fn upsert_metrics(&self, records: &[MetricRecord]) -> anyhow::Result<()>;
     async fn save_samples_inline(
         &self,
         records: &[SampleRecord],
@@ -25,6 +47,32 @@ pub trait ResultStore: Send + Sync {
         run_id: uuid::Uuid,
         location: &SampleResultLocation,
     ) -> anyhow::Result<()>;
+    /// Reads a run's samples back in `sample_index` order. Stores that keep
+    /// samples as opaque blobs don't support this.
+    async fn read_samples(&self, filter: &SampleFilter) -> anyhow::Result<Vec<SampleOutput>> {
+        bail!(
+            "reading samples of run {} is not supported by this result store",
+            filter.run_id
+        )
+    }
+
+    /// Reads a run's metrics back, e.g. to copy them to another store.
+    async fn read_metrics(&self, run_id: Uuid) -> anyhow::Result<Vec<MetricRecord>> {
+        bail!("reading metrics of run {run_id} is not supported by this result store")
+    }
+
+    /// Saves metrics of a run still in progress. Stores that can flag them
+    /// as partial do; others store them like `upsert_metrics`, and the final
+    /// result overwrites them either way.
+    async fn save_partial_metrics(&self, records: &[MetricRecord]) -> anyhow::Result<()> {
+        self.upsert_metrics(records).await
+    }
+
+    /// Called once the run's final metrics are saved: drops partial metrics
+    /// the final result didn't report again.
+    async fn finalize_metrics(&self, _run_id: Uuid) -> anyhow::Result<()> {
+        Ok(())
+    }
 }
 
 pub struct DbResultStore {
@@ -38,6 +86,11 @@ impl ResultStore for DbResultStore {
         Ok(())
     }
 
+    async fn upsert_metrics(&self, records: &[MetricRecord]) -> anyhow::Result<()> {
+        crate::metrics::upsert_records(&self.db, records).await?;
+        Ok(())
+    }
+
     async fn save_samples_inline(
         &self,
         records: &[SampleRecord],
@@ -47,9 +100,34 @@ impl ResultStore for DbResultStore {
 
     async fn save_samples_location(
         &self,
-        _run_id: Uuid,
-        _location: &SampleResultLocation,
+        run_id: Uuid,
+        location: &SampleResultLocation,
     ) -> anyhow::Result<()> {
+        crate::runs::set_samples_location(&self.db, &run_id, location).await?;
+        Ok(())
+    }
+
+    async fn read_samples(&self, filter: &SampleFilter) -> anyhow::Result<Vec<SampleOutput>> {
+        Ok(crate::sample_outputs::list_by_run(&self.db, filter).await?)
+    }
+
+    async fn read_metrics(&self, run_id: Uuid) -> anyhow::Result<Vec<MetricRecord>> {
+        let metrics = crate::metrics::list_by_run(&self.db, &MetricFilter::for_run(run_id)).await?;
+        Ok(metrics.into_iter().map(Metric::into_record).collect())
+    }
+
+    async fn save_partial_metrics(&self, records: &[MetricRecord]) -> anyhow::Result<()> {
+        crate::metrics::save_partial_records(&self.db, records).await?;
+        Ok(())
+    }
+
+    async fn finalize_metrics(&self, run_id: Uuid) -> anyhow::Result<()> {
+        let dropped = crate::metrics::drop_partial(&self.db, &run_id).await?;
+        if dropped > 0 {
+            tracing::info!(
+                "dropped {dropped} partial metric(s) of run {run_id} missing from its final result"
+            );
+        }
         Ok(())
     }
 }
@@ -59,9 +137,57 @@ pub struct ClickHouseResultStore {
     pub settings: ClickhouseSettings,
 }
 
-#[async_trait]
-impl ResultStore for ClickHouseResultStore {
-    async fn save_metrics(&self, records: &[MetricRecord]) -> anyhow::Result<()> {
+impl ClickHouseResultStore {
+    pub async fn ping(&self) -> anyhow::Result<()> {
+        self.client
+            .query("SELECT 1")
+            .fetch_one::<u8>()
+            .await
+            .with_context(|| format!("clickhouse at {} is unreachable", self.settings.url))?;
+        Ok(())
+    }
+
+    /// ClickHouse counterpart of [`crate::sample_outputs::search`]. Text
+    /// matching is case-sensitive `LIKE`, as in MySQL with a binary collation.
+    pub async fn search_samples(&self, search: &SampleSearch) -> anyhow::Result<SamplePage> {
+        let threshold = search.metric_threshold()?;
+        let limit = search.page_size();
+        let offset = search.offset.max(0);
+
+        let mut sql = format!(
+            "SELECT ?fields FROM {} WHERE run_id = ?",
+            self.settings.samples_table
+        );
+        if search.text().is_some() {
+            sql.push_str(" AND (output LIKE ? OR input LIKE ?)");
+        }
+        if threshold.is_some() {
+            sql.push_str(" AND JSONHas(metrics_json, ?) AND JSONExtractFloat(metrics_json, ?) < ?");
+        }
+        sql.push_str(" ORDER BY sample_index ASC, dataset ASC LIMIT ? OFFSET ?");
+
+        let mut query = self.client.query(&sql).bind(search.run_id.to_string());
+        if let Some(q) = search.text() {
+            let pattern = like_pattern(q);
+            query = query.bind(pattern.clone()).bind(pattern);
+        }
+        if let Some((metric, lt)) = threshold {
+            query = query.bind(metric).bind(metric).bind(lt);
+        }
+        let rows = query
+            .bind(limit + 1)
+            .bind(offset)
+            .fetch_all::<StoredSampleRow>()
+            .await?;
+        let items = rows
+            .into_iter()
+            .map(StoredSampleRow::into_output)
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        Ok(SamplePage::from_rows(items, limit, offset))
+    }
+
+    async fn insert_metrics(&self, records: &[MetricRecord]) -> anyhow::Result<()> {
         #[derive(Row)]
         struct MetricRow<'a> {
             run_id: &'a str,
@@ -92,7 +218,7 @@ impl ResultStore for ClickHouseResultStore {
                     extra_json: record
                         .extra
                         .as_ref()
-                        .map(|v| serde_json::to_string(v).unwrap_or_default())
+                        .map(|v| to_canonical_json(v).unwrap_or_default())
                         .as_deref(),
                 })
                 .await?;
@@ -101,6 +227,304 @@ impl ResultStore for ClickHouseResultStore {
         Ok(())
     }
 
+    /// ClickHouse counterpart of [`crate::metrics::list_by_run`] without
+    /// filters. The metrics table has no `direction` column, so records read
+    /// back carry none and callers fall back to the run config and the
+    /// direction registry.
+    async fn list_metrics(&self, run_id: Uuid) -> anyhow::Result<Vec<MetricRecord>> {
+        #[derive(Row, serde::Deserialize)]
+        struct StoredMetricRow {
+            dataset: String,
+            subset: Option<String>,
+            split: Option<String>,
+            metric_name: String,
+            value: f64,
+            n_samples: Option<i64>,
+            ci_low: Option<f64>,
+            ci_high: Option<f64>,
+            extra_json: Option<String>,
+        }
+
+        let sql = format!(
+            "SELECT ?fields FROM {} FINAL WHERE run_id = ? \
+             ORDER BY dataset, subset, split, metric_name",
+            self.settings.metrics_table
+        );
+        let rows = self
+            .client
+            .query(&sql)
+            .bind(run_id.to_string())
+            .fetch_all::<StoredMetricRow>()
+            .await?;
+        rows.into_iter()
+            .map(|row| {
+                let extra = match row.extra_json.filter(|raw| !raw.trim().is_empty()) {
+                    Some(raw) => Some(serde_json::from_str(&raw).with_context(|| {
+                        format!("invalid extra_json for metric {}", row.metric_name)
+                    })?),
+                    None => None,
+                };
+                Ok(MetricRecord {
+                    run_id,
+                    dataset: row.dataset,
+                    subset: row.subset,
+                    split: row.split,
+                    metric_name: row.metric_name,
+                    value: row.value,
+                    n_samples: row.n_samples,
+                    ci_low: row.ci_low,
+                    ci_high: row.ci_high,
+                    extra,
+                    direction: None,
+                })
+            })
+            .collect()
+    }
+
+    /// ClickHouse counterpart of [`crate::sample_outputs::list_by_run`]. Rows
+    /// sharing a `sample_index` across datasets are ordered by dataset.
+    async fn list_samples(&self, filter: &SampleFilter) -> anyhow::Result<Vec<SampleOutput>> {
+        let mut sql = format!(
+            "SELECT ?fields FROM {} WHERE run_id = ?",
+            self.settings.samples_table
+        );
+        if filter.dataset.is_some() {
+            sql.push_str(" AND dataset = ?");
+        }
+        if filter.split.is_some() {
+            sql.push_str(" AND split = ?");
+        }
+        sql.push_str(" ORDER BY sample_index ASC, dataset ASC");
+        let bounds = page_bounds(filter.limit, filter.offset);
+        if bounds.is_some() {
+            sql.push_str(" LIMIT ? OFFSET ?");
+        }
+
+        let mut query = self.client.query(&sql).bind(filter.run_id.to_string());
+        if let Some(dataset) = &filter.dataset {
+            query = query.bind(dataset.as_str());
+        }
+        if let Some(split) = &filter.split {
+            query = query.bind(split.as_str());
+        }
+        if let Some((limit, offset)) = bounds {
+            query = query.bind(limit).bind(offset);
+        }
+        query
+            .fetch_all::<StoredSampleRow>()
+            .await?
+            .into_iter()
+            .map(StoredSampleRow::into_output)
+            .collect()
+    }
+
+    /// ClickHouse counterpart of [`crate::sample_outputs::get_one`].
+    pub async fn get_sample(
+        &self,
+        run_id: Uuid,
+        sample_index: i64,
+    ) -> anyhow::Result<Option<SampleOutput>> {
+        let sql = format!(
+            "SELECT ?fields FROM {} WHERE run_id = ? AND sample_index = ? ORDER BY dataset ASC LIMIT 1",
+            self.settings.samples_table
+        );
+        let row = self
+            .client
+            .query(&sql)
+            .bind(run_id.to_string())
+            .bind(sample_index)
+            .fetch_optional::<StoredSampleRow>()
+            .await?;
+        row.map(StoredSampleRow::into_output).transpose()
+    }
+
+    /// ClickHouse counterpart of [`crate::sample_outputs::token_summary`].
+    pub async fn token_summary(&self, run_id: Uuid) -> anyhow::Result<TokenSummary> {
+        #[derive(Row, serde::Deserialize)]
+        struct SummaryRow {
+            samples: u64,
+            missing: u64,
+            prompt_tokens: i64,
+            completion_tokens: i64,
+            total_tokens: i64,
+        }
+
+        let sql = format!(
+            "SELECT count() AS samples, \
+             countIf(token_counts_json IS NULL) AS missing, \
+             sum(JSONExtractInt(ifNull(token_counts_json, ''), 'prompt_tokens')) AS prompt_tokens, \
+             sum(JSONExtractInt(ifNull(token_counts_json, ''), 'completion_tokens')) AS completion_tokens, \
+             sum(JSONExtractInt(ifNull(token_counts_json, ''), 'total_tokens')) AS total_tokens \
+             FROM {} WHERE run_id = ?",
+            self.settings.samples_table
+        );
+        let row = self
+            .client
+            .query(&sql)
+            .bind(run_id.to_string())
+            .fetch_one::<SummaryRow>()
+            .await?;
+
+        Ok(TokenSummary::new(
+            row.samples as i64,
+            row.missing as i64,
+            row.prompt_tokens,
+            row.completion_tokens,
+            row.total_tokens,
+        ))
+    }
+
+    /// Counts the run's failed samples by error kind.
+    pub async fn error_summary(&self, run_id: Uuid) -> anyhow::Result<SampleErrorSummary> {
+        #[derive(Row, serde::Deserialize)]
+        struct KindRow {
+            kind: String,
+            failed: u64,
+        }
+
+        let sql = format!(
+            "SELECT JSONExtractString(ifNull(error_json, ''), 'kind') AS kind, count() AS failed \
+             FROM {} WHERE run_id = ? AND error_json IS NOT NULL GROUP BY kind",
+            self.settings.samples_table
+        );
+        let rows = self
+            .client
+            .query(&sql)
+            .bind(run_id.to_string())
+            .fetch_all::<KindRow>()
+            .await?;
+
+        Ok(SampleErrorSummary::from_counts(
+            run_id,
+            rows.into_iter().map(|row| {
+                (
+                    (!row.kind.is_empty()).then_some(row.kind),
+                    row.failed as i64,
+                )
+            }),
+        ))
+    }
+
+    /// ClickHouse counterpart of [`crate::sample_outputs::latency_percentiles`],
+    /// using the native (approximate) `quantiles`.
+    pub async fn latency_percentiles(&self, run_id: Uuid) -> anyhow::Result<LatencyPercentiles> {
+        #[derive(Row, serde::Deserialize)]
+        struct QuantileRow {
+            samples: u64,
+            quantiles: Vec<f64>,
+        }
+
+        let sql = format!(
+            "SELECT count() AS samples, quantiles({})(assumeNotNull(latency_ms)) AS quantiles \
+             FROM {} WHERE run_id = ? AND latency_ms IS NOT NULL AND error_json IS NULL",
+            LATENCY_PERCENTILES.map(|p| p.to_string()).join(", "),
+            self.settings.samples_table
+        );
+        let row = self
+            .client
+            .query(&sql)
+            .bind(run_id.to_string())
+            .fetch_one::<QuantileRow>()
+            .await?;
+        let value = |i: usize| {
+            row.quantiles
+                .get(i)
+                .copied()
+                .filter(|value| row.samples > 0 && value.is_finite())
+        };
+        Ok(LatencyPercentiles {
+            run_id,
+            samples: row.samples as i64,
+            p50_ms: value(0),
+            p95_ms: value(1),
+            p99_ms: value(2),
+        })
+    }
+}
+
+/// A sample row as read back from ClickHouse.
+#[derive(Row, serde::Deserialize)]
+struct StoredSampleRow {
+    run_id: String,
+    dataset: String,
+    subset: Option<String>,
+    split: Option<String>,
+    sample_index: i64,
+    input: String,
+    reference: Option<String>,
+    output: String,
+    metrics_json: Option<String>,
+    latency_ms: Option<i64>,
+    token_counts_json: Option<String>,
+    error_json: Option<String>,
+}
+
+impl StoredSampleRow {
+    /// ClickHouse rows carry no surrogate id or insert time, so `id` is nil
+    /// and `created_at` is the read time. JSON columns that are NULL or empty
+    /// read as `None`; malformed JSON is an error rather than a silent `null`.
+    fn into_output(self) -> anyhow::Result<SampleOutput> {
+        let sample_index = self.sample_index;
+        let parse =
+            |column: &str, raw: Option<String>| -> anyhow::Result<Option<serde_json::Value>> {
+                match raw.filter(|raw| !raw.trim().is_empty()) {
+                    Some(raw) => serde_json::from_str(&raw)
+                        .map(Some)
+                        .with_context(|| format!("invalid {column} for sample {sample_index}")),
+                    None => Ok(None),
+                }
+            };
+        Ok(SampleOutput {
+            id: Uuid::nil(),
+            run_id: Uuid::parse_str(&self.run_id)?,
+            dataset: self.dataset,
+            subset: self.subset,
+            split: self.split,
+            sample_index: self.sample_index,
+            input: self.input,
+            reference: self.reference,
+            output: self.output,
+            metrics: parse("metrics_json", self.metrics_json)?,
+            latency_ms: self.latency_ms,
+            token_counts: parse("token_counts_json", self.token_counts_json)?,
+            error: parse("error_json", self.error_json)?,
+            created_at: chrono::Utc::now(),
+        })
+    }
+}
+
+#[async_trait]
+impl ResultStore for ClickHouseResultStore {
+    /// Metrics are keyed by `(run_id, dataset, subset, split, metric_name)`;
+    /// an existing row with the same key is deleted before inserting, so
+    /// retries don't duplicate. Concurrent writers can still both insert:
+    /// reads use `FINAL`, which only collapses those duplicates when
+    /// `clickhouse.metrics_table` is a `ReplacingMergeTree` ordered by that
+    /// key. This service doesn't create the table, so that is up to its DDL.
+    async fn save_metrics(&self, records: &[MetricRecord]) -> anyhow::Result<()> {
+        self.upsert_metrics(records).await
+    }
+
+    async fn upsert_metrics(&self, records: &[MetricRecord]) -> anyhow::Result<()> {
+        let sql = format!(
+            "DELETE FROM {} WHERE run_id = ? AND dataset = ? AND ifNull(subset, '') = ? \
+             AND ifNull(split, '') = ? AND metric_name = ?",
+            self.settings.metrics_table
+        );
+        for record in records {
+            self.client
+                .query(&sql)
+                .bind(record.run_id.to_string())
+                .bind(&record.dataset)
+                .bind(record.subset.as_deref().unwrap_or(""))
+                .bind(record.split.as_deref().unwrap_or(""))
+                .bind(&record.metric_name)
+                .execute()
+                .await?;
+        }
+        self.insert_metrics(records).await
+    }
+
     async fn save_samples_inline(
         &self,
         records: &[SampleRecord],
@@ -136,18 +560,18 @@ impl ResultStore for ClickHouseResultStore {
                     metrics_json: record
                         .metrics
                         .as_ref()
-                        .map(|v| serde_json::to_string(v).unwrap_or_default())
+                        .map(|v| to_canonical_json(v).unwrap_or_default())
                         .as_deref(),
                     latency_ms: record.latency_ms,
                     token_counts_json: record
                         .token_counts
                         .as_ref()
-                        .map(|v| serde_json::to_string(v).unwrap_or_default())
+                        .map(|v| to_canonical_json(v).unwrap_or_default())
                         .as_deref(),
                     error_json: record
                         .error
                         .as_ref()
-                        .map(|v| serde_json::to_string(v).unwrap_or_default())
+                        .map(|v| to_canonical_json(v).unwrap_or_default())
                         .as_deref(),
                 })
                 .await?;
@@ -166,6 +590,14 @@ impl ResultStore for ClickHouseResultStore {
     ) -> anyhow::Result<()> {
         Ok(())
     }
+
+    async fn read_samples(&self, filter: &SampleFilter) -> anyhow::Result<Vec<SampleOutput>> {
+        self.list_samples(filter).await
+    }
+
+    async fn read_metrics(&self, run_id: Uuid) -> anyhow::Result<Vec<MetricRecord>> {
+        self.list_metrics(run_id).await
+    }
 }
 
 pub struct ObjectStoreResultStore {
@@ -196,8 +628,394 @@ impl ObjectStoreResultStore {
         bucket.set_endpoint(&settings.endpoint)?;
         Ok(Self { settings, bucket })
     }
+
+    pub async fn ping(&self) -> anyhow::Result<()> {
+        if !self.bucket.exists().await? {
+            bail!("bucket {} does not exist", self.settings.bucket);
+        }
+        Ok(())
+    }
+
+    /// Presigned GET URL for the `samples.jsonl` written for `run_id`.
+    pub async fn presign_samples(&self, run_id: Uuid, expiry_secs: u32) -> anyhow::Result<String> {
+        Ok(self
+            .bucket
+            .presign_get(samples_key(run_id), expiry_secs, None)
+            .await?)
+    }
+
+    /// Reads `key` from `bucket` using this store's endpoint and credentials.
+    pub async fn get_object(&self, bucket: &str, key: &str) -> anyhow::Result<Vec<u8>> {
+        let mut source = self.bucket.clone();
+        source.name = bucket.to_string();
+        let response = source.get_object(key).await?;
+        let code = response.status_code();
+        if code >= 300 {
+            bail!("object store returned status {code} for s3://{bucket}/{key}");
+        }
+        Ok(response.bytes().to_vec())
+    }
+
+    /// Reads an `s3://bucket/key` URI.
+    pub async fn get_uri(&self, uri: &str) -> anyhow::Result<Vec<u8>> {
+        let (bucket, key) = uri
+            .strip_prefix("s3://")
+            .and_then(|location| location.split_once('/'))
+            .with_context(|| format!("{uri} is not an s3://bucket/key URI"))?;
+        self.get_object(bucket, key).await
+    }
+
+    /// Splits an `s3://bucket/key` URI, or an `{endpoint}/{bucket}/{key}` URL
+    /// as returned for uploaded samples, into bucket and key.
+    pub fn locate<'a>(&self, uri: &'a str) -> Option<(&'a str, &'a str)> {
+        let endpoint = self.settings.endpoint.trim_end_matches('/');
+        uri.strip_prefix("s3://")
+            .or_else(|| uri.strip_prefix(endpoint)?.strip_prefix('/'))
+            .and_then(|location| location.split_once('/'))
+    }
+
+    /// Size and content type of `key` in `bucket`; `None` when it doesn't
+    /// exist.
+    pub async fn head_object(&self, bucket: &str, key: &str) -> anyhow::Result<Option<ObjectHead>> {
+        let mut source = self.bucket.clone();
+        source.name = bucket.to_string();
+        let (head, code) = source.head_object(key).await?;
+        match code {
+            404 => Ok(None),
+            code if code >= 300 => {
+                bail!("object store returned status {code} for HEAD s3://{bucket}/{key}")
+            }
+            _ => Ok(Some(ObjectHead {
+                size: head.content_length.and_then(|len| u64::try_from(len).ok()),
+                content_type: head.content_type,
+            })),
+        }
+    }
+
+    /// `s3://` URI of `name` under the run's prefix in this store's bucket.
+    pub fn run_object_uri(&self, run_id: Uuid, name: &str) -> String {
+        format!("s3://{}/{}{name}", self.settings.bucket, run_prefix(run_id))
+    }
+
+    /// Uploads a run's full `EvalConfig` JSON to `runs/{run_id}/config.json`
+    /// and returns its `s3://` URI.
+    pub async fn put_config(&self, run_id: Uuid, body: &[u8]) -> anyhow::Result<String> {
+        let key = format!("{}config.json", run_prefix(run_id));
+        self.put_with_retry(&key, body).await?;
+        Ok(format!("s3://{}/{key}", self.settings.bucket))
+    }
+
+    /// Uploads the engine's unparsed `result.json` to
+    /// `runs/{run_id}/result.json` and returns its `s3://` URI.
+    pub async fn put_raw_result(&self, run_id: Uuid, body: &[u8]) -> anyhow::Result<String> {
+        let key = format!("{}{RAW_RESULT_OBJECT}", run_prefix(run_id));
+        self.put_with_retry(&key, body).await?;
+        Ok(format!("s3://{}/{key}", self.settings.bucket))
+    }
+
+    /// Reads back what [`Self::put_raw_result`] stored; `None` when the run
+    /// has no raw result.
+    pub async fn get_raw_result(&self, run_id: Uuid) -> anyhow::Result<Option<Vec<u8>>> {
+        let key = format!("{}{RAW_RESULT_OBJECT}", run_prefix(run_id));
+        if self
+            .head_object(&self.settings.bucket, &key)
+            .await?
+            .is_none()
+        {
+            return Ok(None);
+        }
+        Ok(Some(self.get_object(&self.settings.bucket, &key).await?))
+    }
+
+    /// Reads a page of the samples in the object at `uri` (see
+    /// [`Self::locate`]), in file order, applying `filter`'s dataset, split
+    /// and paging. Only the `jsonl` format is understood. The object is
+    /// fetched in range requests of [`SAMPLES_RANGE_CHUNK`] bytes and reading
+    /// stops once the page is full, so a small page of a huge file only
+    /// downloads its start. Gzip-compressed objects are recognized by their
+    /// magic bytes and decompressed as they stream in. A missing object has
+    /// no samples.
+    pub async fn read_samples_page(
+        &self,
+        uri: &str,
+        format: &str,
+        filter: &SampleFilter,
+    ) -> anyhow::Result<Vec<SampleOutput>> {
+        if format != "jsonl" {
+            bail!("samples at {uri} are in unsupported format {format:?}");
+        }
+        let (bucket, key) = self
+            .locate(uri)
+            .with_context(|| format!("{uri} is not in the object store"))?;
+        let Some(head) = self.head_object(bucket, key).await? else {
+            return Ok(Vec::new());
+        };
+        let mut source = self.bucket.clone();
+        source.name = bucket.to_string();
+        let mut page = JsonlPage::new(filter);
+        let mut decoder: Option<GzDecoder<Vec<u8>>> = None;
+        let mut start = 0;
+        loop {
+            if head.size.is_some_and(|size| start >= size) {
+                break;
+            }
+            let end = start + SAMPLES_RANGE_CHUNK - 1;
+            let end = head.size.map_or(end, |size| end.min(size - 1));
+            let response = source
+                .get_object_range(key, start, (end > start).then_some(end))
+                .await?;
+            match response.status_code() {
+                416 => break,
+                code if code >= 300 => {
+                    bail!("object store returned status {code} for range {start}-{end} of {key}")
+                }
+                _ => {}
+            }
+            let chunk = response.bytes();
+            if chunk.is_empty() {
+                break;
+            }
+            if start == 0 && chunk.starts_with(&GZIP_MAGIC) {
+                decoder = Some(GzDecoder::new(Vec::new()));
+            }
+            let full = match &mut decoder {
+                Some(decoder) => {
+                    decoder
+                        .write_all(chunk)
+                        .with_context(|| format!("failed to decompress {key}"))?;
+                    let data = std::mem::take(decoder.get_mut());
+                    page.push(&data)?
+                }
+                None => page.push(chunk)?,
+            };
+            // A server ignoring the range sends the whole object at once.
+            if full || response.status_code() == 200 {
+                break;
+            }
+            start += chunk.len() as u64;
+        }
+        if let Some(decoder) = &mut decoder {
+            if !page.is_full() {
+                decoder
+                    .try_finish()
+                    .with_context(|| format!("failed to decompress {key}"))?;
+                let data = std::mem::take(decoder.get_mut());
+                page.push(&data)?;
+            }
+        }
+        page.finish()
+    }
+
+    /// Reads every sample of the object at `uri` (see [`Self::locate`]) as
+    /// reported by a runner. Only the `jsonl` format is understood; a
+    /// gzip-compressed object is decompressed.
+    pub async fn read_sample_records(
+        &self,
+        uri: &str,
+        format: &str,
+    ) -> anyhow::Result<Vec<SampleRecord>> {
+        if format != "jsonl" {
+            bail!("samples at {uri} are in unsupported format {format:?}");
+        }
+        let (bucket, key) = self
+            .locate(uri)
+            .with_context(|| format!("{uri} is not in the object store"))?;
+        let mut data = self.get_object(bucket, key).await?;
+        if data.starts_with(&GZIP_MAGIC) {
+            let mut decoder = GzDecoder::new(Vec::new());
+            decoder
+                .write_all(&data)
+                .and_then(|()| decoder.try_finish())
+                .with_context(|| format!("failed to decompress {uri}"))?;
+            data = std::mem::take(decoder.get_mut());
+        }
+        data.split(|byte| *byte == b'\n')
+            .enumerate()
+            .filter(|(_, line)| !line.iter().all(u8::is_ascii_whitespace))
+            .map(|(index, line)| {
+                serde_json::from_slice(line)
+                    .with_context(|| format!("invalid sample on line {} of {uri}", index + 1))
+            })
+            .collect()
+    }
+
+    /// Deletes every object under `runs/{run_id}/` and returns how many were
+    /// removed.
+    pub async fn delete_run_prefix(&self, run_id: Uuid) -> anyhow::Result<usize> {
+        let pages = self.bucket.list(run_prefix(run_id), None).await?;
+        let mut deleted = 0;
+        for object in pages.into_iter().flat_map(|page| page.contents) {
+            self.bucket.delete_object(&object.key).await?;
+            deleted += 1;
+        }
+        Ok(deleted)
+    }
+
+    /// Uploads `body`, retrying 5xx responses and transport errors with
+    /// exponential backoff. 4xx responses are returned immediately.
+    async fn put_with_retry(&self, key: &str, body: &[u8]) -> anyhow::Result<()> {
+        let policy = RetryPolicy {
+            max_attempts: self.settings.upload_max_attempts,
+            backoff: Backoff {
+                base_delay: Duration::from_millis(self.settings.upload_base_delay_ms),
+                max_delay: MAX_BACKOFF,
+                jitter: true,
+            },
+        };
+        upload_with_retry(&policy, key, |_attempt| async move {
+            self.bucket
+                .put_object(key, body)
+                .await
+                .map(|(_, code)| code)
+        })
+        .await
+    }
+}
+
+/// Runs `put`, one upload attempt resolving to the response status, under
+/// `policy`. 5xx responses and transport errors are retried; other statuses
+/// fail at once.
+async fn upload_with_retry<F, Fut, E>(
+    policy: &RetryPolicy,
+    key: &str,
+    mut put: F,
+) -> anyhow::Result<()>
+where
+    F: FnMut(u32) -> Fut,
+    Fut: Future<Output = Result<u16, E>>,
+    E: std::fmt::Display,
+{
+    let upload = |attempt| {
+        let put = put(attempt);
+        async move {
+            match put.await {
+                Ok(code) if code < 300 => Ok(()),
+                Ok(code) => Err(UploadError {
+                    retryable: code >= 500,
+                    message: format!("status {code}"),
+                }),
+                Err(err) => Err(UploadError {
+                    retryable: true,
+                    message: err.to_string(),
+                }),
+            }
+        }
+    };
+    retry_with_backoff(policy, |err: &UploadError| err.retryable, upload)
+        .await
+        .map_err(|err| anyhow::anyhow!("failed to upload {key} to object store: {err}"))
+}
+
+#[derive(Debug, Clone)]
+pub struct ObjectHead {
+    pub size: Option<u64>,
+    pub content_type: Option<String>,
+}
+
+#[derive(Debug)]
+struct UploadError {
+    retryable: bool,
+    message: String,
+}
+
+impl std::fmt::Display for UploadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+/// Collects one page of samples from `samples.jsonl` bytes as they arrive.
+struct JsonlPage<'a> {
+    filter: &'a SampleFilter,
+    /// Matching samples still to skip before the page starts.
+    skip: usize,
+    limit: Option<usize>,
+    /// Bytes of an incomplete trailing line.
+    pending: Vec<u8>,
+    samples: Vec<SampleOutput>,
+}
+
+impl<'a> JsonlPage<'a> {
+    fn new(filter: &'a SampleFilter) -> Self {
+        let (limit, skip) = match page_bounds(filter.limit, filter.offset) {
+            Some((limit, offset)) => (Some(limit as usize), offset as usize),
+            None => (None, 0),
+        };
+        Self {
+            filter,
+            skip,
+            limit,
+            pending: Vec::new(),
+            samples: Vec::new(),
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        self.limit.is_some_and(|limit| self.samples.len() >= limit)
+    }
+
+    /// Parses the complete lines in `pending` plus `data`; returns whether
+    /// the page is full.
+    fn push(&mut self, data: &[u8]) -> anyhow::Result<bool> {
+        self.pending.extend_from_slice(data);
+        while let Some(newline) = self.pending.iter().position(|byte| *byte == b'\n') {
+            let line: Vec<u8> = self.pending.drain(..=newline).collect();
+            self.parse_line(&line)?;
+            if self.is_full() {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// The page, after parsing a final line without a trailing newline.
+    fn finish(mut self) -> anyhow::Result<Vec<SampleOutput>> {
+        if !self.is_full() {
+            let line = std::mem::take(&mut self.pending);
+            self.parse_line(&line)?;
+        }
+        Ok(self.samples)
+    }
+
+    fn parse_line(&mut self, line: &[u8]) -> anyhow::Result<()> {
+        if line.iter().all(u8::is_ascii_whitespace) {
+            return Ok(());
+        }
+        let record: SampleRecord =
+            serde_json::from_slice(line).context("invalid line in samples.jsonl")?;
+        let excluded = matches!(&self.filter.dataset, Some(dataset) if *dataset != record.dataset)
+            || matches!(&self.filter.split, Some(split) if record.split.as_ref() != Some(split));
+        if excluded {
+            return Ok(());
+        }
+        if self.skip > 0 {
+            self.skip -= 1;
+        } else {
+            self.samples.push(SampleOutput::from_record(record));
+        }
+        Ok(())
+    }
+}
+
+/// Bytes fetched per range request by
+/// [`ObjectStoreResultStore::read_samples_page`].
+pub const SAMPLES_RANGE_CHUNK: u64 = 1 << 20;
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Name of a run's raw engine output under its object-store prefix.
+pub const RAW_RESULT_OBJECT: &str = "result.json";
+
+fn run_prefix(run_id: Uuid) -> String {
+    format!("runs/{run_id}/")
 }
 
+fn samples_key(run_id: Uuid) -> String {
+    format!("{}samples.jsonl", run_prefix(run_id))
+}
+
+const MAX_BACKOFF: Duration = Duration::from_secs(10);
+
 #[async_trait]
 impl ResultStore for ObjectStoreResultStore {
     async fn save_metrics(&self, records: &[MetricRecord]) -> anyhow::Result<()> {
@@ -205,6 +1023,10 @@ impl ResultStore for ObjectStoreResultStore {
         anyhow::bail!("ObjectStoreResultStore does not support metrics");
     }
 
+    async fn upsert_metrics(&self, _records: &[MetricRecord]) -> anyhow::Result<()> {
+        anyhow::bail!("ObjectStoreResultStore does not support metrics");
+    }
+
     async fn save_samples_inline(
         &self,
         records: &[SampleRecord],
@@ -213,19 +1035,16 @@ impl ResultStore for ObjectStoreResultStore {
             .first()
             .map(|r| r.run_id)
             .unwrap_or_else(Uuid::new_v4);
-        let key = format!("runs/{run_id}/samples.jsonl");
+        let key = samples_key(run_id);
         let mut body = Vec::new();
         for record in records {
             writeln!(
                 body,
                 "{}",
-                serde_json::to_string(record).unwrap_or_else(|_| "{}".into())
+                to_canonical_json(record).unwrap_or_else(|_| "{}".into())
             )?;
         }
-        let (_, code) = self.bucket.put_object(&key, &body).await?;
-        if code >= 300 {
-            bail!("failed to upload samples to object store (status {code})");
-        }
+        self.put_with_retry(&key, &body).await?;
         let base = format!(
             "{}/{}",
             self.settings.endpoint.trim_end_matches('/'),
@@ -245,12 +1064,102 @@ impl ResultStore for ObjectStoreResultStore {
     ) -> anyhow::Result<()> {
         Ok(())
     }
+
+    /// Reads the run's `samples.jsonl` under its prefix, where
+    /// [`ResultStore::save_samples_inline`] puts it; samples a runner
+    /// uploaded elsewhere are read through [`ResultStoreHandles::read_samples`].
+    async fn read_samples(&self, filter: &SampleFilter) -> anyhow::Result<Vec<SampleOutput>> {
+        let uri = self.run_object_uri(filter.run_id, "samples.jsonl");
+        self.read_samples_page(&uri, "jsonl", filter).await
+    }
+}
+
+/// The stores [`StoreRoutes::for_output`] picks from, as trait objects so
+/// other implementations (e.g. the in-memory stores of `mock_store`) can
+/// stand in for MySQL, ClickHouse and S3.
+#[derive(Clone)]
+pub struct StoreRoutes {
+    /// Metrics by default, and always the run's sample location.
+    pub db: Arc<dyn ResultStore>,
+    pub clickhouse: Option<Arc<dyn ResultStore>>,
+    pub object_store: Option<Arc<dyn ResultStore>>,
+    /// Samples headed for the DB are spilled to `object_store`, when there
+    /// is one, once a run has more than this many.
+    pub max_inline_samples: Option<usize>,
+}
+
+impl StoreRoutes {
+    /// Resolves the store a run's results go to. Optional stores that are not
+    /// configured fall back to the DB, with a warning. A configured store
+    /// that is down is never swapped out: writes to it fail and so does the
+    /// run.
+    pub fn for_output(&self, output: &OutputConfig) -> Arc<dyn ResultStore> {
+        if let Some(kind) = output.requires_store() {
+            let configured = match kind {
+                ResultStoreKind::ClickHouse => self.clickhouse.is_some(),
+                ResultStoreKind::ObjectStore => self.object_store.is_some(),
+            };
+            if !configured {
+                tracing::warn!("{kind} is not configured; writing results to the DB instead");
+            }
+        }
+        let db = self.db.clone();
+        let clickhouse = self.clickhouse.clone();
+        let object_store = self.object_store.clone();
+
+        let (metrics, samples) = match output {
+            OutputConfig::DbOnly => (db.clone(), db),
+            OutputConfig::ObjectStore { .. } => (db.clone(), object_store.unwrap_or(db)),
+            OutputConfig::ClickHouse { .. } => {
+                let store = clickhouse.unwrap_or(db);
+                (store.clone(), store)
+            }
+            OutputConfig::Hybrid { .. } => (db.clone(), clickhouse.unwrap_or(db)),
+        };
+        let spill = match (&self.object_store, self.max_inline_samples) {
+            (Some(store), Some(max_inline_samples)) if self.samples_in_db(output) => {
+                Some(SampleSpill {
+                    max_inline_samples,
+                    store: store.clone(),
+                })
+            }
+            _ => None,
+        };
+        Arc::new(RoutedResultStore {
+            metrics,
+            samples,
+            locations: self.db.clone(),
+            spill,
+        })
+    }
+
+    /// Whether samples of runs with this output config end up in the DB.
+    pub fn samples_in_db(&self, output: &OutputConfig) -> bool {
+        match output {
+            OutputConfig::DbOnly => true,
+            OutputConfig::ObjectStore { .. } => self.object_store.is_none(),
+            OutputConfig::ClickHouse { .. } | OutputConfig::Hybrid { .. } => {
+                self.clickhouse.is_none()
+            }
+        }
+    }
 }
 
+#[derive(Clone)]
 pub struct ResultStoreHandles {
-    pub db: Arc<DbResultStore>,
+    /// The concrete stores, for what goes beyond [`ResultStore`]: pings, the
+    /// direction registry, ClickHouse aggregates and object uploads. All
+    /// `None` when built with [`ResultStoreHandles::from_routes`].
+    pub db: Option<Arc<DbResultStore>>,
     pub clickhouse: Option<Arc<ClickHouseResultStore>>,
     pub object_store: Option<Arc<ObjectStoreResultStore>>,
+    /// What results are written through; built from the stores above by
+    /// [`ResultStoreHandles::new`].
+    pub routes: StoreRoutes,
+    pub bootstrap: BootstrapSettings,
+    /// Derive extra metrics before they are saved; see
+    /// [`MetricPostProcessors`].
+    pub post_processors: MetricPostProcessors,
 }
 
 impl ResultStoreHandles {
@@ -260,8 +1169,15 @@ impl ResultStoreHandles {
     ) -> anyhow::Result<Self> {
         let db_store = Arc::new(DbResultStore { db });
         let clickhouse = settings.clickhouse.as_ref().map(|cfg| {
+            // Same as `ClickHouseClient::default()`, plus a connect timeout.
+            let mut connector = HttpConnector::new();
+            connector.set_keepalive(Some(Duration::from_secs(60)));
+            connector.set_connect_timeout(Some(Duration::from_secs(cfg.connect_timeout_seconds)));
+            let http = HyperClient::builder(TokioExecutor::new())
+                .pool_idle_timeout(Duration::from_secs(2))
+                .build(connector);
             Arc::new(ClickHouseResultStore {
-                client: ClickHouseClient::default()
+                client: ClickHouseClient::with_http_client(http)
                     .with_url(&cfg.url)
                     .with_database(&cfg.database)
                     .with_user(
@@ -277,73 +1193,718 @@ impl ResultStoreHandles {
             None => None,
         };
 
+        let routes = StoreRoutes {
+            db: db_store.clone(),
+            clickhouse: clickhouse
+                .clone()
+                .map(|store| store as Arc<dyn ResultStore>),
+            object_store: object_store
+                .clone()
+                .map(|store| store as Arc<dyn ResultStore>),
+            max_inline_samples: settings
+                .object_store
+                .as_ref()
+                .map(|cfg| cfg.max_inline_samples),
+        };
         Ok(ResultStoreHandles {
-            db: db_store,
+            db: Some(db_store),
             clickhouse,
             object_store,
+            routes,
+            bootstrap: settings.bootstrap.clone(),
+            post_processors: MetricPostProcessors::with_builtins(),
         })
     }
 
+    /// Pings every configured optional store and returns the ones that are
+    /// unreachable, so callers can log or refuse to start.
+    pub async fn check_stores(&self) -> Vec<(ResultStoreKind, anyhow::Error)> {
+        let mut failures = Vec::new();
+        if let Some(clickhouse) = &self.clickhouse {
+            if let Err(err) = clickhouse.ping().await {
+                failures.push((ResultStoreKind::ClickHouse, err));
+            }
+        }
+        if let Some(object_store) = &self.object_store {
+            if let Err(err) = object_store.ping().await {
+                failures.push((ResultStoreKind::ObjectStore, err));
+            }
+        }
+        failures
+    }
+
+    /// Handles writing only through `routes`, e.g. in-memory stores in
+    /// tests, without settings or a DB pool. Without the concrete stores,
+    /// metric directions come from the run config and the built-in names
+    /// only.
+    pub fn from_routes(routes: StoreRoutes, bootstrap: BootstrapSettings) -> Self {
+        ResultStoreHandles {
+            db: None,
+            clickhouse: None,
+            object_store: None,
+            routes,
+            bootstrap,
+            post_processors: MetricPostProcessors::with_builtins(),
+        }
+    }
+
+    /// The store a run's results go to; see [`StoreRoutes::for_output`].
+    pub fn for_output(&self, output: &OutputConfig) -> Arc<dyn ResultStore> {
+        self.routes.for_output(output)
+    }
+
+    /// Whether samples of runs with this output config end up in MySQL.
+    pub fn samples_in_db(&self, output: &OutputConfig) -> bool {
+        self.routes.samples_in_db(output)
+    }
+
+    /// The store to read a run's samples from. A run whose samples were
+    /// spilled to the object store records that location even though its
+    /// output config points at the DB.
+    pub fn samples_store(&self, run: &crate::runs::Run) -> Arc<dyn ResultStore> {
+        match (&run.samples_location, &self.routes.object_store) {
+            (Some(SampleResultLocation::ObjectStore { .. }), Some(store)) => store.clone(),
+            _ => self.for_output(&run.output()),
+        }
+    }
+
+    /// Reads a page of the run's samples. Samples in the object store are
+    /// read from the location and in the format recorded for them, which
+    /// fails for formats other than `jsonl`; the rest come from
+    /// [`Self::samples_store`].
+    pub async fn read_samples(
+        &self,
+        run: &crate::runs::Run,
+        filter: &SampleFilter,
+    ) -> anyhow::Result<Vec<SampleOutput>> {
+        match (&run.samples_location, &self.object_store) {
+            (Some(SampleResultLocation::ObjectStore { uri, format }), Some(store)) => {
+                store.read_samples_page(uri, format, filter).await
+            }
+            _ => self.samples_store(run).read_samples(filter).await,
+        }
+    }
+
+    /// Stores the run's metrics, including those derived by post-processors,
+    /// and its samples. `result` is first put in canonical order (see
+    /// [`EvalResult::sort_canonical`]), so identical runs persist identically.
+    /// A failing post-processor fails with a
+    /// [`crate::post_processors::PostProcessError`] before anything is
+    /// stored. Returns how many of the stored metrics are outside the canary
+    /// subsets.
     pub async fn persist_eval_result(
         &self,
         config: &EvalConfig,
-        result: &EvalResult,
+        result: &mut EvalResult,
+    ) -> anyhow::Result<usize> {
+        let store = self.for_output(&config.output);
+        result.sort_canonical();
+        let mut metrics = result.metrics.clone();
+        metrics.extend(self.post_processors.apply(&config.metrics, result)?);
+        let directions = match &self.db {
+            Some(db) => crate::metrics::direction_registry(&db.db).await?,
+            None => crate::metrics::DirectionRegistry::default(),
+        };
+        directions.fill(&mut metrics, &config.metrics);
+        if let (true, SampleResultLocation::Inline { samples }) =
+            (self.bootstrap.enabled, &result.samples)
+        {
+            crate::metrics::fill_bootstrap_cis(
+                &mut metrics,
+                samples,
+                self.bootstrap.confidence,
+                self.bootstrap.iterations,
+            );
+        }
+        // Derived metrics were appended after the engine's.
+        sort_metrics(&mut metrics);
+        store.save_metrics(&metrics).await?;
+        store.finalize_metrics(result.run_id).await?;
+        let location = match &result.samples {
+            SampleResultLocation::Inline { samples } => store.save_samples_inline(samples).await?,
+            location => location.clone(),
+        };
+        store
+            .save_samples_location(result.run_id, &location)
+            .await?;
+        Ok(metrics
+            .iter()
+            .filter(|record| !record.subset.as_deref().is_some_and(is_canary_subset))
+            .count())
+    }
+
+    /// What the post-processors derive for finished run `run` once `ingested`
+    /// records are attached to it. They see the run's stored metrics, with
+    /// `ingested` over those sharing a key, and its samples where the store
+    /// can read them back.
+    pub async fn derive_ingested(
+        &self,
+        run: &crate::runs::Run,
+        configs: &[MetricConfig],
+        ingested: &[MetricRecord],
+    ) -> anyhow::Result<Vec<MetricRecord>> {
+        let key = |record: &MetricRecord| {
+            (
+                record.dataset.clone(),
+                record.subset.clone(),
+                record.split.clone(),
+                record.metric_name.clone(),
+            )
+        };
+        let replaced: std::collections::HashSet<_> = ingested.iter().map(key).collect();
+        let mut metrics = self.for_output(&run.output()).read_metrics(run.id).await?;
+        metrics.retain(|record| !replaced.contains(&key(record)));
+        metrics.extend_from_slice(ingested);
+        let samples = match &run.samples_location {
+            None | Some(SampleResultLocation::None) => SampleResultLocation::None,
+            Some(_) => SampleResultLocation::Inline {
+                samples: self
+                    .read_samples(run, &SampleFilter::for_run(run.id))
+                    .await?
+                    .into_iter()
+                    .map(SampleOutput::into_record)
+                    .collect::<Result<Vec<_>, _>>()?,
+            },
+        };
+        let result = EvalResult {
+            run_id: run.id,
+            status: run.status,
+            started_at: run.started_at.unwrap_or(run.created_at),
+            completed_at: run.finished_at.unwrap_or(run.created_at),
+            metrics,
+            samples,
+            error: None,
+            metadata: None,
+        };
+        Ok(self.post_processors.apply(configs, &result)?)
+    }
+
+    /// Copies a finished run's metrics and samples from the stores they were
+    /// written to into the stores of `target` and records the new sample
+    /// location. The source copies are left in place. Samples kept as
+    /// object-store blobs can't be read back, so such runs are refused.
+    pub async fn migrate_run(
+        &self,
+        run: &crate::runs::Run,
+        target: &OutputConfig,
+    ) -> anyhow::Result<MigratedResults> {
+        let samples_source: Option<Arc<dyn ResultStore>> = match &run.samples_location {
+            None | Some(SampleResultLocation::None) => None,
+            Some(SampleResultLocation::Inline { .. }) => Some(self.routes.db.clone()),
+            Some(SampleResultLocation::ClickHouse { .. }) => Some(
+                self.routes
+                    .clickhouse
+                    .clone()
+                    .context("the run's samples are in ClickHouse, which is not configured")?,
+            ),
+            Some(SampleResultLocation::ObjectStore { uri, .. }) => {
+                bail!("samples stored as an object ({uri}) can't be read back for migration")
+            }
+        };
+
+        let metrics = self.for_output(&run.output()).read_metrics(run.id).await?;
+        let store = self.for_output(target);
+        store.save_metrics(&metrics).await?;
+
+        let mut migrated = MigratedResults {
+            metrics: metrics.len(),
+            samples: 0,
+        };
+        let Some(source) = samples_source else {
+            return Ok(migrated);
+        };
+        let samples = source
+            .read_samples(&SampleFilter::for_run(run.id))
+            .await?
+            .into_iter()
+            .map(SampleOutput::into_record)
+            .collect::<Result<Vec<_>, _>>()?;
+        if samples.is_empty() {
+            return Ok(migrated);
+        }
+        let location = store.save_samples_inline(&samples).await?;
+        store.save_samples_location(run.id, &location).await?;
+        migrated.samples = samples.len();
+        Ok(migrated)
+    }
+}
+
+/// What [`ResultStoreHandles::migrate_run`] copied.
+#[derive(Debug, Clone, Copy, serde::Serialize)]
+pub struct MigratedResults {
+    pub metrics: usize,
+    pub samples: usize,
+}
+
+/// Sends metrics and samples to the stores chosen for a run's
+/// `OutputConfig`. Sample locations are always recorded on the run in MySQL.
+pub struct RoutedResultStore {
+    pub metrics: Arc<dyn ResultStore>,
+    pub samples: Arc<dyn ResultStore>,
+    pub locations: Arc<dyn ResultStore>,
+    pub spill: Option<SampleSpill>,
+}
+
+/// Where [`RoutedResultStore`] writes samples instead of the DB once a run
+/// has more than `max_inline_samples`.
+pub struct SampleSpill {
+    pub max_inline_samples: usize,
+    pub store: Arc<dyn ResultStore>,
+}
+
+#[async_trait]
+impl ResultStore for RoutedResultStore {
+    async fn save_metrics(&self, records: &[MetricRecord]) -> anyhow::Result<()> {
+        self.metrics.save_metrics(records).await
+    }
+
+    async fn upsert_metrics(&self, records: &[MetricRecord]) -> anyhow::Result<()> {
+        self.metrics.upsert_metrics(records).await
+    }
+
+    async fn save_samples_inline(
+        &self,
+        records: &[SampleRecord],
+    ) -> anyhow::Result<SampleResultLocation> {
+        match &self.spill {
+            Some(spill) if records.len() > spill.max_inline_samples => {
+                tracing::info!(
+                    "spilling {} samples to the object store (more than {})",
+                    records.len(),
+                    spill.max_inline_samples
+                );
+                spill.store.save_samples_inline(records).await
+            }
+            _ => self.samples.save_samples_inline(records).await,
+        }
+    }
+
+    async fn save_samples_location(
+        &self,
+        run_id: Uuid,
+        location: &SampleResultLocation,
     ) -> anyhow::Result<()> {
-        self.save_metrics(config, result).await?;
-        self.save_samples(config, result).await?;
-        Ok(())
+        self.locations.save_samples_location(run_id, location).await
     }
 
-    async fn save_metrics(&self, config: &EvalConfig, result: &EvalResult) -> anyhow::Result<()> {
-        match config.output {
-            OutputConfig::ClickHouse { .. } => {
-                if let Some(ch) = &self.clickhouse {
-                    ch.save_metrics(&result.metrics).await
-                } else {
-                    self.db.save_metrics(&result.metrics).await
+    async fn read_samples(&self, filter: &SampleFilter) -> anyhow::Result<Vec<SampleOutput>> {
+        self.samples.read_samples(filter).await
+    }
+
+    async fn read_metrics(&self, run_id: Uuid) -> anyhow::Result<Vec<MetricRecord>> {
+        self.metrics.read_metrics(run_id).await
+    }
+
+    async fn save_partial_metrics(&self, records: &[MetricRecord]) -> anyhow::Result<()> {
+        self.metrics.save_partial_metrics(records).await
+    }
+
+    async fn finalize_metrics(&self, run_id: Uuid) -> anyhow::Result<()> {
+        self.metrics.finalize_metrics(run_id).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_store::{MockStores, StoreCall};
+    use unified_shared::eval::{
+        DatasetConfig, DatasetSource, EvalEngine, ModelConfig, RunStatus, TaskConfig, TaskType,
+    };
+
+    fn metric(run_id: Uuid) -> MetricRecord {
+        MetricRecord {
+            run_id,
+            dataset: "gsm8k".into(),
+            subset: None,
+            split: None,
+            metric_name: "accuracy".into(),
+            value: 0.5,
+            n_samples: None,
+            ci_low: None,
+            ci_high: None,
+            extra: None,
+            direction: None,
+        }
+    }
+
+    fn samples(run_id: Uuid, count: i64) -> Vec<SampleRecord> {
+        (0..count)
+            .map(|sample_index| SampleRecord {
+                run_id,
+                dataset: "gsm8k".into(),
+                subset: None,
+                split: None,
+                sample_index,
+                input: "1 + 1".into(),
+                reference: Some("2".into()),
+                output: "2".into(),
+                metrics: None,
+                latency_ms: None,
+                token_counts: None,
+                error: None,
+            })
+            .collect()
+    }
+
+    fn config() -> EvalConfig {
+        EvalConfig::builder()
+            .project_id(Uuid::new_v4())
+            .engine(EvalEngine::LmEvalHarness)
+            .model(ModelConfig {
+                logical_name: "base".into(),
+                provider: "hf".into(),
+                model_name: "gpt2".into(),
+                endpoint: None,
+                api_key_ref: None,
+                extra: None,
+            })
+            .dataset(DatasetConfig {
+                source: DatasetSource::BuiltIn,
+                name: "gsm8k".into(),
+                split: None,
+                uri: None,
+                filters: None,
+                no_reference: false,
+            })
+            .task(TaskConfig {
+                task_type: TaskType::Qa,
+                task_name: "gsm8k".into(),
+                args: serde_json::Value::Null,
+                harness_args: Vec::new(),
+            })
+            .build()
+            .unwrap()
+    }
+
+    /// Writes one metric, `sample_count` samples and their location through
+    /// the store `routes` picks for `output`.
+    async fn write(routes: &StoreRoutes, output: &OutputConfig, sample_count: i64) {
+        let run_id = Uuid::new_v4();
+        let store = routes.for_output(output);
+        store.save_metrics(&[metric(run_id)]).await.unwrap();
+        let location = store
+            .save_samples_inline(&samples(run_id, sample_count))
+            .await
+            .unwrap();
+        store.save_samples_location(run_id, &location).await.unwrap();
+    }
+
+    fn location_calls(calls: &[StoreCall]) -> usize {
+        calls
+            .iter()
+            .filter(|call| matches!(call, StoreCall::SaveSamplesLocation(..)))
+            .count()
+    }
+
+    fn clickhouse_output() -> OutputConfig {
+        OutputConfig::ClickHouse {
+            table: "metrics".into(),
+        }
+    }
+
+    #[tokio::test]
+    async fn db_only_writes_everything_to_the_db() {
+        let stores = MockStores::new(true, true);
+        write(&stores.routes, &OutputConfig::DbOnly, 2).await;
+        assert_eq!(stores.db.metrics().len(), 1);
+        assert_eq!(stores.db.samples().len(), 2);
+        assert_eq!(location_calls(&stores.db.calls()), 1);
+        assert!(stores.clickhouse.calls().is_empty());
+        assert!(stores.object_store.calls().is_empty());
+    }
+
+    #[tokio::test]
+    async fn clickhouse_output_keeps_only_the_location_in_the_db() {
+        let stores = MockStores::new(true, false);
+        write(&stores.routes, &clickhouse_output(), 2).await;
+        assert_eq!(stores.clickhouse.metrics().len(), 1);
+        assert_eq!(stores.clickhouse.samples().len(), 2);
+        assert_eq!(location_calls(&stores.clickhouse.calls()), 0);
+        assert_eq!(stores.db.calls().len(), 1);
+        assert_eq!(location_calls(&stores.db.calls()), 1);
+    }
+
+    #[tokio::test]
+    async fn unconfigured_clickhouse_falls_back_to_the_db() {
+        let stores = MockStores::new(false, false);
+        write(&stores.routes, &clickhouse_output(), 2).await;
+        assert_eq!(stores.db.metrics().len(), 1);
+        assert_eq!(stores.db.samples().len(), 2);
+        assert!(stores.clickhouse.calls().is_empty());
+    }
+
+    #[tokio::test]
+    async fn hybrid_output_keeps_metrics_in_the_db() {
+        let stores = MockStores::new(true, false);
+        let output = OutputConfig::Hybrid {
+            ch_table: "samples".into(),
+            samples_uri: None,
+        };
+        write(&stores.routes, &output, 2).await;
+        assert_eq!(stores.db.metrics().len(), 1);
+        assert!(stores.db.samples().is_empty());
+        assert!(stores.clickhouse.metrics().is_empty());
+        assert_eq!(stores.clickhouse.samples().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn object_store_output_writes_samples_to_the_object_store() {
+        let stores = MockStores::new(false, true);
+        let output = OutputConfig::ObjectStore {
+            samples_uri: "s3://bucket/samples".into(),
+            format: "jsonl".into(),
+        };
+        write(&stores.routes, &output, 2).await;
+        assert_eq!(stores.db.metrics().len(), 1);
+        assert!(stores.db.samples().is_empty());
+        assert_eq!(stores.object_store.samples().len(), 2);
+        assert!(stores.object_store.metrics().is_empty());
+    }
+
+    /// `metric_type: "doubled"`: twice each `accuracy` record.
+    struct Doubled;
+
+    impl crate::post_processors::MetricPostProcessor for Doubled {
+        fn name(&self) -> &'static str {
+            "doubled"
+        }
+
+        fn check(&self, _config: &MetricConfig) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn process(
+            &self,
+            config: &MetricConfig,
+            result: &EvalResult,
+        ) -> Result<Vec<MetricRecord>, String> {
+            Ok(result
+                .metrics
+                .iter()
+                .filter(|record| record.metric_name == "accuracy")
+                .map(|record| MetricRecord {
+                    metric_name: config.name.clone(),
+                    value: record.value * 2.0,
+                    ..record.clone()
+                })
+                .collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn registered_post_processors_are_persisted() {
+        let stores = MockStores::new(false, false);
+        let mut handles =
+            ResultStoreHandles::from_routes(stores.routes.clone(), BootstrapSettings::default());
+        handles.post_processors.register(Arc::new(Doubled));
+        let mut config = config();
+        config.metrics.push(MetricConfig {
+            name: "accuracy_x2".into(),
+            metric_type: "doubled".into(),
+            params: None,
+            direction: None,
+            reference_free: None,
+            aggregation: None,
+        });
+        let mut result = EvalResult {
+            run_id: config.run_id,
+            status: RunStatus::Completed,
+            started_at: chrono::Utc::now(),
+            completed_at: chrono::Utc::now(),
+            metrics: vec![metric(config.run_id)],
+            samples: SampleResultLocation::None,
+            error: None,
+            metadata: None,
+        };
+        handles
+            .persist_eval_result(&config, &mut result)
+            .await
+            .unwrap();
+        let saved = stores.db.metrics();
+        let derived = saved
+            .iter()
+            .find(|record| record.metric_name == "accuracy_x2")
+            .unwrap();
+        assert_eq!(derived.value, 1.0);
+        assert_eq!(saved.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn db_samples_spill_past_max_inline_samples() {
+        let mut stores = MockStores::new(false, true);
+        stores.routes.max_inline_samples = Some(2);
+        write(&stores.routes, &OutputConfig::DbOnly, 2).await;
+        assert_eq!(stores.db.samples().len(), 2);
+        assert!(stores.object_store.samples().is_empty());
+
+        write(&stores.routes, &OutputConfig::DbOnly, 3).await;
+        assert_eq!(stores.db.samples().len(), 2);
+        assert_eq!(stores.object_store.samples().len(), 3);
+        assert_eq!(location_calls(&stores.db.calls()), 2);
+    }
+
+    /// Persists a result with `sample_count` inline samples through
+    /// `stores` and returns the samples location recorded on the run.
+    async fn persisted_location(stores: &MockStores, sample_count: i64) -> SampleResultLocation {
+        let handles =
+            ResultStoreHandles::from_routes(stores.routes.clone(), BootstrapSettings::default());
+        let config = config();
+        let mut result = EvalResult {
+            run_id: config.run_id,
+            status: RunStatus::Completed,
+            started_at: chrono::Utc::now(),
+            completed_at: chrono::Utc::now(),
+            metrics: vec![metric(config.run_id)],
+            samples: SampleResultLocation::Inline {
+                samples: samples(config.run_id, sample_count),
+            },
+            error: None,
+            metadata: None,
+        };
+        handles
+            .persist_eval_result(&config, &mut result)
+            .await
+            .unwrap();
+        stores
+            .db
+            .calls()
+            .into_iter()
+            .find_map(|call| match call {
+                StoreCall::SaveSamplesLocation(run_id, location) if run_id == config.run_id => {
+                    Some(location)
                 }
+                _ => None,
+            })
+            .expect("no samples location recorded")
+    }
+
+    #[tokio::test]
+    async fn persisted_runs_record_where_their_samples_went() {
+        let mut stores = MockStores::new(false, true);
+        stores.routes.max_inline_samples = Some(2);
+
+        match persisted_location(&stores, 2).await {
+            SampleResultLocation::Inline { samples } => assert_eq!(samples.len(), 2),
+            other => panic!("a small run should stay inline, got {other:?}"),
+        }
+
+        match persisted_location(&stores, 3).await {
+            SampleResultLocation::ObjectStore { uri, format } => {
+                assert!(uri.ends_with("/samples.jsonl"), "{uri}");
+                assert_eq!(format, "jsonl");
             }
-            OutputConfig::Hybrid { .. }
-            | OutputConfig::DbOnly
-            | OutputConfig::ObjectStore { .. } => self.db.save_metrics(&result.metrics).await,
+            other => panic!("a large run should spill, got {other:?}"),
         }
+        assert_eq!(stores.object_store.samples().len(), 3);
     }
 
-    async fn save_samples(&self, config: &EvalConfig, result: &EvalResult) -> anyhow::Result<()> {
-        match (&config.output, &result.samples) {
-            (_, SampleResultLocation::Inline { samples }) => match config.output {
-                OutputConfig::DbOnly => {
-                    self.db.save_samples_inline(samples).await?;
-                }
-                OutputConfig::ObjectStore { .. } => {
-                    if let Some(obj) = &self.object_store {
-                        obj.save_samples_inline(samples).await?;
-                    } else {
-                        self.db.save_samples_inline(samples).await?;
-                    }
-                }
-                OutputConfig::ClickHouse { .. } => {
-                    if let Some(ch) = &self.clickhouse {
-                        ch.save_samples_inline(samples).await?;
-                    } else {
-                        self.db.save_samples_inline(samples).await?;
-                    }
-                }
-                OutputConfig::Hybrid { .. } => {
-                    if let Some(ch) = &self.clickhouse {
-                        ch.save_samples_inline(samples).await?;
-                    } else {
-                        self.db.save_samples_inline(samples).await?;
-                    }
-                }
-            },
-            (_, location) => {
-                self.db
-                    .save_samples_location(result.run_id, location)
-                    .await?;
+    /// Pages `records`, written as `samples.jsonl`, through [`JsonlPage`]
+    /// in small chunks that split lines, returning the sample indices.
+    fn jsonl_page(records: &[SampleRecord], filter: &SampleFilter) -> Vec<i64> {
+        let jsonl: String = records
+            .iter()
+            .map(|record| serde_json::to_string(record).unwrap() + "\n")
+            .collect();
+        let mut page = JsonlPage::new(filter);
+        for chunk in jsonl.as_bytes().chunks(7) {
+            if page.push(chunk).unwrap() {
+                break;
             }
         }
+        page.finish()
+            .unwrap()
+            .iter()
+            .map(|sample| sample.sample_index)
+            .collect()
+    }
 
-        Ok(())
+    #[test]
+    fn object_store_samples_are_filtered_and_paged() {
+        let run_id = Uuid::new_v4();
+        let mut records = samples(run_id, 6);
+        for record in &mut records[3..] {
+            record.dataset = "mmlu".into();
+        }
+        for record in records.iter_mut().step_by(2) {
+            record.split = Some("test".into());
+        }
+        let all = SampleFilter::for_run(run_id);
+        assert_eq!(jsonl_page(&records, &all), [0, 1, 2, 3, 4, 5]);
+
+        let mmlu = SampleFilter {
+            dataset: Some("mmlu".into()),
+            ..all.clone()
+        };
+        assert_eq!(jsonl_page(&records, &mmlu), [3, 4, 5]);
+        let test = SampleFilter {
+            split: Some("test".into()),
+            ..all.clone()
+        };
+        assert_eq!(jsonl_page(&records, &test), [0, 2, 4]);
+        let both = SampleFilter {
+            split: Some("test".into()),
+            ..mmlu.clone()
+        };
+        assert_eq!(jsonl_page(&records, &both), [4]);
+
+        // Offsets count matching samples only.
+        let paged = SampleFilter {
+            limit: Some(2),
+            offset: Some(1),
+            ..test
+        };
+        assert_eq!(jsonl_page(&records, &paged), [2, 4]);
+        let last = SampleFilter {
+            limit: Some(2),
+            offset: Some(2),
+            ..mmlu
+        };
+        assert_eq!(jsonl_page(&records, &last), [5]);
+    }
+
+    /// Uploads through a bucket answering each attempt with the next of
+    /// `responses`, returning the outcome and the number of attempts made.
+    async fn upload(responses: &[Result<u16, &str>]) -> (anyhow::Result<()>, usize) {
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            backoff: Backoff {
+                base_delay: Duration::ZERO,
+                max_delay: Duration::ZERO,
+                jitter: false,
+            },
+        };
+        let mut responses = responses.iter().copied();
+        let attempts = std::cell::Cell::new(0);
+        let result = upload_with_retry(&policy, "runs/1/samples.jsonl", |_attempt| {
+            attempts.set(attempts.get() + 1);
+            let response = responses.next().expect("no response left");
+            async move { response }
+        })
+        .await;
+        (result, attempts.get())
+    }
+
+    #[tokio::test]
+    async fn uploads_retry_through_server_errors() {
+        let (result, attempts) = upload(&[Ok(503), Ok(503), Ok(200)]).await;
+        result.unwrap();
+        assert_eq!(attempts, 3);
+
+        let (result, attempts) = upload(&[Err("connection reset"), Ok(200)]).await;
+        result.unwrap();
+        assert_eq!(attempts, 2);
+    }
+
+    #[tokio::test]
+    async fn uploads_fail_on_client_errors_and_exhaustion() {
+        let (result, attempts) = upload(&[Ok(403)]).await;
+        assert_eq!(attempts, 1);
+        assert!(result.unwrap_err().to_string().contains("status 403"));
+
+        let (result, attempts) = upload(&[Ok(503), Ok(502), Ok(500)]).await;
+        assert_eq!(attempts, 3);
+        let err = result.unwrap_err().to_string();
+        assert!(err.contains("runs/1/samples.jsonl") && err.contains("status 500"));
     }
 }
@@ -1,12 +1,25 @@
-use crate::db::DbPool;
-use crate::utils::parse_uuid;
+use crate::db::{db_error, DbPool};
+use crate::result_store::ObjectStoreResultStore;
+use crate::utils::{
+    canonical_json_hash, diff_json, json_column, optional_json_column, parse_uuid, JsonDiff,
+    ParsedConfig, DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE,
+};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use sqlx::mysql::MySqlRow;
-use sqlx::Row;
-use unified_shared::error::DomainError;
-use unified_shared::eval::{EvalErrorKind, EvalErrorPayload, RunStatus};
+use sqlx::types::Json;
+use sqlx::{MySql, QueryBuilder, Row, Transaction};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+use unified_shared::error::{DomainError, FieldErrors};
+use unified_shared::eval::{
+    ConfigReference, EvalConfig, EvalEngine, EvalErrorKind, EvalErrorPayload, OutputConfig,
+    QueuedRun, RunStatus, SampleResultLocation, ENGINE_VERSION_WARNING_KEY,
+    RESULT_CHECKSUM_METADATA_KEY,
+};
 use uuid::Uuid;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -20,9 +33,34 @@ pub struct Run {
     pub run_type: String,
     pub status: RunStatus,
     pub error: Option<EvalErrorPayload>,
+    pub created_at: DateTime<Utc>,
     pub started_at: Option<DateTime<Utc>>,
     pub finished_at: Option<DateTime<Utc>>,
-    pub eval_config: Value,
+    /// The full config, or for referenced configs a stub holding only the
+    /// run's ids, `engine`, `model`, `models` and `output`. Kept raw so
+    /// fields this build doesn't know survive; private so it can't change
+    /// under the [`Run::parsed_config`] cache.
+    eval_config: Value,
+    /// Object-store location of the full config when it was too large to
+    /// inline.
+    pub config_uri: Option<String>,
+    /// See [`compile_hash`]; `None` for runs not created by a compile.
+    pub compile_hash: Option<String>,
+    /// Where the run's samples were written; `None` until results are persisted.
+    pub samples_location: Option<SampleResultLocation>,
+    /// `EvalResult::metadata` reported by the engine, once results are persisted.
+    pub result_metadata: Option<Value>,
+    /// SHA-256 of the `result.json` the results were read from, when the
+    /// engine reported one.
+    pub result_checksum: Option<String>,
+    /// Completed with an `error`: some sub-tasks failed and only the metrics
+    /// of the finished ones were stored.
+    pub partial: bool,
+    /// Runs that must complete before this one is queued; see
+    /// [`RunStatus::Blocked`].
+    pub depends_on: Vec<Uuid>,
+    #[serde(skip)]
+    parsed_config: ParsedConfig,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -35,10 +73,230 @@ pub struct NewRun {
     pub run_type: String,
     pub status: RunStatus,
     pub eval_config: Value,
+    #[serde(default)]
+    pub compile_hash: Option<String>,
+    /// Runs of the same project that must complete first. The run is created
+    /// `Blocked` unless they all have already.
+    #[serde(default)]
+    pub depends_on: Vec<Uuid>,
+}
+
+/// Identifies a compile request: the same model impl, checkpoint, task, run
+/// type and config always hash the same, whatever the JSON key order.
+pub fn compile_hash(
+    model_impl_id: &Uuid,
+    checkpoint_id: &Uuid,
+    task_id: &Uuid,
+    run_type: &str,
+    eval_config: &Value,
+) -> String {
+    canonical_json_hash(&serde_json::json!({
+        "model_impl_id": model_impl_id,
+        "checkpoint_id": checkpoint_id,
+        "task_id": task_id,
+        "run_type": run_type,
+        "eval_config": eval_config,
+    }))
+}
+
+impl Run {
+    /// The stored config as an [`EvalConfig`], parsed on first call. Fails
+    /// with `Unprocessable` when it doesn't match the struct, or when the
+    /// run's config is stored by reference and `eval_config` is only a stub.
+    pub fn parsed_config(&self) -> Result<&EvalConfig, DomainError> {
+        if let Some(config_uri) = &self.config_uri {
+            return Err(DomainError::Unprocessable(format!(
+                "config of run {} is stored at {config_uri}",
+                self.id
+            )));
+        }
+        self.parsed_config
+            .get(&self.eval_config, || format!("run {}", self.id))
+    }
+
+    /// The stored config as is; see the `eval_config` field.
+    pub fn eval_config(&self) -> &Value {
+        &self.eval_config
+    }
+
+    /// Replaces the stored config, dropping the parsed one.
+    pub fn set_eval_config(&mut self, eval_config: Value) {
+        self.eval_config = eval_config;
+        self.parsed_config = ParsedConfig::default();
+    }
+
+    /// `logical_name`s of a multi-model run's members, read from the stored
+    /// config (stubs of referenced configs keep `model` and `models`); empty
+    /// for single-model runs.
+    pub fn member_names(&self) -> Vec<String> {
+        let logical_name = |model: &Value| {
+            model
+                .get("logical_name")
+                .and_then(Value::as_str)
+                .map(str::to_string)
+        };
+        match self.eval_config.get("models").and_then(Value::as_array) {
+            Some(models) if !models.is_empty() => self
+                .eval_config
+                .get("model")
+                .into_iter()
+                .chain(models)
+                .filter_map(logical_name)
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// The run's `OutputConfig`, from the parsed config or else the stub's
+    /// `output`, falling back to `DbOnly` when neither has one that parses.
+    pub fn output(&self) -> OutputConfig {
+        if let Ok(config) = self.parsed_config() {
+            return config.output.clone();
+        }
+        self.eval_config
+            .get("output")
+            .cloned()
+            .and_then(|output| serde_json::from_value(output).ok())
+            .unwrap_or(OutputConfig::DbOnly)
+    }
+
+    /// The payload pushed onto the run queue for this run. An inline config
+    /// must parse as an [`EvalConfig`] naming each of its models once and
+    /// pairing no reference-requiring metric with a dataset without
+    /// references, so a mismatch is reported here rather than by the worker
+    /// that pops it.
+    pub fn queue_payload(&self) -> Result<String, DomainError> {
+        let payload = match &self.config_uri {
+            Some(config_uri) => serde_json::to_string(&QueuedRun::Reference(ConfigReference {
+                run_id: self.id,
+                config_uri: config_uri.clone(),
+            })),
+            None => {
+                let config = self.parsed_config()?;
+                config
+                    .check_members()
+                    .and_then(|()| config.check_references())
+                    .map_err(DomainError::Unprocessable)?;
+                serde_json::to_string(&QueuedRun::Inline(Box::new(config.clone())))
+            }
+        };
+        payload.map_err(|e| DomainError::Internal(e.to_string()))
+    }
+}
+
+const RUN_COLUMNS: &str = "id, experiment_id, project_id, model_impl_id, checkpoint_id, task_id, run_type, status, error_kind, error_code, error_message, error_engine, error_details_json, created_at, started_at, finished_at, eval_config_json, config_uri, compile_hash, samples_location_json, result_metadata_json, result_checksum, \
+    (SELECT JSON_ARRAYAGG(d.depends_on_run_id) FROM run_dependencies d WHERE d.run_id = runs.id) AS depends_on_json";
+
+/// One recorded status change of a run, as written by [`update_status`].
+#[derive(Debug, Clone, Serialize)]
+pub struct StatusTransition {
+    pub from_status: Option<RunStatus>,
+    pub to_status: RunStatus,
+    pub error_kind: Option<String>,
+    pub at: DateTime<Utc>,
+}
+
+/// Optional filters for [`search`]; `None` fields are not constrained.
+#[derive(Debug, Clone)]
+pub struct RunFilter {
+    pub project_id: Uuid,
+    pub status: Option<RunStatus>,
+    pub model_impl_id: Option<Uuid>,
+    pub checkpoint_id: Option<Uuid>,
+    pub task_id: Option<Uuid>,
+    pub created_after: Option<DateTime<Utc>>,
+    pub created_before: Option<DateTime<Utc>>,
+    /// Page size; with neither `limit` nor `cursor` every match is returned.
+    pub limit: Option<i64>,
+    /// Continue after this run, in `(created_at, id)` descending order.
+    pub cursor: Option<RunCursor>,
+}
+
+/// Filters for [`search_global`], which lists runs of every project and is
+/// always paged. An empty `statuses` matches any status.
+#[derive(Debug, Clone, Default)]
+pub struct GlobalRunFilter {
+    pub statuses: Vec<RunStatus>,
+    pub engine: Option<EvalEngine>,
+    pub created_after: Option<DateTime<Utc>>,
+    pub created_before: Option<DateTime<Utc>>,
+    pub limit: Option<i64>,
+    pub cursor: Option<RunCursor>,
+}
+
+impl GlobalRunFilter {
+    pub fn page_size(&self) -> i64 {
+        self.limit
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE)
+    }
+}
+
+/// Keyset position for [`search`]. Runs compiled together share
+/// `created_at`, so the id breaks ties. Encoded as `{created_at micros}_{id}`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RunCursor {
+    pub created_at: DateTime<Utc>,
+    pub id: Uuid,
+}
+
+impl From<&Run> for RunCursor {
+    fn from(run: &Run) -> Self {
+        Self {
+            created_at: run.created_at,
+            id: run.id,
+        }
+    }
+}
+
+impl fmt::Display for RunCursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}_{}", self.created_at.timestamp_micros(), self.id)
+    }
+}
+
+impl FromStr for RunCursor {
+    type Err = DomainError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || DomainError::Validation(format!("invalid run cursor {value:?}"));
+        let (micros, id) = value.split_once('_').ok_or_else(invalid)?;
+        let micros = micros.parse::<i64>().map_err(|_| invalid())?;
+        Ok(Self {
+            created_at: DateTime::from_timestamp_micros(micros).ok_or_else(invalid)?,
+            id: Uuid::parse_str(id).map_err(|_| invalid())?,
+        })
+    }
+}
+
+impl RunFilter {
+    pub fn for_project(project_id: Uuid) -> Self {
+        Self {
+            project_id,
+            status: None,
+            model_impl_id: None,
+            checkpoint_id: None,
+            task_id: None,
+            created_after: None,
+            created_before: None,
+            limit: None,
+            cursor: None,
+        }
+    }
+
+    /// The clamped page size, or `None` when the listing is unpaged.
+    pub fn page_size(&self) -> Option<i64> {
+        (self.limit.is_some() || self.cursor.is_some()).then(|| {
+            self.limit
+                .unwrap_or(DEFAULT_PAGE_SIZE)
+                .clamp(1, MAX_PAGE_SIZE)
+        })
+    }
 }
 
 fn status_to_str(status: RunStatus) -> &'static str {
     match status {
+        RunStatus::Blocked => "blocked",
         RunStatus::Queued => "queued",
         RunStatus::Running => "running",
         RunStatus::Completed => "completed",
@@ -50,24 +308,29 @@ fn status_to_str(status: RunStatus) -> &'static str {
     }
 }
 
-fn status_from_str(value: &str) -> RunStatus {
+pub fn parse_status(value: &str) -> Result<RunStatus, DomainError> {
     match value {
-        "queued" => RunStatus::Queued,
-        "running" => RunStatus::Running,
-        "completed" => RunStatus::Completed,
-        "failed_config" => RunStatus::FailedConfig,
-        "failed_engine" => RunStatus::FailedEngine,
-        "failed_infra" => RunStatus::FailedInfra,
-        "timed_out" => RunStatus::TimedOut,
-        "cancelled" => RunStatus::Cancelled,
-        _ => RunStatus::Queued,
+        "blocked" => Ok(RunStatus::Blocked),
+        "queued" => Ok(RunStatus::Queued),
+        "running" => Ok(RunStatus::Running),
+        "completed" => Ok(RunStatus::Completed),
+        "failed_config" => Ok(RunStatus::FailedConfig),
+        "failed_engine" => Ok(RunStatus::FailedEngine),
+        "failed_infra" => Ok(RunStatus::FailedInfra),
+        "timed_out" => Ok(RunStatus::TimedOut),
+        "cancelled" => Ok(RunStatus::Cancelled),
+        other => Err(DomainError::Validation(format!(
+            "unknown run status: {other}"
+        ))),
     }
 }
 
+fn status_from_str(value: &str) -> RunStatus {
+    parse_status(value).unwrap_or(RunStatus::Queued)
+}
+
 fn row_to_run(row: &MySqlRow) -> Result<Run, DomainError> {
-    let eval_config: String = row.try_get("eval_config_json")?;
-    let eval_value: Value =
-        serde_json::from_str(&eval_config).map_err(|e| DomainError::Internal(e.to_string()))?;
+    let eval_value = json_column(row, "eval_config_json")?;
 
     let error_kind = row.try_get::<Option<String>, _>("error_kind")?;
     let error_message = row.try_get::<Option<String>, _>("error_message")?;
@@ -90,6 +353,7 @@ fn row_to_run(row: &MySqlRow) -> Result<Run, DomainError> {
             .map(|raw| serde_json::from_str(&raw).unwrap_or(Value::Null)),
     });
 
+    let status = status_from_str(row.try_get::<String, _>("status")?.as_str());
     Ok(Run {
         id: parse_uuid(row.try_get::<String, _>("id")?.as_str())?,
         experiment_id: parse_uuid(row.try_get::<String, _>("experiment_id")?.as_str())?,
@@ -98,30 +362,145 @@ fn row_to_run(row: &MySqlRow) -> Result<Run, DomainError> {
         checkpoint_id: parse_uuid(row.try_get::<String, _>("checkpoint_id")?.as_str())?,
         task_id: parse_uuid(row.try_get::<String, _>("task_id")?.as_str())?,
         run_type: row.try_get("run_type")?,
-        status: status_from_str(row.try_get::<String, _>("status")?.as_str()),
+        status,
+        partial: matches!(status, RunStatus::Completed) && error.is_some(),
         error,
+        created_at: row.try_get("created_at")?,
         started_at: row.try_get("started_at")?,
         finished_at: row.try_get("finished_at")?,
         eval_config: eval_value,
+        config_uri: row.try_get("config_uri")?,
+        compile_hash: row.try_get("compile_hash")?,
+        samples_location: row
+            .try_get::<Option<String>, _>("samples_location_json")?
+            .map(|raw| serde_json::from_str(&raw))
+            .transpose()
+            .map_err(|e| DomainError::Internal(e.to_string()))?,
+        result_metadata: optional_json_column(row, "result_metadata_json")?,
+        result_checksum: row.try_get("result_checksum")?,
+        depends_on: optional_json_column(row, "depends_on_json")?
+            .map(serde_json::from_value)
+            .transpose()
+            .map_err(|e| DomainError::Internal(e.to_string()))?
+            .unwrap_or_default(),
+        parsed_config: ParsedConfig::default(),
     })
 }
 
 pub async fn list(pool: &DbPool, project_id: &Uuid) -> Result<Vec<Run>, DomainError> {
-    let rows = sqlx::query("SELECT id, experiment_id, project_id, model_impl_id, checkpoint_id, task_id, run_type, status, error_kind, error_code, error_message, error_engine, error_details_json, started_at, finished_at, eval_config_json FROM runs WHERE project_id = ? ORDER BY created_at DESC")
-        .bind(project_id.to_string())
+    search(pool, RunFilter::for_project(*project_id)).await
+}
+
+pub async fn search(pool: &DbPool, filter: RunFilter) -> Result<Vec<Run>, DomainError> {
+    let rows = search_query(&filter)
+        .build()
         .fetch_all(pool)
         .await
-        .map_err(|e| DomainError::Internal(e.to_string()))?;
+        .map_err(db_error)?;
 
     rows.iter().map(row_to_run).collect()
 }
 
+/// The query behind [`search`]: each set filter adds one bound condition.
+fn search_query(filter: &RunFilter) -> QueryBuilder<'static, MySql> {
+    let mut query: QueryBuilder<MySql> = QueryBuilder::new(format!(
+        "SELECT {RUN_COLUMNS} FROM runs WHERE project_id = "
+    ));
+    query.push_bind(filter.project_id.to_string());
+    if let Some(status) = filter.status {
+        query
+            .push(" AND status = ")
+            .push_bind(status_to_str(status));
+    }
+    if let Some(model_impl_id) = filter.model_impl_id {
+        query
+            .push(" AND model_impl_id = ")
+            .push_bind(model_impl_id.to_string());
+    }
+    if let Some(checkpoint_id) = filter.checkpoint_id {
+        query
+            .push(" AND checkpoint_id = ")
+            .push_bind(checkpoint_id.to_string());
+    }
+    if let Some(task_id) = filter.task_id {
+        query.push(" AND task_id = ").push_bind(task_id.to_string());
+    }
+    push_created_window(
+        &mut query,
+        filter.created_after,
+        filter.created_before,
+        filter.cursor,
+    );
+    query.push(" ORDER BY created_at DESC, id DESC");
+    if let Some(page_size) = filter.page_size() {
+        query.push(" LIMIT ").push_bind(page_size);
+    }
+    query
+}
+
+/// Runs across all projects, newest first, for operator views. Served by
+/// the `(status, created_at, id)` and `(created_at, id)` indexes; `engine`
+/// matches the generated `runs.engine` column.
+pub async fn search_global(
+    pool: &DbPool,
+    filter: &GlobalRunFilter,
+) -> Result<Vec<Run>, DomainError> {
+    let mut query: QueryBuilder<MySql> =
+        QueryBuilder::new(format!("SELECT {RUN_COLUMNS} FROM runs WHERE 1 = 1"));
+    if !filter.statuses.is_empty() {
+        query.push(" AND status IN (");
+        let mut separated = query.separated(", ");
+        for status in &filter.statuses {
+            separated.push_bind(status_to_str(*status));
+        }
+        query.push(")");
+    }
+    if let Some(engine) = &filter.engine {
+        query.push(" AND engine = ").push_bind(engine.label());
+    }
+    push_created_window(
+        &mut query,
+        filter.created_after,
+        filter.created_before,
+        filter.cursor,
+    );
+    query
+        .push(" ORDER BY created_at DESC, id DESC LIMIT ")
+        .push_bind(filter.page_size());
+
+    let rows = query.build().fetch_all(pool).await.map_err(db_error)?;
+
+    rows.iter().map(row_to_run).collect()
+}
+
+fn push_created_window(
+    query: &mut QueryBuilder<MySql>,
+    after: Option<DateTime<Utc>>,
+    before: Option<DateTime<Utc>>,
+    cursor: Option<RunCursor>,
+) {
+    if let Some(after) = after {
+        query.push(" AND created_at >= ").push_bind(after);
+    }
+    if let Some(before) = before {
+        query.push(" AND created_at < ").push_bind(before);
+    }
+    if let Some(cursor) = cursor {
+        query
+            .push(" AND (created_at, id) < (")
+            .push_bind(cursor.created_at)
+            .push(", ")
+            .push_bind(cursor.id.to_string())
+            .push(")");
+    }
+}
+
 pub async fn get(pool: &DbPool, id: &Uuid) -> Result<Run, DomainError> {
-    let row = sqlx::query("SELECT id, experiment_id, project_id, model_impl_id, checkpoint_id, task_id, run_type, status, error_kind, error_code, error_message, error_engine, error_details_json, started_at, finished_at, eval_config_json FROM runs WHERE id = ?")
+    let row = sqlx::query(&format!("SELECT {RUN_COLUMNS} FROM runs WHERE id = ?"))
         .bind(id.to_string())
         .fetch_optional(pool)
         .await
-        .map_err(|e| DomainError::Internal(e.to_string()))?;
+        .map_err(db_error)?;
 
     match row {
         Some(row) => row_to_run(&row),
@@ -129,7 +508,37 @@ pub async fn get(pool: &DbPool, id: &Uuid) -> Result<Run, DomainError> {
     }
 }
 
+/// The runs among `ids` that exist, in the order of `ids`.
+pub async fn list_by_ids(pool: &DbPool, ids: &[Uuid]) -> Result<Vec<Run>, DomainError> {
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut query: QueryBuilder<MySql> =
+        QueryBuilder::new(format!("SELECT {RUN_COLUMNS} FROM runs WHERE id IN ("));
+    let mut separated = query.separated(", ");
+    for id in ids {
+        separated.push_bind(id.to_string());
+    }
+    separated.push_unseparated(")");
+
+    let rows = query.build().fetch_all(pool).await.map_err(db_error)?;
+    let mut runs = rows.iter().map(row_to_run).collect::<Result<Vec<_>, _>>()?;
+    runs.sort_by_key(|run| ids.iter().position(|id| *id == run.id));
+    Ok(runs)
+}
+
 pub async fn create(pool: &DbPool, payload: NewRun) -> Result<Run, DomainError> {
+    create_with_store(pool, payload, None).await
+}
+
+/// Like [`create`], but a config whose JSON exceeds the object store's
+/// `max_inline_config_bytes` is uploaded there first; the row then keeps only
+/// a stub plus `config_uri`, and the queue carries a [`ConfigReference`].
+pub async fn create_with_store(
+    pool: &DbPool,
+    payload: NewRun,
+    object_store: Option<&ObjectStoreResultStore>,
+) -> Result<Run, DomainError> {
     let id = Uuid::new_v4();
     let mut eval_config = payload.eval_config;
     if let Some(map) = eval_config.as_object_mut() {
@@ -138,11 +547,59 @@ pub async fn create(pool: &DbPool, payload: NewRun) -> Result<Run, DomainError>
             "project_id".into(),
             Value::String(payload.project_id.to_string()),
         );
+        // Aliases such as `lm_eval_harness` are stored as the variant name,
+        // which the generated `engine` column exposes to engine filters.
+        if let Some(engine) = map
+            .get("engine")
+            .and_then(|engine| serde_json::from_value::<EvalEngine>(engine.clone()).ok())
+        {
+            let engine =
+                serde_json::to_value(engine).map_err(|e| DomainError::Internal(e.to_string()))?;
+            map.insert("engine".into(), engine);
+        }
     }
     let eval_config_str =
         serde_json::to_string(&eval_config).map_err(|e| DomainError::Internal(e.to_string()))?;
 
-    sqlx::query("INSERT INTO runs (id, experiment_id, project_id, model_impl_id, checkpoint_id, task_id, run_type, status, eval_config_json, created_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, NOW())")
+    let mut config_uri = None;
+    if let Some(store) =
+        object_store.filter(|store| eval_config_str.len() > store.settings.max_inline_config_bytes)
+    {
+        let uri = store
+            .put_config(id, eval_config_str.as_bytes())
+            .await
+            .map_err(|e| DomainError::Internal(format!("failed to store run config: {e}")))?;
+        let mut stub = serde_json::Map::new();
+        for field in [
+            "run_id",
+            "project_id",
+            "experiment_id",
+            "engine",
+            "model",
+            "models",
+            "output",
+        ] {
+            if let Some(value) = eval_config.get(field) {
+                stub.insert(field.into(), value.clone());
+            }
+        }
+        eval_config = Value::Object(stub);
+        config_uri = Some(uri);
+    }
+    let created_at = Utc::now();
+
+    let mut depends_on = payload.depends_on;
+    depends_on.sort();
+    depends_on.dedup();
+    let mut status = payload.status;
+    let mut tx = pool.begin().await.map_err(db_error)?;
+    if !depends_on.is_empty()
+        && !check_dependencies(&mut tx, &payload.project_id, &depends_on).await?
+    {
+        status = RunStatus::Blocked;
+    }
+
+    sqlx::query("INSERT INTO runs (id, experiment_id, project_id, model_impl_id, checkpoint_id, task_id, run_type, status, eval_config_json, config_uri, compile_hash, created_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)")
         .bind(id.to_string())
         .bind(payload.experiment_id.to_string())
         .bind(payload.project_id.to_string())
@@ -150,11 +607,23 @@ pub async fn create(pool: &DbPool, payload: NewRun) -> Result<Run, DomainError>
         .bind(payload.checkpoint_id.to_string())
         .bind(payload.task_id.to_string())
         .bind(&payload.run_type)
-        .bind(status_to_str(payload.status))
-        .bind(eval_config_str)
-        .execute(pool)
+        .bind(status_to_str(status))
+        .bind(Json(&eval_config))
+        .bind(&config_uri)
+        .bind(&payload.compile_hash)
+        .bind(created_at)
+        .execute(&mut *tx)
         .await
-        .map_err(|e| DomainError::Internal(e.to_string()))?;
+        .map_err(db_error)?;
+    for dependency in &depends_on {
+        sqlx::query("INSERT INTO run_dependencies (run_id, depends_on_run_id) VALUES (?, ?)")
+            .bind(id.to_string())
+            .bind(dependency.to_string())
+            .execute(&mut *tx)
+            .await
+            .map_err(db_error)?;
+    }
+    tx.commit().await.map_err(db_error)?;
 
     Ok(Run {
         id,
@@ -164,29 +633,377 @@ pub async fn create(pool: &DbPool, payload: NewRun) -> Result<Run, DomainError>
         checkpoint_id: payload.checkpoint_id,
         task_id: payload.task_id,
         run_type: payload.run_type,
-        status: payload.status,
+        status,
         error: None,
+        created_at,
         started_at: None,
         finished_at: None,
         eval_config,
+        config_uri,
+        compile_hash: payload.compile_hash,
+        samples_location: None,
+        result_metadata: None,
+        result_checksum: None,
+        partial: false,
+        depends_on,
+        parsed_config: ParsedConfig::default(),
     })
 }
 
+/// Validates the dependencies of a new run of `project_id` and returns
+/// whether they have all completed. Unknown runs, runs of other projects and
+/// runs that already failed or were cancelled are rejected.
+async fn check_dependencies(
+    tx: &mut Transaction<'_, MySql>,
+    project_id: &Uuid,
+    depends_on: &[Uuid],
+) -> Result<bool, DomainError> {
+    let mut query: QueryBuilder<MySql> =
+        QueryBuilder::new("SELECT id, project_id, status FROM runs WHERE id IN (");
+    let mut ids = query.separated(", ");
+    for id in depends_on {
+        ids.push_bind(id.to_string());
+    }
+    ids.push_unseparated(")");
+    let rows = query.build().fetch_all(&mut **tx).await.map_err(db_error)?;
+
+    let mut found = HashMap::new();
+    for row in &rows {
+        let id: String = row.try_get("id")?;
+        let project: String = row.try_get("project_id")?;
+        let status: String = row.try_get("status")?;
+        found.insert(id, (project, status_from_str(&status)));
+    }
+    let mut errors = FieldErrors::new();
+    let mut completed = true;
+    for id in depends_on {
+        match found.get(&id.to_string()) {
+            None => errors.push("depends_on", format!("run {id} not found")),
+            Some((project, _)) if *project != project_id.to_string() => {
+                errors.push("depends_on", format!("run {id} belongs to another project"))
+            }
+            Some((_, RunStatus::Completed)) => {}
+            Some((_, status)) if status.is_terminal() => errors.push(
+                "depends_on",
+                format!("run {id} already finished as {}", status_to_str(*status)),
+            ),
+            Some(_) => completed = false,
+        }
+    }
+    errors.finish()?;
+    Ok(completed)
+}
+
+/// The most recently created run of the experiment for each compile hash.
+pub async fn latest_by_compile_hash(
+    pool: &DbPool,
+    experiment_id: &Uuid,
+) -> Result<HashMap<String, Run>, DomainError> {
+    let rows = sqlx::query(&format!(
+        "SELECT {RUN_COLUMNS} FROM runs WHERE experiment_id = ? AND compile_hash IS NOT NULL \
+         ORDER BY created_at ASC, id ASC"
+    ))
+    .bind(experiment_id.to_string())
+    .fetch_all(pool)
+    .await
+    .map_err(db_error)?;
+
+    let mut latest = HashMap::new();
+    for row in &rows {
+        let run = row_to_run(row)?;
+        if let Some(hash) = run.compile_hash.clone() {
+            latest.insert(hash, run);
+        }
+    }
+    Ok(latest)
+}
+
+/// Replaces the `output` of the run's stored config, e.g. after its results
+/// were migrated to another store. For configs stored by reference only the
+/// stub is updated, which is what [`Run::output`] reads.
+pub async fn set_output(
+    pool: &DbPool,
+    id: &Uuid,
+    output: &OutputConfig,
+) -> Result<(), DomainError> {
+    let raw = serde_json::to_string(output).map_err(|e| DomainError::Internal(e.to_string()))?;
+    let result = sqlx::query(
+        "UPDATE runs SET eval_config_json = JSON_SET(eval_config_json, '$.output', CAST(? AS JSON)), updated_at = NOW() WHERE id = ?",
+    )
+    .bind(raw)
+    .bind(id.to_string())
+    .execute(pool)
+    .await
+    .map_err(db_error)?;
+    if result.rows_affected() == 0 {
+        return Err(DomainError::NotFound("run not found".into()));
+    }
+    Ok(())
+}
+
+/// Records where the run's samples were written. Inline samples live in
+/// `sample_outputs`, so only their mode is stored, not the records.
+pub async fn set_samples_location(
+    pool: &DbPool,
+    id: &Uuid,
+    location: &SampleResultLocation,
+) -> Result<(), DomainError> {
+    let stored = match location {
+        SampleResultLocation::Inline { .. } => SampleResultLocation::Inline {
+            samples: Vec::new(),
+        },
+        other => other.clone(),
+    };
+    let raw = serde_json::to_string(&stored).map_err(|e| DomainError::Internal(e.to_string()))?;
+
+    let result =
+        sqlx::query("UPDATE runs SET samples_location_json = ?, updated_at = NOW() WHERE id = ?")
+            .bind(raw)
+            .bind(id.to_string())
+            .execute(pool)
+            .await
+            .map_err(db_error)?;
+    if result.rows_affected() == 0 {
+        return Err(DomainError::NotFound("run not found".into()));
+    }
+    Ok(())
+}
+
+/// Stores the metadata the engine reported with the run's result, and the
+/// `result.json` checksum it carries under [`RESULT_CHECKSUM_METADATA_KEY`].
+pub async fn set_result_metadata(
+    pool: &DbPool,
+    id: &Uuid,
+    metadata: &Value,
+) -> Result<(), DomainError> {
+    let checksum = metadata
+        .get(RESULT_CHECKSUM_METADATA_KEY)
+        .and_then(Value::as_str);
+    sqlx::query(
+        "UPDATE runs SET result_metadata_json = ?, result_checksum = ?, updated_at = NOW() WHERE id = ?",
+    )
+        .bind(Json(metadata))
+        .bind(checksum)
+        .bind(id.to_string())
+        .execute(pool)
+        .await
+        .map_err(db_error)?;
+    Ok(())
+}
+
+/// Fields `create` writes into every run's config; they always differ
+/// between runs, so [`config_diff`] ignores them.
+pub const INJECTED_CONFIG_FIELDS: &[&str] = &["run_id", "project_id"];
+
+/// How the `eval_config` of `right` differs from that of `left`, leaving out
+/// [`INJECTED_CONFIG_FIELDS`].
+pub fn config_diff(left: &Value, right: &Value) -> Vec<JsonDiff> {
+    let normalize = |config: &Value| {
+        let mut config = config.clone();
+        if let Some(map) = config.as_object_mut() {
+            for field in INJECTED_CONFIG_FIELDS {
+                map.remove(*field);
+            }
+        }
+        config
+    };
+    diff_json(&normalize(left), &normalize(right))
+}
+
+/// What determines a run's outputs and whether it can be repeated exactly.
+#[derive(Debug, Clone, Serialize)]
+pub struct Reproducibility {
+    pub run_id: Uuid,
+    /// The seed the engine reported using, else the one requested.
+    pub seed: Option<u64>,
+    pub engine_version: Option<String>,
+    /// Hash of the config fields that determine outputs; see
+    /// [`OUTPUT_DETERMINING_FIELDS`].
+    pub config_hash: String,
+    pub library_versions: Option<Value>,
+    pub reproducible: bool,
+    pub warnings: Vec<String>,
+}
+
+/// Config fields that affect what a run produces. Ids, resources, output
+/// routing and free-form metadata are left out of the reproducibility hash.
+pub const OUTPUT_DETERMINING_FIELDS: &[&str] = &[
+    "engine",
+    "engine_version",
+    "model",
+    "dataset",
+    "task",
+    "metrics",
+    "sampling",
+];
+
+/// Builds the reproducibility report of `run` from its full `eval_config`
+/// (which differs from `run.eval_config()` for configs stored by reference).
+pub fn reproducibility(run: &Run, eval_config: &Value) -> Reproducibility {
+    let determining: serde_json::Map<String, Value> = OUTPUT_DETERMINING_FIELDS
+        .iter()
+        .filter_map(|field| Some((field.to_string(), eval_config.get(field)?.clone())))
+        .collect();
+    let reported = |key: &str| run.result_metadata.as_ref().and_then(|m| m.get(key));
+    let requested_seed = eval_config
+        .pointer("/sampling/seed")
+        .and_then(Value::as_u64);
+    let seed = reported("seed").and_then(Value::as_u64).or(requested_seed);
+    let engine_version = reported("engine_version")
+        .or_else(|| eval_config.get("engine_version"))
+        .and_then(Value::as_str)
+        .map(str::to_string);
+
+    let mut warnings = Vec::new();
+    let mut reproducible = true;
+    match (requested_seed, seed) {
+        (_, None) => {
+            reproducible = false;
+            warnings.push("no sampling seed was set, so the run is not reproducible".to_string());
+        }
+        (Some(requested), Some(effective)) if requested != effective => {
+            reproducible = false;
+            warnings.push(format!(
+                "seed {requested} was requested but the engine used {effective}"
+            ));
+        }
+        _ => {}
+    }
+    if let Some(warning) = reported(ENGINE_VERSION_WARNING_KEY).and_then(Value::as_str) {
+        reproducible = false;
+        warnings.push(warning.to_string());
+    }
+    if engine_version.is_none() {
+        warnings.push(
+            "engine_version is not pinned; other engine versions may produce different outputs"
+                .to_string(),
+        );
+    }
+
+    Reproducibility {
+        run_id: run.id,
+        seed,
+        engine_version,
+        config_hash: canonical_json_hash(&Value::Object(determining)),
+        library_versions: reported("library_versions").cloned(),
+        reproducible,
+        warnings,
+    }
+}
+
+/// Which projects' runs [`list_reapable`] considers.
+#[derive(Debug, Clone, Copy)]
+pub enum ProjectScope<'a> {
+    Only(&'a Uuid),
+    /// Every project except these.
+    Except(&'a [Uuid]),
+}
+
+/// Terminal runs in `statuses` of the projects in `scope` that finished
+/// before `finished_before` and whose artifacts haven't been reaped yet,
+/// oldest first. Only runs last claimed by `worker_id`, or never claimed,
+/// are listed.
+pub async fn list_reapable(
+    pool: &DbPool,
+    worker_id: &str,
+    statuses: &[RunStatus],
+    scope: ProjectScope<'_>,
+    finished_before: DateTime<Utc>,
+    limit: i64,
+) -> Result<Vec<Run>, DomainError> {
+    let mut query: QueryBuilder<MySql> = QueryBuilder::new(format!(
+        "SELECT {RUN_COLUMNS} FROM runs WHERE artifacts_reaped_at IS NULL AND finished_at < "
+    ));
+    query
+        .push_bind(finished_before)
+        .push(" AND (worker_id IS NULL OR worker_id = ")
+        .push_bind(worker_id.to_string())
+        .push(")");
+    match scope {
+        ProjectScope::Only(project_id) => {
+            query
+                .push(" AND project_id = ")
+                .push_bind(project_id.to_string());
+        }
+        ProjectScope::Except([]) => {}
+        ProjectScope::Except(project_ids) => {
+            query.push(" AND project_id NOT IN (");
+            let mut separated = query.separated(", ");
+            for project_id in project_ids {
+                separated.push_bind(project_id.to_string());
+            }
+            separated.push_unseparated(")");
+        }
+    }
+    query.push(" AND status IN (");
+    let mut separated = query.separated(", ");
+    for status in statuses.iter().filter(|status| status.is_terminal()) {
+        separated.push_bind(status_to_str(*status));
+    }
+    query
+        .push(") ORDER BY finished_at ASC LIMIT ")
+        .push_bind(limit);
+
+    let rows = query.build().fetch_all(pool).await.map_err(db_error)?;
+
+    rows.iter().map(row_to_run).collect()
+}
+
+pub async fn mark_artifacts_reaped(pool: &DbPool, id: &Uuid) -> Result<(), DomainError> {
+    sqlx::query("UPDATE runs SET artifacts_reaped_at = NOW() WHERE id = ?")
+        .bind(id.to_string())
+        .execute(pool)
+        .await
+        .map_err(db_error)?;
+    Ok(())
+}
+
+/// Rejects moving run `id` from `current` to `next` as a `Conflict` unless
+/// [`RunStatus::can_transition_to`] allows it.
+fn check_transition(id: &Uuid, current: RunStatus, next: RunStatus) -> Result<(), DomainError> {
+    if current.can_transition_to(next) {
+        Ok(())
+    } else {
+        Err(DomainError::Conflict(format!(
+            "run {id} is {current:?} and can't become {next:?}"
+        )))
+    }
+}
+
+/// Sets the run's status and appends the transition to `run_status_history`.
+/// The previous status is read under a row lock in the same transaction, so
+/// concurrent updates can't record the same `from_status` twice. When the
+/// run finishes, blocked runs depending on it are released or skipped in
+/// that transaction too; see [`DependencyResolution`]. Changes
+/// [`RunStatus::can_transition_to`] disallows, e.g. from a finished run back
+/// to `Running`, fail with `Conflict`.
 pub async fn update_status(
     pool: &DbPool,
     id: &Uuid,
     status: RunStatus,
     error: Option<EvalErrorPayload>,
-) -> Result<(), DomainError> {
+) -> Result<DependencyResolution, DomainError> {
+    let mut tx = pool.begin().await.map_err(db_error)?;
+    let previous: Option<String> =
+        sqlx::query_scalar("SELECT status FROM runs WHERE id = ? FOR UPDATE")
+            .bind(id.to_string())
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(db_error)?;
+    let Some(previous) = previous else {
+        return Err(DomainError::NotFound("run not found".into()));
+    };
+    check_transition(id, status_from_str(&previous), status)?;
+    let error_kind = error
+        .as_ref()
+        .map(|e| format!("{:?}", e.kind).to_lowercase());
+
     let mut query = String::from("UPDATE runs SET ");
     match status {
         RunStatus::Running => query.push_str("started_at = IFNULL(started_at, NOW()), "),
-        RunStatus::Completed
-        | RunStatus::FailedConfig
-        | RunStatus::FailedEngine
-        | RunStatus::FailedInfra
-        | RunStatus::TimedOut
-        | RunStatus::Cancelled => query.push_str("finished_at = NOW(), "),
+        status if status.is_terminal() => {
+            query.push_str("finished_at = NOW(), lease_expires_at = NULL, ")
+        }
         _ => {}
     }
     query.push_str(
@@ -195,11 +1012,7 @@ pub async fn update_status(
 
     sqlx::query(&query)
         .bind(status_to_str(status))
-        .bind(
-            error
-                .as_ref()
-                .map(|e| format!("{:?}", e.kind).to_lowercase()),
-        )
+        .bind(&error_kind)
         .bind(error.as_ref().and_then(|e| e.code.clone()))
         .bind(error.as_ref().map(|e| e.message.clone()))
         .bind(error.as_ref().and_then(|e| e.engine.clone()))
@@ -210,9 +1023,589 @@ pub async fn update_status(
                 .map(|d| serde_json::to_string(&d).unwrap_or_else(|_| "{}".into())),
         )
         .bind(id.to_string())
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+
+    record_transition(&mut tx, id, &previous, status, error_kind).await?;
+    let resolution = if status.is_terminal() {
+        resolve_dependents(&mut tx, id, status).await?
+    } else {
+        DependencyResolution::default()
+    };
+
+    tx.commit().await.map_err(db_error)?;
+    Ok(resolution)
+}
+
+/// Blocked runs whose status changed because a run they depend on finished.
+#[derive(Debug, Clone, Default)]
+pub struct DependencyResolution {
+    /// Runs whose dependencies have now all completed. They are `Queued` but
+    /// not yet on the run queue; the caller pushes them and then calls
+    /// [`clear_pending_enqueue`]. Until it does they stay in the enqueue
+    /// outbox, see [`pending_enqueues`].
+    pub released: Vec<Run>,
+    /// Runs cancelled because a dependency failed, timed out or was
+    /// cancelled, with the error recorded on them. Runs depending on a
+    /// skipped run are skipped too.
+    pub skipped: Vec<(Uuid, EvalErrorPayload)>,
+}
+
+/// The error recorded on runs skipped because `dependency` finished as
+/// `status`.
+pub fn dependency_failed_error(dependency: &Uuid, status: RunStatus) -> EvalErrorPayload {
+    EvalErrorPayload {
+        kind: EvalErrorKind::Cancelled,
+        message: format!(
+            "dependency {dependency} finished as {}",
+            status_to_str(status)
+        ),
+        code: Some("dependency_failed".into()),
+        engine: None,
+        details: Some(serde_json::json!({
+            "dependency": dependency,
+            "status": status_to_str(status),
+        })),
+    }
+}
+
+async fn resolve_dependents(
+    tx: &mut Transaction<'_, MySql>,
+    id: &Uuid,
+    status: RunStatus,
+) -> Result<DependencyResolution, DomainError> {
+    let mut resolution = DependencyResolution::default();
+    let mut released = Vec::new();
+    let mut finished = vec![(*id, status)];
+    while let Some((dependency, status)) = finished.pop() {
+        let dependents: Vec<String> = sqlx::query_scalar(
+            "SELECT r.id FROM run_dependencies d JOIN runs r ON r.id = d.run_id \
+             WHERE d.depends_on_run_id = ? AND r.status = 'blocked' ORDER BY r.id FOR UPDATE",
+        )
+        .bind(dependency.to_string())
+        .fetch_all(&mut **tx)
+        .await
+        .map_err(db_error)?;
+
+        for dependent in dependents {
+            let dependent_id = parse_uuid(&dependent)?;
+            if matches!(status, RunStatus::Completed) {
+                let pending: i64 = sqlx::query_scalar(
+                    "SELECT COUNT(*) FROM run_dependencies d JOIN runs r ON r.id = d.depends_on_run_id \
+                     WHERE d.run_id = ? AND r.status <> 'completed'",
+                )
+                .bind(&dependent)
+                .fetch_one(&mut **tx)
+                .await
+                .map_err(db_error)?;
+                if pending > 0 {
+                    continue;
+                }
+                sqlx::query("UPDATE runs SET status = 'queued', updated_at = NOW() WHERE id = ?")
+                    .bind(&dependent)
+                    .execute(&mut **tx)
+                    .await
+                    .map_err(db_error)?;
+                record_transition(tx, &dependent_id, "blocked", RunStatus::Queued, None).await?;
+                sqlx::query(
+                    "INSERT IGNORE INTO run_enqueue_outbox (run_id, created_at) VALUES (?, NOW(6))",
+                )
+                .bind(&dependent)
+                .execute(&mut **tx)
+                .await
+                .map_err(db_error)?;
+                released.push(dependent);
+            } else {
+                let error = dependency_failed_error(&dependency, status);
+                sqlx::query(
+                    "UPDATE runs SET status = 'cancelled', error_kind = 'cancelled', error_code = ?, error_message = ?, \
+                     error_engine = NULL, error_details_json = ?, finished_at = NOW(), updated_at = NOW() WHERE id = ?",
+                )
+                .bind(&error.code)
+                .bind(&error.message)
+                .bind(error.details.as_ref().map(Value::to_string))
+                .bind(&dependent)
+                .execute(&mut **tx)
+                .await
+                .map_err(db_error)?;
+                record_transition(
+                    tx,
+                    &dependent_id,
+                    "blocked",
+                    RunStatus::Cancelled,
+                    Some("cancelled".into()),
+                )
+                .await?;
+                finished.push((dependent_id, RunStatus::Cancelled));
+                resolution.skipped.push((dependent_id, error));
+            }
+        }
+    }
+
+    for id in released {
+        let row = sqlx::query(&format!("SELECT {RUN_COLUMNS} FROM runs WHERE id = ?"))
+            .bind(id)
+            .fetch_one(&mut **tx)
+            .await
+            .map_err(db_error)?;
+        resolution.released.push(row_to_run(&row)?);
+    }
+    Ok(resolution)
+}
+
+/// Released runs still waiting in the enqueue outbox more than `min_age`
+/// after their release, least recently updated first. Ones no longer
+/// `queued`, e.g. cancelled meanwhile, are dropped from the outbox instead of
+/// returned.
+pub async fn pending_enqueues(
+    pool: &DbPool,
+    min_age: Duration,
+    limit: i64,
+) -> Result<Vec<Run>, DomainError> {
+    sqlx::query(
+        "DELETE o FROM run_enqueue_outbox o JOIN runs r ON r.id = o.run_id \
+         WHERE r.status <> 'queued'",
+    )
+    .execute(pool)
+    .await
+    .map_err(db_error)?;
+    let rows = sqlx::query(&format!(
+        "SELECT {RUN_COLUMNS} FROM runs WHERE id IN \
+         (SELECT run_id FROM run_enqueue_outbox WHERE created_at < NOW(6) - INTERVAL ? SECOND) \
+         ORDER BY updated_at ASC LIMIT ?"
+    ))
+    .bind(min_age.as_secs())
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+    .map_err(db_error)?;
+    rows.iter().map(row_to_run).collect()
+}
+
+/// Removes a released run from the enqueue outbox once it is on the queue.
+pub async fn clear_pending_enqueue(pool: &DbPool, run_id: &Uuid) -> Result<(), DomainError> {
+    sqlx::query("DELETE FROM run_enqueue_outbox WHERE run_id = ?")
+        .bind(run_id.to_string())
         .execute(pool)
         .await
-        .map_err(|e| DomainError::Internal(e.to_string()))?;
+        .map_err(db_error)?;
+    Ok(())
+}
+
+/// Result of [`cancel_experiment`]. `cancelled` holds the runs as they were
+/// before cancelling, so callers can tell queued runs from running ones.
+#[derive(Debug, Clone)]
+pub struct ExperimentCancellation {
+    pub cancelled: Vec<Run>,
+    pub already_terminal: usize,
+    /// Runs of other experiments that depended on a cancelled run.
+    pub dependents: DependencyResolution,
+}
+
+/// The error recorded on runs cancelled by [`cancel_experiment`].
+pub fn experiment_cancelled_error() -> EvalErrorPayload {
+    EvalErrorPayload {
+        kind: EvalErrorKind::Cancelled,
+        message: "experiment was cancelled".into(),
+        code: Some("experiment_cancelled".into()),
+        engine: None,
+        details: None,
+    }
+}
+
+/// Cancels every blocked, queued or running run of an experiment in one
+/// transaction. Finished runs are left alone and only counted.
+pub async fn cancel_experiment(
+    pool: &DbPool,
+    experiment_id: &Uuid,
+) -> Result<ExperimentCancellation, DomainError> {
+    let mut tx = pool.begin().await.map_err(db_error)?;
+    let rows = sqlx::query(&format!(
+        "SELECT {RUN_COLUMNS} FROM runs WHERE experiment_id = ? ORDER BY created_at ASC, id ASC FOR UPDATE"
+    ))
+    .bind(experiment_id.to_string())
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(db_error)?;
+    let (cancelled, finished): (Vec<Run>, Vec<Run>) = rows
+        .iter()
+        .map(row_to_run)
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .partition(|run| !run.status.is_terminal());
+
+    if !cancelled.is_empty() {
+        let error = experiment_cancelled_error();
+        sqlx::query(
+            "UPDATE runs SET status = 'cancelled', error_kind = 'cancelled', error_code = ?, error_message = ?, \
+             error_engine = NULL, error_details_json = NULL, finished_at = NOW(), lease_expires_at = NULL, \
+             updated_at = NOW() WHERE experiment_id = ? AND status IN ('blocked', 'queued', 'running')",
+        )
+        .bind(error.code)
+        .bind(error.message)
+        .bind(experiment_id.to_string())
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+        for run in &cancelled {
+            record_transition(
+                &mut tx,
+                &run.id,
+                status_to_str(run.status),
+                RunStatus::Cancelled,
+                Some("cancelled".into()),
+            )
+            .await?;
+        }
+    }
+
+    let mut dependents = DependencyResolution::default();
+    for run in &cancelled {
+        let resolution = resolve_dependents(&mut tx, &run.id, RunStatus::Cancelled).await?;
+        dependents.skipped.extend(resolution.skipped);
+    }
+
+    tx.commit().await.map_err(db_error)?;
+    Ok(ExperimentCancellation {
+        cancelled,
+        already_terminal: finished.len(),
+        dependents,
+    })
+}
 
+async fn record_transition(
+    tx: &mut Transaction<'_, MySql>,
+    id: &Uuid,
+    previous: &str,
+    status: RunStatus,
+    error_kind: Option<String>,
+) -> Result<(), DomainError> {
+    sqlx::query("INSERT INTO run_status_history (run_id, from_status, to_status, error_kind, at) VALUES (?, ?, ?, ?, ?)")
+        .bind(id.to_string())
+        .bind(previous)
+        .bind(status_to_str(status))
+        .bind(error_kind)
+        .bind(Utc::now())
+        .execute(&mut **tx)
+        .await
+        .map_err(db_error)?;
     Ok(())
 }
+
+fn lease_deadline(lease: Duration) -> Result<DateTime<Utc>, DomainError> {
+    chrono::Duration::from_std(lease)
+        .map(|lease| Utc::now() + lease)
+        .map_err(|e| DomainError::Validation(e.to_string()))
+}
+
+/// Marks a queued or running run as running under `worker_id` for `lease`.
+/// Succeeds only when the run is unclaimed, already held by `worker_id`, or
+/// its lease has expired; returns `false` when another worker holds it or
+/// the run has finished.
+pub async fn try_claim(
+    pool: &DbPool,
+    id: &Uuid,
+    worker_id: &str,
+    lease: Duration,
+) -> Result<bool, DomainError> {
+    let expires_at = lease_deadline(lease)?;
+    let mut tx = pool.begin().await.map_err(db_error)?;
+    let previous: Option<String> =
+        sqlx::query_scalar("SELECT status FROM runs WHERE id = ? FOR UPDATE")
+            .bind(id.to_string())
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(db_error)?;
+    let Some(previous) = previous else {
+        return Err(DomainError::NotFound("run not found".into()));
+    };
+
+    let result = sqlx::query(
+        "UPDATE runs SET status = 'running', started_at = IFNULL(started_at, NOW()), worker_id = ?, lease_expires_at = ?, updated_at = NOW() \
+         WHERE id = ? AND status IN ('queued', 'running') \
+         AND (worker_id IS NULL OR worker_id = ? OR lease_expires_at IS NULL OR lease_expires_at < ?)",
+    )
+    .bind(worker_id)
+    .bind(expires_at)
+    .bind(id.to_string())
+    .bind(worker_id)
+    .bind(Utc::now())
+    .execute(&mut *tx)
+    .await
+    .map_err(db_error)?;
+    if result.rows_affected() == 0 {
+        return Ok(false);
+    }
+    if previous != status_to_str(RunStatus::Running) {
+        record_transition(&mut tx, id, &previous, RunStatus::Running, None).await?;
+    }
+
+    tx.commit().await.map_err(db_error)?;
+    Ok(true)
+}
+
+/// Extends the lease held by `worker_id`. Returns `false` when the run is no
+/// longer claimed by this worker.
+pub async fn renew_lease(
+    pool: &DbPool,
+    id: &Uuid,
+    worker_id: &str,
+    lease: Duration,
+) -> Result<bool, DomainError> {
+    let result = sqlx::query(
+        "UPDATE runs SET lease_expires_at = ? WHERE id = ? AND worker_id = ? AND status = 'running'",
+    )
+    .bind(lease_deadline(lease)?)
+    .bind(id.to_string())
+    .bind(worker_id)
+    .execute(pool)
+    .await
+    .map_err(db_error)?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Status transitions of a run, oldest first.
+pub async fn history(pool: &DbPool, id: &Uuid) -> Result<Vec<StatusTransition>, DomainError> {
+    let rows = sqlx::query(
+        "SELECT from_status, to_status, error_kind, at FROM run_status_history WHERE run_id = ? ORDER BY id ASC",
+    )
+    .bind(id.to_string())
+    .fetch_all(pool)
+    .await
+    .map_err(db_error)?;
+
+    rows.iter()
+        .map(|row| {
+            Ok(StatusTransition {
+                from_status: row
+                    .try_get::<Option<String>, _>("from_status")?
+                    .map(|status| status_from_str(&status)),
+                to_status: status_from_str(row.try_get::<String, _>("to_status")?.as_str()),
+                error_kind: row.try_get("error_kind")?,
+                at: row.try_get("at")?,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn illegal_transitions_are_conflicts() {
+        let id = Uuid::new_v4();
+        assert!(check_transition(&id, RunStatus::Queued, RunStatus::Running).is_ok());
+        let err = check_transition(&id, RunStatus::Completed, RunStatus::Running).unwrap_err();
+        assert!(matches!(err, DomainError::Conflict(_)), "{err}");
+        assert!(err.to_string().contains(&id.to_string()), "{err}");
+    }
+
+    #[test]
+    fn leases_expire_after_their_duration() {
+        let lease = Duration::from_secs(300);
+        let earliest = Utc::now() + chrono::Duration::seconds(300);
+        let deadline = lease_deadline(lease).unwrap();
+        assert!(deadline >= earliest);
+        assert!(deadline <= Utc::now() + chrono::Duration::seconds(300));
+
+        let err = lease_deadline(Duration::MAX).unwrap_err();
+        assert!(matches!(err, DomainError::Validation(_)), "{err}");
+    }
+
+    #[test]
+    fn statuses_round_trip_through_their_column_values() {
+        // The history stores both ends of a transition in this form.
+        for status in [
+            RunStatus::Blocked,
+            RunStatus::Queued,
+            RunStatus::Running,
+            RunStatus::Completed,
+            RunStatus::FailedConfig,
+            RunStatus::FailedEngine,
+            RunStatus::FailedInfra,
+            RunStatus::TimedOut,
+            RunStatus::Cancelled,
+        ] {
+            assert_eq!(parse_status(status_to_str(status)).unwrap(), status);
+        }
+        assert!(matches!(
+            parse_status("finished"),
+            Err(DomainError::Validation(_))
+        ));
+    }
+
+    /// What follows `FROM runs WHERE` in the query for `filter`.
+    fn conditions(filter: &RunFilter) -> String {
+        let query = search_query(filter);
+        let (_, conditions) = query.sql().split_once(" FROM runs WHERE ").unwrap();
+        conditions.to_string()
+    }
+
+    #[test]
+    fn runs_without_filters_list_the_whole_project() {
+        assert_eq!(
+            conditions(&RunFilter::for_project(Uuid::new_v4())),
+            "project_id = ? ORDER BY created_at DESC, id DESC"
+        );
+    }
+
+    #[test]
+    fn each_run_filter_adds_one_bound_condition() {
+        let all = RunFilter::for_project(Uuid::new_v4());
+        let cases = [
+            (
+                RunFilter {
+                    status: Some(RunStatus::FailedEngine),
+                    ..all.clone()
+                },
+                "status = ?",
+            ),
+            (
+                RunFilter {
+                    model_impl_id: Some(Uuid::new_v4()),
+                    ..all.clone()
+                },
+                "model_impl_id = ?",
+            ),
+            (
+                RunFilter {
+                    checkpoint_id: Some(Uuid::new_v4()),
+                    ..all.clone()
+                },
+                "checkpoint_id = ?",
+            ),
+            (
+                RunFilter {
+                    task_id: Some(Uuid::new_v4()),
+                    ..all.clone()
+                },
+                "task_id = ?",
+            ),
+            (
+                RunFilter {
+                    created_after: Some(Utc::now()),
+                    ..all.clone()
+                },
+                "created_at >= ?",
+            ),
+            (
+                RunFilter {
+                    created_before: Some(Utc::now()),
+                    ..all.clone()
+                },
+                "created_at < ?",
+            ),
+        ];
+        for (filter, condition) in cases {
+            assert_eq!(
+                conditions(&filter),
+                format!("project_id = ? AND {condition} ORDER BY created_at DESC, id DESC")
+            );
+        }
+    }
+
+    #[test]
+    fn run_filters_combine() {
+        let checkpoint_id = Uuid::new_v4();
+        let filter = RunFilter {
+            status: Some(RunStatus::FailedEngine),
+            model_impl_id: Some(Uuid::new_v4()),
+            checkpoint_id: Some(checkpoint_id),
+            task_id: Some(Uuid::new_v4()),
+            created_after: Some(Utc::now() - chrono::Duration::days(7)),
+            created_before: Some(Utc::now()),
+            limit: Some(20),
+            ..RunFilter::for_project(Uuid::new_v4())
+        };
+        let conditions = conditions(&filter);
+        assert_eq!(
+            conditions,
+            "project_id = ? AND status = ? AND model_impl_id = ? AND checkpoint_id = ? \
+             AND task_id = ? AND created_at >= ? AND created_at < ? \
+             ORDER BY created_at DESC, id DESC LIMIT ?"
+        );
+        // Values are bound, never spliced into the SQL.
+        assert!(!conditions.contains(&checkpoint_id.to_string()));
+    }
+
+    #[test]
+    fn run_cursors_round_trip_through_their_encoding() {
+        let cursor = RunCursor {
+            created_at: DateTime::from_timestamp_micros(1_700_000_000_123_456).unwrap(),
+            id: Uuid::new_v4(),
+        };
+        assert_eq!(cursor.to_string().parse::<RunCursor>().unwrap(), cursor);
+        for invalid in ["", "1700000000", "abc_def", &format!("x_{}", cursor.id)] {
+            let err = invalid.parse::<RunCursor>().unwrap_err();
+            assert!(
+                matches!(err, DomainError::Validation(_)),
+                "{invalid}: {err}"
+            );
+        }
+    }
+
+    #[test]
+    fn cursors_continue_after_the_last_run_in_keyset_order() {
+        let filter = RunFilter {
+            cursor: Some(RunCursor {
+                created_at: Utc::now(),
+                id: Uuid::new_v4(),
+            }),
+            ..RunFilter::for_project(Uuid::new_v4())
+        };
+        assert_eq!(
+            conditions(&filter),
+            "project_id = ? AND (created_at, id) < (?, ?) \
+             ORDER BY created_at DESC, id DESC LIMIT ?"
+        );
+    }
+
+    #[test]
+    fn keyset_pages_neither_skip_nor_repeat_runs_sharing_a_timestamp() {
+        // Runs compiled together share `created_at`; ids are stored as text.
+        let created_at = DateTime::from_timestamp_micros(1_700_000_000_000_000).unwrap();
+        let mut rows: Vec<(DateTime<Utc>, String)> = (0..7)
+            .map(|_| (created_at, Uuid::new_v4().to_string()))
+            .collect();
+        rows.push((
+            created_at - chrono::Duration::seconds(1),
+            Uuid::new_v4().to_string(),
+        ));
+        // `ORDER BY created_at DESC, id DESC`.
+        rows.sort_by(|a, b| b.cmp(a));
+
+        let mut seen = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            // `WHERE (created_at, id) < (?, ?) ... LIMIT 3`, with the cursor
+            // round-tripped through its query-string form as the API does.
+            let after = cursor.map(|cursor| {
+                let cursor: RunCursor = cursor.parse().unwrap();
+                (cursor.created_at, cursor.id.to_string())
+            });
+            let page: Vec<&(DateTime<Utc>, String)> = rows
+                .iter()
+                .filter(|row| match &after {
+                    Some(after) => *row < after,
+                    None => true,
+                })
+                .take(3)
+                .collect();
+            let Some(last) = page.last() else {
+                break;
+            };
+            cursor = Some(
+                RunCursor {
+                    created_at: last.0,
+                    id: Uuid::parse_str(&last.1).unwrap(),
+                }
+                .to_string(),
+            );
+            seen.extend(page.iter().map(|row| row.1.clone()));
+        }
+        let expected: Vec<String> = rows.into_iter().map(|row| row.1).collect();
+        assert_eq!(seen, expected);
+    }
+}
@@ -1,10 +1,16 @@
-use crate::db::DbPool;
+use crate::db::{db_error, DbPool};
+use crate::utils::{page_bounds, parse_uuid};
+pub use crate::utils::{DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use sqlx::Row;
+use sqlx::mysql::MySqlRow;
+use sqlx::{MySql, QueryBuilder, Row};
+use std::collections::HashMap;
 use unified_shared::error::DomainError;
-use unified_shared::eval::{SampleRecord, SampleResultLocation};
+use unified_shared::eval::{
+    to_canonical_json, SampleErrorKind, SampleRecord, SampleResultLocation,
+};
 use uuid::Uuid;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -25,60 +31,601 @@ pub struct SampleOutput {
     pub created_at: DateTime<Utc>,
 }
 
-pub async fn list_by_run(pool: &DbPool, run_id: &Uuid) -> Result<Vec<SampleOutput>, DomainError> {
-    let rows = sqlx::query("SELECT id, run_id, dataset, subset, split, sample_index, input_text, reference_text, output_text, metrics_json, latency_ms, token_counts_json, error_json, created_at FROM sample_outputs WHERE run_id = ? ORDER BY sample_index ASC")
-        .bind(run_id.to_string())
+impl SampleOutput {
+    /// A record read from a store without row ids, such as a `samples.jsonl`
+    /// object: `id` is nil and `created_at` is the read time.
+    pub fn from_record(record: SampleRecord) -> Self {
+        Self {
+            id: Uuid::nil(),
+            run_id: record.run_id,
+            dataset: record.dataset,
+            subset: record.subset,
+            split: record.split,
+            sample_index: record.sample_index,
+            input: record.input,
+            reference: record.reference,
+            output: record.output,
+            metrics: record.metrics,
+            latency_ms: record.latency_ms,
+            token_counts: record
+                .token_counts
+                .and_then(|counts| serde_json::to_value(counts).ok()),
+            error: record
+                .error
+                .and_then(|error| serde_json::to_value(error).ok()),
+            created_at: Utc::now(),
+        }
+    }
+
+    /// Back to the record the engine reported, e.g. to write it to another
+    /// store. Fails if `token_counts` or `error` no longer match their types.
+    pub fn into_record(self) -> Result<SampleRecord, DomainError> {
+        let sample_index = self.sample_index;
+        let invalid = |column: &str, e: serde_json::Error| {
+            DomainError::Internal(format!("invalid {column} for sample {sample_index}: {e}"))
+        };
+        Ok(SampleRecord {
+            run_id: self.run_id,
+            dataset: self.dataset,
+            subset: self.subset,
+            split: self.split,
+            sample_index: self.sample_index,
+            input: self.input,
+            reference: self.reference,
+            output: self.output,
+            metrics: self.metrics,
+            latency_ms: self.latency_ms,
+            token_counts: self
+                .token_counts
+                .map(serde_json::from_value)
+                .transpose()
+                .map_err(|e| invalid("token_counts", e))?,
+            error: self
+                .error
+                .map(serde_json::from_value)
+                .transpose()
+                .map_err(|e| invalid("error", e))?,
+        })
+    }
+}
+
+const SAMPLE_COLUMNS: &str = "id, run_id, dataset, subset, split, sample_index, input_text, reference_text, output_text, metrics_json, latency_ms, token_counts_json, error_json, created_at";
+
+/// Optional filters and paging for [`list_by_run`]; without `limit` or
+/// `offset` every matching sample is returned.
+#[derive(Debug, Clone)]
+pub struct SampleFilter {
+    pub run_id: Uuid,
+    pub dataset: Option<String>,
+    pub split: Option<String>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+impl SampleFilter {
+    pub fn for_run(run_id: Uuid) -> Self {
+        Self {
+            run_id,
+            dataset: None,
+            split: None,
+            limit: None,
+            offset: None,
+        }
+    }
+}
+
+/// Keyset position for [`list_after`]. `sample_index` is only unique per
+/// dataset/split, so the row id breaks ties.
+#[derive(Debug, Clone)]
+pub struct SampleCursor {
+    pub sample_index: i64,
+    pub id: Uuid,
+}
+
+impl From<&SampleOutput> for SampleCursor {
+    fn from(sample: &SampleOutput) -> Self {
+        Self {
+            sample_index: sample.sample_index,
+            id: sample.id,
+        }
+    }
+}
+
+/// Criteria for [`search`]. An empty `q` is ignored, so a metric threshold on
+/// its own is a valid search.
+#[derive(Debug, Clone)]
+pub struct SampleSearch {
+    pub run_id: Uuid,
+    pub q: Option<String>,
+    pub metric: Option<String>,
+    pub lt: Option<f64>,
+    pub limit: i64,
+    pub offset: i64,
+}
+
+impl SampleSearch {
+    pub fn text(&self) -> Option<&str> {
+        self.q.as_deref().filter(|q| !q.is_empty())
+    }
+
+    /// The `(metric, threshold)` pair, or a validation error when only one of
+    /// them was given.
+    pub fn metric_threshold(&self) -> Result<Option<(&str, f64)>, DomainError> {
+        match (self.metric.as_deref(), self.lt) {
+            (Some(metric), Some(lt)) if !metric.is_empty() => Ok(Some((metric, lt))),
+            (None, None) => Ok(None),
+            _ => Err(DomainError::Validation(
+                "metric and lt must be given together".into(),
+            )),
+        }
+    }
+
+    pub fn page_size(&self) -> i64 {
+        self.limit.clamp(1, MAX_PAGE_SIZE)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SamplePage {
+    pub items: Vec<SampleOutput>,
+    pub next_offset: Option<i64>,
+}
+
+impl SamplePage {
+    /// Builds a page from up to `limit + 1` rows; the extra row only signals
+    /// that another page exists.
+    pub fn from_rows(mut items: Vec<SampleOutput>, limit: i64, offset: i64) -> Self {
+        let next_offset = if items.len() as i64 > limit {
+            items.truncate(limit as usize);
+            Some(offset + limit)
+        } else {
+            None
+        };
+        Self { items, next_offset }
+    }
+}
+
+/// Two runs' outputs for the same `(dataset, subset, split, sample_index)`.
+#[derive(Debug, Clone, Serialize)]
+pub struct SampleDiffRow {
+    pub dataset: String,
+    pub subset: Option<String>,
+    pub split: Option<String>,
+    pub sample_index: i64,
+    pub input: String,
+    pub reference: Option<String>,
+    pub left_output: String,
+    pub right_output: String,
+    pub left_metrics: Option<Value>,
+    pub right_metrics: Option<Value>,
+}
+
+/// Samples present in both runs, plus how many only one of them evaluated.
+#[derive(Debug, Clone, Serialize)]
+pub struct SampleDiff {
+    pub items: Vec<SampleDiffRow>,
+    pub left_only: i64,
+    pub right_only: i64,
+}
+
+/// Token totals across the samples of a run. Samples without token counts
+/// are only counted in `samples_missing_tokens`.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TokenSummary {
+    pub samples: i64,
+    pub samples_missing_tokens: i64,
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+    pub total_tokens: i64,
+    pub avg_tokens_per_sample: Option<f64>,
+}
+
+impl TokenSummary {
+    pub fn new(
+        samples: i64,
+        samples_missing_tokens: i64,
+        prompt_tokens: i64,
+        completion_tokens: i64,
+        total_tokens: i64,
+    ) -> Self {
+        let counted = samples - samples_missing_tokens;
+        Self {
+            samples,
+            samples_missing_tokens,
+            prompt_tokens,
+            completion_tokens,
+            total_tokens,
+            avg_tokens_per_sample: (counted > 0).then(|| total_tokens as f64 / counted as f64),
+        }
+    }
+}
+
+/// Generation latency across the successful samples of a run. Samples
+/// without `latency_ms` or with an `error` are left out; every percentile is
+/// `None` when no sample remains.
+#[derive(Debug, Clone, Serialize)]
+pub struct LatencyPercentiles {
+    pub run_id: Uuid,
+    /// Samples the percentiles are computed over.
+    pub samples: i64,
+    pub p50_ms: Option<f64>,
+    pub p95_ms: Option<f64>,
+    pub p99_ms: Option<f64>,
+}
+
+/// The percentiles reported by [`latency_percentiles`].
+pub const LATENCY_PERCENTILES: [f64; 3] = [0.50, 0.95, 0.99];
+
+/// Zero-based position of the nearest-rank `percentile` among `n` sorted
+/// values.
+fn nearest_rank(percentile: f64, n: i64) -> i64 {
+    ((percentile * n as f64).ceil() as i64 - 1).clamp(0, n - 1)
+}
+
+/// Failed samples of a run grouped by [`SampleErrorKind`].
+#[derive(Debug, Clone, Serialize)]
+pub struct SampleErrorSummary {
+    pub run_id: Uuid,
+    pub failed_samples: i64,
+    /// Every kind, most frequent first; kinds with no failures have `count: 0`.
+    pub kinds: Vec<SampleErrorKindCount>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SampleErrorKindCount {
+    pub kind: SampleErrorKind,
+    pub count: i64,
+}
+
+impl SampleErrorSummary {
+    /// Builds the summary from `(stored kind, count)` rows. Missing and
+    /// unrecognized kinds count as `Other`.
+    pub fn from_counts(
+        run_id: Uuid,
+        rows: impl IntoIterator<Item = (Option<String>, i64)>,
+    ) -> Self {
+        let mut counts: HashMap<SampleErrorKind, i64> = HashMap::new();
+        for (kind, count) in rows {
+            *counts
+                .entry(SampleErrorKind::parse(kind.as_deref()))
+                .or_default() += count;
+        }
+        let mut kinds: Vec<SampleErrorKindCount> = SampleErrorKind::ALL
+            .into_iter()
+            .map(|kind| SampleErrorKindCount {
+                kind,
+                count: counts.get(&kind).copied().unwrap_or(0),
+            })
+            .collect();
+        // Stable, so ties keep the declaration order.
+        kinds.sort_by_key(|kind| std::cmp::Reverse(kind.count));
+        Self {
+            run_id,
+            failed_samples: kinds.iter().map(|kind| kind.count).sum(),
+            kinds,
+        }
+    }
+}
+
+/// Escapes `LIKE` wildcards so `q` matches literally.
+pub fn like_pattern(q: &str) -> String {
+    let escaped = q
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("%{escaped}%")
+}
+
+/// JSON path selecting a top-level key of `metrics_json`.
+pub fn metric_path(metric: &str) -> String {
+    format!("$.\"{}\"", metric.replace('"', "\\\""))
+}
+
+fn parse_json(row: &MySqlRow, column: &str) -> Option<Value> {
+    row.try_get::<Option<String>, _>(column)
+        .ok()
+        .flatten()
+        .map(|raw| serde_json::from_str(&raw).unwrap_or(Value::Null))
+}
+
+fn row_to_sample(row: &MySqlRow) -> Result<SampleOutput, DomainError> {
+    Ok(SampleOutput {
+        id: parse_uuid(row.try_get::<String, _>("id")?.as_str())?,
+        run_id: parse_uuid(row.try_get::<String, _>("run_id")?.as_str())?,
+        dataset: row.try_get("dataset")?,
+        subset: row.try_get("subset")?,
+        split: row.try_get("split")?,
+        sample_index: row.try_get("sample_index")?,
+        input: row.try_get("input_text")?,
+        reference: row.try_get("reference_text")?,
+        output: row.try_get("output_text")?,
+        metrics: parse_json(row, "metrics_json"),
+        latency_ms: row.try_get("latency_ms")?,
+        token_counts: parse_json(row, "token_counts_json"),
+        error: parse_json(row, "error_json"),
+        created_at: row.try_get("created_at")?,
+    })
+}
+
+pub async fn list_by_run(
+    pool: &DbPool,
+    filter: &SampleFilter,
+) -> Result<Vec<SampleOutput>, DomainError> {
+    let rows = list_query(filter)
+        .build()
         .fetch_all(pool)
         .await
-        .map_err(|e| DomainError::Internal(e.to_string()))?;
+        .map_err(db_error)?;
+
+    rows.iter().map(row_to_sample).collect()
+}
+
+/// The query behind [`list_by_run`]: each set filter adds one bound
+/// condition.
+fn list_query(filter: &SampleFilter) -> QueryBuilder<'static, MySql> {
+    let mut query: QueryBuilder<MySql> = QueryBuilder::new(format!(
+        "SELECT {SAMPLE_COLUMNS} FROM sample_outputs WHERE run_id = "
+    ));
+    query.push_bind(filter.run_id.to_string());
+    if let Some(dataset) = &filter.dataset {
+        query.push(" AND dataset = ").push_bind(dataset.clone());
+    }
+    if let Some(split) = &filter.split {
+        query.push(" AND split = ").push_bind(split.clone());
+    }
+    query.push(" ORDER BY sample_index ASC, id ASC");
+    if let Some((limit, offset)) = page_bounds(filter.limit, filter.offset) {
+        query
+            .push(" LIMIT ")
+            .push_bind(limit)
+            .push(" OFFSET ")
+            .push_bind(offset);
+    }
+    query
+}
+
+/// Returns the sample at `sample_index`. When a run covers several
+/// datasets/splits with overlapping indices, the first by `(dataset, id)` wins.
+pub async fn get_one(
+    pool: &DbPool,
+    run_id: &Uuid,
+    sample_index: i64,
+) -> Result<SampleOutput, DomainError> {
+    let row = sqlx::query(&format!(
+        "SELECT {SAMPLE_COLUMNS} FROM sample_outputs WHERE run_id = ? AND sample_index = ? ORDER BY dataset ASC, id ASC LIMIT 1"
+    ))
+    .bind(run_id.to_string())
+    .bind(sample_index)
+    .fetch_optional(pool)
+    .await
+    .map_err(db_error)?
+    .ok_or_else(|| DomainError::NotFound(format!("sample {sample_index} of run {run_id}")))?;
+
+    row_to_sample(&row)
+}
+
+/// Returns up to `limit` samples ordered by `(sample_index, id)`, starting
+/// after `cursor` (or from the beginning).
+pub async fn list_after(
+    pool: &DbPool,
+    run_id: &Uuid,
+    cursor: Option<&SampleCursor>,
+    limit: i64,
+) -> Result<Vec<SampleOutput>, DomainError> {
+    let mut query: QueryBuilder<MySql> = QueryBuilder::new(format!(
+        "SELECT {SAMPLE_COLUMNS} FROM sample_outputs WHERE run_id = "
+    ));
+    query.push_bind(run_id.to_string());
+    if let Some(cursor) = cursor {
+        query
+            .push(" AND (sample_index, id) > (")
+            .push_bind(cursor.sample_index)
+            .push(", ")
+            .push_bind(cursor.id.to_string())
+            .push(")");
+    }
+    query
+        .push(" ORDER BY sample_index ASC, id ASC LIMIT ")
+        .push_bind(limit);
+
+    let rows = query.build().fetch_all(pool).await.map_err(db_error)?;
+
+    rows.iter().map(row_to_sample).collect()
+}
+
+/// Finds samples of a run whose input or output contains `q` and/or whose
+/// per-sample `metric` is below `lt`, ordered by `sample_index`.
+pub async fn search(pool: &DbPool, search: &SampleSearch) -> Result<SamplePage, DomainError> {
+    let threshold = search.metric_threshold()?;
+    let limit = search.page_size();
+    let offset = search.offset.max(0);
+
+    let mut query: QueryBuilder<MySql> = QueryBuilder::new(format!(
+        "SELECT {SAMPLE_COLUMNS} FROM sample_outputs WHERE run_id = "
+    ));
+    query.push_bind(search.run_id.to_string());
+    if let Some(q) = search.text() {
+        let pattern = like_pattern(q);
+        query
+            .push(" AND (output_text LIKE ")
+            .push_bind(pattern.clone())
+            .push(" OR input_text LIKE ")
+            .push_bind(pattern)
+            .push(")");
+    }
+    if let Some((metric, lt)) = threshold {
+        query
+            .push(" AND JSON_EXTRACT(metrics_json, ")
+            .push_bind(metric_path(metric))
+            .push(") < ")
+            .push_bind(lt);
+    }
+    query
+        .push(" ORDER BY sample_index ASC, id ASC LIMIT ")
+        .push_bind(limit + 1)
+        .push(" OFFSET ")
+        .push_bind(offset);
 
-    let mut samples = Vec::new();
+    let rows = query.build().fetch_all(pool).await.map_err(db_error)?;
+    let items = rows
+        .iter()
+        .map(row_to_sample)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(SamplePage::from_rows(items, limit, offset))
+}
+
+/// Aligns the samples of two runs on `(dataset, subset, split, sample_index)`.
+/// Input and reference are taken from the left run.
+pub async fn diff(pool: &DbPool, left: &Uuid, right: &Uuid) -> Result<SampleDiff, DomainError> {
+    let rows = sqlx::query(
+        "SELECT l.dataset, l.subset, l.split, l.sample_index, l.input_text, l.reference_text, \
+         l.output_text AS left_output, r.output_text AS right_output, \
+         l.metrics_json AS left_metrics, r.metrics_json AS right_metrics \
+         FROM sample_outputs l JOIN sample_outputs r \
+         ON r.run_id = ? AND r.dataset = l.dataset AND r.subset <=> l.subset \
+         AND r.split <=> l.split AND r.sample_index = l.sample_index \
+         WHERE l.run_id = ? \
+         ORDER BY l.dataset ASC, l.subset ASC, l.split ASC, l.sample_index ASC",
+    )
+    .bind(right.to_string())
+    .bind(left.to_string())
+    .fetch_all(pool)
+    .await
+    .map_err(db_error)?;
+    let items = rows
+        .iter()
+        .map(|row| {
+            Ok(SampleDiffRow {
+                dataset: row.try_get("dataset")?,
+                subset: row.try_get("subset")?,
+                split: row.try_get("split")?,
+                sample_index: row.try_get("sample_index")?,
+                input: row.try_get("input_text")?,
+                reference: row.try_get("reference_text")?,
+                left_output: row.try_get("left_output")?,
+                right_output: row.try_get("right_output")?,
+                left_metrics: parse_json(row, "left_metrics"),
+                right_metrics: parse_json(row, "right_metrics"),
+            })
+        })
+        .collect::<Result<Vec<_>, DomainError>>()?;
+
+    let totals = sqlx::query(
+        "SELECT (SELECT COUNT(*) FROM sample_outputs WHERE run_id = ?) AS left_total, \
+         (SELECT COUNT(*) FROM sample_outputs WHERE run_id = ?) AS right_total",
+    )
+    .bind(left.to_string())
+    .bind(right.to_string())
+    .fetch_one(pool)
+    .await
+    .map_err(db_error)?;
+    let matched = items.len() as i64;
+
+    Ok(SampleDiff {
+        left_only: totals.try_get::<i64, _>("left_total")? - matched,
+        right_only: totals.try_get::<i64, _>("right_total")? - matched,
+        items,
+    })
+}
+
+pub async fn token_summary(pool: &DbPool, run_id: &Uuid) -> Result<TokenSummary, DomainError> {
+    let row = sqlx::query(
+        "SELECT COUNT(*) AS samples, \
+         CAST(COALESCE(SUM(token_counts_json IS NULL), 0) AS SIGNED) AS missing, \
+         CAST(COALESCE(SUM(JSON_EXTRACT(token_counts_json, '$.prompt_tokens')), 0) AS SIGNED) AS prompt_tokens, \
+         CAST(COALESCE(SUM(JSON_EXTRACT(token_counts_json, '$.completion_tokens')), 0) AS SIGNED) AS completion_tokens, \
+         CAST(COALESCE(SUM(JSON_EXTRACT(token_counts_json, '$.total_tokens')), 0) AS SIGNED) AS total_tokens \
+         FROM sample_outputs WHERE run_id = ?",
+    )
+    .bind(run_id.to_string())
+    .fetch_one(pool)
+    .await
+    .map_err(db_error)?;
+
+    Ok(TokenSummary::new(
+        row.try_get("samples")?,
+        row.try_get("missing")?,
+        row.try_get("prompt_tokens")?,
+        row.try_get("completion_tokens")?,
+        row.try_get("total_tokens")?,
+    ))
+}
+
+/// Nearest-rank latency percentiles of the run's successful samples. Each
+/// percentile is an `OFFSET` walk along the `(run_id, latency_ms)` index up
+/// to its rank, checking `error_json` on every row it passes, so the cost
+/// grows with the run's size, but only the selected values are fetched.
+pub async fn latency_percentiles(
+    pool: &DbPool,
+    run_id: &Uuid,
+) -> Result<LatencyPercentiles, DomainError> {
+    const FILTER: &str = "run_id = ? AND latency_ms IS NOT NULL AND error_json IS NULL";
+    let samples: i64 = sqlx::query_scalar(&format!(
+        "SELECT COUNT(*) FROM sample_outputs WHERE {FILTER}"
+    ))
+    .bind(run_id.to_string())
+    .fetch_one(pool)
+    .await
+    .map_err(db_error)?;
+
+    let mut values = [None; LATENCY_PERCENTILES.len()];
+    if samples > 0 {
+        let sql = format!(
+            "SELECT latency_ms FROM sample_outputs WHERE {FILTER} \
+             ORDER BY latency_ms ASC LIMIT 1 OFFSET ?"
+        );
+        for (value, percentile) in values.iter_mut().zip(LATENCY_PERCENTILES) {
+            let latency: Option<i64> = sqlx::query_scalar(&sql)
+                .bind(run_id.to_string())
+                .bind(nearest_rank(percentile, samples))
+                .fetch_optional(pool)
+                .await
+                .map_err(db_error)?;
+            *value = latency.map(|ms| ms as f64);
+        }
+    }
+    let [p50_ms, p95_ms, p99_ms] = values;
+    Ok(LatencyPercentiles {
+        run_id: *run_id,
+        samples,
+        p50_ms,
+        p95_ms,
+        p99_ms,
+    })
+}
+
+/// Counts the run's failed samples by error kind.
+pub async fn error_summary(
+    pool: &DbPool,
+    run_id: &Uuid,
+) -> Result<SampleErrorSummary, DomainError> {
+    let rows = sqlx::query(
+        "SELECT JSON_UNQUOTE(JSON_EXTRACT(error_json, '$.kind')) AS kind, COUNT(*) AS failed \
+         FROM sample_outputs WHERE run_id = ? AND error_json IS NOT NULL \
+         GROUP BY kind",
+    )
+    .bind(run_id.to_string())
+    .fetch_all(pool)
+    .await
+    .map_err(db_error)?;
+
+    let mut counts = Vec::with_capacity(rows.len());
     for row in rows {
-        let metrics = row
-            .try_get::<Option<String>, _>("metrics_json")
-            .ok()
-            .flatten()
-            .map(|raw| serde_json::from_str(&raw).unwrap_or(Value::Null));
-        let token_counts = row
-            .try_get::<Option<String>, _>("token_counts_json")
-            .ok()
-            .flatten()
-            .map(|raw| serde_json::from_str(&raw).unwrap_or(Value::Null));
-        let error = row
-            .try_get::<Option<String>, _>("error_json")
-            .ok()
-            .flatten()
-            .map(|raw| serde_json::from_str(&raw).unwrap_or(Value::Null));
-
-        samples.push(SampleOutput {
-            id: Uuid::parse_str(row.try_get::<String, _>("id")?.as_str())
-                .map_err(|e| DomainError::Internal(e.to_string()))?,
-            run_id: Uuid::parse_str(row.try_get::<String, _>("run_id")?.as_str())
-                .map_err(|e| DomainError::Internal(e.to_string()))?,
-            dataset: row.try_get("dataset")?,
-            subset: row.try_get("subset")?,
-            split: row.try_get("split")?,
-            sample_index: row.try_get("sample_index")?,
-            input: row.try_get("input_text")?,
-            reference: row.try_get("reference_text")?,
-            output: row.try_get("output_text")?,
-            metrics,
-            latency_ms: row.try_get("latency_ms")?,
-            token_counts,
-            error,
-            created_at: row.try_get("created_at")?,
-        });
-    }
-
-    Ok(samples)
+        counts.push((row.try_get("kind")?, row.try_get("failed")?));
+    }
+    Ok(SampleErrorSummary::from_counts(*run_id, counts))
 }
 
+/// Writes samples, replacing any earlier row at the same
+/// `(run_id, dataset, subset, split, sample_index)` so a resumed run that
+/// re-reports samples doesn't duplicate them.
 pub async fn save_inline(
     pool: &DbPool,
     records: &[SampleRecord],
 ) -> Result<SampleResultLocation, DomainError> {
     for record in records {
-        sqlx::query("INSERT INTO sample_outputs (id, run_id, dataset, subset, split, sample_index, input_text, reference_text, output_text, metrics_json, latency_ms, token_counts_json, error_json, created_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)")
+        sqlx::query("INSERT INTO sample_outputs (id, run_id, dataset, subset, split, sample_index, input_text, reference_text, output_text, metrics_json, latency_ms, token_counts_json, error_json, created_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?) ON DUPLICATE KEY UPDATE input_text = VALUES(input_text), reference_text = VALUES(reference_text), output_text = VALUES(output_text), metrics_json = VALUES(metrics_json), latency_ms = VALUES(latency_ms), token_counts_json = VALUES(token_counts_json), error_json = VALUES(error_json)")
             .bind(Uuid::new_v4().to_string())
             .bind(record.run_id.to_string())
             .bind(&record.dataset)
@@ -88,17 +635,87 @@ pub async fn save_inline(
             .bind(&record.input)
             .bind(&record.reference)
             .bind(&record.output)
-            .bind(record.metrics.as_ref().map(|v| serde_json::to_string(v).unwrap_or_else(|_| "{}".into())))
+            .bind(record.metrics.as_ref().map(|v| to_canonical_json(v).unwrap_or_else(|_| "{}".into())))
             .bind(record.latency_ms)
-            .bind(record.token_counts.as_ref().map(|v| serde_json::to_string(v).unwrap_or_else(|_| "{}".into())))
-            .bind(record.error.as_ref().map(|v| serde_json::to_string(v).unwrap_or_else(|_| "{}".into())))
+            .bind(record.token_counts.as_ref().map(|v| to_canonical_json(v).unwrap_or_else(|_| "{}".into())))
+            .bind(record.error.as_ref().map(|v| to_canonical_json(v).unwrap_or_else(|_| "{}".into())))
             .bind(Utc::now())
             .execute(pool)
             .await
-            .map_err(|e| DomainError::Internal(e.to_string()))?;
+            .map_err(db_error)?;
     }
 
     Ok(SampleResultLocation::Inline {
         samples: records.to_vec(),
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The percentiles the OFFSET queries select from `sorted` latencies.
+    fn percentiles(sorted: &[i64]) -> Vec<i64> {
+        LATENCY_PERCENTILES
+            .iter()
+            .map(|&percentile| sorted[nearest_rank(percentile, sorted.len() as i64) as usize])
+            .collect()
+    }
+
+    #[test]
+    fn latency_percentiles_use_the_nearest_rank() {
+        let latencies: Vec<i64> = (1..=100).map(|ms| ms * 10).collect();
+        assert_eq!(percentiles(&latencies), [500, 950, 990]);
+
+        // Ranks round up, so the tail percentiles of small runs are their
+        // slowest sample.
+        assert_eq!(percentiles(&[80, 95, 120, 300]), [95, 300, 300]);
+        assert_eq!(percentiles(&[42]), [42, 42, 42]);
+    }
+
+    /// What follows `FROM sample_outputs WHERE` in the query for `filter`.
+    fn conditions(filter: &SampleFilter) -> String {
+        let query = list_query(filter);
+        let (_, conditions) = query
+            .sql()
+            .split_once(" FROM sample_outputs WHERE ")
+            .unwrap();
+        conditions.to_string()
+    }
+
+    #[test]
+    fn sample_filters_add_bound_conditions() {
+        let all = SampleFilter::for_run(Uuid::new_v4());
+        assert_eq!(
+            conditions(&all),
+            "run_id = ? ORDER BY sample_index ASC, id ASC"
+        );
+        let dataset = SampleFilter {
+            dataset: Some("gsm8k".into()),
+            ..all.clone()
+        };
+        assert_eq!(
+            conditions(&dataset),
+            "run_id = ? AND dataset = ? ORDER BY sample_index ASC, id ASC"
+        );
+        let split = SampleFilter {
+            split: Some("test".into()),
+            ..all.clone()
+        };
+        assert_eq!(
+            conditions(&split),
+            "run_id = ? AND split = ? ORDER BY sample_index ASC, id ASC"
+        );
+        let both = SampleFilter {
+            dataset: Some("gsm8k".into()),
+            split: Some("test".into()),
+            limit: Some(25),
+            ..all
+        };
+        assert_eq!(
+            conditions(&both),
+            "run_id = ? AND dataset = ? AND split = ? \
+             ORDER BY sample_index ASC, id ASC LIMIT ? OFFSET ?"
+        );
+    }
+}
@@ -1,11 +1,13 @@
-use crate::db::DbPool;
-use crate::utils::parse_uuid;
+use crate::db::{db_error, DbPool};
+use crate::utils::{json_column, parse_uuid};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use sqlx::mysql::MySqlRow;
-use sqlx::Row;
-use unified_shared::error::DomainError;
+use sqlx::types::Json;
+use sqlx::{MySql, QueryBuilder, Row};
+use unified_shared::error::{DomainError, FieldErrors};
+use unified_shared::eval::TaskType;
 use uuid::Uuid;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -16,6 +18,8 @@ pub struct Task {
     pub name: String,
     pub task_type: String,
     pub eval_engine: String,
+    /// Merged over the experiment's `global_config` when runs are compiled,
+    /// so it often holds only part of a config.
     pub eval_config: Value,
     pub default_metrics: Option<Value>,
     pub created_at: DateTime<Utc>,
@@ -32,10 +36,23 @@ pub struct NewTask {
     pub default_metrics: Option<Value>,
 }
 
+impl NewTask {
+    /// Checks the payload and returns the canonical task type.
+    pub fn validate(&self) -> Result<String, DomainError> {
+        let mut errors = FieldErrors::new();
+        errors.require_non_empty("name", &self.name);
+        let task_type = self
+            .task_type
+            .parse::<TaskType>()
+            .map_err(|err| errors.push("task_type", err.to_string()))
+            .ok();
+        errors.finish()?;
+        Ok(task_type.map(|t| t.to_string()).unwrap_or_default())
+    }
+}
+
 fn row_to_task(row: &MySqlRow) -> Result<Task, DomainError> {
-    let eval_config: String = row.try_get("eval_config_json")?;
-    let eval_value: Value =
-        serde_json::from_str(&eval_config).map_err(|e| DomainError::Internal(e.to_string()))?;
+    let eval_value = json_column(row, "eval_config_json")?;
     let metrics_value = row
         .try_get::<Option<String>, _>("default_metrics_json")?
         .map(|raw| serde_json::from_str(&raw).unwrap_or(Value::Null));
@@ -54,20 +71,54 @@ fn row_to_task(row: &MySqlRow) -> Result<Task, DomainError> {
 }
 
 pub async fn list(pool: &DbPool, project_id: &Uuid) -> Result<Vec<Task>, DomainError> {
-    let rows = sqlx::query("SELECT id, project_id, dataset_id, name, task_type, eval_engine, eval_config_json, default_metrics_json, created_at FROM tasks WHERE project_id = ? ORDER BY created_at DESC")
+    let rows = sqlx::query("SELECT id, project_id, dataset_id, name, task_type, eval_engine, eval_config_json, default_metrics_json, created_at FROM tasks WHERE project_id = ? ORDER BY created_at DESC, id DESC")
         .bind(project_id.to_string())
         .fetch_all(pool)
         .await
-        .map_err(|e| DomainError::Internal(e.to_string()))?;
+        .map_err(db_error)?;
 
     rows.iter().map(row_to_task).collect()
 }
 
+/// Fetches the tasks with the given ids in a single query, in the order of
+/// `ids`. Ids without a matching task are skipped.
+pub async fn list_by_ids(pool: &DbPool, ids: &[Uuid]) -> Result<Vec<Task>, DomainError> {
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut query: QueryBuilder<MySql> = QueryBuilder::new("SELECT id, project_id, dataset_id, name, task_type, eval_engine, eval_config_json, default_metrics_json, created_at FROM tasks WHERE id IN (");
+    let mut separated = query.separated(", ");
+    for id in ids {
+        separated.push_bind(id.to_string());
+    }
+    separated.push_unseparated(")");
+
+    let rows = query.build().fetch_all(pool).await.map_err(db_error)?;
+    let mut tasks = rows
+        .iter()
+        .map(row_to_task)
+        .collect::<Result<Vec<_>, _>>()?;
+    tasks.sort_by_key(|task| ids.iter().position(|id| *id == task.id));
+    Ok(tasks)
+}
+
+pub async fn get(pool: &DbPool, id: &Uuid) -> Result<Task, DomainError> {
+    let row = sqlx::query("SELECT id, project_id, dataset_id, name, task_type, eval_engine, eval_config_json, default_metrics_json, created_at FROM tasks WHERE id = ?")
+        .bind(id.to_string())
+        .fetch_optional(pool)
+        .await
+        .map_err(db_error)?;
+
+    match row {
+        Some(row) => row_to_task(&row),
+        None => Err(DomainError::NotFound("task not found".into())),
+    }
+}
+
 pub async fn create(pool: &DbPool, payload: NewTask) -> Result<Task, DomainError> {
+    let task_type = payload.validate()?;
     let id = Uuid::new_v4();
     let now = Utc::now();
-    let eval_config_str = serde_json::to_string(&payload.eval_config)
-        .map_err(|e| DomainError::Internal(e.to_string()))?;
     let default_metrics_str = match payload.default_metrics {
         Some(ref value) => {
             Some(serde_json::to_string(value).map_err(|e| DomainError::Internal(e.to_string()))?)
@@ -80,21 +131,21 @@ pub async fn create(pool: &DbPool, payload: NewTask) -> Result<Task, DomainError
         .bind(payload.project_id.to_string())
         .bind(payload.dataset_id.to_string())
         .bind(&payload.name)
-        .bind(&payload.task_type)
+        .bind(&task_type)
         .bind(&payload.eval_engine)
-        .bind(eval_config_str)
+        .bind(Json(&payload.eval_config))
         .bind(default_metrics_str)
         .bind(now)
         .execute(pool)
         .await
-        .map_err(|e| DomainError::Internal(e.to_string()))?;
+        .map_err(db_error)?;
 
     Ok(Task {
         id,
         project_id: payload.project_id,
         dataset_id: payload.dataset_id,
         name: payload.name,
-        task_type: payload.task_type,
+        task_type,
         eval_engine: payload.eval_engine,
         eval_config: payload.eval_config,
         default_metrics: payload.default_metrics,
@@ -0,0 +1,183 @@
+use std::collections::BTreeMap;
+
+use serde_json::Value;
+use unified_shared::error::{DomainError, FieldErrors};
+
+/// Variables available to a template, by name.
+pub type Variables = BTreeMap<String, Value>;
+
+/// Substitutes `${name}` references in every string of `template`.
+///
+/// A string that is exactly one reference takes the variable's value as is,
+/// so `"seed": "${seed}"` stays a number; references inside longer strings
+/// are replaced by the value's text (strings without quotes). `$${` stands
+/// for a literal `${`. References to unknown variables are field errors
+/// keyed by the path of the string they appear in.
+pub fn render(template: &Value, vars: &Variables) -> Result<Value, DomainError> {
+    let mut errors = FieldErrors::new();
+    let rendered = render_value(template, vars, "", &mut errors);
+    errors.finish()?;
+    Ok(rendered)
+}
+
+/// Every combination of the values in `grid`, e.g. `{seed: [1, 2], lr: [a]}`
+/// gives `{seed: 1, lr: a}` and `{seed: 2, lr: a}`. An empty grid has one
+/// empty combination; a variable with no values has none.
+pub fn grid(grid: &BTreeMap<String, Vec<Value>>) -> Vec<Variables> {
+    let mut combinations = vec![Variables::new()];
+    for (name, values) in grid {
+        combinations = combinations
+            .into_iter()
+            .flat_map(|vars| {
+                values.iter().map(move |value| {
+                    let mut vars = vars.clone();
+                    vars.insert(name.clone(), value.clone());
+                    vars
+                })
+            })
+            .collect();
+    }
+    combinations
+}
+
+/// How many combinations [`grid`] gives, without building them; saturates
+/// at `usize::MAX`.
+pub fn grid_len(grid: &BTreeMap<String, Vec<Value>>) -> usize {
+    grid.values()
+        .fold(1, |len, values| len.saturating_mul(values.len()))
+}
+
+fn render_value(value: &Value, vars: &Variables, path: &str, errors: &mut FieldErrors) -> Value {
+    match value {
+        Value::String(text) => render_string(text, vars, path, errors),
+        Value::Array(items) => Value::Array(
+            items
+                .iter()
+                .enumerate()
+                .map(|(i, item)| render_value(item, vars, &format!("{path}[{i}]"), errors))
+                .collect(),
+        ),
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(key, item)| {
+                    let child = if path.is_empty() {
+                        key.clone()
+                    } else {
+                        format!("{path}.{key}")
+                    };
+                    (key.clone(), render_value(item, vars, &child, errors))
+                })
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+fn render_string(text: &str, vars: &Variables, path: &str, errors: &mut FieldErrors) -> Value {
+    if let Some(name) = text
+        .strip_prefix("${")
+        .and_then(|rest| rest.strip_suffix('}'))
+        .filter(|name| !name.contains(['{', '}']))
+    {
+        return match vars.get(name) {
+            Some(value) => value.clone(),
+            None => {
+                errors.push(path, format!("unresolved template variable ${{{name}}}"));
+                Value::String(text.to_string())
+            }
+        };
+    }
+
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("${") {
+        if rest[..start].ends_with('$') {
+            out.push_str(&rest[..start - 1]);
+            out.push_str("${");
+            rest = &rest[start + 2..];
+            continue;
+        }
+        out.push_str(&rest[..start]);
+        let Some(len) = rest[start..].find('}') else {
+            break;
+        };
+        let name = &rest[start + 2..start + len];
+        match vars.get(name) {
+            Some(Value::String(value)) => out.push_str(value),
+            Some(value) => out.push_str(&value.to_string()),
+            None => {
+                errors.push(path, format!("unresolved template variable ${{{name}}}"));
+                out.push_str(&rest[start..=start + len]);
+            }
+        }
+        rest = &rest[start + len + 1..];
+    }
+    out.push_str(rest);
+    Value::String(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn vars(value: Value) -> Variables {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn exact_references_keep_their_type() {
+        let rendered = render(
+            &json!({"seed": "${seed}", "args": ["${flag}"]}),
+            &vars(json!({"seed": 3, "flag": true})),
+        )
+        .unwrap();
+        assert_eq!(rendered, json!({"seed": 3, "args": [true]}));
+    }
+
+    #[test]
+    fn references_inside_text_are_replaced_by_their_text() {
+        let rendered = render(
+            &json!("run-${name}-${seed}"),
+            &vars(json!({"name": "base", "seed": 7})),
+        )
+        .unwrap();
+        assert_eq!(rendered, json!("run-base-7"));
+    }
+
+    #[test]
+    fn escaped_references_stay_literal() {
+        let vars = vars(json!({"seed": 1}));
+        assert_eq!(render(&json!("$${seed}"), &vars).unwrap(), json!("${seed}"));
+        assert_eq!(
+            render(&json!("a $${seed} b ${seed}"), &vars).unwrap(),
+            json!("a ${seed} b 1")
+        );
+    }
+
+    #[test]
+    fn unresolved_references_are_field_errors() {
+        let err = render(&json!({"model": {"path": "${missing}"}}), &Variables::new()).unwrap_err();
+        match err {
+            DomainError::InvalidFields(fields) => {
+                assert_eq!(fields.len(), 1);
+                assert_eq!(fields[0].field, "model.path");
+            }
+            other => panic!("unexpected error: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn grid_gives_every_combination() {
+        let grid: BTreeMap<String, Vec<Value>> =
+            serde_json::from_value(json!({"seed": [1, 2], "lr": ["a", "b", "c"]})).unwrap();
+        let combinations = super::grid(&grid);
+        assert_eq!(combinations.len(), 6);
+        assert_eq!(grid_len(&grid), 6);
+        assert!(combinations.contains(&vars(json!({"seed": 2, "lr": "c"}))));
+
+        let empty = BTreeMap::new();
+        assert_eq!(super::grid(&empty), vec![Variables::new()]);
+        assert_eq!(grid_len(&empty), 1);
+    }
+}
@@ -1,6 +1,300 @@
+use serde::Serialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use sqlx::mysql::MySqlRow;
+use sqlx::types::Json;
+use sqlx::Row;
+use std::collections::BTreeSet;
+use std::sync::OnceLock;
 use unified_shared::error::DomainError;
+use unified_shared::eval::{EvalConfig, TaskType};
 use uuid::Uuid;
 
 pub fn parse_uuid(value: &str) -> Result<Uuid, DomainError> {
     Uuid::parse_str(value).map_err(|err| DomainError::Internal(err.to_string()))
 }
+
+/// A stored `eval_config` deserialized into [`EvalConfig`] on first use. The
+/// error of a config that doesn't match is kept too, so every call reports
+/// the same mismatch.
+#[derive(Debug, Clone, Default)]
+pub struct ParsedConfig(OnceLock<Result<EvalConfig, String>>);
+
+impl ParsedConfig {
+    /// Parses `raw` unless an earlier call did; `owner` names the config in
+    /// the error, e.g. `run <id>`. A mismatch is `Unprocessable`.
+    pub fn get(
+        &self,
+        raw: &Value,
+        owner: impl FnOnce() -> String,
+    ) -> Result<&EvalConfig, DomainError> {
+        self.0
+            .get_or_init(|| {
+                serde_json::from_value(raw.clone()).map_err(|err| {
+                    format!(
+                        "stored eval_config of {} does not match EvalConfig: {err}",
+                        owner()
+                    )
+                })
+            })
+            .as_ref()
+            .map_err(|message| DomainError::Unprocessable(message.clone()))
+    }
+}
+
+/// Deep-merges `overlay` into `base`: objects are merged key by key, and any
+/// other overlay value (including arrays and `null`) replaces the base one.
+pub fn merge_json(base: &mut Value, overlay: &Value) {
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                merge_json(base.entry(key.clone()).or_insert(Value::Null), value);
+            }
+        }
+        (base, overlay) => *base = overlay.clone(),
+    }
+}
+
+/// One difference found by [`diff_json`]. `path` is dotted for object keys and
+/// indexed for array elements, e.g. `sampling.temperature` or
+/// `metrics[1].name`; the root itself is `""`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct JsonDiff {
+    pub path: String,
+    pub change: JsonChange,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub left: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub right: Option<Value>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JsonChange {
+    /// Only in `right`.
+    Added,
+    /// Only in `left`.
+    Removed,
+    Changed,
+}
+
+/// Recursively diffs two JSON values. Objects are compared key by key and
+/// arrays element by element, so a change deep inside is reported at its own
+/// path; any other mismatch (including a type change) is one `Changed` entry.
+/// Entries are ordered by path.
+pub fn diff_json(left: &Value, right: &Value) -> Vec<JsonDiff> {
+    let mut diffs = Vec::new();
+    diff_into(&mut diffs, String::new(), left, right);
+    diffs
+}
+
+fn diff_into(diffs: &mut Vec<JsonDiff>, path: String, left: &Value, right: &Value) {
+    match (left, right) {
+        (Value::Object(left), Value::Object(right)) => {
+            let keys: BTreeSet<&String> = left.keys().chain(right.keys()).collect();
+            for key in keys {
+                let child = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{path}.{key}")
+                };
+                diff_entry(diffs, child, left.get(key), right.get(key));
+            }
+        }
+        (Value::Array(left), Value::Array(right)) => {
+            for index in 0..left.len().max(right.len()) {
+                diff_entry(
+                    diffs,
+                    format!("{path}[{index}]"),
+                    left.get(index),
+                    right.get(index),
+                );
+            }
+        }
+        (left, right) if left != right => diffs.push(JsonDiff {
+            path,
+            change: JsonChange::Changed,
+            left: Some(left.clone()),
+            right: Some(right.clone()),
+        }),
+        _ => {}
+    }
+}
+
+fn diff_entry(
+    diffs: &mut Vec<JsonDiff>,
+    path: String,
+    left: Option<&Value>,
+    right: Option<&Value>,
+) {
+    let change = match (left, right) {
+        (Some(left), Some(right)) => return diff_into(diffs, path, left, right),
+        (Some(_), None) => JsonChange::Removed,
+        (None, Some(_)) => JsonChange::Added,
+        (None, None) => return,
+    };
+    diffs.push(JsonDiff {
+        path,
+        change,
+        left: left.cloned(),
+        right: right.cloned(),
+    });
+}
+
+/// Reads a `JSON` column (or a legacy `TEXT` one holding serialized JSON).
+/// Rows whose document was stored as a JSON string wrapping the serialized
+/// object, as happens when text is copied into a `JSON` column verbatim, are
+/// unwrapped once. Malformed JSON is an error rather than `null`.
+pub fn json_column(row: &MySqlRow, column: &str) -> Result<Value, DomainError> {
+    let Json(value) = row.try_get::<Json<Value>, _>(column)?;
+    Ok(unwrap_legacy_json(value))
+}
+
+/// [`json_column`] for a nullable column.
+pub fn optional_json_column(row: &MySqlRow, column: &str) -> Result<Option<Value>, DomainError> {
+    let value = row.try_get::<Option<Json<Value>>, _>(column)?;
+    Ok(value.map(|Json(value)| unwrap_legacy_json(value)))
+}
+
+fn unwrap_legacy_json(value: Value) -> Value {
+    if let Value::String(raw) = &value {
+        if let Ok(inner @ (Value::Object(_) | Value::Array(_))) = serde_json::from_str(raw) {
+            return inner;
+        }
+    }
+    value
+}
+
+pub const DEFAULT_PAGE_SIZE: i64 = 50;
+pub const MAX_PAGE_SIZE: i64 = 500;
+
+/// Resolves optional `limit`/`offset` query parameters into a clamped
+/// `(limit, offset)` pair, or `None` when neither was given.
+pub fn page_bounds(limit: Option<i64>, offset: Option<i64>) -> Option<(i64, i64)> {
+    if limit.is_none() && offset.is_none() {
+        return None;
+    }
+    Some((
+        limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE),
+        offset.unwrap_or(0).max(0),
+    ))
+}
+
+/// SHA-256 (hex) of `value` serialized with object keys sorted, so equal
+/// JSON hashes equally regardless of key order.
+pub fn canonical_json_hash(value: &Value) -> String {
+    fn write(value: &Value, out: &mut String) {
+        match value {
+            Value::Object(map) => {
+                let mut entries: Vec<_> = map.iter().collect();
+                entries.sort_by(|a, b| a.0.cmp(b.0));
+                out.push('{');
+                for (i, (key, value)) in entries.into_iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    out.push_str(&Value::String(key.clone()).to_string());
+                    out.push(':');
+                    write(value, out);
+                }
+                out.push('}');
+            }
+            Value::Array(items) => {
+                out.push('[');
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    write(item, out);
+                }
+                out.push(']');
+            }
+            scalar => out.push_str(&scalar.to_string()),
+        }
+    }
+
+    let mut out = String::new();
+    write(value, &mut out);
+    format!("{:x}", Sha256::digest(out.as_bytes()))
+}
+
+/// Validates a task type string and returns its canonical spelling.
+pub fn canonical_task_type(value: &str) -> Result<String, DomainError> {
+    value
+        .parse::<TaskType>()
+        .map(|task_type| task_type.to_string())
+        .map_err(|err| DomainError::Validation(err.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn changes(diffs: &[JsonDiff]) -> Vec<(&str, JsonChange)> {
+        diffs
+            .iter()
+            .map(|diff| (diff.path.as_str(), diff.change))
+            .collect()
+    }
+
+    #[test]
+    fn nested_changes_are_reported_at_their_path() {
+        let left = json!({
+            "sampling": {"temperature": 0.0, "top_p": 1.0},
+            "metrics": [{"name": "accuracy"}, {"name": "f1", "args": {"average": "macro"}}],
+            "canary": {"name": "gsm8k"},
+        });
+        let right = json!({
+            "sampling": {"temperature": 0.7, "top_p": 1.0, "max_tokens": 256},
+            "metrics": [{"name": "accuracy"}, {"name": "f1", "args": {"average": "micro"}}],
+            "canary": null,
+        });
+        let diffs = diff_json(&left, &right);
+        assert_eq!(
+            changes(&diffs),
+            [
+                ("canary", JsonChange::Changed),
+                ("metrics[1].args.average", JsonChange::Changed),
+                ("sampling.max_tokens", JsonChange::Added),
+                ("sampling.temperature", JsonChange::Changed),
+            ]
+        );
+        assert_eq!(diffs[1].left, Some(json!("macro")));
+        assert_eq!(diffs[1].right, Some(json!("micro")));
+        assert_eq!(diffs[2].left, None);
+        assert_eq!(diffs[2].right, Some(json!(256)));
+    }
+
+    #[test]
+    fn lists_are_compared_element_by_element() {
+        let diffs = diff_json(
+            &json!({"tags": ["a", "b", "c"]}),
+            &json!({"tags": ["a", "x"]}),
+        );
+        assert_eq!(
+            changes(&diffs),
+            [
+                ("tags[1]", JsonChange::Changed),
+                ("tags[2]", JsonChange::Removed),
+            ]
+        );
+        assert_eq!(diffs[1].left, Some(json!("c")));
+
+        let diffs = diff_json(&json!([1]), &json!([1, {"k": true}]));
+        assert_eq!(changes(&diffs), [("[1]", JsonChange::Added)]);
+    }
+
+    #[test]
+    fn equal_values_have_no_diff_and_type_changes_are_one_entry() {
+        let value = json!({"a": [1, {"b": null}], "c": "d"});
+        assert!(diff_json(&value, &value).is_empty());
+
+        let diffs = diff_json(&json!({"a": {"b": 1}}), &json!({"a": [1]}));
+        assert_eq!(changes(&diffs), [("a", JsonChange::Changed)]);
+        assert_eq!(
+            changes(&diff_json(&json!(1), &json!("1"))),
+            [("", JsonChange::Changed)]
+        );
+    }
+}
@@ -1,11 +1,660 @@
-use anyhow::Result;
+use anyhow::Context;
 use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::ffi::OsStr;
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use thiserror::Error;
+use tokio::process::Command;
 use unified_shared::eval::EvalConfig;
+use unified_shared::eval::EvalErrorKind;
+use unified_shared::eval::EvalErrorPayload;
 use unified_shared::eval::EvalResult;
+use unified_shared::eval::MetricRecord;
+use unified_shared::eval::ResourceEstimate;
+use unified_shared::eval::TaskConfig;
+use unified_shared::eval::ENGINE_VERSION_WARNING_KEY;
+use unified_shared::secrets;
+use unified_shared::settings::{IntegrationSettings, MissingChecksum};
+
+#[derive(Debug, Error)]
+pub enum RunnerError {
+    #[error("evaluation failure")]
+    Eval(EvalErrorPayload),
+    #[error(transparent)]
+    Io(#[from] anyhow::Error),
+    #[error("engine not supported")]
+    NotSupported,
+    /// The worker stopped the run because it was cancelled; runners never
+    /// return it themselves.
+    #[error("run was cancelled")]
+    Cancelled,
+}
+
+/// Env var through which runners hand the resolved API key to Python runners.
+pub const API_KEY_ENV: &str = "EVAL_API_KEY";
+
+/// Resolves `model.api_key_ref`; an unresolvable reference is a config error
+/// attributed to `engine`.
+pub fn resolve_api_key(config: &EvalConfig, engine: &str) -> Result<Option<String>, RunnerError> {
+    let Some(reference) = config.model.api_key_ref.as_deref() else {
+        return Ok(None);
+    };
+    secrets::resolve(reference).map(Some).map_err(|err| {
+        RunnerError::Eval(EvalErrorPayload {
+            kind: EvalErrorKind::Config,
+            message: format!("failed to resolve api_key_ref: {err}"),
+            code: Some("missing_secret".into()),
+            engine: Some(engine.into()),
+            details: None,
+        })
+    })
+}
+
+/// Checks a task's `harness_args` against `allowlist`, see
+/// [`TaskConfig::check_harness_args`]. The error is a config error
+/// attributed to `engine`.
+pub fn check_harness_args(
+    args: &[String],
+    allowlist: &[String],
+    engine: &str,
+) -> Result<(), RunnerError> {
+    TaskConfig::check_harness_args(args, allowlist).map_err(|message| {
+        RunnerError::Eval(EvalErrorPayload {
+            kind: EvalErrorKind::Config,
+            message,
+            code: Some("harness_args_rejected".into()),
+            engine: Some(engine.into()),
+            details: Some(serde_json::json!({ "allowlist": allowlist })),
+        })
+    })
+}
+
+/// Per-run working directories under `integrations.work_dir`. Paths are
+/// absolute, so runners may hand them to engines running from elsewhere, and
+/// keyed by run id, so concurrent runs never share one.
+#[derive(Debug, Clone)]
+pub struct RunDirs {
+    root: PathBuf,
+    cleanup_on_success: bool,
+}
+
+/// Resolves a relative path against the worker's working directory, since
+/// runners spawn engines from elsewhere.
+pub fn absolute(path: &Path) -> PathBuf {
+    if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        std::env::current_dir()
+            .map(|cwd| cwd.join(path))
+            .unwrap_or_else(|_| path.to_path_buf())
+    }
+}
+
+impl RunDirs {
+    pub fn new(settings: &IntegrationSettings) -> Self {
+        Self {
+            root: absolute(Path::new(&settings.work_dir)).join("runs"),
+            cleanup_on_success: settings.cleanup_run_dir_on_success,
+        }
+    }
+
+    pub fn path(&self, run_id: impl Display) -> PathBuf {
+        self.root.join(run_id.to_string())
+    }
+
+    /// Creates the run's directory if needed and returns its path.
+    pub async fn create(&self, run_id: impl Display) -> anyhow::Result<PathBuf> {
+        let run_dir = self.path(run_id);
+        tokio::fs::create_dir_all(&run_dir)
+            .await
+            .with_context(|| format!("failed to create run dir {}", run_dir.display()))?;
+        Ok(run_dir)
+    }
+
+    /// Removes the directory of a successful run when cleanup is enabled.
+    pub async fn cleanup(&self, run_id: impl Display) -> anyhow::Result<()> {
+        if !self.cleanup_on_success {
+            return Ok(());
+        }
+        self.remove(run_id).await
+    }
+
+    /// Removes the run's directory; a missing directory is not an error.
+    pub async fn remove(&self, run_id: impl Display) -> anyhow::Result<()> {
+        let run_dir = self.path(run_id);
+        match tokio::fs::remove_dir_all(&run_dir).await {
+            Ok(()) => Ok(()),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(err) => {
+                Err(err).with_context(|| format!("failed to remove run dir {}", run_dir.display()))
+            }
+        }
+    }
+}
+
+/// The interpreter and virtualenv an engine runs under, from
+/// `integrations.python_executable`/`virtualenv_path` and the engine's entry
+/// in `integrations.engines`.
+#[derive(Debug, Clone)]
+pub struct PythonEnv {
+    python: PathBuf,
+    virtualenv: Option<PathBuf>,
+}
+
+impl PythonEnv {
+    pub fn for_engine(settings: &IntegrationSettings, engine: &str) -> Self {
+        let overrides = settings.engines.get(engine);
+        let virtualenv = overrides
+            .and_then(|o| o.virtualenv_path.as_deref())
+            .or(settings.virtualenv_path.as_deref())
+            .map(|venv| absolute(Path::new(venv)));
+        let python = overrides
+            .and_then(|o| o.python_executable.as_deref())
+            .unwrap_or(&settings.python_executable);
+        let python = Path::new(python);
+        // A bare name means "the venv's interpreter" when a venv is set;
+        // explicit paths are used as given.
+        let python = match &virtualenv {
+            _ if python.components().count() > 1 => absolute(python),
+            Some(venv) => venv_bin(venv).join(python),
+            None => python.to_path_buf(),
+        };
+        Self { python, virtualenv }
+    }
+
+    /// The interpreter that gets spawned.
+    pub fn python(&self) -> &Path {
+        &self.python
+    }
+
+    /// A command running the configured interpreter.
+    pub fn python_command(&self) -> Command {
+        self.command(&self.python)
+    }
+
+    /// A command for `program` inside the virtualenv, if any: `VIRTUAL_ENV`
+    /// is set and its `bin` directory leads `PATH`, so console scripts such as
+    /// `oaieval` resolve to the venv's copy.
+    pub fn command(&self, program: impl AsRef<OsStr>) -> Command {
+        let mut cmd = Command::new(program);
+        if let Some(venv) = &self.virtualenv {
+            let mut paths = vec![venv_bin(venv)];
+            if let Some(path) = std::env::var_os("PATH") {
+                paths.extend(std::env::split_paths(&path));
+            }
+            if let Ok(path) = std::env::join_paths(paths) {
+                cmd.env("PATH", path);
+            }
+            cmd.env("VIRTUAL_ENV", venv).env_remove("PYTHONHOME");
+        }
+        cmd
+    }
+
+    /// Version of the installed distribution `package`, as reported by
+    /// `importlib.metadata`. `None` when it isn't installed or the
+    /// interpreter can't be run.
+    pub async fn package_version(&self, package: &str) -> Option<String> {
+        let mut cmd = self.python_command();
+        cmd.arg("-c")
+            .arg("import sys, importlib.metadata as m; print(m.version(sys.argv[1]))")
+            .arg(package);
+        match probe_command(cmd).await {
+            Ok(version) if !version.is_empty() => Some(version),
+            Ok(_) => None,
+            Err(err) => {
+                tracing::warn!("failed to query the installed version of {package}: {err:#}");
+                None
+            }
+        }
+    }
+}
+
+fn venv_bin(venv: &Path) -> PathBuf {
+    venv.join(if cfg!(windows) { "Scripts" } else { "bin" })
+}
+
+/// Written by the Python runner to `progress.json` in the run directory as
+/// samples complete, so a crashed run can resume instead of starting over.
+/// The worker also reads it while the run is going to check its error rate.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunProgress {
+    pub last_completed_sample_index: i64,
+    /// Samples finished so far, failed ones included.
+    #[serde(default)]
+    pub completed_samples: Option<u64>,
+    /// Samples that finished with an error.
+    #[serde(default)]
+    pub failed_samples: Option<u64>,
+}
+
+pub const PROGRESS_FILE: &str = "progress.json";
+
+/// Reads `progress.json` from `run_dir`, if a previous attempt left one.
+pub async fn read_progress(run_dir: &Path) -> anyhow::Result<Option<RunProgress>> {
+    let path = run_dir.join(PROGRESS_FILE);
+    match tokio::fs::read(&path).await {
+        Ok(data) => Ok(Some(
+            serde_json::from_slice(&data).context("invalid progress.json")?,
+        )),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err).context("failed to read progress.json"),
+    }
+}
+
+/// Written by runners of multi-task suites to `partial_result.json` in the
+/// run directory as sub-tasks finish: every metric so far, rewritten whole
+/// on each update (via a temporary file and a rename, so readers never see
+/// half a file). The worker stores them as partial metrics until the final
+/// result replaces them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PartialResult {
+    pub metrics: Vec<MetricRecord>,
+}
+
+pub const PARTIAL_RESULT_FILE: &str = "partial_result.json";
+
+/// Reads `partial_result.json` from `run_dir`, if the runner wrote one.
+pub async fn read_partial_result(run_dir: &Path) -> anyhow::Result<Option<PartialResult>> {
+    let path = run_dir.join(PARTIAL_RESULT_FILE);
+    match tokio::fs::read(&path).await {
+        Ok(data) => Ok(Some(
+            serde_json::from_slice(&data).context("invalid partial_result.json")?,
+        )),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err).context("failed to read partial_result.json"),
+    }
+}
+
+/// Interval at which [`wait_for_file`] checks for the file.
+const FILE_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Whether `path` exists, polling for up to `grace` if it doesn't yet: a
+/// runner may exit before its output file is flushed to disk. A file that
+/// never appears costs at most `grace`.
+pub async fn wait_for_file(path: &Path, grace: Duration) -> bool {
+    let deadline = tokio::time::Instant::now() + grace;
+    loop {
+        if tokio::fs::try_exists(path).await.unwrap_or(false) {
+            return true;
+        }
+        let now = tokio::time::Instant::now();
+        if now >= deadline {
+            return false;
+        }
+        tokio::time::sleep(FILE_POLL_INTERVAL.min(deadline - now)).await;
+    }
+}
+
+/// Written by Python runners next to `result.json`: the hex SHA-256 of the
+/// file, optionally followed by whitespace and a file name (`sha256sum`
+/// format).
+pub const RESULT_CHECKSUM_FILE: &str = "result.json.sha256";
+
+/// Checks `data`, the bytes of the run's `result.json`, against
+/// [`RESULT_CHECKSUM_FILE`] and returns its SHA-256. A mismatch (e.g. a
+/// truncated file) is an `infra` error with code `result_checksum_mismatch`;
+/// a missing checksum file is logged, or with `MissingChecksum::Error` an
+/// `infra` error with code `result_checksum_missing`.
+pub async fn verify_result_checksum(
+    run_dir: &Path,
+    data: &[u8],
+    missing: MissingChecksum,
+    engine: &str,
+) -> Result<String, EvalErrorPayload> {
+    let actual = format!("{:x}", Sha256::digest(data));
+    let error = |message: String, code: &str, details: serde_json::Value| EvalErrorPayload {
+        kind: EvalErrorKind::Infra,
+        message,
+        code: Some(code.into()),
+        engine: Some(engine.into()),
+        details: Some(details),
+    };
+    let path = run_dir.join(RESULT_CHECKSUM_FILE);
+    let expected = match tokio::fs::read_to_string(&path).await {
+        Ok(contents) => contents
+            .split_whitespace()
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase(),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            let message = format!("{} has no {RESULT_CHECKSUM_FILE}", run_dir.display());
+            return match missing {
+                MissingChecksum::Warn => {
+                    tracing::warn!("{message}; result.json is not verified");
+                    Ok(actual)
+                }
+                MissingChecksum::Error => Err(error(
+                    format!("{message}; set integrations.missing_result_checksum = \"warn\" to accept unverified results"),
+                    "result_checksum_missing",
+                    serde_json::json!({ "actual": actual }),
+                )),
+            };
+        }
+        Err(err) => {
+            return Err(error(
+                format!("failed to read {}: {err}", path.display()),
+                "result_checksum_unreadable",
+                serde_json::json!({ "actual": actual }),
+            ))
+        }
+    };
+    if expected != actual {
+        return Err(error(
+            format!(
+                "result.json does not match {RESULT_CHECKSUM_FILE} ({} bytes read); the file may be truncated or corrupted",
+                data.len()
+            ),
+            "result_checksum_mismatch",
+            serde_json::json!({ "expected": expected, "actual": actual }),
+        ));
+    }
+    Ok(actual)
+}
+
+/// Parameter count in billions from a model name such as `llama-2-7b`,
+/// `Qwen2-1.5B-Instruct` or `mixtral-8x7b` (counted as 56).
+pub fn model_size_billions(model_name: &str) -> Option<f64> {
+    let name = model_name.to_ascii_lowercase();
+    name.split(|c: char| !(c.is_ascii_alphanumeric() || c == '.'))
+        .filter_map(|part| {
+            let size = part.strip_suffix('b')?;
+            let (experts, size) = match size.split_once('x') {
+                Some((experts, size)) => (experts.parse::<f64>().ok()?, size),
+                None => (1.0, size),
+            };
+            let size: f64 = size.parse().ok()?;
+            (size > 0.0).then_some(experts * size)
+        })
+        .next_back()
+}
+
+/// Sample counts of common benchmarks, for estimating run length when the
+/// config sets no limit.
+pub fn known_dataset_size(name: &str) -> Option<u64> {
+    let size = match name.to_ascii_lowercase().as_str() {
+        "mmlu" => 14_042,
+        "hellaswag" => 10_042,
+        "gsm8k" => 1_319,
+        "arc_challenge" => 1_172,
+        "arc_easy" => 2_376,
+        "truthfulqa" | "truthfulqa_mc" | "truthfulqa_mc2" => 817,
+        "winogrande" => 1_267,
+        "humaneval" => 164,
+        "mbpp" => 500,
+        "boolq" => 3_270,
+        "piqa" => 1_838,
+        _ => return None,
+    };
+    Some(size)
+}
+
+/// `error.json` as written by Python runners. Every field is optional so a
+/// partially written file still yields a usable payload. `details` may carry
+/// anything; by convention `traceback` (string) and `offending_field` (dotted
+/// path into the `EvalConfig`).
+#[derive(Debug, Deserialize)]
+struct HarnessError {
+    kind: Option<EvalErrorKind>,
+    message: Option<String>,
+    code: Option<String>,
+    engine: Option<String>,
+    details: Option<serde_json::Value>,
+}
+
+/// Longest stderr excerpt attached to an error payload.
+const MAX_STDERR_BYTES: usize = 4096;
+
+fn stderr_tail(stderr: &[u8]) -> String {
+    let text = String::from_utf8_lossy(stderr);
+    let text = text.trim();
+    let mut start = text.len().saturating_sub(MAX_STDERR_BYTES);
+    while !text.is_char_boundary(start) {
+        start += 1;
+    }
+    text[start..].to_string()
+}
+
+/// Turns the contents of a runner's `error.json` into an error payload,
+/// defaulting `engine` to `engine`. A file that isn't valid JSON or has an
+/// unknown `kind` degrades to an `infra` error with code `invalid_error_json`
+/// that keeps the raw file and the tail of stderr in `details`.
+pub fn parse_error_file(data: &[u8], engine: &str, stderr: &[u8]) -> EvalErrorPayload {
+    match serde_json::from_slice::<HarnessError>(data) {
+        Ok(error) => EvalErrorPayload {
+            kind: error.kind.unwrap_or(EvalErrorKind::Unknown),
+            message: error
+                .message
+                .unwrap_or_else(|| "runner reported an error without a message".into()),
+            code: error.code,
+            engine: error.engine.or_else(|| Some(engine.into())),
+            details: error.details,
+        },
+        Err(err) => EvalErrorPayload {
+            kind: EvalErrorKind::Infra,
+            message: format!("runner wrote an unreadable error.json: {err}"),
+            code: Some("invalid_error_json".into()),
+            engine: Some(engine.into()),
+            details: Some(serde_json::json!({
+                "raw": String::from_utf8_lossy(data),
+                "stderr": stderr_tail(stderr),
+            })),
+        },
+    }
+}
+
+/// How long a startup probe may take before the engine counts as unavailable.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Runs a cheap command (`--version`, `--help`) to check that an engine is
+/// installed, returning its trimmed stdout. A missing executable, a non-zero
+/// exit or a hang are all errors that name the command.
+pub async fn probe_command(mut cmd: Command) -> anyhow::Result<String> {
+    let program = format!("{:?}", cmd.as_std());
+    cmd.kill_on_drop(true);
+    let output = tokio::time::timeout(PROBE_TIMEOUT, cmd.output())
+        .await
+        .with_context(|| format!("{program} did not finish within {PROBE_TIMEOUT:?}"))?
+        .with_context(|| format!("failed to start {program}"))?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "{program} exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
 
 #[async_trait]
 pub trait EvalRunner: Send + Sync {
-    async fn run(&self, config: &EvalConfig) -> Result<EvalResult>;
+    async fn run(&self, config: &EvalConfig) -> Result<EvalResult, RunnerError>;
     fn name(&self) -> &'static str;
+    /// Projects the run's footprint before it starts. Runners without
+    /// heuristics report what `config.resources` asks for.
+    fn estimate_resources(&self, config: &EvalConfig) -> ResourceEstimate {
+        ResourceEstimate::from_config(&config.resources)
+    }
+    /// Checks that the engine is installed and runnable. The worker calls
+    /// this once at startup; runners without external dependencies keep the
+    /// default.
+    async fn health_check(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
+    /// Where a finished run leaves the engine's unparsed output. The worker
+    /// copies it to the object store, when one is configured, before the run
+    /// directory is cleaned up; runners that don't keep one report `None`.
+    fn raw_result_path(&self, _config: &EvalConfig) -> Option<PathBuf> {
+        None
+    }
+    /// Version of the engine package installed for this runner, which it
+    /// records as `engine_version` in the result metadata. `None` when the
+    /// runner can't tell.
+    async fn installed_version(&self) -> Option<String> {
+        None
+    }
 }
 
+/// Compares the `engine_version` a runner recorded in the result metadata
+/// with the one the config requested, see [`check_installed_version`]. On a
+/// mismatch the warning is recorded under [`ENGINE_VERSION_WARNING_KEY`], or
+/// with `strict_version` returned as a config error attributed to `engine`.
+/// Nothing is checked unless both versions are known.
+pub fn check_engine_version(
+    config: &EvalConfig,
+    result: &mut EvalResult,
+    engine: &str,
+) -> Result<(), EvalErrorPayload> {
+    let Some(Value::Object(metadata)) = &mut result.metadata else {
+        return Ok(());
+    };
+    let Some(installed) = metadata.get("engine_version").and_then(Value::as_str) else {
+        return Ok(());
+    };
+    if let Some(message) = check_installed_version(config, installed, engine)? {
+        tracing::warn!("run {}: {message}", config.run_id);
+        metadata.insert(ENGINE_VERSION_WARNING_KEY.into(), Value::String(message));
+    }
+    Ok(())
+}
+
+/// Compares an `installed` engine version with the one the config
+/// requested. A requested version matches the same version or any release
+/// under it (`0.4` matches `0.4.2`). A mismatch is a config error attributed
+/// to `engine` under `strict_version`, else the returned warning; nothing is
+/// checked when the config requests no version.
+pub fn check_installed_version(
+    config: &EvalConfig,
+    installed: &str,
+    engine: &str,
+) -> Result<Option<String>, EvalErrorPayload> {
+    let Some(requested) = config.engine_version.as_deref().map(str::trim) else {
+        return Ok(None);
+    };
+    let installed = installed.trim();
+    let normalize = |version: &str| version.trim_start_matches('v').to_string();
+    let (wanted, actual) = (normalize(requested), normalize(installed));
+    if actual == wanted || actual.starts_with(&format!("{wanted}.")) {
+        return Ok(None);
+    }
+    let message = format!("engine_version {requested} was requested but {installed} is installed");
+    if config.strict_version {
+        return Err(EvalErrorPayload {
+            kind: EvalErrorKind::Config,
+            message,
+            code: Some("engine_version_mismatch".into()),
+            engine: Some(engine.into()),
+            details: Some(serde_json::json!({
+                "requested": requested,
+                "installed": installed,
+            })),
+        });
+    }
+    Ok(Some(message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn error_files_keep_their_fields_and_details() {
+        let data = json!({
+            "kind": "config",
+            "message": "unknown task gsm9k",
+            "code": "unknown_task",
+            "details": {"traceback": "Traceback ...", "offending_field": "task.task_name"},
+        })
+        .to_string();
+        let error = parse_error_file(data.as_bytes(), "lm_eval_harness", b"");
+        assert!(matches!(error.kind, EvalErrorKind::Config));
+        assert_eq!(error.message, "unknown task gsm9k");
+        assert_eq!(error.code.as_deref(), Some("unknown_task"));
+        assert_eq!(error.engine.as_deref(), Some("lm_eval_harness"));
+        let details = error.details.unwrap();
+        assert_eq!(details["offending_field"], "task.task_name");
+        assert_eq!(details["traceback"], "Traceback ...");
+    }
+
+    #[test]
+    fn partial_error_files_fall_back_to_defaults() {
+        let error = parse_error_file(br#"{"engine": "helm-fork"}"#, "helm", b"");
+        assert!(matches!(error.kind, EvalErrorKind::Unknown));
+        assert_eq!(error.message, "runner reported an error without a message");
+        assert_eq!(error.code, None);
+        assert_eq!(error.engine.as_deref(), Some("helm-fork"));
+        assert!(error.details.is_none());
+    }
+
+    #[test]
+    fn unreadable_error_files_become_infra_errors() {
+        // A truncated file, and one with a kind this worker doesn't know.
+        for data in [&br#"{"kind": "config", "mess"#[..], br#"{"kind": "oom"}"#] {
+            let error = parse_error_file(data, "custom", b"Traceback\nMemoryError\n");
+            assert!(matches!(error.kind, EvalErrorKind::Infra));
+            assert_eq!(error.code.as_deref(), Some("invalid_error_json"));
+            assert_eq!(error.engine.as_deref(), Some("custom"));
+            let details = error.details.unwrap();
+            assert_eq!(details["raw"], String::from_utf8_lossy(data).as_ref());
+            assert_eq!(details["stderr"], "Traceback\nMemoryError");
+        }
+    }
+
+    #[test]
+    fn stderr_excerpts_keep_the_tail() {
+        let stderr = format!("{}é{}", "a".repeat(10), "b".repeat(MAX_STDERR_BYTES - 1));
+        let tail = stderr_tail(stderr.as_bytes());
+        // The cut lands inside `é`, so it moves past it.
+        assert_eq!(tail, "b".repeat(MAX_STDERR_BYTES - 1));
+    }
+
+    /// A scratch path for `name`, unique to this test process.
+    fn scratch_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("integration-core-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[tokio::test]
+    async fn result_files_written_after_exit_are_picked_up() {
+        let path = scratch_path("late-result.json");
+        let writer = {
+            let path = path.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_millis(150)).await;
+                tokio::fs::write(path, b"{}").await.unwrap();
+            })
+        };
+
+        assert!(wait_for_file(&path, Duration::from_secs(5)).await);
+        writer.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn result_files_that_never_appear_cost_the_grace() {
+        let path = scratch_path("missing-result.json");
+        let grace = Duration::from_millis(200);
+
+        let started = std::time::Instant::now();
+        assert!(!wait_for_file(&path, grace).await);
+        let waited = started.elapsed();
+        assert!(waited >= grace, "gave up after {waited:?}");
+        assert!(waited < grace * 5, "waited {waited:?}");
+    }
+
+    #[tokio::test]
+    async fn existing_result_files_are_not_waited_for() {
+        let path = scratch_path("result.json");
+        std::fs::write(&path, b"{}").unwrap();
+
+        let started = std::time::Instant::now();
+        assert!(wait_for_file(&path, Duration::from_secs(5)).await);
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,365 @@
+//! Custom engines: runs an in-house eval command built from a template and
+//! reads back the same `result.json`/`error.json` as the Python runners.
+
+use anyhow::Context;
+use async_trait::async_trait;
+use integration_core::{
+    parse_error_file, resolve_api_key, verify_result_checksum, wait_for_file, RunDirs, API_KEY_ENV,
+    RESULT_CHECKSUM_FILE,
+};
+pub use integration_core::{EvalRunner, RunnerError};
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::time::Duration;
+use tokio::process::Command;
+use unified_shared::eval::{
+    CustomEngine, EvalConfig, EvalEngine, EvalErrorKind, EvalErrorPayload, EvalResult,
+    RESULT_CHECKSUM_METADATA_KEY,
+};
+use unified_shared::settings::{CustomEngineSettings, MissingChecksum, Settings};
+
+const ENGINE_NAME: &str = "custom";
+
+pub struct CommandRunner {
+    run_dirs: RunDirs,
+    engines: HashMap<String, CustomEngineSettings>,
+    missing_checksum: MissingChecksum,
+    result_grace: Duration,
+    env_passthrough: Vec<String>,
+}
+
+impl CommandRunner {
+    pub fn new(settings: &Settings) -> Self {
+        Self {
+            run_dirs: RunDirs::new(&settings.integrations),
+            engines: settings.integrations.custom_engines.clone(),
+            missing_checksum: settings.integrations.missing_result_checksum,
+            result_grace: Duration::from_millis(settings.integrations.result_grace_ms),
+            env_passthrough: settings.integrations.custom_env_passthrough.clone(),
+        }
+    }
+
+    /// The argv template and env of `engine`: its own `command`, else the
+    /// `integrations.custom_engines` entry it names.
+    fn template(
+        &self,
+        engine: &CustomEngine,
+    ) -> Result<(Vec<String>, BTreeMap<String, String>), EvalErrorPayload> {
+        if let Some(command) = engine
+            .command
+            .as_ref()
+            .filter(|command| !command.is_empty())
+        {
+            return Ok((command.clone(), engine.env.clone()));
+        }
+        let Some(name) = &engine.name else {
+            return Err(config_error(
+                "custom engine needs a `command` or the `name` of an integrations.custom_engines entry"
+                    .into(),
+                "custom_command_missing",
+            ));
+        };
+        let Some(settings) = self.engines.get(name) else {
+            return Err(config_error(
+                format!("integrations.custom_engines has no entry `{name}`"),
+                "unknown_custom_engine",
+            ));
+        };
+        // The run's own env extends the configured one.
+        let mut env = settings.env.clone();
+        env.extend(engine.env.clone());
+        Ok((settings.command.clone(), env))
+    }
+}
+
+fn config_error(message: String, code: &str) -> EvalErrorPayload {
+    EvalErrorPayload {
+        kind: EvalErrorKind::Config,
+        message,
+        code: Some(code.into()),
+        engine: Some(ENGINE_NAME.into()),
+        details: None,
+    }
+}
+
+/// Replaces `{run_dir}`, `{config_path}` and `{run_id}` in `template`. The
+/// result is always a single argument, so substituted values can't inject
+/// further arguments or shell syntax. Unknown placeholders are left as they
+/// are.
+pub fn substitute(template: &str, run_dir: &Path, config_path: &Path, run_id: &str) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        let Some(len) = rest[start..].find('}') else {
+            break;
+        };
+        match &rest[start + 1..start + len] {
+            "run_dir" => out.push_str(&run_dir.to_string_lossy()),
+            "config_path" => out.push_str(&config_path.to_string_lossy()),
+            "run_id" => out.push_str(run_id),
+            _ => out.push_str(&rest[start..=start + len]),
+        }
+        rest = &rest[start + len + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+#[async_trait]
+impl EvalRunner for CommandRunner {
+    fn name(&self) -> &'static str {
+        ENGINE_NAME
+    }
+
+    async fn run(&self, config: &EvalConfig) -> Result<EvalResult, RunnerError> {
+        let EvalEngine::Custom(engine) = &config.engine else {
+            return Err(RunnerError::NotSupported);
+        };
+        let (template, env) = self.template(engine).map_err(RunnerError::Eval)?;
+        let run_dir = self.run_dirs.create(config.run_id).await?;
+        let config_path = run_dir.join("config.json");
+        let encoded = serde_json::to_vec_pretty(config).context("failed to encode config")?;
+        tokio::fs::write(&config_path, encoded)
+            .await
+            .context("failed to write config.json")?;
+        for stale in ["result.json", RESULT_CHECKSUM_FILE, "error.json"] {
+            let _ = tokio::fs::remove_file(run_dir.join(stale)).await;
+        }
+        let run_id = config.run_id.to_string();
+        let argv = template
+            .iter()
+            .map(|arg| substitute(arg, &run_dir, &config_path, &run_id))
+            .collect::<Vec<_>>();
+        let api_key = resolve_api_key(config, self.name())?;
+
+        let (program, args) = argv
+            .split_first()
+            .context("custom engine command is empty")?;
+        let mut cmd = Command::new(program);
+        // The command is user-supplied, so it only sees the allowlisted part
+        // of the worker's environment.
+        cmd.env_clear();
+        for name in &self.env_passthrough {
+            if let Some(value) = std::env::var_os(name) {
+                cmd.env(name, value);
+            }
+        }
+        cmd.args(args)
+            .env("EVAL_RUN_ID", &run_id)
+            .env("EVAL_RUN_DIR", &run_dir)
+            .env("EVAL_CONFIG_PATH", &config_path)
+            .current_dir(&run_dir)
+            .kill_on_drop(true);
+        for (name, value) in &env {
+            cmd.env(name, substitute(value, &run_dir, &config_path, &run_id));
+        }
+        if let Some(api_key) = api_key {
+            cmd.env(API_KEY_ENV, api_key);
+        }
+        tracing::info!("running custom engine for run {run_id}: {argv:?}");
+
+        let output = match config.resources.timeout_seconds {
+            Some(secs) => match tokio::time::timeout(Duration::from_secs(secs), cmd.output()).await
+            {
+                Ok(output) => output,
+                Err(_) => {
+                    return Err(RunnerError::Eval(EvalErrorPayload {
+                        kind: EvalErrorKind::Timeout,
+                        message: format!("custom engine exceeded timeout of {secs}s"),
+                        code: None,
+                        engine: Some(ENGINE_NAME.into()),
+                        details: None,
+                    }))
+                }
+            },
+            None => cmd.output().await,
+        }
+        .with_context(|| format!("failed to launch custom engine {program}"))?;
+
+        if !output.status.success() {
+            let error_path = run_dir.join("error.json");
+            if wait_for_file(&error_path, self.result_grace).await {
+                let data = tokio::fs::read(error_path)
+                    .await
+                    .context("failed to read error.json")?;
+                return Err(RunnerError::Eval(parse_error_file(
+                    &data,
+                    self.name(),
+                    &output.stderr,
+                )));
+            }
+            return Err(RunnerError::Eval(EvalErrorPayload {
+                kind: EvalErrorKind::Engine,
+                message: format!("custom engine {program} exited with {}", output.status),
+                code: None,
+                engine: Some(ENGINE_NAME.into()),
+                details: Some(json!({
+                    "stderr": String::from_utf8_lossy(&output.stderr),
+                })),
+            }));
+        }
+
+        let result_path = run_dir.join("result.json");
+        if !wait_for_file(&result_path, self.result_grace).await {
+            return Err(anyhow::anyhow!(
+                "custom engine {program} exited without writing result.json"
+            )
+            .into());
+        }
+        let data = tokio::fs::read(&result_path)
+            .await
+            .context("failed to read result.json")?;
+        let checksum = verify_result_checksum(&run_dir, &data, self.missing_checksum, self.name())
+            .await
+            .map_err(RunnerError::Eval)?;
+        let mut result: EvalResult =
+            serde_json::from_slice(&data).context("invalid eval result json")?;
+        let mut metadata = match result.metadata.take() {
+            Some(serde_json::Value::Object(map)) => map,
+            _ => serde_json::Map::new(),
+        };
+        metadata.insert(RESULT_CHECKSUM_METADATA_KEY.into(), json!(checksum));
+        result.metadata = Some(serde_json::Value::Object(metadata));
+        result.classify_sample_errors();
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+    use std::path::PathBuf;
+    use unified_shared::eval::{
+        DatasetConfig, DatasetSource, ModelConfig, RunStatus, TaskConfig, TaskType,
+    };
+    use uuid::Uuid;
+
+    /// Writes `result.json` into the run dir it's given as `$1`, echoing the
+    /// run id and an env var set through the engine's `env`.
+    const RESULT_SCRIPT: &str = r#"#!/bin/sh
+set -e
+test -f "$EVAL_CONFIG_PATH"
+cat > "$1/result.json" <<JSON
+{"run_id": "$EVAL_RUN_ID", "status": "Completed",
+ "started_at": "2024-01-01T00:00:00Z", "completed_at": "2024-01-01T00:01:00Z",
+ "metrics": [{"run_id": "$EVAL_RUN_ID", "dataset": "$DATASET", "subset": null,
+              "split": null, "metric_name": "accuracy", "value": 0.75,
+              "n_samples": 4, "ci_low": null, "ci_high": null, "extra": null}],
+ "samples": {"mode": "none"}, "error": null, "metadata": {"seed": 7}}
+JSON
+"#;
+
+    const ERROR_SCRIPT: &str = r#"#!/bin/sh
+echo '{"kind": "config", "message": "no such split", "code": "bad_split"}' > "$EVAL_RUN_DIR/error.json"
+exit 3
+"#;
+
+    /// A scratch directory holding `script` and the runner's `work_dir`.
+    fn scratch(script: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("custom-runner-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("eval.sh"), script).unwrap();
+        dir
+    }
+
+    fn runner(dir: &Path) -> CommandRunner {
+        let integrations = serde_json::from_value(json!({
+            "third_party_root": dir.join("third_party"),
+            "work_dir": dir.join("work"),
+        }))
+        .unwrap();
+        CommandRunner {
+            run_dirs: RunDirs::new(&integrations),
+            engines: HashMap::new(),
+            missing_checksum: MissingChecksum::Warn,
+            result_grace: Duration::from_millis(100),
+            env_passthrough: vec!["PATH".into()],
+        }
+    }
+
+    fn config(dir: &Path) -> EvalConfig {
+        let engine = CustomEngine {
+            name: None,
+            command: Some(vec![
+                "/bin/sh".into(),
+                dir.join("eval.sh").to_string_lossy().into_owned(),
+                "{run_dir}".into(),
+            ]),
+            env: BTreeMap::from([("DATASET".into(), "in-house-{run_id}".into())]),
+        };
+        EvalConfig::builder()
+            .project_id(Uuid::new_v4())
+            .engine(EvalEngine::Custom(engine))
+            .model(ModelConfig {
+                logical_name: "base".into(),
+                provider: "hf".into(),
+                model_name: "gpt2".into(),
+                endpoint: None,
+                api_key_ref: None,
+                extra: None,
+            })
+            .dataset(DatasetConfig {
+                source: DatasetSource::BuiltIn,
+                name: "in-house".into(),
+                split: None,
+                uri: None,
+                filters: None,
+                no_reference: false,
+            })
+            .task(TaskConfig {
+                task_type: TaskType::Qa,
+                task_name: "in-house".into(),
+                args: Value::Null,
+                harness_args: Vec::new(),
+            })
+            .build()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn a_custom_command_reports_its_result_json() {
+        let dir = scratch(RESULT_SCRIPT);
+        let config = config(&dir);
+        let result = runner(&dir).run(&config).await.unwrap();
+
+        assert_eq!(result.run_id, config.run_id);
+        assert_eq!(result.status, RunStatus::Completed);
+        assert_eq!(result.metrics.len(), 1);
+        let metric = &result.metrics[0];
+        assert_eq!(metric.metric_name, "accuracy");
+        assert_eq!(metric.value, 0.75);
+        assert_eq!(metric.dataset, format!("in-house-{}", config.run_id));
+        let metadata = result.metadata.unwrap();
+        assert_eq!(metadata["seed"], 7);
+        assert!(metadata[RESULT_CHECKSUM_METADATA_KEY].is_string());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn a_failing_custom_command_reports_its_error_json() {
+        let dir = scratch(ERROR_SCRIPT);
+        let config = config(&dir);
+        let Err(RunnerError::Eval(error)) = runner(&dir).run(&config).await else {
+            panic!("expected an eval error");
+        };
+        assert!(matches!(error.kind, EvalErrorKind::Config));
+        assert_eq!(error.message, "no such split");
+        assert_eq!(error.code.as_deref(), Some("bad_split"));
+        assert_eq!(error.engine.as_deref(), Some(ENGINE_NAME));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn placeholders_are_substituted_within_one_argument() {
+        let arg = substitute(
+            "--out={run_dir}/out --cfg {config_path} {run_id} {other}",
+            Path::new("/runs/1"),
+            Path::new("/runs/1/config.json"),
+            "1",
+        );
+        assert_eq!(arg, "--out=/runs/1/out --cfg /runs/1/config.json 1 {other}");
+    }
+}
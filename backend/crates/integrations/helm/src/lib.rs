@@ -1,5 +1,327 @@
-//! Placeholder HELM integration.
+//! HELM integration: runs Stanford HELM from `third_party_root/helm` and maps
+//! its per-scenario `stats.json` output into `MetricRecord`s.
 
-pub fn not_implemented() {
-    tracing::info!("HELM integration not implemented yet");
+use anyhow::Context;
+use async_trait::async_trait;
+use chrono::Utc;
+use integration_core::{probe_command, resolve_api_key, PythonEnv, RunDirs, API_KEY_ENV};
+pub use integration_core::{EvalRunner, RunnerError};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use unified_shared::eval::{
+    EvalConfig, EvalErrorKind, EvalErrorPayload, EvalResult, MetricRecord, RunStatus,
+    SampleResultLocation,
+};
+use unified_shared::settings::Settings;
+use uuid::Uuid;
+
+const ENGINE_NAME: &str = "helm";
+
+pub struct HelmRunner {
+    helm_root: PathBuf,
+    run_dirs: RunDirs,
+    python: PythonEnv,
+}
+
+impl HelmRunner {
+    pub fn new(settings: &Settings) -> Self {
+        let root = Path::new(&settings.integrations.third_party_root).join("helm");
+        Self {
+            helm_root: root,
+            run_dirs: RunDirs::new(&settings.integrations),
+            python: PythonEnv::for_engine(&settings.integrations, ENGINE_NAME),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct HelmStat {
+    name: HelmStatName,
+    count: Option<i64>,
+    mean: Option<f64>,
+    min: Option<f64>,
+    max: Option<f64>,
+    stddev: Option<f64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HelmStatName {
+    name: String,
+    split: Option<String>,
+    sub_split: Option<String>,
+    perturbation: Option<Value>,
+}
+
+/// Converts one scenario's `stats.json` into metric records. `run_name` is the
+/// HELM run directory name, e.g. `mmlu:subject=anatomy,model=openai_gpt2`.
+/// Perturbed variants are skipped so they don't collide with the clean metric;
+/// a stat's `sub_split` goes into the record's `split` (see [`split_key`]),
+/// so stats differing only in it stay apart.
+pub fn parse_stats(run_id: Uuid, run_name: &str, raw: &[u8]) -> anyhow::Result<Vec<MetricRecord>> {
+    let stats: Vec<HelmStat> = serde_json::from_slice(raw).context("invalid HELM stats json")?;
+    let (dataset, subset) = split_run_name(run_name);
+
+    Ok(stats
+        .into_iter()
+        .filter(|stat| stat.name.perturbation.is_none())
+        .filter_map(|stat| {
+            let value = stat.mean?;
+            Some(MetricRecord {
+                run_id,
+                dataset: dataset.clone(),
+                subset: subset.clone(),
+                split: split_key(stat.name.split, stat.name.sub_split.as_deref()),
+                metric_name: stat.name.name,
+                value,
+                n_samples: stat.count,
+                ci_low: None,
+                ci_high: None,
+                extra: Some(json!({
+                    "min": stat.min,
+                    "max": stat.max,
+                    "stddev": stat.stddev,
+                    "sub_split": stat.name.sub_split,
+                })),
+                direction: None,
+            })
+        })
+        .collect())
+}
+
+/// `split`, or `split/sub_split` for a stat with a sub-split (`/sub_split`
+/// without a split). HELM's split names never contain `/`, so these can't be
+/// mistaken for a plain split.
+fn split_key(split: Option<String>, sub_split: Option<&str>) -> Option<String> {
+    match sub_split {
+        Some(sub_split) => Some(format!("{}/{sub_split}", split.unwrap_or_default())),
+        None => split,
+    }
+}
+
+fn split_run_name(run_name: &str) -> (String, Option<String>) {
+    match run_name.split_once(':') {
+        Some((scenario, args)) => {
+            let subset = args
+                .split(',')
+                .filter(|arg| !arg.starts_with("model=") && !arg.starts_with("model_deployment="))
+                .collect::<Vec<_>>()
+                .join(",");
+            (scenario.to_string(), (!subset.is_empty()).then_some(subset))
+        }
+        None => (run_name.to_string(), None),
+    }
+}
+
+async fn collect_metrics(run_id: Uuid, suite_dir: &Path) -> anyhow::Result<Vec<MetricRecord>> {
+    let mut metrics = Vec::new();
+    let mut entries = tokio::fs::read_dir(suite_dir)
+        .await
+        .with_context(|| format!("HELM output missing at {}", suite_dir.display()))?;
+    while let Some(entry) = entries.next_entry().await? {
+        let stats_path = entry.path().join("stats.json");
+        if !stats_path.exists() {
+            continue;
+        }
+        let run_name = entry.file_name().to_string_lossy().into_owned();
+        let raw = tokio::fs::read(&stats_path).await?;
+        metrics.extend(parse_stats(run_id, &run_name, &raw)?);
+    }
+    Ok(metrics)
+}
+
+fn engine_error(kind: EvalErrorKind, message: String, details: Option<Value>) -> RunnerError {
+    RunnerError::Eval(EvalErrorPayload {
+        kind,
+        message,
+        code: None,
+        engine: Some(ENGINE_NAME.into()),
+        details,
+    })
+}
+
+#[async_trait]
+impl EvalRunner for HelmRunner {
+    fn name(&self) -> &'static str {
+        ENGINE_NAME
+    }
+
+    async fn health_check(&self) -> anyhow::Result<()> {
+        let mut cmd = self.python.python_command();
+        cmd.arg("-m").arg("helm.benchmark.run").arg("--help");
+        if self.helm_root.exists() {
+            cmd.current_dir(&self.helm_root);
+        }
+        probe_command(cmd).await?;
+        Ok(())
+    }
+
+    async fn installed_version(&self) -> Option<String> {
+        self.python.package_version("crfm-helm").await
+    }
+
+    async fn run(&self, config: &EvalConfig) -> Result<EvalResult, RunnerError> {
+        let started_at = Utc::now();
+        let run_dir = self.run_dirs.create(config.run_id).await?;
+        let config_json = serde_json::to_vec_pretty(config).context("failed to encode config")?;
+        tokio::fs::write(run_dir.join("config.json"), config_json)
+            .await
+            .context("failed to write config.json")?;
+
+        let api_key = resolve_api_key(config, self.name())?;
+        let output_dir = run_dir.join("helm");
+        let suite = config.run_id.to_string();
+        let run_entry = config
+            .task
+            .args
+            .get("run_entry")
+            .and_then(Value::as_str)
+            .map(str::to_string)
+            .unwrap_or_else(|| {
+                format!(
+                    "{}:model={}",
+                    config.task.task_name, config.model.model_name
+                )
+            });
+
+        let mut cmd = self.python.python_command();
+        cmd.arg("-m")
+            .arg("helm.benchmark.run")
+            .arg("--run-entries")
+            .arg(&run_entry)
+            .arg("--suite")
+            .arg(&suite)
+            .arg("--output-path")
+            .arg(&output_dir)
+            .env("EVAL_RUN_ID", config.run_id.to_string())
+            .env("EVAL_RUN_DIR", &run_dir)
+            .kill_on_drop(true);
+        if let Some(api_key) = api_key {
+            cmd.env(API_KEY_ENV, api_key);
+        }
+        if let Some(max_instances) = config
+            .task
+            .args
+            .get("max_eval_instances")
+            .and_then(Value::as_u64)
+        {
+            cmd.arg("--max-eval-instances")
+                .arg(max_instances.to_string());
+        }
+        if self.helm_root.exists() {
+            cmd.current_dir(&self.helm_root);
+        }
+
+        let output = match config.resources.timeout_seconds {
+            Some(secs) => {
+                match tokio::time::timeout(Duration::from_secs(secs), cmd.output()).await {
+                    Ok(output) => output,
+                    Err(_) => {
+                        return Err(engine_error(
+                            EvalErrorKind::Timeout,
+                            format!("HELM run exceeded timeout of {secs}s"),
+                            None,
+                        ))
+                    }
+                }
+            }
+            None => cmd.output().await,
+        }
+        .context("failed to launch HELM")?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(engine_error(
+                EvalErrorKind::Engine,
+                format!("HELM exited with {}", output.status),
+                Some(json!({ "stderr": stderr })),
+            ));
+        }
+
+        let suite_dir = output_dir.join("runs").join(&suite);
+        let metrics = collect_metrics(config.run_id, &suite_dir).await?;
+        if metrics.is_empty() {
+            return Err(engine_error(
+                EvalErrorKind::Engine,
+                format!("HELM produced no stats for run entry {run_entry}"),
+                None,
+            ));
+        }
+
+        let result = EvalResult {
+            run_id: config.run_id,
+            status: RunStatus::Completed,
+            started_at,
+            completed_at: Utc::now(),
+            metrics,
+            samples: SampleResultLocation::None,
+            error: None,
+            metadata: None,
+        };
+        let result_json = serde_json::to_vec_pretty(&result).context("failed to encode result")?;
+        tokio::fs::write(run_dir.join("result.json"), result_json)
+            .await
+            .context("failed to write result.json")?;
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stats_fixture_parses_into_metric_records() {
+        let run_id = Uuid::new_v4();
+        let records = parse_stats(
+            run_id,
+            "mmlu:subject=anatomy,model=openai_gpt2",
+            include_bytes!("../testdata/stats.json"),
+        )
+        .unwrap();
+
+        // The perturbed variant and the stat without a mean are skipped.
+        let keys: Vec<(&str, Option<&str>, f64)> = records
+            .iter()
+            .map(|record| {
+                (
+                    record.metric_name.as_str(),
+                    record.split.as_deref(),
+                    record.value,
+                )
+            })
+            .collect();
+        assert_eq!(
+            keys,
+            [
+                ("exact_match", Some("test"), 0.42),
+                ("exact_match", Some("test/hard"), 0.25),
+                ("num_prompt_tokens", Some("valid"), 301.2),
+            ]
+        );
+        for record in &records {
+            assert_eq!(record.run_id, run_id);
+            assert_eq!(record.dataset, "mmlu");
+            assert_eq!(record.subset.as_deref(), Some("subject=anatomy"));
+        }
+        assert_eq!(records[0].n_samples, Some(100));
+        let extra = records[1].extra.as_ref().unwrap();
+        assert_eq!(extra["sub_split"], "hard");
+        assert_eq!(extra["stddev"], 0.433);
+    }
+
+    #[test]
+    fn run_names_without_arguments_have_no_subset() {
+        assert_eq!(split_run_name("boolq"), ("boolq".into(), None));
+        assert_eq!(
+            split_run_name("boolq:model=openai_gpt2"),
+            ("boolq".into(), None)
+        );
+    }
+
+    #[test]
+    fn invalid_stats_are_an_error() {
+        assert!(parse_stats(Uuid::new_v4(), "boolq", b"{\"not\": \"a list\"}").is_err());
+    }
 }
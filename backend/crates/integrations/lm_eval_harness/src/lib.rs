@@ -1,71 +1,425 @@
 use anyhow::Context;
+use async_trait::async_trait;
+use integration_core::{
+    absolute, check_harness_args, known_dataset_size, model_size_billions, parse_error_file,
+    probe_command, read_progress, resolve_api_key, verify_result_checksum, wait_for_file,
+    PythonEnv, RunDirs, API_KEY_ENV, PROGRESS_FILE, RESULT_CHECKSUM_FILE,
+};
+pub use integration_core::{EvalRunner, RunnerError};
+use serde_json::{json, Value};
 use std::path::{Path, PathBuf};
-use thiserror::Error;
-use tokio::process::Command;
-use unified_shared::eval::{EvalConfig, EvalErrorPayload, EvalResult};
+use std::time::Duration;
+use unified_shared::eval::{
+    EvalConfig, EvalErrorKind, EvalErrorPayload, EvalResult, ResourceEstimate,
+    RESULT_CHECKSUM_METADATA_KEY,
+};
+use unified_shared::settings::MissingChecksum;
 use unified_shared::settings::Settings;
 
-#[derive(Debug, Error)]
-pub enum RunnerError {
-    #[error("evaluation failure")]
-    Eval(EvalErrorPayload),
-    #[error(transparent)]
-    Io(#[from] anyhow::Error),
-    #[error("engine not supported")]
-    NotSupported,
-}
-
 pub struct LmEvalRunner {
     harness_root: PathBuf,
+    run_dirs: RunDirs,
+    python: PythonEnv,
+    missing_checksum: MissingChecksum,
+    result_grace: Duration,
+    harness_arg_allowlist: Vec<String>,
 }
 
+/// Module the runner starts with `python -m` from the harness root.
+const ENTRYPOINT: &str = "eval_runner";
+
+// Rough constants for `estimate_resources`.
+const GPU_MEMORY_GB: f64 = 80.0;
+/// Activations and KV cache on top of the fp16 weights.
+const MEMORY_OVERHEAD: f64 = 1.2;
+const DEFAULT_MODEL_BILLIONS: f64 = 7.0;
+const DEFAULT_SAMPLES: u64 = 1_000;
+const STARTUP_SECONDS: u64 = 60;
+const HOSTED_SECONDS_PER_SAMPLE: f64 = 0.5;
+const LOCAL_SECONDS_PER_BILLION_PER_SAMPLE: f64 = 0.02;
+const HOSTED_PROVIDERS: &[&str] = &[
+    "openai",
+    "anthropic",
+    "azure",
+    "google",
+    "cohere",
+    "together",
+];
+
 impl LmEvalRunner {
     pub fn new(settings: &Settings) -> Self {
-        let root = Path::new(&settings.integrations.third_party_root).join("lm-evaluation-harness");
-        Self { harness_root: root }
+        let root = absolute(
+            &Path::new(&settings.integrations.third_party_root).join("lm-evaluation-harness"),
+        );
+        let runner = Self {
+            harness_root: root,
+            run_dirs: RunDirs::new(&settings.integrations),
+            python: PythonEnv::for_engine(&settings.integrations, "lm_eval_harness"),
+            missing_checksum: settings.integrations.missing_result_checksum,
+            result_grace: Duration::from_millis(settings.integrations.result_grace_ms),
+            harness_arg_allowlist: settings.integrations.harness_arg_allowlist.clone(),
+        };
+        match runner.check_harness() {
+            Ok(()) => tracing::info!("lm-eval harness root: {}", runner.harness_root.display()),
+            Err(err) => tracing::warn!("{}", err.message),
+        }
+        runner
+    }
+
+    /// Checks that the harness root is a directory holding the
+    /// [`ENTRYPOINT`] module, so a bad `third_party_root` fails with a clear
+    /// message rather than a Python import error.
+    fn check_harness(&self) -> Result<(), EvalErrorPayload> {
+        let root = &self.harness_root;
+        let problem = if !root.is_dir() {
+            format!("lm-eval harness root {} does not exist", root.display())
+        } else if !root.join(ENTRYPOINT).join("__main__.py").is_file()
+            && !root.join(format!("{ENTRYPOINT}.py")).is_file()
+        {
+            format!(
+                "lm-eval harness root {} has no `{ENTRYPOINT}` module",
+                root.display()
+            )
+        } else {
+            return Ok(());
+        };
+        Err(EvalErrorPayload {
+            kind: EvalErrorKind::Infra,
+            message: format!("{problem}; check integrations.third_party_root"),
+            code: Some("harness_missing".into()),
+            engine: Some(self.name().into()),
+            details: Some(json!({ "harness_root": root })),
+        })
+    }
+}
+
+#[async_trait]
+impl EvalRunner for LmEvalRunner {
+    fn name(&self) -> &'static str {
+        "lm_eval_harness"
+    }
+
+    async fn health_check(&self) -> anyhow::Result<()> {
+        self.check_harness()
+            .map_err(|err| anyhow::anyhow!(err.message))?;
+        let mut cmd = self.python.python_command();
+        cmd.arg("-m")
+            .arg(ENTRYPOINT)
+            .arg("--version")
+            .current_dir(&self.harness_root);
+        let version = probe_command(cmd).await?;
+        tracing::info!(
+            "eval_runner available via {}: {version}",
+            self.python.python().display()
+        );
+        Ok(())
     }
 
-    pub async fn run(&self, config: &EvalConfig) -> Result<EvalResult, RunnerError> {
-        let run_dir = PathBuf::from(format!("runs/{}", config.run_id));
-        tokio::fs::create_dir_all(&run_dir).await?;
+    async fn installed_version(&self) -> Option<String> {
+        self.python.package_version("lm_eval").await
+    }
+
+    /// Local models need their fp16 weights plus overhead in GPU memory,
+    /// spread over as many [`GPU_MEMORY_GB`] GPUs as that takes; models behind
+    /// an endpoint or a hosted provider need none. Duration scales with the
+    /// sample count (`task.args.limit`, else the benchmark's known size) and,
+    /// for local models, with model size. Explicit `resources` win.
+    fn estimate_resources(&self, config: &EvalConfig) -> ResourceEstimate {
+        let samples = config
+            .task
+            .args
+            .get("limit")
+            .and_then(Value::as_u64)
+            .or_else(|| known_dataset_size(&config.task.task_name))
+            .or_else(|| known_dataset_size(&config.dataset.name))
+            .unwrap_or(DEFAULT_SAMPLES);
+        let hosted = config.model.endpoint.is_some()
+            || HOSTED_PROVIDERS.contains(&config.model.provider.to_ascii_lowercase().as_str());
+        let (num_gpus, memory_gb, seconds_per_sample) = if hosted {
+            (0, 2.0, HOSTED_SECONDS_PER_SAMPLE)
+        } else {
+            let billions =
+                model_size_billions(&config.model.model_name).unwrap_or(DEFAULT_MODEL_BILLIONS);
+            let memory = billions * 2.0 * MEMORY_OVERHEAD;
+            let gpus = (memory / GPU_MEMORY_GB).ceil().max(1.0);
+            (
+                gpus.min(f64::from(u8::MAX)) as u8,
+                memory,
+                LOCAL_SECONDS_PER_BILLION_PER_SAMPLE * billions,
+            )
+        };
+        let projected = ResourceEstimate {
+            num_gpus,
+            memory_gb: memory_gb.ceil().min(f64::from(u16::MAX)) as u16,
+            duration_seconds: STARTUP_SECONDS + (samples as f64 * seconds_per_sample).ceil() as u64,
+        };
+        let resources = &config.resources;
+        ResourceEstimate {
+            num_gpus: resources.num_gpus.unwrap_or(projected.num_gpus),
+            memory_gb: resources.memory_gb.unwrap_or(projected.memory_gb),
+            duration_seconds: projected.duration_seconds,
+        }
+    }
+
+    fn raw_result_path(&self, config: &EvalConfig) -> Option<PathBuf> {
+        Some(self.run_dirs.path(config.run_id).join("result.json"))
+    }
+
+    async fn run(&self, config: &EvalConfig) -> Result<EvalResult, RunnerError> {
+        self.check_harness().map_err(RunnerError::Eval)?;
+        check_harness_args(
+            &config.task.harness_args,
+            &self.harness_arg_allowlist,
+            self.name(),
+        )?;
+        let run_dir = self.run_dirs.create(config.run_id).await?;
         let config_path = run_dir.join("config.json");
-        tokio::fs::write(&config_path, serde_json::to_vec_pretty(config)?).await?;
+        let encoded = serde_json::to_vec_pretty(config).context("failed to encode config")?;
+        tokio::fs::write(&config_path, encoded)
+            .await
+            .context("failed to write config.json")?;
+        // Outputs of an earlier attempt must not be mistaken for this one's.
+        for stale in ["result.json", RESULT_CHECKSUM_FILE, "error.json"] {
+            let _ = tokio::fs::remove_file(run_dir.join(stale)).await;
+        }
+        let progress = read_progress(&run_dir).await?;
+        let api_key = resolve_api_key(config, self.name())?;
 
-        let mut cmd = Command::new("python");
+        let mut cmd = self.python.python_command();
         cmd.arg("-m")
-            .arg("eval_runner")
+            .arg(ENTRYPOINT)
             .arg("--run-dir")
             .arg(&run_dir)
             .env("EVAL_RUN_ID", config.run_id.to_string())
             .env("EVAL_RUN_DIR", &run_dir);
-        if self.harness_root.exists() {
-            cmd.current_dir(&self.harness_root);
+        if let Some(api_key) = api_key {
+            cmd.env(API_KEY_ENV, api_key);
+        }
+        if let Some(seed) = config.sampling.seed {
+            cmd.arg("--seed").arg(seed.to_string());
+        }
+        if let Some(progress) = &progress {
+            // The harness appends to the samples it already wrote.
+            tracing::info!(
+                "resuming run {} after sample {}",
+                config.run_id,
+                progress.last_completed_sample_index
+            );
+            cmd.arg("--resume-from")
+                .arg(progress.last_completed_sample_index.to_string());
         }
+        cmd.args(&config.task.harness_args);
+        // Dropping the run, e.g. when the worker aborts it, stops the harness.
+        cmd.current_dir(&self.harness_root).kill_on_drop(true);
 
-        let output = cmd.output().await?;
+        let output = cmd
+            .output()
+            .await
+            .context("failed to run the lm-eval harness")?;
         if output.status.success() {
             let result_path = run_dir.join("result.json");
-            if result_path.exists() {
-                let data = tokio::fs::read(result_path).await?;
-                let result: EvalResult =
+            if wait_for_file(&result_path, self.result_grace).await {
+                let data = tokio::fs::read(result_path)
+                    .await
+                    .context("failed to read result.json")?;
+                let checksum = self
+                    .verify_checksum(&run_dir, &data)
+                    .await
+                    .map_err(RunnerError::Eval)?;
+                let mut result: EvalResult =
                     serde_json::from_slice(&data).context("invalid eval result json")?;
+                record_reproducibility(config, &mut result);
+                record_checksum(&mut result, checksum);
+                result.classify_sample_errors();
+                // The run is complete; a later re-enqueue starts from scratch.
+                let _ = tokio::fs::remove_file(run_dir.join(PROGRESS_FILE)).await;
                 Ok(result)
             } else {
-                Err(anyhow::anyhow!("result.json missing").into())
+                Err(anyhow::anyhow!(
+                    "result.json missing {}ms after the harness exited",
+                    self.result_grace.as_millis()
+                )
+                .into())
             }
         } else {
             let error_path = run_dir.join("error.json");
-            if error_path.exists() {
-                let data = tokio::fs::read(error_path).await?;
-                let payload: EvalErrorPayload =
-                    serde_json::from_slice(&data).context("invalid error payload")?;
-                Err(RunnerError::Eval(payload))
+            let error = if wait_for_file(&error_path, self.result_grace).await {
+                let data = tokio::fs::read(error_path)
+                    .await
+                    .context("failed to read error.json")?;
+                Some(parse_error_file(&data, self.name(), &output.stderr))
             } else {
-                let stderr = String::from_utf8_lossy(&output.stderr);
-                Err(RunnerError::Io(anyhow::anyhow!(
-                    "lm-eval harness failed: {stderr}"
-                )))
+                None
+            };
+            // A harness that crashed part-way may still have written the
+            // metrics of the sub-tasks it finished.
+            if let Some((mut result, checksum)) = self
+                .read_partial_result(&run_dir)
+                .await
+                .map_err(RunnerError::Eval)?
+            {
+                result.error = error.or(result.error).or_else(|| {
+                    Some(EvalErrorPayload {
+                        kind: EvalErrorKind::Engine,
+                        message: format!(
+                            "lm-eval harness failed: {}",
+                            String::from_utf8_lossy(&output.stderr)
+                        ),
+                        code: Some("partial_result".into()),
+                        engine: Some(self.name().into()),
+                        details: None,
+                    })
+                });
+                record_reproducibility(config, &mut result);
+                record_checksum(&mut result, checksum);
+                result.classify_sample_errors();
+                tracing::warn!(
+                    "run {} failed after reporting {} metrics; keeping them",
+                    config.run_id,
+                    result.metrics.len()
+                );
+                return Ok(result);
+            }
+            match error {
+                Some(error) => Err(RunnerError::Eval(error)),
+                None => {
+                    let stderr = String::from_utf8_lossy(&output.stderr);
+                    Err(RunnerError::Io(anyhow::anyhow!(
+                        "lm-eval harness failed: {stderr}"
+                    )))
+                }
             }
         }
     }
 }
+
+impl LmEvalRunner {
+    async fn verify_checksum(
+        &self,
+        run_dir: &Path,
+        data: &[u8],
+    ) -> Result<String, EvalErrorPayload> {
+        verify_result_checksum(run_dir, data, self.missing_checksum, self.name()).await
+    }
+
+    /// A `result.json` with at least one metric left behind by a failed run,
+    /// with its checksum. A file failing its checksum is an error rather than
+    /// a partial result.
+    async fn read_partial_result(
+        &self,
+        run_dir: &Path,
+    ) -> Result<Option<(EvalResult, String)>, EvalErrorPayload> {
+        let Ok(data) = tokio::fs::read(run_dir.join("result.json")).await else {
+            return Ok(None);
+        };
+        let checksum = self.verify_checksum(run_dir, &data).await?;
+        Ok(serde_json::from_slice::<EvalResult>(&data)
+            .ok()
+            .filter(|result| !result.metrics.is_empty())
+            .map(|result| (result, checksum)))
+    }
+}
+
+/// Records the verified `result.json` checksum in the result metadata.
+fn record_checksum(result: &mut EvalResult, checksum: String) {
+    if let Some(Value::Object(metadata)) = &mut result.metadata {
+        metadata.insert(RESULT_CHECKSUM_METADATA_KEY.into(), json!(checksum));
+    }
+}
+
+/// Adds the effective `seed` to the result metadata, keeping whatever the
+/// harness reported (such as `library_versions`). A seed reported by the
+/// harness wins over the requested one. `engine_version` is left to the
+/// harness or the worker, which records the installed version.
+fn record_reproducibility(config: &EvalConfig, result: &mut EvalResult) {
+    let mut metadata = match result.metadata.take() {
+        Some(Value::Object(map)) => map,
+        _ => serde_json::Map::new(),
+    };
+    let reported = metadata.get("seed").and_then(Value::as_u64);
+    if let (Some(requested), Some(reported)) = (config.sampling.seed, reported) {
+        if requested != reported {
+            tracing::warn!(
+                "run {} requested seed {requested} but the harness used {reported}",
+                config.run_id
+            );
+        }
+    }
+    metadata.insert("seed".into(), json!(reported.or(config.sampling.seed)));
+    result.metadata = Some(Value::Object(metadata));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    /// A runner whose `third_party_root` is a fresh scratch directory, so the
+    /// harness root inside it starts out missing.
+    fn runner() -> LmEvalRunner {
+        let dir = std::env::temp_dir().join(format!("lm-eval-runner-{}", Uuid::new_v4()));
+        let integrations = serde_json::from_value(json!({
+            "third_party_root": dir.join("third_party"),
+            "work_dir": dir.join("work"),
+        }))
+        .unwrap();
+        LmEvalRunner {
+            harness_root: dir.join("third_party").join("lm-evaluation-harness"),
+            run_dirs: RunDirs::new(&integrations),
+            python: PythonEnv::for_engine(&integrations, "lm_eval_harness"),
+            missing_checksum: MissingChecksum::Warn,
+            result_grace: Duration::from_millis(100),
+            harness_arg_allowlist: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn a_missing_harness_root_fails_clearly() {
+        let runner = runner();
+
+        let error = runner.check_harness().unwrap_err();
+        assert!(matches!(error.kind, EvalErrorKind::Infra));
+        assert_eq!(error.code.as_deref(), Some("harness_missing"));
+        assert_eq!(error.engine.as_deref(), Some("lm_eval_harness"));
+        assert!(
+            error.message.contains("does not exist"),
+            "{}",
+            error.message
+        );
+        assert!(error.message.contains("integrations.third_party_root"));
+        assert_eq!(
+            error.details,
+            Some(json!({ "harness_root": runner.harness_root }))
+        );
+
+        let health = runner.health_check().await.unwrap_err();
+        assert_eq!(health.to_string(), error.message);
+    }
+
+    #[test]
+    fn a_harness_root_needs_the_entrypoint_module() {
+        let runner = runner();
+        std::fs::create_dir_all(&runner.harness_root).unwrap();
+
+        let error = runner.check_harness().unwrap_err();
+        assert_eq!(error.code.as_deref(), Some("harness_missing"));
+        assert!(
+            error.message.contains("has no `eval_runner` module"),
+            "{}",
+            error.message
+        );
+
+        std::fs::write(runner.harness_root.join("eval_runner.py"), "").unwrap();
+        assert!(runner.check_harness().is_ok());
+    }
+
+    #[test]
+    fn the_entrypoint_may_be_a_package() {
+        let runner = runner();
+        let package = runner.harness_root.join(ENTRYPOINT);
+        std::fs::create_dir_all(&package).unwrap();
+        std::fs::write(package.join("__main__.py"), "").unwrap();
+
+        assert!(runner.check_harness().is_ok());
+    }
+}
@@ -0,0 +1,268 @@
+//! OpenAI Evals integration: runs `oaieval` and maps the `final_report` of its
+//! record file into `MetricRecord`s.
+
+use anyhow::Context;
+use async_trait::async_trait;
+use chrono::Utc;
+use integration_core::{probe_command, resolve_api_key, PythonEnv, RunDirs};
+pub use integration_core::{EvalRunner, RunnerError};
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use unified_shared::eval::{
+    EvalConfig, EvalErrorKind, EvalErrorPayload, EvalResult, MetricRecord, RunStatus,
+    SampleResultLocation, SamplingConfig,
+};
+use unified_shared::settings::Settings;
+use uuid::Uuid;
+
+const ENGINE_NAME: &str = "openai_evals";
+
+pub struct OpenAiEvalsRunner {
+    evals_root: PathBuf,
+    run_dirs: RunDirs,
+    python: PythonEnv,
+}
+
+impl OpenAiEvalsRunner {
+    pub fn new(settings: &Settings) -> Self {
+        let root = Path::new(&settings.integrations.third_party_root).join("openai-evals");
+        Self {
+            evals_root: root,
+            run_dirs: RunDirs::new(&settings.integrations),
+            python: PythonEnv::for_engine(&settings.integrations, ENGINE_NAME),
+        }
+    }
+}
+
+/// Parses an `oaieval` record file. Numeric `final_report` entries become
+/// metrics; `n_samples` counts the distinct `sample_id`s seen in events.
+pub fn parse_record(run_id: Uuid, eval_name: &str, raw: &str) -> anyhow::Result<Vec<MetricRecord>> {
+    let mut report = None;
+    let mut sample_ids = HashSet::new();
+    for line in raw.lines().filter(|line| !line.trim().is_empty()) {
+        let event: Value = serde_json::from_str(line).context("invalid oaieval record line")?;
+        if let Some(final_report) = event.get("final_report") {
+            report = Some(final_report.clone());
+        } else if let Some(sample_id) = event.get("sample_id").and_then(Value::as_str) {
+            sample_ids.insert(sample_id.to_string());
+        }
+    }
+    let report = report.context("oaieval record has no final_report")?;
+    let report = report
+        .as_object()
+        .context("oaieval final_report is not an object")?;
+    let n_samples = (!sample_ids.is_empty()).then_some(sample_ids.len() as i64);
+
+    Ok(report
+        .iter()
+        .filter_map(|(name, value)| {
+            Some(MetricRecord {
+                run_id,
+                dataset: eval_name.to_string(),
+                subset: None,
+                split: None,
+                metric_name: name.clone(),
+                value: value.as_f64()?,
+                n_samples,
+                ci_low: None,
+                ci_high: None,
+                extra: None,
+                direction: None,
+            })
+        })
+        .collect())
+}
+
+fn completion_args(sampling: &SamplingConfig) -> Option<String> {
+    let mut args = Vec::new();
+    if let Some(temperature) = sampling.temperature {
+        args.push(format!("temperature={temperature}"));
+    }
+    if let Some(top_p) = sampling.top_p {
+        args.push(format!("top_p={top_p}"));
+    }
+    if let Some(max_tokens) = sampling.max_tokens {
+        args.push(format!("max_tokens={max_tokens}"));
+    }
+    (!args.is_empty()).then(|| args.join(","))
+}
+
+/// Classifies a failed `oaieval` invocation from its stderr.
+fn classify_failure(stderr: &str) -> (EvalErrorKind, Option<String>) {
+    let lowered = stderr.to_lowercase();
+    if lowered.contains("authenticationerror")
+        || lowered.contains("incorrect api key")
+        || lowered.contains("401")
+    {
+        (EvalErrorKind::Config, Some("auth_failed".into()))
+    } else if lowered.contains("ratelimiterror") || lowered.contains("429") {
+        (EvalErrorKind::Infra, Some("rate_limited".into()))
+    } else {
+        (EvalErrorKind::Engine, None)
+    }
+}
+
+fn engine_error(
+    kind: EvalErrorKind,
+    message: String,
+    code: Option<String>,
+    details: Option<Value>,
+) -> RunnerError {
+    RunnerError::Eval(EvalErrorPayload {
+        kind,
+        message,
+        code,
+        engine: Some(ENGINE_NAME.into()),
+        details,
+    })
+}
+
+#[async_trait]
+impl EvalRunner for OpenAiEvalsRunner {
+    fn name(&self) -> &'static str {
+        ENGINE_NAME
+    }
+
+    async fn health_check(&self) -> anyhow::Result<()> {
+        let mut cmd = self.python.command("oaieval");
+        cmd.arg("--help");
+        if self.evals_root.exists() {
+            cmd.current_dir(&self.evals_root);
+        }
+        probe_command(cmd).await?;
+        Ok(())
+    }
+
+    async fn installed_version(&self) -> Option<String> {
+        self.python.package_version("evals").await
+    }
+
+    async fn run(&self, config: &EvalConfig) -> Result<EvalResult, RunnerError> {
+        let started_at = Utc::now();
+        let api_key = resolve_api_key(config, ENGINE_NAME)?;
+        let run_dir = self.run_dirs.create(config.run_id).await?;
+        let config_json = serde_json::to_vec_pretty(config).context("failed to encode config")?;
+        tokio::fs::write(run_dir.join("config.json"), config_json)
+            .await
+            .context("failed to write config.json")?;
+
+        let eval_name = config
+            .task
+            .args
+            .get("eval")
+            .and_then(Value::as_str)
+            .unwrap_or(&config.task.task_name)
+            .to_string();
+        let record_path = run_dir.join("record.jsonl");
+
+        let mut cmd = self.python.command("oaieval");
+        cmd.arg(&config.model.model_name)
+            .arg(&eval_name)
+            .arg("--record_path")
+            .arg(&record_path)
+            .env("EVAL_RUN_ID", config.run_id.to_string())
+            .env("EVAL_RUN_DIR", &run_dir)
+            .kill_on_drop(true);
+        if let Some(args) = completion_args(&config.sampling) {
+            cmd.arg("--completion_args").arg(args);
+        }
+        if let Some(seed) = config.sampling.seed {
+            cmd.arg("--seed").arg(seed.to_string());
+        }
+        if let Some(max_samples) = config.task.args.get("max_samples").and_then(Value::as_u64) {
+            cmd.arg("--max_samples").arg(max_samples.to_string());
+        }
+        if let Some(endpoint) = &config.model.endpoint {
+            cmd.env("OPENAI_BASE_URL", endpoint);
+        }
+        if let Some(api_key) = api_key {
+            cmd.env("OPENAI_API_KEY", api_key);
+        }
+        if self.evals_root.exists() {
+            cmd.current_dir(&self.evals_root);
+        }
+
+        let output = cmd.output().await.context("failed to launch oaieval")?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let (kind, code) = classify_failure(&stderr);
+            return Err(engine_error(
+                kind,
+                format!("oaieval exited with {}", output.status),
+                code,
+                Some(json!({ "stderr": stderr })),
+            ));
+        }
+
+        let raw = tokio::fs::read_to_string(&record_path)
+            .await
+            .context("oaieval record file missing")?;
+        let metrics = parse_record(config.run_id, &eval_name, &raw)?;
+
+        Ok(EvalResult {
+            run_id: config.run_id,
+            status: RunStatus::Completed,
+            started_at,
+            completed_at: Utc::now(),
+            metrics,
+            samples: SampleResultLocation::None,
+            error: None,
+            metadata: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_fixture_parses_into_metric_records() {
+        let run_id = Uuid::new_v4();
+        let raw = include_str!("../testdata/record.jsonl");
+        let mut records = parse_record(run_id, "test-match", raw).unwrap();
+        records.sort_by(|a, b| a.metric_name.cmp(&b.metric_name));
+
+        // The non-numeric `completion_fn` entry is skipped.
+        let metrics: Vec<(&str, f64)> = records
+            .iter()
+            .map(|record| (record.metric_name.as_str(), record.value))
+            .collect();
+        assert_eq!(
+            metrics,
+            [
+                ("accuracy", 0.6666666666666666),
+                ("boostrap_std", 0.2721655269759087),
+            ]
+        );
+        for record in &records {
+            assert_eq!(record.run_id, run_id);
+            assert_eq!(record.dataset, "test-match");
+            // Three distinct sample ids across four events.
+            assert_eq!(record.n_samples, Some(3));
+        }
+    }
+
+    #[test]
+    fn records_without_a_final_report_are_rejected() {
+        let raw = r#"{"run_id": "r", "sample_id": "s.0", "type": "match"}"#;
+        let err = parse_record(Uuid::new_v4(), "test-match", raw).unwrap_err();
+        assert!(err.to_string().contains("no final_report"));
+        assert!(parse_record(Uuid::new_v4(), "test-match", "not json").is_err());
+    }
+
+    #[test]
+    fn stderr_is_classified_into_error_kinds() {
+        let (kind, code) = classify_failure("openai.error.AuthenticationError: Incorrect API key");
+        assert!(matches!(kind, EvalErrorKind::Config));
+        assert_eq!(code.as_deref(), Some("auth_failed"));
+        let (kind, code) = classify_failure("openai.error.RateLimitError: 429");
+        assert!(matches!(kind, EvalErrorKind::Infra));
+        assert_eq!(code.as_deref(), Some("rate_limited"));
+        assert!(matches!(
+            classify_failure("Traceback: KeyError").0,
+            EvalErrorKind::Engine
+        ));
+    }
+}
@@ -1,25 +1,126 @@
 use axum::http::StatusCode;
 use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::Serialize;
 use thiserror::Error;
 
+/// One invalid request field, e.g. `name: must not be empty`.
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for FieldError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.field, self.message)
+    }
+}
+
+/// Collects field errors while checking a payload; [`FieldErrors::finish`]
+/// turns any into [`DomainError::InvalidFields`].
+#[derive(Debug, Default)]
+pub struct FieldErrors(Vec<FieldError>);
+
+impl FieldErrors {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, field: impl Into<String>, message: impl Into<String>) {
+        self.0.push(FieldError::new(field, message));
+    }
+
+    pub fn require_non_empty(&mut self, field: &str, value: &str) {
+        if value.trim().is_empty() {
+            self.push(field, "must not be empty");
+        }
+    }
+
+    pub fn finish(self) -> Result<(), DomainError> {
+        if self.0.is_empty() {
+            Ok(())
+        } else {
+            Err(DomainError::InvalidFields(self.0))
+        }
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum DomainError {
     #[error("resource not found: {0}")]
     NotFound(String),
     #[error("validation failed: {0}")]
     Validation(String),
+    #[error("validation failed: {}", join_fields(.0))]
+    InvalidFields(Vec<FieldError>),
     #[error("conflict: {0}")]
     Conflict(String),
+    /// The request is well-formed but can't be applied to the resources it
+    /// names (422).
+    #[error("unprocessable: {0}")]
+    Unprocessable(String),
+    /// A dependency such as MySQL or Redis is unreachable; the request may
+    /// succeed when retried (503).
+    #[error("service unavailable: {0}")]
+    Unavailable(String),
+    /// The resource exists but can't be served this way, e.g. samples kept
+    /// as an object-store blob that has no index to query (501).
+    #[error("not implemented: {0}")]
+    NotImplemented(String),
     #[error("internal error: {0}")]
     Internal(String),
 }
 
+impl DomainError {
+    /// A validation error scoped to a single field.
+    pub fn field(field: impl Into<String>, message: impl Into<String>) -> Self {
+        DomainError::InvalidFields(vec![FieldError::new(field, message)])
+    }
+}
+
+fn join_fields(fields: &[FieldError]) -> String {
+    fields
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+#[derive(Serialize)]
+struct ValidationBody<'a> {
+    error: &'a str,
+    fields: &'a [FieldError],
+}
+
 impl IntoResponse for DomainError {
     fn into_response(self) -> Response {
         match self {
             DomainError::NotFound(msg) => (StatusCode::NOT_FOUND, msg).into_response(),
             DomainError::Validation(msg) => (StatusCode::BAD_REQUEST, msg).into_response(),
+            DomainError::InvalidFields(fields) => (
+                StatusCode::BAD_REQUEST,
+                Json(ValidationBody {
+                    error: "validation failed",
+                    fields: &fields,
+                }),
+            )
+                .into_response(),
             DomainError::Conflict(msg) => (StatusCode::CONFLICT, msg).into_response(),
+            DomainError::Unprocessable(msg) => {
+                (StatusCode::UNPROCESSABLE_ENTITY, msg).into_response()
+            }
+            DomainError::Unavailable(msg) => (StatusCode::SERVICE_UNAVAILABLE, msg).into_response(),
+            DomainError::NotImplemented(msg) => (StatusCode::NOT_IMPLEMENTED, msg).into_response(),
             DomainError::Internal(msg) => (StatusCode::INTERNAL_SERVER_ERROR, msg).into_response(),
         }
     }
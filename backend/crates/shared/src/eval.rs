@@ -1,35 +1,172 @@
+use schemars::gen::SchemaGenerator;
+use schemars::schema::Schema;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
 use uuid::Uuid;
 
 pub type Timestamp = chrono::DateTime<chrono::Utc>;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Also accepts snake_case names (`lm_eval_harness`), which is how they come
+/// out of settings files, whose keys are lowercased.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
 pub enum EvalEngine {
+    #[serde(alias = "lm_eval_harness")]
     LmEvalHarness,
+    #[serde(alias = "open_compass", alias = "opencompass")]
     OpenCompass,
+    #[serde(alias = "helm")]
     Helm,
+    #[serde(alias = "deep_eval", alias = "deepeval")]
     DeepEval,
+    #[serde(alias = "openai_evals", alias = "open_ai_evals")]
     OpenAiEvals,
+    /// An in-house script run as an external command.
+    #[serde(alias = "custom")]
+    Custom(CustomEngine),
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl EvalEngine {
+    /// The variant name, as stored in the `runs.engine` column.
+    pub fn label(&self) -> &'static str {
+        match self {
+            EvalEngine::LmEvalHarness => "LmEvalHarness",
+            EvalEngine::OpenCompass => "OpenCompass",
+            EvalEngine::Helm => "Helm",
+            EvalEngine::DeepEval => "DeepEval",
+            EvalEngine::OpenAiEvals => "OpenAiEvals",
+            EvalEngine::Custom(_) => "Custom",
+        }
+    }
+}
+
+/// The command of an [`EvalEngine::Custom`] run. It follows the same
+/// `result.json`/`error.json` contract as the Python runners.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+pub struct CustomEngine {
+    /// Entry of `integrations.custom_engines` to take the command from when
+    /// `command` is unset.
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Program followed by its arguments, run without a shell. `{run_dir}`,
+    /// `{config_path}` and `{run_id}` are substituted within each argument.
+    #[serde(default)]
+    pub command: Option<Vec<String>>,
+    /// Extra environment for the command; values are substituted like
+    /// arguments.
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+}
+
+/// Serialized as its canonical string (see `Display`); parsing also accepts
+/// the legacy variant names such as `"CodeGen"`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
 pub enum TaskType {
     Qa,
     Summarization,
     Rag,
     CodeGen,
     Classification,
-    Custom,
+    /// `custom:<name>`; a bare `custom` carries an empty name.
+    Custom(String),
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Error)]
+#[error(
+    "unknown task type {0:?}; expected qa, summarization, rag, code_gen, classification or custom:<name>"
+)]
+pub struct UnknownTaskType(pub String);
+
+impl FromStr for TaskType {
+    type Err = UnknownTaskType;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let trimmed = value.trim();
+        if let Some((prefix, name)) = trimmed.split_once(':') {
+            if prefix.eq_ignore_ascii_case("custom") && !name.trim().is_empty() {
+                return Ok(TaskType::Custom(name.trim().to_string()));
+            }
+            return Err(UnknownTaskType(value.to_string()));
+        }
+        let normalized: String = trimmed
+            .chars()
+            .filter(|c| *c != '_' && *c != '-')
+            .collect::<String>()
+            .to_ascii_lowercase();
+        match normalized.as_str() {
+            "qa" => Ok(TaskType::Qa),
+            "summarization" => Ok(TaskType::Summarization),
+            "rag" => Ok(TaskType::Rag),
+            "codegen" => Ok(TaskType::CodeGen),
+            "classification" => Ok(TaskType::Classification),
+            "custom" => Ok(TaskType::Custom(String::new())),
+            _ => Err(UnknownTaskType(value.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for TaskType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TaskType::Qa => f.write_str("qa"),
+            TaskType::Summarization => f.write_str("summarization"),
+            TaskType::Rag => f.write_str("rag"),
+            TaskType::CodeGen => f.write_str("code_gen"),
+            TaskType::Classification => f.write_str("classification"),
+            TaskType::Custom(name) if name.is_empty() => f.write_str("custom"),
+            TaskType::Custom(name) => write!(f, "custom:{name}"),
+        }
+    }
+}
+
+impl JsonSchema for TaskType {
+    fn schema_name() -> String {
+        "TaskType".into()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        let mut schema = String::json_schema(gen).into_object();
+        schema.metadata().description = Some(
+            "qa, summarization, rag, code_gen, classification, custom or custom:<name>".into(),
+        );
+        schema.into()
+    }
+}
+
+impl TryFrom<String> for TaskType {
+    type Error = UnknownTaskType;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<TaskType> for String {
+    fn from(value: TaskType) -> Self {
+        value.to_string()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct EvalConfig {
     pub run_id: Uuid,
     pub project_id: Uuid,
     pub engine: EvalEngine,
     pub engine_version: Option<String>,
+    /// Fail the run, rather than only warn, when the installed engine isn't
+    /// `engine_version`.
+    #[serde(default)]
+    pub strict_version: bool,
     pub model: ModelConfig,
+    /// Further models of a multi-model run, evaluated one by one like
+    /// `model`. See [`EvalConfig::members`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub models: Vec<ModelConfig>,
     pub dataset: DatasetConfig,
     pub task: TaskConfig,
     pub metrics: Vec<MetricConfig>,
@@ -37,9 +174,36 @@ pub struct EvalConfig {
     pub resources: ResourceConfig,
     pub output: OutputConfig,
     pub metadata: Option<Value>,
+    /// A small fixed dataset evaluated after the main one, for drift
+    /// detection. Its metrics are stored under [`canary_subset`]; a failing
+    /// canary doesn't fail the run.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub canary: Option<DatasetConfig>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// `MetricRecord.subset` of metrics computed on a run's canary dataset.
+pub const CANARY_SUBSET: &str = "canary";
+
+/// `MetricRecord.subset` of a canary metric the engine reported under
+/// `subset`: [`CANARY_SUBSET`], followed by `/subset` if it had one, so the
+/// canary dataset's subsets stay apart.
+pub fn canary_subset(subset: Option<&str>) -> String {
+    match subset {
+        Some(subset) => format!("{CANARY_SUBSET}/{subset}"),
+        None => CANARY_SUBSET.to_string(),
+    }
+}
+
+/// Whether `subset` holds canary metrics: [`CANARY_SUBSET`], or it followed
+/// by `/` and the canary dataset's own subset.
+pub fn is_canary_subset(subset: &str) -> bool {
+    subset == CANARY_SUBSET
+        || subset
+            .strip_prefix(CANARY_SUBSET)
+            .is_some_and(|rest| rest.starts_with('/'))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct ModelConfig {
     pub logical_name: String,
     pub provider: String,
@@ -49,16 +213,21 @@ pub struct ModelConfig {
     pub extra: Option<Value>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct DatasetConfig {
     pub source: DatasetSource,
     pub name: String,
     pub split: Option<String>,
     pub uri: Option<String>,
     pub filters: Option<Value>,
+    /// Set for datasets whose samples have no `reference`, such as raw text
+    /// scored by perplexity. Only reference-free metrics can run on them;
+    /// see [`EvalConfig::check_references`].
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub no_reference: bool,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(tag = "kind", rename_all = "snake_case")]
 pub enum DatasetSource {
     BuiltIn,
@@ -66,21 +235,231 @@ pub enum DatasetSource {
     External,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct TaskConfig {
     pub task_type: TaskType,
     pub task_name: String,
     pub args: Value,
+    /// Extra command-line arguments for the engine, e.g.
+    /// `["--num_fewshot", "5"]`. Flags must be in
+    /// `integrations.harness_arg_allowlist`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub harness_args: Vec<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl TaskConfig {
+    /// Checks `harness_args` against `allowlist`. Every flag (an argument
+    /// starting with `-`) must be a listed `--name`, alone or as
+    /// `--name=value`; any other argument, or a number such as the `-1` of
+    /// `--limit -1`, is the value of the flag just before it.
+    pub fn check_harness_args(args: &[String], allowlist: &[String]) -> Result<(), String> {
+        let mut expects_value = false;
+        for arg in args {
+            if expects_value && (!arg.starts_with('-') || arg.parse::<f64>().is_ok()) {
+                expects_value = false;
+            } else if arg.starts_with('-') {
+                let (flag, inline_value) = match arg.split_once('=') {
+                    Some((flag, _)) => (flag, true),
+                    None => (arg.as_str(), false),
+                };
+                if !allowlist.iter().any(|allowed| allowed == flag) {
+                    return Err(format!("harness argument {flag} is not allowed"));
+                }
+                expects_value = !inline_value;
+            } else {
+                return Err(format!("harness argument {arg:?} does not follow a flag"));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct MetricConfig {
     pub name: String,
     pub metric_type: String,
     pub params: Option<Value>,
+    /// Whether the metric improves upward or downward; unset falls back to
+    /// `params.higher_is_better`, then to the direction registry.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub direction: Option<MetricDirection>,
+    /// Whether the metric scores outputs without a `reference`; unset falls
+    /// back to [`REFERENCE_FREE`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reference_free: Option<bool>,
+    /// How per-sample values combine into the run-level value when the
+    /// engine reports only per-sample scores; unset means `mean`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aggregation: Option<SampleAggregation>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// How the per-sample values of a metric combine into one value.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SampleAggregation {
+    #[default]
+    Mean,
+    Median,
+    Sum,
+}
+
+impl SampleAggregation {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SampleAggregation::Mean => "mean",
+            SampleAggregation::Median => "median",
+            SampleAggregation::Sum => "sum",
+        }
+    }
+}
+
+/// Which way a metric improves. The default, `higher_better`, is assumed
+/// where a metric of unknown direction still has to be ranked.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum MetricDirection {
+    #[default]
+    HigherBetter,
+    LowerBetter,
+}
+
+/// Names, or runs of whole name tokens, of metrics that improve downward.
+pub const LOWER_IS_BETTER: &[&str] = &[
+    "perplexity",
+    "loss",
+    "error",
+    "wer",
+    "cer",
+    "latency",
+    "bits_per_byte",
+    "bpb",
+    "fid",
+];
+
+/// Names, or runs of whole name tokens, of metrics that improve upward.
+/// Metrics matching neither list have no built-in direction.
+pub const HIGHER_IS_BETTER: &[&str] = &[
+    "accuracy",
+    "acc",
+    "acc_norm",
+    "f1",
+    "exact_match",
+    "em",
+    "bleu",
+    "rouge1",
+    "rouge2",
+    "rougel",
+    "chrf",
+    "meteor",
+    "pass_at_k",
+    "mc1",
+    "mc2",
+    "precision",
+    "recall",
+    "auc",
+    "mrr",
+    "ndcg",
+    "win_rate",
+];
+
+/// Exact metric names and types that score outputs on their own or are
+/// derived from other metrics, so need no `reference`. Every other metric is
+/// taken to compare against one, even one merely containing these names.
+pub const REFERENCE_FREE: &[&str] = &[
+    "perplexity",
+    "word_perplexity",
+    "byte_perplexity",
+    "bits_per_byte",
+    "bpb",
+    "latency",
+    "pass_at_k",
+    "macro_average",
+    "composite",
+];
+
+impl MetricDirection {
+    /// Built-in direction of a metric by name, from [`LOWER_IS_BETTER`] and
+    /// then [`HIGHER_IS_BETTER`]; `None` for names in neither. Entries match
+    /// whole tokens only, so `answer_f1` isn't taken for `wer`.
+    pub fn for_name(metric_name: &str) -> Option<Self> {
+        let name = metric_name.to_lowercase();
+        let listed = |entries: &[&str]| entries.iter().any(|entry| contains_tokens(&name, entry));
+        if listed(LOWER_IS_BETTER) {
+            Some(MetricDirection::LowerBetter)
+        } else if listed(HIGHER_IS_BETTER) {
+            Some(MetricDirection::HigherBetter)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            MetricDirection::HigherBetter => "higher_better",
+            MetricDirection::LowerBetter => "lower_better",
+        }
+    }
+
+    pub fn from_higher_is_better(higher: bool) -> Self {
+        if higher {
+            MetricDirection::HigherBetter
+        } else {
+            MetricDirection::LowerBetter
+        }
+    }
+}
+
+/// Whether the `_`-separated tokens of `entry` occur consecutively among
+/// those of `name`, whose tokens are its runs of alphanumerics.
+fn contains_tokens(name: &str, entry: &str) -> bool {
+    let tokens: Vec<&str> = name
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|token| !token.is_empty())
+        .collect();
+    let wanted: Vec<&str> = entry.split('_').collect();
+    tokens.windows(wanted.len()).any(|window| window == wanted)
+}
+
+impl FromStr for MetricDirection {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "higher_better" => Ok(MetricDirection::HigherBetter),
+            "lower_better" => Ok(MetricDirection::LowerBetter),
+            other => Err(format!("unknown metric direction: {other}")),
+        }
+    }
+}
+
+impl MetricConfig {
+    /// The direction the config states, via `direction` or the older
+    /// `params.higher_is_better`.
+    pub fn configured_direction(&self) -> Option<MetricDirection> {
+        self.direction.or_else(|| {
+            self.params
+                .as_ref()?
+                .get("higher_is_better")?
+                .as_bool()
+                .map(MetricDirection::from_higher_is_better)
+        })
+    }
+
+    /// Whether the metric needs samples with a `reference`: the negation of
+    /// `reference_free`, else whether neither its name nor its type is in
+    /// [`REFERENCE_FREE`].
+    pub fn requires_reference(&self) -> bool {
+        if let Some(reference_free) = self.reference_free {
+            return !reference_free;
+        }
+        let (name, metric_type) = (self.name.to_lowercase(), self.metric_type.to_lowercase());
+        !REFERENCE_FREE
+            .iter()
+            .any(|entry| name == *entry || metric_type == *entry)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
 pub struct SamplingConfig {
     pub max_tokens: Option<u32>,
     pub temperature: Option<f32>,
@@ -89,7 +468,7 @@ pub struct SamplingConfig {
     pub seed: Option<u64>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
 pub struct ResourceConfig {
     pub priority: Option<u8>,
     pub num_gpus: Option<u8>,
@@ -99,7 +478,41 @@ pub struct ResourceConfig {
     pub timeout_seconds: Option<u64>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl ResourceConfig {
+    /// Fills every unset field from `defaults`; fields already set win.
+    pub fn with_defaults(self, defaults: &ResourceConfig) -> Self {
+        Self {
+            priority: self.priority.or(defaults.priority),
+            num_gpus: self.num_gpus.or(defaults.num_gpus),
+            gpu_type: self.gpu_type.or_else(|| defaults.gpu_type.clone()),
+            cpu_cores: self.cpu_cores.or(defaults.cpu_cores),
+            memory_gb: self.memory_gb.or(defaults.memory_gb),
+            timeout_seconds: self.timeout_seconds.or(defaults.timeout_seconds),
+        }
+    }
+}
+
+/// A runner's projection of what a run will need, used for admission before
+/// the run starts.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ResourceEstimate {
+    pub num_gpus: u8,
+    pub memory_gb: u16,
+    pub duration_seconds: u64,
+}
+
+impl ResourceEstimate {
+    /// What the config itself asks for, with unset fields as zero.
+    pub fn from_config(resources: &ResourceConfig) -> Self {
+        Self {
+            num_gpus: resources.num_gpus.unwrap_or(0),
+            memory_gb: resources.memory_gb.unwrap_or(0),
+            duration_seconds: resources.timeout_seconds.unwrap_or(0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(tag = "mode", rename_all = "snake_case")]
 pub enum OutputConfig {
     DbOnly,
@@ -116,7 +529,235 @@ pub enum OutputConfig {
     },
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Optional result stores that must be configured for an output mode to be
+/// honoured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResultStoreKind {
+    ClickHouse,
+    ObjectStore,
+}
+
+impl fmt::Display for ResultStoreKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ResultStoreKind::ClickHouse => "clickhouse",
+            ResultStoreKind::ObjectStore => "object_store",
+        })
+    }
+}
+
+impl OutputConfig {
+    /// The optional store this mode writes to, if any. Hybrid keeps metrics in
+    /// MySQL and sends samples to ClickHouse.
+    pub fn requires_store(&self) -> Option<ResultStoreKind> {
+        match self {
+            OutputConfig::DbOnly => None,
+            OutputConfig::ObjectStore { .. } => Some(ResultStoreKind::ObjectStore),
+            OutputConfig::ClickHouse { .. } | OutputConfig::Hybrid { .. } => {
+                Some(ResultStoreKind::ClickHouse)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum BuildError {
+    #[error("missing required field: {0}")]
+    MissingField(&'static str),
+}
+
+/// Fluent builder for [`EvalConfig`]. Output defaults to `DbOnly`, sampling and
+/// resources to all-`None`, and `run_id` to a fresh UUID.
+#[derive(Debug, Clone, Default)]
+pub struct EvalConfigBuilder {
+    run_id: Option<Uuid>,
+    project_id: Option<Uuid>,
+    engine: Option<EvalEngine>,
+    engine_version: Option<String>,
+    strict_version: bool,
+    model: Option<ModelConfig>,
+    models: Vec<ModelConfig>,
+    dataset: Option<DatasetConfig>,
+    task: Option<TaskConfig>,
+    metrics: Vec<MetricConfig>,
+    sampling: SamplingConfig,
+    resources: ResourceConfig,
+    output: Option<OutputConfig>,
+    metadata: Option<Value>,
+    canary: Option<DatasetConfig>,
+}
+
+impl EvalConfig {
+    pub fn builder() -> EvalConfigBuilder {
+        EvalConfigBuilder::default()
+    }
+
+    /// Whether the run evaluates more than one model.
+    pub fn is_multi_model(&self) -> bool {
+        !self.models.is_empty()
+    }
+
+    /// `model` followed by `models`. The metrics of each member of a
+    /// multi-model run are stored with its `logical_name` as `subset`.
+    pub fn members(&self) -> impl Iterator<Item = &ModelConfig> {
+        std::iter::once(&self.model).chain(&self.models)
+    }
+
+    /// Rejects multi-model runs whose members share a `logical_name`, since
+    /// their metrics couldn't be told apart.
+    pub fn check_members(&self) -> Result<(), String> {
+        let mut seen = std::collections::HashSet::new();
+        match self
+            .members()
+            .find(|member| !seen.insert(member.logical_name.as_str()))
+        {
+            Some(member) => Err(format!(
+                "model {} appears more than once in the run",
+                member.logical_name
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Rejects metrics that need a `reference` when the dataset or the
+    /// canary dataset, which runs the same metrics, is marked
+    /// `no_reference` and would otherwise score every sample against
+    /// nothing.
+    pub fn check_references(&self) -> Result<(), String> {
+        let Some(dataset) = std::iter::once(&self.dataset)
+            .chain(&self.canary)
+            .find(|dataset| dataset.no_reference)
+        else {
+            return Ok(());
+        };
+        let needing: Vec<&str> = self
+            .metrics
+            .iter()
+            .filter(|metric| metric.requires_reference())
+            .map(|metric| metric.name.as_str())
+            .collect();
+        if needing.is_empty() {
+            return Ok(());
+        }
+        Err(format!(
+            "dataset {} has no references, but metric(s) {} need one; mark reference-free metrics with reference_free",
+            dataset.name,
+            needing.join(", ")
+        ))
+    }
+}
+
+impl EvalConfigBuilder {
+    pub fn run_id(mut self, run_id: Uuid) -> Self {
+        self.run_id = Some(run_id);
+        self
+    }
+
+    pub fn project_id(mut self, project_id: Uuid) -> Self {
+        self.project_id = Some(project_id);
+        self
+    }
+
+    pub fn engine(mut self, engine: EvalEngine) -> Self {
+        self.engine = Some(engine);
+        self
+    }
+
+    pub fn engine_version(mut self, version: impl Into<String>) -> Self {
+        self.engine_version = Some(version.into());
+        self
+    }
+
+    pub fn strict_version(mut self, strict: bool) -> Self {
+        self.strict_version = strict;
+        self
+    }
+
+    pub fn model(mut self, model: ModelConfig) -> Self {
+        self.model = Some(model);
+        self
+    }
+
+    /// Adds a further model, making the run a multi-model one.
+    pub fn add_model(mut self, model: ModelConfig) -> Self {
+        self.models.push(model);
+        self
+    }
+
+    pub fn dataset(mut self, dataset: DatasetConfig) -> Self {
+        self.dataset = Some(dataset);
+        self
+    }
+
+    pub fn task(mut self, task: TaskConfig) -> Self {
+        self.task = Some(task);
+        self
+    }
+
+    pub fn add_metric(mut self, metric: MetricConfig) -> Self {
+        self.metrics.push(metric);
+        self
+    }
+
+    pub fn metrics(mut self, metrics: Vec<MetricConfig>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    pub fn sampling(mut self, sampling: SamplingConfig) -> Self {
+        self.sampling = sampling;
+        self
+    }
+
+    pub fn resources(mut self, resources: ResourceConfig) -> Self {
+        self.resources = resources;
+        self
+    }
+
+    pub fn timeout(mut self, seconds: u64) -> Self {
+        self.resources.timeout_seconds = Some(seconds);
+        self
+    }
+
+    pub fn output(mut self, output: OutputConfig) -> Self {
+        self.output = Some(output);
+        self
+    }
+
+    pub fn metadata(mut self, metadata: Value) -> Self {
+        self.metadata = Some(metadata);
+        self
+    }
+
+    pub fn canary(mut self, canary: DatasetConfig) -> Self {
+        self.canary = Some(canary);
+        self
+    }
+
+    pub fn build(self) -> Result<EvalConfig, BuildError> {
+        Ok(EvalConfig {
+            run_id: self.run_id.unwrap_or_else(Uuid::new_v4),
+            project_id: self
+                .project_id
+                .ok_or(BuildError::MissingField("project_id"))?,
+            engine: self.engine.ok_or(BuildError::MissingField("engine"))?,
+            engine_version: self.engine_version,
+            strict_version: self.strict_version,
+            model: self.model.ok_or(BuildError::MissingField("model"))?,
+            models: self.models,
+            dataset: self.dataset.ok_or(BuildError::MissingField("dataset"))?,
+            task: self.task.ok_or(BuildError::MissingField("task"))?,
+            metrics: self.metrics,
+            sampling: self.sampling,
+            resources: self.resources,
+            output: self.output.unwrap_or(OutputConfig::DbOnly),
+            metadata: self.metadata,
+            canary: self.canary,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct EvalResult {
     pub run_id: Uuid,
     pub status: RunStatus,
@@ -124,10 +765,47 @@ pub struct EvalResult {
     pub completed_at: Timestamp,
     pub metrics: Vec<MetricRecord>,
     pub samples: SampleResultLocation,
+    /// Set alongside `metrics` when only some sub-tasks finished; see
+    /// [`EvalResult::is_partial`].
     pub error: Option<EvalErrorPayload>,
+    /// What the engine reports about how it ran, e.g. the effective `seed`
+    /// and `library_versions`.
+    #[serde(default)]
+    pub metadata: Option<Value>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Serializes `value` as compact JSON with the keys of every object sorted,
+/// so equal values encode to the same bytes however their maps were built.
+pub fn to_canonical_json<T: Serialize + ?Sized>(value: &T) -> serde_json::Result<String> {
+    let mut value = serde_json::to_value(value)?;
+    sort_keys(&mut value);
+    serde_json::to_string(&value)
+}
+
+fn sort_keys(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            let mut entries: Vec<(String, Value)> = std::mem::take(map).into_iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+            for (key, mut item) in entries {
+                sort_keys(&mut item);
+                map.insert(key, item);
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(sort_keys),
+        _ => {}
+    }
+}
+
+/// Key of `EvalResult::metadata` under which runners record the SHA-256 of
+/// the `result.json` they read.
+pub const RESULT_CHECKSUM_METADATA_KEY: &str = "result_sha256";
+
+/// Key of `EvalResult::metadata` holding the warning recorded when the
+/// engine that ran isn't the requested `engine_version`.
+pub const ENGINE_VERSION_WARNING_KEY: &str = "engine_version_warning";
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct MetricRecord {
     pub run_id: Uuid,
     pub dataset: String,
@@ -139,9 +817,40 @@ pub struct MetricRecord {
     pub ci_low: Option<f64>,
     pub ci_high: Option<f64>,
     pub extra: Option<Value>,
+    /// Filled in when the metric is persisted, from the run config or the
+    /// direction registry, unless the engine reported one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub direction: Option<MetricDirection>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Sorts `metrics` by `(dataset, subset, split, metric_name)`, so equivalent
+/// results persist identically whatever order the engine reported them in.
+/// Unset subsets and splits sort before set ones; ties keep their order.
+pub fn sort_metrics(metrics: &mut [MetricRecord]) {
+    metrics.sort_by(|a, b| {
+        (&a.dataset, &a.subset, &a.split, &a.metric_name).cmp(&(
+            &b.dataset,
+            &b.subset,
+            &b.split,
+            &b.metric_name,
+        ))
+    });
+}
+
+/// Sorts `samples` by `sample_index`, then `(dataset, subset, split)` for
+/// samples sharing an index, e.g. the members of a multi-model run.
+pub fn sort_samples(samples: &mut [SampleRecord]) {
+    samples.sort_by(|a, b| {
+        (a.sample_index, &a.dataset, &a.subset, &a.split).cmp(&(
+            b.sample_index,
+            &b.dataset,
+            &b.subset,
+            &b.split,
+        ))
+    });
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct SampleRecord {
     pub run_id: Uuid,
     pub dataset: String,
@@ -157,14 +866,14 @@ pub struct SampleRecord {
     pub error: Option<SampleError>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct TokenCount {
     pub prompt_tokens: i32,
     pub completion_tokens: i32,
     pub total_tokens: i32,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(tag = "mode", rename_all = "snake_case")]
 pub enum SampleResultLocation {
     Inline { samples: Vec<SampleRecord> },
@@ -173,13 +882,85 @@ pub enum SampleResultLocation {
     None,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct SampleError {
     pub message: String,
     pub code: Option<String>,
+    /// Missing (older results) or unrecognized kinds read as `Other`.
+    #[serde(default)]
+    pub kind: SampleErrorKind,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Failure mode of a single sample, for aggregating failures across a run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SampleErrorKind {
+    Timeout,
+    ContentFilter,
+    ParseError,
+    ProviderError,
+    #[default]
+    #[serde(other)]
+    Other,
+}
+
+impl SampleErrorKind {
+    pub const ALL: [SampleErrorKind; 5] = [
+        SampleErrorKind::Timeout,
+        SampleErrorKind::ContentFilter,
+        SampleErrorKind::ParseError,
+        SampleErrorKind::ProviderError,
+        SampleErrorKind::Other,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SampleErrorKind::Timeout => "timeout",
+            SampleErrorKind::ContentFilter => "content_filter",
+            SampleErrorKind::ParseError => "parse_error",
+            SampleErrorKind::ProviderError => "provider_error",
+            SampleErrorKind::Other => "other",
+        }
+    }
+
+    /// Parses a stored kind; unknown or missing kinds are `Other`.
+    pub fn parse(kind: Option<&str>) -> Self {
+        Self::ALL
+            .into_iter()
+            .find(|candidate| Some(candidate.as_str()) == kind)
+            .unwrap_or(SampleErrorKind::Other)
+    }
+
+    /// Best guess from the `code` and `message` of an error whose runner
+    /// didn't classify it.
+    pub fn infer(code: Option<&str>, message: &str) -> Self {
+        let text = format!("{} {}", code.unwrap_or_default(), message).to_lowercase();
+        if text.contains("timeout") || text.contains("timed out") {
+            SampleErrorKind::Timeout
+        } else if text.contains("content_filter")
+            || text.contains("content filter")
+            || text.contains("content policy")
+            || text.contains("safety")
+        {
+            SampleErrorKind::ContentFilter
+        } else if text.contains("parse") || text.contains("json") || text.contains("extract") {
+            SampleErrorKind::ParseError
+        } else if text.contains("rate limit")
+            || text.contains("rate_limit")
+            || text.contains("api error")
+            || text.contains("api_error")
+            || text.contains("provider")
+            || text.contains("overloaded")
+            || text.contains("status 5")
+        {
+            SampleErrorKind::ProviderError
+        } else {
+            SampleErrorKind::Other
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct EvalErrorPayload {
     pub kind: EvalErrorKind,
     pub message: String,
@@ -188,7 +969,7 @@ pub struct EvalErrorPayload {
     pub details: Option<Value>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum EvalErrorKind {
     Config,
@@ -199,8 +980,10 @@ pub enum EvalErrorKind {
     Unknown,
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
 pub enum RunStatus {
+    /// Waiting for the runs it depends on to complete; queued once they have.
+    Blocked,
     Queued,
     Running,
     Completed,
@@ -210,3 +993,515 @@ pub enum RunStatus {
     TimedOut,
     Cancelled,
 }
+
+impl EvalResult {
+    /// A result carrying both metrics and an error: the finished sub-tasks'
+    /// metrics are kept and the run completes with the error attached.
+    pub fn is_partial(&self) -> bool {
+        self.error.is_some() && !self.metrics.is_empty()
+    }
+
+    /// Puts metrics and inline samples in the order of [`sort_metrics`] and
+    /// [`sort_samples`].
+    pub fn sort_canonical(&mut self) {
+        sort_metrics(&mut self.metrics);
+        if let SampleResultLocation::Inline { samples } = &mut self.samples {
+            sort_samples(samples);
+        }
+    }
+
+    /// The result in canonical order, encoded with [`to_canonical_json`].
+    pub fn canonical_json(&self) -> serde_json::Result<String> {
+        let mut result = self.clone();
+        result.sort_canonical();
+        to_canonical_json(&result)
+    }
+
+    /// Hex SHA-256 of [`EvalResult::canonical_json`]: equal for results
+    /// that differ only in metric, sample or map key order.
+    pub fn canonical_sha256(&self) -> serde_json::Result<String> {
+        use sha2::{Digest, Sha256};
+        Ok(format!("{:x}", Sha256::digest(self.canonical_json()?)))
+    }
+
+    /// Fills in the `kind` of inline sample errors left as `Other` by the
+    /// engine, using [`SampleErrorKind::infer`].
+    pub fn classify_sample_errors(&mut self) {
+        let SampleResultLocation::Inline { samples } = &mut self.samples else {
+            return;
+        };
+        for error in samples
+            .iter_mut()
+            .filter_map(|sample| sample.error.as_mut())
+        {
+            if error.kind == SampleErrorKind::Other {
+                error.kind = SampleErrorKind::infer(error.code.as_deref(), &error.message);
+            }
+        }
+    }
+}
+
+impl RunStatus {
+    /// Whether the run has finished, successfully or not.
+    pub fn is_terminal(&self) -> bool {
+        !matches!(
+            self,
+            RunStatus::Blocked | RunStatus::Queued | RunStatus::Running
+        )
+    }
+
+    /// Whether a run may move from this status to `next`: `Blocked` to
+    /// `Queued`, `Queued` to `Running`, and any unfinished status to a
+    /// terminal one. Terminal statuses are final; retrying creates a new run.
+    /// Setting the current status again is always allowed.
+    pub fn can_transition_to(&self, next: RunStatus) -> bool {
+        if *self == next {
+            return true;
+        }
+        match self {
+            RunStatus::Blocked => matches!(next, RunStatus::Queued | RunStatus::Cancelled),
+            RunStatus::Queued | RunStatus::Running => {
+                next.is_terminal() || (*self, next) == (RunStatus::Queued, RunStatus::Running)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// A job on the run queue: the full config, or for configs too large to
+/// inline, where to fetch it from the object store. Tagged with `kind`
+/// (`inline` or `reference`), so a payload that matches neither shape is
+/// rejected instead of being read as the other one.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum QueuedRun {
+    Inline(Box<EvalConfig>),
+    Reference(ConfigReference),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigReference {
+    pub run_id: Uuid,
+    /// `s3://bucket/key` of the run's `EvalConfig` JSON.
+    pub config_uri: String,
+}
+
+/// Published by the worker on [`run_status_channel`] whenever a run changes
+/// status.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct RunStatusEvent {
+    pub run_id: Uuid,
+    pub status: RunStatus,
+    pub error: Option<EvalErrorPayload>,
+    pub at: Timestamp,
+}
+
+/// Published by the worker on `redis.completion_channel`, and POSTed to
+/// `webhooks.url`, when it finishes a job.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct RunCompletedEvent {
+    pub run_id: Uuid,
+    pub status: RunStatus,
+    /// From claiming the run to recording its outcome.
+    pub duration_ms: u64,
+    /// Metrics stored for the run, derived ones included and canary ones
+    /// not; zero when it failed.
+    pub metric_count: usize,
+    pub error: Option<EvalErrorPayload>,
+    /// When the event was sent. Part of the signed webhook body, so
+    /// receivers can reject replays of old deliveries.
+    pub at: Timestamp,
+}
+
+/// Redis pub/sub channel carrying [`RunStatusEvent`]s for one run.
+pub fn run_status_channel(prefix: &str, run_id: &Uuid) -> String {
+    format!("{prefix}:{run_id}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn model() -> ModelConfig {
+        ModelConfig {
+            logical_name: "base".into(),
+            provider: "hf".into(),
+            model_name: "gpt2".into(),
+            endpoint: None,
+            api_key_ref: None,
+            extra: None,
+        }
+    }
+
+    fn dataset() -> DatasetConfig {
+        DatasetConfig {
+            source: DatasetSource::BuiltIn,
+            name: "gsm8k".into(),
+            split: None,
+            uri: None,
+            filters: None,
+            no_reference: false,
+        }
+    }
+
+    fn task() -> TaskConfig {
+        TaskConfig {
+            task_type: TaskType::Qa,
+            task_name: "gsm8k".into(),
+            args: Value::Null,
+            harness_args: Vec::new(),
+        }
+    }
+
+    fn config() -> EvalConfig {
+        EvalConfig::builder()
+            .project_id(Uuid::new_v4())
+            .engine(EvalEngine::LmEvalHarness)
+            .model(model())
+            .dataset(dataset())
+            .task(task())
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn minimal_builds_fill_in_defaults() {
+        let project_id = Uuid::new_v4();
+        let config = EvalConfig::builder()
+            .project_id(project_id)
+            .engine(EvalEngine::LmEvalHarness)
+            .model(model())
+            .dataset(dataset())
+            .task(task())
+            .build()
+            .unwrap();
+        assert_eq!(config.project_id, project_id);
+        assert!(!config.run_id.is_nil());
+        assert!(matches!(config.output, OutputConfig::DbOnly));
+        assert!(!config.is_multi_model());
+        assert!(config.metrics.is_empty());
+        assert!(config.resources.num_gpus.is_none());
+        assert!(config.resources.timeout_seconds.is_none());
+        assert!(!config.strict_version);
+        assert!(config.canary.is_none());
+
+        let run_id = Uuid::new_v4();
+        let config = EvalConfig::builder()
+            .run_id(run_id)
+            .project_id(project_id)
+            .engine(EvalEngine::LmEvalHarness)
+            .model(model())
+            .dataset(dataset())
+            .task(task())
+            .timeout(60)
+            .build()
+            .unwrap();
+        assert_eq!(config.run_id, run_id);
+        assert_eq!(config.resources.timeout_seconds, Some(60));
+    }
+
+    #[test]
+    fn builds_without_a_model_name_the_missing_field() {
+        let err = EvalConfig::builder()
+            .project_id(Uuid::new_v4())
+            .engine(EvalEngine::LmEvalHarness)
+            .dataset(dataset())
+            .task(task())
+            .build()
+            .unwrap_err();
+        assert!(matches!(err, BuildError::MissingField("model")), "{err}");
+
+        let err = EvalConfig::builder()
+            .engine(EvalEngine::LmEvalHarness)
+            .model(model())
+            .dataset(dataset())
+            .task(task())
+            .build()
+            .unwrap_err();
+        assert!(
+            matches!(err, BuildError::MissingField("project_id")),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn queued_runs_round_trip() {
+        let config = config();
+        let inline = serde_json::to_string(&QueuedRun::Inline(Box::new(config.clone()))).unwrap();
+        match serde_json::from_str::<QueuedRun>(&inline).unwrap() {
+            QueuedRun::Inline(parsed) => assert_eq!(parsed.run_id, config.run_id),
+            other => panic!("expected an inline config, got {other:?}"),
+        }
+
+        let reference = serde_json::to_string(&QueuedRun::Reference(ConfigReference {
+            run_id: config.run_id,
+            config_uri: "s3://bucket/configs/run.json".into(),
+        }))
+        .unwrap();
+        match serde_json::from_str::<QueuedRun>(&reference).unwrap() {
+            QueuedRun::Reference(parsed) => {
+                assert_eq!(parsed.run_id, config.run_id);
+                assert_eq!(parsed.config_uri, "s3://bucket/configs/run.json");
+            }
+            other => panic!("expected a reference, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn queued_runs_reject_unknown_shapes() {
+        // A bare config without `kind`.
+        let bare = serde_json::to_string(&config()).unwrap();
+        assert!(serde_json::from_str::<QueuedRun>(&bare).is_err());
+
+        let run_id = Uuid::new_v4();
+        let malformed = serde_json::json!({ "kind": "inline", "run_id": run_id });
+        assert!(serde_json::from_value::<QueuedRun>(malformed).is_err());
+
+        let extra = serde_json::json!({
+            "kind": "reference",
+            "run_id": run_id,
+            "config_uri": "s3://bucket/run.json",
+            "engine": "lm-eval-harness",
+        });
+        assert!(serde_json::from_value::<QueuedRun>(extra).is_err());
+
+        let unknown = serde_json::json!({ "kind": "template", "run_id": run_id });
+        assert!(serde_json::from_value::<QueuedRun>(unknown).is_err());
+    }
+
+    #[test]
+    fn direction_matches_whole_tokens() {
+        use MetricDirection::{HigherBetter, LowerBetter};
+        assert_eq!(MetricDirection::for_name("answer_f1"), Some(HigherBetter));
+        assert_eq!(
+            MetricDirection::for_name("answer_accuracy"),
+            Some(HigherBetter)
+        );
+        assert_eq!(MetricDirection::for_name("wer"), Some(LowerBetter));
+        assert_eq!(
+            MetricDirection::for_name("word_perplexity,none"),
+            Some(LowerBetter)
+        );
+        assert_eq!(
+            MetricDirection::for_name("Bits_Per_Byte"),
+            Some(LowerBetter)
+        );
+        // `confidence` contains `fid` but no known token.
+        assert_eq!(MetricDirection::for_name("confidence"), None);
+        assert_eq!(
+            MetricDirection::for_name("confidence").unwrap_or_default(),
+            HigherBetter
+        );
+    }
+
+    #[test]
+    fn harness_args_take_negative_values() {
+        let allowlist = ["--limit".to_string(), "--num_fewshot".to_string()];
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+        assert!(TaskConfig::check_harness_args(&args(&["--limit", "-1"]), &allowlist).is_ok());
+        assert!(TaskConfig::check_harness_args(
+            &args(&["--num_fewshot=5", "--limit", "0.5"]),
+            &allowlist
+        )
+        .is_ok());
+        assert!(
+            TaskConfig::check_harness_args(&args(&["--limit", "--output_path"]), &allowlist)
+                .is_err()
+        );
+        assert!(TaskConfig::check_harness_args(&args(&["-1"]), &allowlist).is_err());
+        assert!(TaskConfig::check_harness_args(&args(&["5"]), &allowlist).is_err());
+    }
+
+    #[test]
+    fn reference_free_metrics_match_whole_names() {
+        let metric = |name: &str| MetricConfig {
+            name: name.into(),
+            metric_type: "exact_match".into(),
+            params: None,
+            direction: None,
+            reference_free: None,
+            aggregation: None,
+        };
+        assert!(!metric("word_perplexity").requires_reference());
+        assert!(metric("perplexity_judge_agreement").requires_reference());
+        assert!(metric("latency_adjusted_accuracy").requires_reference());
+    }
+
+    #[test]
+    fn canary_datasets_without_references_are_checked() {
+        let mut config = config();
+        config.metrics = vec![MetricConfig {
+            name: "exact_match".into(),
+            metric_type: "exact_match".into(),
+            params: None,
+            direction: None,
+            reference_free: None,
+            aggregation: None,
+        }];
+        assert!(config.check_references().is_ok());
+        config.canary = Some(DatasetConfig {
+            source: DatasetSource::BuiltIn,
+            name: "canary_text".into(),
+            split: None,
+            uri: None,
+            filters: None,
+            no_reference: true,
+        });
+        let err = config.check_references().unwrap_err();
+        assert!(err.contains("canary_text"), "{err}");
+    }
+
+    fn result(run_id: Uuid, metric_order: &[usize], sample_order: &[usize]) -> EvalResult {
+        let at = chrono::DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let metrics = [
+            ("gsm8k", None, "exact_match"),
+            ("gsm8k", Some("hard"), "exact_match"),
+            ("arc", None, "acc"),
+        ]
+        .map(|(dataset, subset, name)| MetricRecord {
+            run_id,
+            dataset: dataset.into(),
+            subset: subset.map(Into::into),
+            split: None,
+            metric_name: name.into(),
+            value: 0.5,
+            n_samples: Some(2),
+            ci_low: None,
+            ci_high: None,
+            extra: Some(
+                serde_json::json!({ "stderr": 0.1, "filter": { "name": "strict", "k": 1 } }),
+            ),
+            direction: None,
+        });
+        let samples = [0, 1].map(|index| SampleRecord {
+            run_id,
+            dataset: "gsm8k".into(),
+            subset: None,
+            split: None,
+            sample_index: index,
+            input: format!("q{index}"),
+            reference: Some("4".into()),
+            output: "4".into(),
+            metrics: Some(serde_json::json!({ "exact_match": 1.0, "acc": 1.0 })),
+            latency_ms: Some(10),
+            token_counts: None,
+            error: None,
+        });
+        EvalResult {
+            run_id,
+            status: RunStatus::Completed,
+            started_at: at,
+            completed_at: at,
+            metrics: metric_order.iter().map(|&i| metrics[i].clone()).collect(),
+            samples: SampleResultLocation::Inline {
+                samples: sample_order.iter().map(|&i| samples[i].clone()).collect(),
+            },
+            error: None,
+            metadata: Some(
+                serde_json::json!({ "seed": 1234, "library_versions": { "torch": "2.2" } }),
+            ),
+        }
+    }
+
+    #[test]
+    fn shuffled_results_encode_and_hash_identically() {
+        let run_id = Uuid::new_v4();
+        let a = result(run_id, &[0, 1, 2], &[0, 1]);
+        let b = result(run_id, &[2, 1, 0], &[1, 0]);
+        assert_eq!(a.canonical_json().unwrap(), b.canonical_json().unwrap());
+        assert_eq!(a.canonical_sha256().unwrap(), b.canonical_sha256().unwrap());
+
+        let c = result(run_id, &[0, 1], &[0, 1]);
+        assert_ne!(a.canonical_sha256().unwrap(), c.canonical_sha256().unwrap());
+    }
+
+    #[test]
+    fn canonical_json_sorts_keys_and_round_trips() {
+        let mut map = std::collections::HashMap::new();
+        for key in ["zeta", "alpha", "mu", "beta"] {
+            map.insert(
+                key,
+                serde_json::json!({ "y": [{ "b": 1, "a": 2 }], "x": null }),
+            );
+        }
+        let encoded = to_canonical_json(&map).unwrap();
+        let entry = r#"{"x":null,"y":[{"a":2,"b":1}]}"#;
+        assert_eq!(
+            encoded,
+            format!(r#"{{"alpha":{entry},"beta":{entry},"mu":{entry},"zeta":{entry}}}"#)
+        );
+
+        let original = result(Uuid::new_v4(), &[2, 0, 1], &[1, 0]);
+        let encoded = original.canonical_json().unwrap();
+        let decoded: EvalResult = serde_json::from_str(&encoded).unwrap();
+        assert_eq!(decoded.canonical_json().unwrap(), encoded);
+        assert_eq!(
+            decoded.canonical_sha256().unwrap(),
+            original.canonical_sha256().unwrap()
+        );
+    }
+
+    #[test]
+    fn status_transitions() {
+        use RunStatus::*;
+        let cases = [
+            (Queued, Running, true),
+            (Running, Completed, true),
+            (Blocked, Queued, true),
+            (Blocked, Cancelled, true),
+            (Queued, Cancelled, true),
+            (Running, FailedInfra, true),
+            (Running, Running, true),
+            (Completed, Completed, true),
+            (Completed, Running, false),
+            (FailedEngine, Queued, false),
+            (Cancelled, Completed, false),
+            (Running, Queued, false),
+            (Blocked, Running, false),
+        ];
+        for (from, to, allowed) in cases {
+            assert_eq!(from.can_transition_to(to), allowed, "{from:?} -> {to:?}");
+        }
+    }
+
+    #[test]
+    fn task_types_parse_from_their_names() {
+        let parse = |value: &str| value.parse::<TaskType>();
+        assert_eq!(parse("qa").unwrap(), TaskType::Qa);
+        assert_eq!(parse("code_gen").unwrap(), TaskType::CodeGen);
+        // Legacy variant names and stray spacing still parse.
+        assert_eq!(parse(" CodeGen ").unwrap(), TaskType::CodeGen);
+        assert_eq!(
+            parse("custom:toxicity").unwrap(),
+            TaskType::Custom("toxicity".into())
+        );
+        assert_eq!(parse("custom").unwrap(), TaskType::Custom(String::new()));
+
+        let UnknownTaskType(value) = parse("sumarization").unwrap_err();
+        assert_eq!(value, "sumarization");
+        assert!(parse("custom:").is_err());
+        assert!(parse("qa:extra").is_err());
+    }
+
+    #[test]
+    fn task_types_round_trip_through_their_display() {
+        for task_type in [
+            TaskType::Qa,
+            TaskType::Summarization,
+            TaskType::Rag,
+            TaskType::CodeGen,
+            TaskType::Classification,
+            TaskType::Custom(String::new()),
+            TaskType::Custom("toxicity".into()),
+        ] {
+            let encoded = serde_json::to_string(&task_type).unwrap();
+            assert_eq!(encoded, format!("\"{task_type}\""));
+            assert_eq!(
+                serde_json::from_str::<TaskType>(&encoded).unwrap(),
+                task_type
+            );
+        }
+        assert!(serde_json::from_str::<TaskType>("\"sumarization\"").is_err());
+    }
+}
@@ -1,3 +1,6 @@
 pub mod error;
 pub mod eval;
+pub mod queue;
+pub mod retry;
+pub mod secrets;
 pub mod settings;
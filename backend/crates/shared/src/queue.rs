@@ -0,0 +1,231 @@
+use std::collections::{HashMap, HashSet};
+
+use uuid::Uuid;
+
+use crate::settings::{QueueStrategy, Settings};
+
+/// The list a run of `project_id` is pushed onto.
+pub fn queue_key(settings: &Settings, project_id: &Uuid) -> String {
+    match settings.queues.strategy {
+        QueueStrategy::Single => settings.redis.queue_key.clone(),
+        QueueStrategy::PerProject | QueueStrategy::Weighted => {
+            format!("{}:{project_id}", settings.redis.queue_key)
+        }
+    }
+}
+
+/// Every list a queued run of `project_id` may sit on: its own queue and,
+/// when that differs, `redis.queue_key`, which still holds runs queued
+/// before a strategy change.
+pub fn queue_keys(settings: &Settings, project_id: &Uuid) -> Vec<String> {
+    let key = queue_key(settings, project_id);
+    if key == settings.redis.queue_key {
+        vec![key]
+    } else {
+        vec![key, settings.redis.queue_key.clone()]
+    }
+}
+
+/// Redis set of the project ids that have had a queue, so workers discover
+/// new projects' queues without a restart.
+pub fn registry_key(settings: &Settings) -> String {
+    format!("{}:projects", settings.redis.queue_key)
+}
+
+/// A list the worker pops from, with its share under the `weighted` strategy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueueLane {
+    pub key: String,
+    pub weight: u32,
+}
+
+/// The lanes to serve given the registered project ids. `redis.queue_key`
+/// is always included so runs queued before a strategy change still drain.
+pub fn lanes(settings: &Settings, project_ids: impl IntoIterator<Item = String>) -> Vec<QueueLane> {
+    let queues = &settings.queues;
+    let mut lanes: Vec<QueueLane> = match queues.strategy {
+        QueueStrategy::Single => Vec::new(),
+        QueueStrategy::PerProject | QueueStrategy::Weighted => project_ids
+            .into_iter()
+            .map(|project_id| QueueLane {
+                key: format!("{}:{project_id}", settings.redis.queue_key),
+                weight: queues
+                    .weights
+                    .get(&project_id)
+                    .copied()
+                    .unwrap_or(queues.default_weight)
+                    .max(1),
+            })
+            .collect(),
+    };
+    lanes.sort_by(|a, b| a.key.cmp(&b.key));
+    lanes.dedup();
+    lanes.push(QueueLane {
+        key: settings.redis.queue_key.clone(),
+        weight: queues.default_weight.max(1),
+    });
+    lanes
+}
+
+/// Decides which lane a worker serves first. The returned order is meant
+/// for a multi-key `BLPOP`, which pops from the first non-empty list, so a
+/// lane without work never holds up the others.
+///
+/// `per_project` rotates round-robin past the lane popped last. `weighted`
+/// uses smooth weighted round-robin: each pop credits every lane its weight
+/// and charges the popped lane the total, and lanes are tried by credit.
+/// Lanes tried before the popped one were empty and forfeit their credit.
+#[derive(Debug, Default)]
+pub struct QueueSelector {
+    strategy: QueueStrategy,
+    credits: HashMap<String, i64>,
+    last: Option<String>,
+}
+
+impl QueueSelector {
+    pub fn new(strategy: QueueStrategy) -> Self {
+        Self {
+            strategy,
+            ..Self::default()
+        }
+    }
+
+    pub fn order(&self, lanes: &[QueueLane]) -> Vec<String> {
+        let mut keys: Vec<&QueueLane> = lanes.iter().collect();
+        match self.strategy {
+            QueueStrategy::Single => {}
+            QueueStrategy::PerProject => {
+                if let Some(last) = &self.last {
+                    let start = keys
+                        .iter()
+                        .position(|lane| &lane.key == last)
+                        .map_or(0, |index| index + 1);
+                    let len = keys.len().max(1);
+                    keys.rotate_left(start % len);
+                }
+            }
+            QueueStrategy::Weighted => {
+                let credit = |lane: &QueueLane| self.credits.get(&lane.key).copied().unwrap_or(0);
+                keys.sort_by(|a, b| {
+                    (credit(b) + i64::from(b.weight))
+                        .cmp(&(credit(a) + i64::from(a.weight)))
+                        .then_with(|| a.key.cmp(&b.key))
+                });
+            }
+        }
+        keys.into_iter().map(|lane| lane.key.clone()).collect()
+    }
+
+    /// Records that a job was popped from `key`.
+    pub fn record_pop(&mut self, lanes: &[QueueLane], key: &str) {
+        match self.strategy {
+            QueueStrategy::Single => {}
+            QueueStrategy::PerProject => self.last = Some(key.to_string()),
+            QueueStrategy::Weighted => {
+                // Lanes tried before `key` were empty: they sit the round out
+                // and lose their credit rather than bank turns that would go
+                // to whichever lane follows them.
+                let order = self.order(lanes);
+                let skipped: HashSet<&String> =
+                    order.iter().take_while(|skipped| *skipped != key).collect();
+                let active = lanes.iter().filter(|lane| !skipped.contains(&lane.key));
+                let total: i64 = active.clone().map(|lane| i64::from(lane.weight)).sum();
+                for lane in active {
+                    *self.credits.entry(lane.key.clone()).or_default() += i64::from(lane.weight);
+                }
+                for skipped in skipped {
+                    self.credits.remove(skipped);
+                }
+                *self.credits.entry(key.to_string()).or_default() -= total;
+                // Forget lanes that are gone so credits don't grow unbounded.
+                self.credits
+                    .retain(|key, _| lanes.iter().any(|lane| &lane.key == key));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::tests::defaults;
+    use std::collections::VecDeque;
+
+    fn with_strategy(strategy: QueueStrategy) -> Settings {
+        let mut settings = defaults();
+        settings.queues.strategy = strategy;
+        settings
+    }
+
+    /// Pops `pops` jobs the way the worker does: a `BLPOP` over the selector's
+    /// order takes the head of the first non-empty list. `queued` holds the
+    /// number of jobs per project; the projects popped from are returned.
+    fn drain(settings: &Settings, queued: &[(&str, usize)], pops: usize) -> Vec<String> {
+        let lanes = lanes(
+            settings,
+            queued.iter().map(|(project, _)| project.to_string()),
+        );
+        let mut lists: HashMap<String, VecDeque<String>> = queued
+            .iter()
+            .map(|(project, count)| {
+                let key = format!("{}:{project}", settings.redis.queue_key);
+                (key, vec![project.to_string(); *count].into())
+            })
+            .collect();
+        let mut selector = QueueSelector::new(settings.queues.strategy);
+        let mut popped = Vec::new();
+        for _ in 0..pops {
+            let Some((key, project)) = selector.order(&lanes).into_iter().find_map(|key| {
+                let project = lists.get_mut(&key)?.pop_front()?;
+                Some((key, project))
+            }) else {
+                break;
+            };
+            selector.record_pop(&lanes, &key);
+            popped.push(project);
+        }
+        popped
+    }
+
+    #[test]
+    fn per_project_queues_take_turns() {
+        let settings = with_strategy(QueueStrategy::PerProject);
+        // A backlog in one project doesn't starve the others.
+        let popped = drain(&settings, &[("a", 5), ("b", 2), ("c", 1)], 8);
+        assert_eq!(popped, ["a", "b", "c", "a", "b", "a", "a", "a"]);
+    }
+
+    #[test]
+    fn weighted_queues_are_served_in_proportion() {
+        let mut settings = with_strategy(QueueStrategy::Weighted);
+        settings.queues.default_weight = 1;
+        settings.queues.weights.insert("a".into(), 3);
+        let popped = drain(&settings, &[("a", 20), ("b", 20)], 12);
+        let from_a = popped.iter().filter(|project| *project == "a").count();
+        assert_eq!((from_a, popped.len() - from_a), (9, 3));
+        // Smooth round-robin spreads `b`'s turns out rather than bunching them.
+        assert!(popped.windows(2).all(|pair| pair != ["b", "b"]));
+    }
+
+    #[test]
+    fn lanes_keep_the_shared_list_last() {
+        let settings = with_strategy(QueueStrategy::PerProject);
+        let keys: Vec<String> = lanes(&settings, ["b".into(), "a".into(), "a".into()])
+            .into_iter()
+            .map(|lane| lane.key)
+            .collect();
+        let queue_key = &settings.redis.queue_key;
+        assert_eq!(
+            keys,
+            [
+                format!("{queue_key}:a"),
+                format!("{queue_key}:b"),
+                queue_key.clone()
+            ]
+        );
+        assert_eq!(
+            lanes(&with_strategy(QueueStrategy::Single), ["a".into()]).len(),
+            1
+        );
+    }
+}
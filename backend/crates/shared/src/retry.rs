@@ -0,0 +1,165 @@
+use std::future::Future;
+use std::time::Duration;
+
+use rand::Rng;
+
+/// Exponential backoff: attempt `n` (1-based) waits `base_delay * 2^(n-1)`,
+/// capped at `max_delay`, plus up to half of that again when `jitter` is set.
+/// Loops that retry until a store comes back use it directly; bounded
+/// retries go through [`RetryPolicy`].
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    pub jitter: bool,
+}
+
+impl Backoff {
+    /// Delay before retrying after failed attempt `attempt` (1-based).
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let exp = self
+            .base_delay
+            .saturating_mul(1 << attempt.saturating_sub(1).min(16))
+            .min(self.max_delay);
+        if !self.jitter {
+            return exp;
+        }
+        let max_jitter = (exp / 2).as_millis() as u64;
+        exp + Duration::from_millis(rand::thread_rng().gen_range(0..=max_jitter))
+    }
+}
+
+/// At most `max_attempts` attempts, `backoff` apart; see
+/// [`retry_with_backoff`].
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Total attempts including the first; `0` is treated as `1`.
+    pub max_attempts: u32,
+    pub backoff: Backoff,
+}
+
+/// Runs `f` until it succeeds, fails with an error `is_retryable` rejects, or
+/// `policy.max_attempts` is used up; the last error is returned. `f` gets the
+/// 1-based attempt number.
+pub async fn retry_with_backoff<T, E, F, Fut>(
+    policy: &RetryPolicy,
+    is_retryable: impl Fn(&E) -> bool,
+    f: F,
+) -> Result<T, E>
+where
+    E: std::fmt::Display,
+    F: FnMut(u32) -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    retry_with_sleep(policy, is_retryable, f, tokio::time::sleep).await
+}
+
+/// [`retry_with_backoff`] with the sleep function injected, so callers (and
+/// tests) can substitute a fake clock.
+pub async fn retry_with_sleep<T, E, F, Fut, S, SleepFut>(
+    policy: &RetryPolicy,
+    is_retryable: impl Fn(&E) -> bool,
+    mut f: F,
+    mut sleep: S,
+) -> Result<T, E>
+where
+    E: std::fmt::Display,
+    F: FnMut(u32) -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+    S: FnMut(Duration) -> SleepFut,
+    SleepFut: Future<Output = ()>,
+{
+    let max_attempts = policy.max_attempts.max(1);
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        match f(attempt).await {
+            Ok(value) => return Ok(value),
+            Err(err) if attempt < max_attempts && is_retryable(&err) => {
+                let delay = policy.backoff.delay(attempt);
+                tracing::warn!(
+                    "attempt {attempt}/{max_attempts} failed ({err}); retrying in {}ms",
+                    delay.as_millis()
+                );
+                sleep(delay).await;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::{Cell, RefCell};
+
+    fn policy(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            backoff: Backoff {
+                base_delay: Duration::from_millis(100),
+                max_delay: Duration::from_millis(250),
+                jitter: false,
+            },
+        }
+    }
+
+    /// Runs `retry_with_sleep` with an operation failing its first `failures`
+    /// attempts, and returns the outcome, the attempts made and the delays
+    /// slept, without actually sleeping.
+    async fn run(
+        max_attempts: u32,
+        failures: u32,
+        retryable: bool,
+    ) -> (Result<u32, String>, u32, Vec<Duration>) {
+        let attempts = Cell::new(0);
+        let slept = RefCell::new(Vec::new());
+        let outcome = retry_with_sleep(
+            &policy(max_attempts),
+            |_: &String| retryable,
+            |attempt| {
+                attempts.set(attempt);
+                std::future::ready(if attempt <= failures {
+                    Err(format!("failure {attempt}"))
+                } else {
+                    Ok(attempt)
+                })
+            },
+            |delay| {
+                slept.borrow_mut().push(delay);
+                std::future::ready(())
+            },
+        )
+        .await;
+        (outcome, attempts.get(), slept.into_inner())
+    }
+
+    #[tokio::test]
+    async fn success_on_the_first_attempt_never_sleeps() {
+        let (outcome, attempts, slept) = run(3, 0, true).await;
+        assert_eq!(outcome, Ok(1));
+        assert_eq!(attempts, 1);
+        assert!(slept.is_empty());
+    }
+
+    #[tokio::test]
+    async fn retries_back_off_until_an_attempt_succeeds() {
+        let (outcome, attempts, slept) = run(5, 3, true).await;
+        assert_eq!(outcome, Ok(4));
+        assert_eq!(attempts, 4);
+        assert_eq!(slept, [100, 200, 250].map(Duration::from_millis).to_vec());
+    }
+
+    #[tokio::test]
+    async fn exhausted_retries_return_the_last_error() {
+        let (outcome, attempts, slept) = run(3, u32::MAX, true).await;
+        assert_eq!(outcome, Err("failure 3".to_string()));
+        assert_eq!(attempts, 3);
+        assert_eq!(slept.len(), 2);
+
+        let (outcome, attempts, slept) = run(3, u32::MAX, false).await;
+        assert_eq!(outcome, Err("failure 1".to_string()));
+        assert_eq!(attempts, 1);
+        assert!(slept.is_empty());
+    }
+}
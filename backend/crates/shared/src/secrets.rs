@@ -0,0 +1,93 @@
+//! Resolution of secret references such as `ModelConfig.api_key_ref`.
+//!
+//! Supported schemes:
+//! - `env:VAR_NAME` reads an environment variable of the worker process.
+//! - `file:/path/to/secret` reads a file, trimming surrounding whitespace.
+//!
+//! Resolved values must never be logged; errors only name the reference.
+
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum SecretError {
+    #[error("secret reference {0:?} has no supported scheme (expected env: or file:)")]
+    UnsupportedScheme(String),
+    #[error("environment variable {0} is not set")]
+    MissingEnv(String),
+    #[error("failed to read secret file {path}: {source}")]
+    File {
+        path: String,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("secret {0:?} resolved to an empty value")]
+    Empty(String),
+}
+
+pub fn resolve(reference: &str) -> Result<String, SecretError> {
+    let (scheme, target) = reference
+        .split_once(':')
+        .ok_or_else(|| SecretError::UnsupportedScheme(reference.to_string()))?;
+    let value = match scheme {
+        "env" => std::env::var(target).map_err(|_| SecretError::MissingEnv(target.to_string()))?,
+        "file" => std::fs::read_to_string(target)
+            .map_err(|source| SecretError::File {
+                path: target.to_string(),
+                source,
+            })?
+            .trim()
+            .to_string(),
+        _ => return Err(SecretError::UnsupportedScheme(reference.to_string())),
+    };
+    if value.is_empty() {
+        return Err(SecretError::Empty(reference.to_string()));
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn env_references_read_the_variable() {
+        std::env::set_var("SECRETS_TEST_API_KEY", "sk-env");
+        assert_eq!(resolve("env:SECRETS_TEST_API_KEY").unwrap(), "sk-env");
+
+        std::env::set_var("SECRETS_TEST_EMPTY_KEY", "");
+        assert!(matches!(
+            resolve("env:SECRETS_TEST_EMPTY_KEY"),
+            Err(SecretError::Empty(_))
+        ));
+    }
+
+    #[test]
+    fn file_references_read_the_trimmed_file() {
+        let path = std::env::temp_dir().join(format!("secret-{}", uuid::Uuid::new_v4()));
+        std::fs::write(&path, "  sk-file\n").unwrap();
+        let reference = format!("file:{}", path.display());
+        assert_eq!(resolve(&reference).unwrap(), "sk-file");
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(resolve(&reference), Err(SecretError::File { .. })));
+    }
+
+    #[test]
+    fn missing_secrets_name_the_reference_only() {
+        let err = resolve("env:SECRETS_TEST_UNSET_KEY").unwrap_err();
+        assert!(matches!(&err, SecretError::MissingEnv(name) if name == "SECRETS_TEST_UNSET_KEY"));
+        assert_eq!(
+            err.to_string(),
+            "environment variable SECRETS_TEST_UNSET_KEY is not set"
+        );
+
+        assert!(matches!(
+            resolve("vault:evals/api-key"),
+            Err(SecretError::UnsupportedScheme(_))
+        ));
+        assert!(matches!(
+            resolve("sk-plaintext"),
+            Err(SecretError::UnsupportedScheme(_))
+        ));
+    }
+}
@@ -1,7 +1,10 @@
+use std::collections::{BTreeMap, HashMap};
 use std::env;
 
 use config::{Config, ConfigError, Environment, File};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+
+use crate::eval::{EvalEngine, OutputConfig, ResourceConfig, ResultStoreKind};
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct Settings {
@@ -11,11 +14,63 @@ pub struct Settings {
     pub integrations: IntegrationSettings,
     pub clickhouse: Option<ClickhouseSettings>,
     pub object_store: Option<ObjectStoreSettings>,
+    #[serde(default)]
+    pub idempotency: IdempotencySettings,
+    #[serde(default)]
+    pub readiness: ReadinessSettings,
+    #[serde(default)]
+    pub logging: LoggingSettings,
+    #[serde(default)]
+    pub pricing: PricingSettings,
+    #[serde(default)]
+    pub bootstrap: BootstrapSettings,
+    #[serde(default)]
+    pub retention: RetentionSettings,
+    #[serde(default)]
+    pub server: ServerSettings,
+    #[serde(default)]
+    pub coverage: CoverageSettings,
+    #[serde(default)]
+    pub canary: CanarySettings,
+    #[serde(default)]
+    pub circuit_breaker: CircuitBreakerSettings,
+    #[serde(default)]
+    pub webhooks: WebhookSettings,
+    /// Resources filled into compiled runs that leave fields unset, per
+    /// engine, e.g. `[default_resources.helm]`.
+    #[serde(default)]
+    pub default_resources: HashMap<EvalEngine, ResourceConfig>,
+    /// Output of compiled runs whose config sets none; unset leaves them
+    /// `db_only`.
+    #[serde(default)]
+    pub default_output: Option<OutputConfig>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct DatabaseSettings {
     pub url: String,
+    #[serde(default = "default_max_connections")]
+    pub max_connections: u32,
+    #[serde(default)]
+    pub min_connections: u32,
+    #[serde(default = "default_acquire_timeout_seconds")]
+    pub acquire_timeout_seconds: u64,
+    /// Idle connections above `min_connections` are closed after this long;
+    /// `None` keeps them open.
+    #[serde(default = "default_idle_timeout_seconds")]
+    pub idle_timeout_seconds: Option<u64>,
+}
+
+fn default_max_connections() -> u32 {
+    5
+}
+
+fn default_acquire_timeout_seconds() -> u64 {
+    30
+}
+
+fn default_idle_timeout_seconds() -> Option<u64> {
+    Some(600)
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -23,6 +78,28 @@ pub struct RedisSettings {
     pub url: String,
     pub queue_key: String,
     pub dlq_key: String,
+    #[serde(default = "default_status_channel_prefix")]
+    pub status_channel_prefix: String,
+    /// While this key exists workers stop popping jobs; queued jobs stay in
+    /// Redis and runs already started finish. Set by `POST /admin/pause`.
+    #[serde(default = "default_pause_key")]
+    pub pause_key: String,
+    /// Channel the worker publishes a `RunCompletedEvent` on when it
+    /// finishes a job.
+    #[serde(default = "default_completion_channel")]
+    pub completion_channel: String,
+}
+
+fn default_status_channel_prefix() -> String {
+    "runs:status".into()
+}
+
+fn default_pause_key() -> String {
+    "runs:paused".into()
+}
+
+fn default_completion_channel() -> String {
+    "runs:completed".into()
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -30,11 +107,160 @@ pub struct QueueSettings {
     pub max_parallel_jobs: u32,
     pub max_parallel_gpu_jobs: u32,
     pub max_gpus_total: u32,
+    /// How long a worker's claim on a run lasts without renewal. Another
+    /// worker may pick up the run once it expires.
+    #[serde(default = "default_lease_seconds")]
+    pub lease_seconds: u64,
+    /// How runs are spread over Redis lists; see [`QueueStrategy`].
+    #[serde(default)]
+    pub strategy: QueueStrategy,
+    /// Relative share of each project's queue under the `weighted` strategy,
+    /// keyed by project id. Unlisted projects get `default_weight`.
+    #[serde(default)]
+    pub weights: HashMap<String, u32>,
+    #[serde(default = "default_queue_weight")]
+    pub default_weight: u32,
+    /// Most runs against one model endpoint that may proceed at once, keyed
+    /// by `ModelConfig.endpoint`, or by `provider` for runs without one.
+    /// Endpoints not listed are unlimited.
+    #[serde(default)]
+    pub endpoint_limits: HashMap<String, u32>,
+    /// Id the worker claims runs under. Keeping it stable across restarts
+    /// keeps the runs it claimed its own, which matters to the artifact
+    /// reaper, as only the worker holding a run directory can delete it.
+    /// Unset picks a random `worker-<uuid>` per start.
+    #[serde(default)]
+    pub worker_id: Option<String>,
+}
+
+fn default_lease_seconds() -> u64 {
+    300
+}
+
+fn default_queue_weight() -> u32 {
+    1
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QueueStrategy {
+    /// Every run goes to `redis.queue_key`.
+    #[default]
+    Single,
+    /// Each project gets its own list, `{queue_key}:{project_id}`; workers
+    /// take turns between the projects that have work.
+    PerProject,
+    /// Per-project lists, served in proportion to `queues.weights`.
+    Weighted,
 }
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct IntegrationSettings {
     pub third_party_root: String,
+    /// Run directories live under `{work_dir}/runs/{run_id}`; relative paths
+    /// are resolved against the worker's working directory at startup.
+    #[serde(default = "default_work_dir")]
+    pub work_dir: String,
+    /// Remove a run's directory once its results are persisted. Failed runs
+    /// always keep theirs for debugging and resuming.
+    #[serde(default)]
+    pub cleanup_run_dir_on_success: bool,
+    /// Local cache for datasets fetched from `http(s)://` or `s3://` URIs;
+    /// a relative path is resolved against the worker's working directory at
+    /// startup.
+    #[serde(default = "default_dataset_cache_dir")]
+    pub dataset_cache_dir: String,
+    /// Interpreter the Python-based runners spawn.
+    #[serde(default = "default_python_executable")]
+    pub python_executable: String,
+    /// Virtualenv to run engines in; its `bin` directory is put first on
+    /// `PATH` and `VIRTUAL_ENV` is set.
+    #[serde(default)]
+    pub virtualenv_path: Option<String>,
+    /// Per-engine overrides keyed by runner name, e.g. `helm`.
+    #[serde(default)]
+    pub engines: HashMap<String, EngineSettings>,
+    /// What to do when a runner left `result.json` without its
+    /// `result.json.sha256`.
+    #[serde(default)]
+    pub missing_result_checksum: MissingChecksum,
+    /// How long to keep polling for `result.json` (or `error.json`) after a
+    /// runner exits without it, in case the file is still being flushed.
+    #[serde(default = "default_result_grace_ms")]
+    pub result_grace_ms: u64,
+    /// Commands of `EvalEngine::Custom` runs that name one instead of
+    /// carrying their own.
+    #[serde(default)]
+    pub custom_engines: HashMap<String, CustomEngineSettings>,
+    /// Flags a task's `harness_args` may pass to the lm-eval harness, as
+    /// `--name`; anything else is rejected so configs can't override the
+    /// flags the runner sets itself.
+    #[serde(default = "default_harness_arg_allowlist")]
+    pub harness_arg_allowlist: Vec<String>,
+    /// Worker environment variables a custom engine command inherits; the
+    /// rest of the worker's environment (database, S3 and Redis
+    /// credentials) is withheld from it.
+    #[serde(default = "default_custom_env_passthrough")]
+    pub custom_env_passthrough: Vec<String>,
+}
+
+/// A named custom engine; see `eval::CustomEngine` for the template syntax.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CustomEngineSettings {
+    pub command: Vec<String>,
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+}
+
+fn default_result_grace_ms() -> u64 {
+    1000
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MissingChecksum {
+    /// Log a warning and trust the file.
+    #[default]
+    Warn,
+    /// Fail the run as `infra`.
+    Error,
+}
+
+/// Overrides of the interpreter settings for a single engine.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct EngineSettings {
+    pub python_executable: Option<String>,
+    pub virtualenv_path: Option<String>,
+}
+
+fn default_work_dir() -> String {
+    ".".into()
+}
+
+fn default_dataset_cache_dir() -> String {
+    "./dataset_cache".into()
+}
+
+fn default_python_executable() -> String {
+    "python".into()
+}
+
+fn default_harness_arg_allowlist() -> Vec<String> {
+    [
+        "--num_fewshot",
+        "--batch_size",
+        "--max_batch_size",
+        "--limit",
+        "--device",
+    ]
+    .map(String::from)
+    .to_vec()
+}
+
+fn default_custom_env_passthrough() -> Vec<String> {
+    ["PATH", "HOME", "LANG", "LC_ALL", "TZ", "TMPDIR"]
+        .map(String::from)
+        .to_vec()
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -45,6 +271,14 @@ pub struct ClickhouseSettings {
     pub password: Option<String>,
     pub samples_table: String,
     pub metrics_table: String,
+    /// Bounds TCP connects, so an unreachable server fails fast instead of
+    /// stalling result persistence and readiness probes.
+    #[serde(default = "default_clickhouse_connect_timeout_seconds")]
+    pub connect_timeout_seconds: u64,
+}
+
+fn default_clickhouse_connect_timeout_seconds() -> u64 {
+    5
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -55,6 +289,421 @@ pub struct ObjectStoreSettings {
     pub access_key: String,
     pub secret_key: String,
     pub use_path_style: bool,
+    #[serde(default = "default_upload_max_attempts")]
+    pub upload_max_attempts: u32,
+    #[serde(default = "default_upload_base_delay_ms")]
+    pub upload_base_delay_ms: u64,
+    /// Run configs whose JSON exceeds this many bytes are stored here and
+    /// referenced from the DB and queue instead of being inlined.
+    #[serde(default = "default_max_inline_config_bytes")]
+    pub max_inline_config_bytes: usize,
+    /// Runs whose samples would go to MySQL but number more than this are
+    /// written here as `samples.jsonl` instead.
+    #[serde(default = "default_max_inline_samples")]
+    pub max_inline_samples: usize,
+}
+
+fn default_max_inline_samples() -> usize {
+    50_000
+}
+
+fn default_max_inline_config_bytes() -> usize {
+    256 * 1024
+}
+
+fn default_upload_max_attempts() -> u32 {
+    3
+}
+
+fn default_upload_base_delay_ms() -> u64 {
+    200
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct IdempotencySettings {
+    pub ttl_seconds: u64,
+}
+
+impl Default for IdempotencySettings {
+    fn default() -> Self {
+        Self {
+            ttl_seconds: 24 * 60 * 60,
+        }
+    }
+}
+
+/// Whether an unreachable optional store fails `/readyz`. Stores that are not
+/// configured at all are never probed.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ReadinessSettings {
+    #[serde(default)]
+    pub require_clickhouse: bool,
+    #[serde(default)]
+    pub require_object_store: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    /// Human-readable output for local development.
+    #[default]
+    Text,
+    /// One JSON object per line, including the fields of enclosing spans.
+    Json,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct LoggingSettings {
+    #[serde(default)]
+    pub format: LogFormat,
+}
+
+/// Per-model token prices used to estimate run cost.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct PricingSettings {
+    #[serde(default)]
+    pub models: Vec<ModelPrice>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ModelPrice {
+    /// Matched against `ModelConfig.model_name`.
+    pub model: String,
+    pub prompt_per_1k_tokens: f64,
+    pub completion_per_1k_tokens: f64,
+}
+
+impl PricingSettings {
+    pub fn price_for(&self, model: &str) -> Option<&ModelPrice> {
+        self.models.iter().find(|price| price.model == model)
+    }
+}
+
+impl ModelPrice {
+    pub fn cost(&self, prompt_tokens: i64, completion_tokens: i64) -> f64 {
+        prompt_tokens as f64 / 1000.0 * self.prompt_per_1k_tokens
+            + completion_tokens as f64 / 1000.0 * self.completion_per_1k_tokens
+    }
+}
+
+/// Bootstrap confidence intervals filled in for metrics reported without
+/// `ci_low`/`ci_high`, computed from per-sample metric values.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BootstrapSettings {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_bootstrap_iterations")]
+    pub iterations: usize,
+    #[serde(default = "default_bootstrap_confidence")]
+    pub confidence: f64,
+}
+
+impl Default for BootstrapSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            iterations: default_bootstrap_iterations(),
+            confidence: default_bootstrap_confidence(),
+        }
+    }
+}
+
+fn default_bootstrap_iterations() -> usize {
+    1000
+}
+
+fn default_bootstrap_confidence() -> f64 {
+    0.95
+}
+
+/// Limits applied to every API request. Oversized bodies get 413, requests
+/// that don't produce a response in time get 408.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ServerSettings {
+    #[serde(default = "default_max_body_bytes")]
+    pub max_body_bytes: usize,
+    #[serde(default = "default_request_timeout_seconds")]
+    pub request_timeout_seconds: u64,
+    /// Most runs one `compile-sweep` may expand to (checkpoints × tasks ×
+    /// variable combinations); larger sweeps get 400.
+    #[serde(default = "default_max_sweep_runs")]
+    pub max_sweep_runs: usize,
+}
+
+impl Default for ServerSettings {
+    fn default() -> Self {
+        Self {
+            max_body_bytes: default_max_body_bytes(),
+            request_timeout_seconds: default_request_timeout_seconds(),
+            max_sweep_runs: default_max_sweep_runs(),
+        }
+    }
+}
+
+/// Generous enough for large experiment configs and metric batches.
+fn default_max_body_bytes() -> usize {
+    8 * 1024 * 1024
+}
+
+fn default_request_timeout_seconds() -> u64 {
+    30
+}
+
+fn default_max_sweep_runs() -> usize {
+    1000
+}
+
+/// How long artifacts (run directories, object-store prefixes) of failed and
+/// cancelled runs are kept before the worker's reaper deletes them. Failed
+/// covers every `failed_*` status and `timed_out`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RetentionSettings {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_retention_interval_seconds")]
+    pub interval_seconds: u64,
+    #[serde(default = "default_failed_retention_days")]
+    pub failed_days: u64,
+    #[serde(default = "default_cancelled_retention_days")]
+    pub cancelled_days: u64,
+}
+
+impl Default for RetentionSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval_seconds: default_retention_interval_seconds(),
+            failed_days: default_failed_retention_days(),
+            cancelled_days: default_cancelled_retention_days(),
+        }
+    }
+}
+
+/// Per-project overrides of [`Settings`], stored in `project_settings`.
+/// Unset fields keep the global value; see [`ProjectSettings::apply`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProjectSettings {
+    /// Merged field by field over the global entry of each engine.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub default_resources: HashMap<EvalEngine, ResourceConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_output: Option<OutputConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retention: Option<RetentionOverrides>,
+}
+
+/// Retention windows of one project; the reaper's schedule stays global.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RetentionOverrides {
+    pub failed_days: Option<u64>,
+    pub cancelled_days: Option<u64>,
+}
+
+impl ProjectSettings {
+    /// `global` with these overrides applied.
+    pub fn apply(&self, global: &Settings) -> Settings {
+        let mut settings = global.clone();
+        for (engine, resources) in &self.default_resources {
+            let merged = match global.default_resources.get(engine) {
+                Some(defaults) => resources.clone().with_defaults(defaults),
+                None => resources.clone(),
+            };
+            settings.default_resources.insert(engine.clone(), merged);
+        }
+        if let Some(output) = &self.default_output {
+            settings.default_output = Some(output.clone());
+        }
+        if let Some(retention) = &self.retention {
+            if let Some(days) = retention.failed_days {
+                settings.retention.failed_days = days;
+            }
+            if let Some(days) = retention.cancelled_days {
+                settings.retention.cancelled_days = days;
+            }
+        }
+        settings
+    }
+
+    /// Whether either retention window is overridden.
+    pub fn overrides_retention(&self) -> bool {
+        self.retention
+            .as_ref()
+            .is_some_and(|r| r.failed_days.is_some() || r.cancelled_days.is_some())
+    }
+}
+
+fn default_retention_interval_seconds() -> u64 {
+    60 * 60
+}
+
+fn default_failed_retention_days() -> u64 {
+    30
+}
+
+fn default_cancelled_retention_days() -> u64 {
+    7
+}
+
+/// When `GET /runs/:id` flags a run as covering too little of its dataset.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CoverageSettings {
+    /// Smallest share of the dataset's `num_samples` the run's metrics may
+    /// cover without a `coverage_warning`.
+    #[serde(default = "default_coverage_min_fraction")]
+    pub min_fraction: f64,
+    /// Subsets where partial coverage is expected, as `subset` or
+    /// `dataset/subset`. They are left out of the check; the rest of the
+    /// dataset is still checked.
+    #[serde(default)]
+    pub exclude_subsets: Vec<String>,
+}
+
+impl CoverageSettings {
+    pub fn excludes(&self, dataset: &str, subset: &str) -> bool {
+        self.exclude_subsets.iter().any(|entry| {
+            entry == subset
+                || entry
+                    .split_once('/')
+                    .is_some_and(|(d, s)| d == dataset && s == subset)
+        })
+    }
+}
+
+impl Default for CoverageSettings {
+    fn default() -> Self {
+        Self {
+            min_fraction: default_coverage_min_fraction(),
+            exclude_subsets: Vec::new(),
+        }
+    }
+}
+
+fn default_coverage_min_fraction() -> f64 {
+    0.95
+}
+
+/// When `GET /runs/:id` flags a run's canary metrics as drifting from the
+/// project's canary baseline.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CanarySettings {
+    /// Largest absolute difference from the baseline a canary metric may
+    /// show without being reported in `canary_drift`.
+    #[serde(default = "default_canary_max_drift")]
+    pub max_drift: f64,
+}
+
+impl Default for CanarySettings {
+    fn default() -> Self {
+        Self {
+            max_drift: default_canary_max_drift(),
+        }
+    }
+}
+
+fn default_canary_max_drift() -> f64 {
+    0.05
+}
+
+/// When the worker aborts a run whose samples mostly fail, judged from the
+/// counts runners write to `progress.json`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CircuitBreakerSettings {
+    #[serde(default = "default_breaker_enabled")]
+    pub enabled: bool,
+    /// Share of failed samples above which the run is aborted.
+    #[serde(default = "default_breaker_max_error_rate")]
+    pub max_error_rate: f64,
+    /// Samples that must finish before the error rate is judged, so a few
+    /// early failures don't kill a run.
+    #[serde(default = "default_breaker_min_samples")]
+    pub min_samples: u64,
+    /// The breaker keeps judging until it has seen this many samples
+    /// complete without tripping; the run is then left to finish.
+    #[serde(default = "default_breaker_window_samples")]
+    pub window_samples: u64,
+}
+
+impl CircuitBreakerSettings {
+    /// Whether `failed` of `completed` samples should abort the run. Any
+    /// count of at least `min_samples` is judged, however far past the
+    /// window, so a run first observed late is still checked once.
+    pub fn trips(&self, completed: u64, failed: u64) -> bool {
+        self.enabled
+            && completed >= self.min_samples
+            && completed > 0
+            && failed as f64 / completed as f64 > self.max_error_rate
+    }
+
+    /// Whether a run that didn't trip at `completed` samples is past the
+    /// window, so its outcome is latched and it isn't checked again.
+    pub fn settled(&self, completed: u64) -> bool {
+        completed >= self.min_samples.max(self.window_samples)
+    }
+}
+
+impl Default for CircuitBreakerSettings {
+    fn default() -> Self {
+        Self {
+            enabled: default_breaker_enabled(),
+            max_error_rate: default_breaker_max_error_rate(),
+            min_samples: default_breaker_min_samples(),
+            window_samples: default_breaker_window_samples(),
+        }
+    }
+}
+
+fn default_breaker_enabled() -> bool {
+    true
+}
+
+fn default_breaker_max_error_rate() -> f64 {
+    0.5
+}
+
+fn default_breaker_min_samples() -> u64 {
+    20
+}
+
+fn default_breaker_window_samples() -> u64 {
+    200
+}
+
+/// Where the worker POSTs a `RunCompletedEvent` for each finished job. Off
+/// while `url` is unset.
+#[derive(Debug, Clone, Deserialize)]
+pub struct WebhookSettings {
+    pub url: Option<String>,
+    /// Key of the HMAC-SHA256 signature sent in `X-Signature-256`; without
+    /// it events are sent unsigned.
+    pub secret: Option<String>,
+    /// Per-attempt request timeout.
+    #[serde(default = "default_webhook_timeout_seconds")]
+    pub timeout_seconds: u64,
+    /// Deliveries including the first; failures and 5xx/429 answers are
+    /// retried with backoff.
+    #[serde(default = "default_webhook_max_attempts")]
+    pub max_attempts: u32,
+}
+
+impl Default for WebhookSettings {
+    fn default() -> Self {
+        Self {
+            url: None,
+            secret: None,
+            timeout_seconds: default_webhook_timeout_seconds(),
+            max_attempts: default_webhook_max_attempts(),
+        }
+    }
+}
+
+fn default_webhook_timeout_seconds() -> u64 {
+    10
+}
+
+fn default_webhook_max_attempts() -> u32 {
+    5
 }
 
 impl Settings {
@@ -73,6 +722,383 @@ impl Settings {
                 .add_source(File::with_name(&format!("config/{}", env_name)).required(false));
         }
 
-        builder.build()?.try_deserialize()
+        let settings: Self = builder.build()?.try_deserialize()?;
+        settings
+            .validate()
+            .map_err(|problems| ConfigError::Message(problems.join("; ")))?;
+        Ok(settings)
+    }
+
+    pub fn has_store(&self, kind: ResultStoreKind) -> bool {
+        match kind {
+            ResultStoreKind::ClickHouse => self.clickhouse.is_some(),
+            ResultStoreKind::ObjectStore => self.object_store.is_some(),
+        }
+    }
+
+    /// Rejects output modes whose store isn't configured, rather than letting
+    /// results silently fall back to MySQL.
+    pub fn check_output(&self, output: &OutputConfig) -> Result<(), String> {
+        match output.requires_store() {
+            Some(kind) if !self.has_store(kind) => Err(format!(
+                "output mode requires the {kind} result store, which is not configured"
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks cross-field consistency that deserialization can't express.
+    /// Returns every problem found, not just the first.
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut problems = Vec::new();
+
+        check_url(
+            &mut problems,
+            "database.url",
+            &self.database.url,
+            &["mysql"],
+        );
+        if self.database.max_connections == 0 {
+            problems.push("database.max_connections must be at least 1".into());
+        }
+        if self.database.min_connections > self.database.max_connections {
+            problems.push(format!(
+                "database.min_connections ({}) must not exceed database.max_connections ({})",
+                self.database.min_connections, self.database.max_connections
+            ));
+        }
+        check_url(
+            &mut problems,
+            "redis.url",
+            &self.redis.url,
+            &["redis", "rediss", "unix"],
+        );
+        check_non_empty(&mut problems, "redis.queue_key", &self.redis.queue_key);
+        check_non_empty(&mut problems, "redis.dlq_key", &self.redis.dlq_key);
+        check_non_empty(&mut problems, "redis.pause_key", &self.redis.pause_key);
+        if !self.redis.queue_key.is_empty() && self.redis.queue_key == self.redis.dlq_key {
+            problems.push("redis.dlq_key must differ from redis.queue_key".into());
+        }
+
+        check_non_empty(
+            &mut problems,
+            "integrations.work_dir",
+            &self.integrations.work_dir,
+        );
+        check_non_empty(
+            &mut problems,
+            "integrations.dataset_cache_dir",
+            &self.integrations.dataset_cache_dir,
+        );
+        check_non_empty(
+            &mut problems,
+            "integrations.python_executable",
+            &self.integrations.python_executable,
+        );
+        for (engine, overrides) in &self.integrations.engines {
+            if let Some(python) = &overrides.python_executable {
+                check_non_empty(
+                    &mut problems,
+                    &format!("integrations.engines.{engine}.python_executable"),
+                    python,
+                );
+            }
+        }
+
+        if self.queues.max_parallel_jobs == 0 {
+            problems.push("queues.max_parallel_jobs must be at least 1".into());
+        }
+        if self.queues.max_parallel_gpu_jobs == 0 && self.queues.max_gpus_total > 0 {
+            // No GPU job could ever start, so GPU runs would requeue forever.
+            problems.push(
+                "queues.max_parallel_gpu_jobs must be at least 1 when queues.max_gpus_total is set"
+                    .into(),
+            );
+        }
+        if self.queues.max_parallel_gpu_jobs > self.queues.max_parallel_jobs {
+            problems.push(format!(
+                "queues.max_parallel_gpu_jobs ({}) must not exceed queues.max_parallel_jobs ({})",
+                self.queues.max_parallel_gpu_jobs, self.queues.max_parallel_jobs
+            ));
+        }
+        if self.queues.lease_seconds == 0 {
+            problems.push("queues.lease_seconds must be at least 1".into());
+        }
+        if self.queues.default_weight == 0 {
+            problems.push("queues.default_weight must be at least 1".into());
+        }
+        for (project_id, weight) in &self.queues.weights {
+            if *weight == 0 {
+                problems.push(format!("queues.weights.{project_id} must be at least 1"));
+            }
+        }
+        for (name, engine) in &self.integrations.custom_engines {
+            let program = engine.command.first().map(|program| program.trim());
+            if matches!(program, None | Some("")) {
+                problems.push(format!(
+                    "integrations.custom_engines.{name}.command must name a program"
+                ));
+            }
+        }
+
+        for (endpoint, limit) in &self.queues.endpoint_limits {
+            if *limit == 0 {
+                problems.push(format!(
+                    "queues.endpoint_limits.\"{endpoint}\" must be at least 1"
+                ));
+            }
+        }
+
+        if self.bootstrap.iterations == 0 {
+            problems.push("bootstrap.iterations must be at least 1".into());
+        }
+        if !(self.bootstrap.confidence > 0.0 && self.bootstrap.confidence < 1.0) {
+            problems.push("bootstrap.confidence must be between 0 and 1 (exclusive)".into());
+        }
+
+        if self.server.max_body_bytes == 0 {
+            problems.push("server.max_body_bytes must be at least 1".into());
+        }
+        if self.server.request_timeout_seconds == 0 {
+            problems.push("server.request_timeout_seconds must be at least 1".into());
+        }
+
+        if let Some(output) = &self.default_output {
+            if let Err(problem) = self.check_output(output) {
+                problems.push(format!("default_output: {problem}"));
+            }
+        }
+
+        if self.retention.enabled && self.retention.interval_seconds == 0 {
+            problems.push("retention.interval_seconds must be at least 1".into());
+        }
+
+        if let Some(flag) = self
+            .integrations
+            .harness_arg_allowlist
+            .iter()
+            .find(|flag| !flag.starts_with("--") || flag.contains('='))
+        {
+            problems.push(format!(
+                "integrations.harness_arg_allowlist entry {flag:?} must be a --flag without a value"
+            ));
+        }
+
+        if !(0.0..=1.0).contains(&self.coverage.min_fraction) {
+            problems.push("coverage.min_fraction must be between 0 and 1".into());
+        }
+        if !(self.canary.max_drift.is_finite() && self.canary.max_drift >= 0.0) {
+            problems.push("canary.max_drift must be a non-negative number".into());
+        }
+        if !(0.0..=1.0).contains(&self.circuit_breaker.max_error_rate) {
+            problems.push("circuit_breaker.max_error_rate must be between 0 and 1".into());
+        }
+        if self.circuit_breaker.window_samples < self.circuit_breaker.min_samples {
+            problems.push(
+                "circuit_breaker.window_samples must be at least circuit_breaker.min_samples"
+                    .into(),
+            );
+        }
+
+        if let Some(url) = &self.webhooks.url {
+            check_url(&mut problems, "webhooks.url", url, &["http", "https"]);
+        }
+        if self.webhooks.secret.as_deref() == Some("") {
+            problems.push("webhooks.secret must not be empty when set".into());
+        }
+        if self.webhooks.timeout_seconds == 0 {
+            problems.push("webhooks.timeout_seconds must be positive".into());
+        }
+
+        if let Some(clickhouse) = &self.clickhouse {
+            check_url(
+                &mut problems,
+                "clickhouse.url",
+                &clickhouse.url,
+                &["http", "https"],
+            );
+            check_non_empty(&mut problems, "clickhouse.database", &clickhouse.database);
+            check_non_empty(
+                &mut problems,
+                "clickhouse.samples_table",
+                &clickhouse.samples_table,
+            );
+            check_non_empty(
+                &mut problems,
+                "clickhouse.metrics_table",
+                &clickhouse.metrics_table,
+            );
+            if clickhouse.connect_timeout_seconds == 0 {
+                problems.push("clickhouse.connect_timeout_seconds must be at least 1".into());
+            }
+        }
+
+        if let Some(store) = &self.object_store {
+            check_url(
+                &mut problems,
+                "object_store.endpoint",
+                &store.endpoint,
+                &["http", "https"],
+            );
+            check_non_empty(&mut problems, "object_store.bucket", &store.bucket);
+            check_non_empty(&mut problems, "object_store.access_key", &store.access_key);
+            check_non_empty(&mut problems, "object_store.secret_key", &store.secret_key);
+            if store.upload_max_attempts == 0 {
+                problems.push("object_store.upload_max_attempts must be at least 1".into());
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems)
+        }
+    }
+}
+
+fn check_non_empty(problems: &mut Vec<String>, field: &str, value: &str) {
+    if value.trim().is_empty() {
+        problems.push(format!("{field} must not be empty"));
+    }
+}
+
+/// Requires `scheme://rest` with one of the allowed schemes and a non-empty rest.
+fn check_url(problems: &mut Vec<String>, field: &str, value: &str, schemes: &[&str]) {
+    let valid = value
+        .split_once("://")
+        .is_some_and(|(scheme, rest)| schemes.contains(&scheme) && !rest.is_empty());
+    if !valid {
+        problems.push(format!(
+            "{field} must be a URL with scheme {}",
+            schemes.join("/")
+        ));
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use config::FileFormat;
+
+    /// The settings shipped in `config/default.toml`.
+    pub(crate) fn defaults() -> Settings {
+        Config::builder()
+            .add_source(File::from_str(
+                include_str!("../../../config/default.toml"),
+                FileFormat::Toml,
+            ))
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap()
+    }
+
+    #[test]
+    fn project_overrides_win_and_unset_fields_fall_back() {
+        let mut global = defaults();
+        global.default_resources.insert(
+            EvalEngine::Helm,
+            ResourceConfig {
+                memory_gb: Some(32),
+                timeout_seconds: Some(3600),
+                ..ResourceConfig::default()
+            },
+        );
+        let overrides = ProjectSettings {
+            default_resources: HashMap::from([(
+                EvalEngine::Helm,
+                ResourceConfig {
+                    memory_gb: Some(64),
+                    ..ResourceConfig::default()
+                },
+            )]),
+            default_output: Some(OutputConfig::DbOnly),
+            retention: Some(RetentionOverrides {
+                failed_days: Some(90),
+                cancelled_days: None,
+            }),
+        };
+
+        let settings = overrides.apply(&global);
+        let helm = &settings.default_resources[&EvalEngine::Helm];
+        assert_eq!(helm.memory_gb, Some(64));
+        assert_eq!(helm.timeout_seconds, Some(3600));
+        assert!(matches!(
+            settings.default_output,
+            Some(OutputConfig::DbOnly)
+        ));
+        assert_eq!(settings.retention.failed_days, 90);
+        assert_eq!(
+            settings.retention.cancelled_days,
+            global.retention.cancelled_days
+        );
+    }
+
+    #[test]
+    fn empty_project_overrides_keep_the_global_settings() {
+        let global = defaults();
+        let settings = ProjectSettings::default().apply(&global);
+        assert!(settings.default_resources.is_empty());
+        assert!(settings.default_output.is_none());
+        assert_eq!(settings.retention.failed_days, global.retention.failed_days);
+        assert_eq!(
+            settings.retention.cancelled_days,
+            global.retention.cancelled_days
+        );
+        assert!(settings.validate().is_ok());
+    }
+
+    #[test]
+    fn gpu_workers_need_a_gpu_job_slot() {
+        let mut settings = defaults();
+        settings.queues.max_gpus_total = 4;
+        settings.queues.max_parallel_gpu_jobs = 0;
+        let problems = settings.validate().unwrap_err();
+        assert!(
+            problems
+                .iter()
+                .any(|problem| problem.contains("queues.max_parallel_gpu_jobs")),
+            "{problems:?}"
+        );
+
+        // A CPU-only worker never starts GPU jobs anyway.
+        settings.queues.max_gpus_total = 0;
+        assert!(settings.validate().is_ok());
+    }
+
+    #[test]
+    fn shipped_defaults_are_valid() {
+        assert_eq!(defaults().validate(), Ok(()));
+    }
+
+    #[test]
+    fn every_invalid_value_is_reported() {
+        let mut settings = defaults();
+        settings.database.url = "postgres://localhost/evals".into();
+        settings.redis.dlq_key = settings.redis.queue_key.clone();
+        settings.queues.max_parallel_jobs = 0;
+        settings.queues.weights.insert("p1".into(), 0);
+        settings.bootstrap.confidence = 1.5;
+        settings.webhooks.url = Some("not a url".into());
+        settings
+            .integrations
+            .harness_arg_allowlist
+            .push("--limit=5".into());
+
+        let problems = settings.validate().unwrap_err();
+        for field in [
+            "database.url",
+            "redis.dlq_key",
+            "queues.max_parallel_jobs",
+            "queues.weights.p1",
+            "bootstrap.confidence",
+            "webhooks.url",
+            "\"--limit=5\"",
+        ] {
+            assert!(
+                problems.iter().any(|problem| problem.contains(field)),
+                "no problem mentions {field}: {problems:?}"
+            );
+        }
     }
 }
@@ -0,0 +1,163 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use unified_shared::eval::ModelConfig;
+use unified_shared::settings::QueueSettings;
+use uuid::Uuid;
+
+/// Caps the runs proceeding against one model endpoint at
+/// `queues.endpoint_limits`, so a burst of runs doesn't trip the provider's
+/// rate limits. Runs over the limit are requeued at admission, or wait for a
+/// permit as members of a multi-model run, rather than fail.
+pub struct EndpointLimiter {
+    semaphores: HashMap<String, Arc<Semaphore>>,
+}
+
+pub enum EndpointAllocation {
+    /// `None` when the endpoint is unlimited.
+    Granted(Option<OwnedSemaphorePermit>),
+    /// The named endpoint has no free slot; the job should stay queued.
+    Busy(String),
+}
+
+impl EndpointLimiter {
+    pub fn new(settings: &QueueSettings) -> Self {
+        let semaphores = settings
+            .endpoint_limits
+            .iter()
+            .map(|(endpoint, limit)| {
+                (
+                    endpoint.clone(),
+                    Arc::new(Semaphore::new((*limit).max(1) as usize)),
+                )
+            })
+            .collect();
+        Self { semaphores }
+    }
+
+    /// A permit for `model`'s endpoint if one is free right now, for admitting
+    /// a job before it takes GPUs or claims its run.
+    pub fn try_acquire(&self, model: &ModelConfig) -> EndpointAllocation {
+        let key = Self::key(model);
+        let Some(semaphore) = self.semaphores.get(key) else {
+            return EndpointAllocation::Granted(None);
+        };
+        match semaphore.clone().try_acquire_owned() {
+            Ok(permit) => EndpointAllocation::Granted(Some(permit)),
+            Err(_) => EndpointAllocation::Busy(key.to_string()),
+        }
+    }
+
+    /// Waits until the run may call its endpoint. The permit is released when
+    /// dropped; `None` means the endpoint is unlimited.
+    pub async fn acquire(
+        &self,
+        run_id: &Uuid,
+        model: &ModelConfig,
+    ) -> Option<OwnedSemaphorePermit> {
+        let key = Self::key(model);
+        let semaphore = self.semaphores.get(key)?.clone();
+        if let Ok(permit) = semaphore.clone().try_acquire_owned() {
+            return Some(permit);
+        }
+        tracing::info!("run {run_id} is waiting for a free slot on endpoint {key}");
+        // The semaphores are never closed.
+        semaphore.acquire_owned().await.ok()
+    }
+
+    fn key(model: &ModelConfig) -> &str {
+        model.endpoint.as_deref().unwrap_or(&model.provider)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn limiter(limits: serde_json::Value) -> EndpointLimiter {
+        let settings: QueueSettings = serde_json::from_value(serde_json::json!({
+            "max_parallel_jobs": 4,
+            "max_parallel_gpu_jobs": 1,
+            "max_gpus_total": 1,
+            "endpoint_limits": limits,
+        }))
+        .unwrap();
+        EndpointLimiter::new(&settings)
+    }
+
+    fn model(provider: &str, endpoint: Option<&str>) -> ModelConfig {
+        ModelConfig {
+            logical_name: "model".into(),
+            provider: provider.into(),
+            model_name: "model".into(),
+            endpoint: endpoint.map(Into::into),
+            api_key_ref: None,
+            extra: None,
+        }
+    }
+
+    #[test]
+    fn an_endpoint_limited_to_one_admits_one_run_at_a_time() {
+        let limiter = limiter(serde_json::json!({ "https://vllm.internal/v1": 1 }));
+        let model = model("vllm", Some("https://vllm.internal/v1"));
+
+        let EndpointAllocation::Granted(Some(permit)) = limiter.try_acquire(&model) else {
+            panic!("the first run should get the endpoint's slot");
+        };
+        match limiter.try_acquire(&model) {
+            EndpointAllocation::Busy(endpoint) => assert_eq!(endpoint, "https://vllm.internal/v1"),
+            EndpointAllocation::Granted(_) => panic!("a second run should stay queued"),
+        }
+
+        drop(permit);
+        assert!(matches!(
+            limiter.try_acquire(&model),
+            EndpointAllocation::Granted(Some(_))
+        ));
+    }
+
+    #[test]
+    fn runs_without_an_endpoint_are_limited_by_provider() {
+        let limiter = limiter(serde_json::json!({ "openai": 1 }));
+        let hosted = model("openai", None);
+
+        let _permit = limiter.try_acquire(&hosted);
+        assert!(matches!(
+            limiter.try_acquire(&hosted),
+            EndpointAllocation::Busy(endpoint) if endpoint == "openai"
+        ));
+        // The same provider behind its own endpoint is counted separately,
+        // and endpoints without a limit are never busy.
+        for _ in 0..3 {
+            assert!(matches!(
+                limiter.try_acquire(&model("openai", Some("https://proxy.internal"))),
+                EndpointAllocation::Granted(None)
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn waiting_runs_proceed_once_the_slot_is_released() {
+        let limiter = Arc::new(limiter(serde_json::json!({ "anthropic": 1 })));
+        let model = model("anthropic", None);
+        let first = limiter.acquire(&Uuid::new_v4(), &model).await;
+        assert!(first.is_some());
+
+        let waiter = {
+            let limiter = limiter.clone();
+            let model = model.clone();
+            tokio::spawn(async move { limiter.acquire(&Uuid::new_v4(), &model).await })
+        };
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!waiter.is_finished(), "the second run should wait");
+
+        drop(first);
+        let second = tokio::time::timeout(Duration::from_secs(5), waiter)
+            .await
+            .expect("the second run should get the slot")
+            .unwrap();
+        assert!(second.is_some());
+    }
+}
@@ -0,0 +1,133 @@
+use std::sync::Arc;
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use unified_shared::settings::QueueSettings;
+
+/// Tracks GPUs reserved by in-flight jobs so the worker never runs more than
+/// `max_gpus_total` GPUs or `max_parallel_gpu_jobs` GPU jobs at the same time.
+#[derive(Clone)]
+pub struct GpuAllocator {
+    gpus: Arc<Semaphore>,
+    gpu_jobs: Arc<Semaphore>,
+    max_gpus_total: u32,
+}
+
+/// Releases the reserved GPUs when dropped.
+pub struct GpuLease {
+    _gpus: Option<OwnedSemaphorePermit>,
+    _job: Option<OwnedSemaphorePermit>,
+}
+
+pub enum GpuAllocation {
+    Granted(GpuLease),
+    /// Not enough free GPUs right now; the job should stay queued.
+    Busy,
+    /// The job asks for more GPUs than this worker will ever have.
+    Unsatisfiable,
+}
+
+impl GpuAllocator {
+    pub fn new(settings: &QueueSettings) -> Self {
+        Self {
+            gpus: Arc::new(Semaphore::new(settings.max_gpus_total as usize)),
+            gpu_jobs: Arc::new(Semaphore::new(settings.max_parallel_gpu_jobs as usize)),
+            max_gpus_total: settings.max_gpus_total,
+        }
+    }
+
+    pub fn try_allocate(&self, num_gpus: Option<u8>) -> GpuAllocation {
+        let requested = u32::from(num_gpus.unwrap_or(0));
+        if requested == 0 {
+            return GpuAllocation::Granted(GpuLease {
+                _gpus: None,
+                _job: None,
+            });
+        }
+        if requested > self.max_gpus_total {
+            return GpuAllocation::Unsatisfiable;
+        }
+
+        let Ok(job) = self.gpu_jobs.clone().try_acquire_owned() else {
+            return GpuAllocation::Busy;
+        };
+        let Ok(gpus) = self.gpus.clone().try_acquire_many_owned(requested) else {
+            return GpuAllocation::Busy;
+        };
+        GpuAllocation::Granted(GpuLease {
+            _gpus: Some(gpus),
+            _job: Some(job),
+        })
+    }
+
+    pub fn in_use(&self) -> u32 {
+        self.max_gpus_total - self.gpus.available_permits() as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn allocator(max_gpus_total: u32, max_parallel_gpu_jobs: u32) -> GpuAllocator {
+        let settings: QueueSettings = serde_json::from_value(serde_json::json!({
+            "max_parallel_jobs": 4,
+            "max_parallel_gpu_jobs": max_parallel_gpu_jobs,
+            "max_gpus_total": max_gpus_total,
+        }))
+        .unwrap();
+        GpuAllocator::new(&settings)
+    }
+
+    #[test]
+    fn a_job_waits_until_enough_gpus_are_free() {
+        let gpus = allocator(6, 2);
+        let GpuAllocation::Granted(first) = gpus.try_allocate(Some(4)) else {
+            panic!("the first 4-GPU job should start");
+        };
+        assert_eq!(gpus.in_use(), 4);
+        assert!(matches!(gpus.try_allocate(Some(4)), GpuAllocation::Busy));
+        // The failed attempt must not hold on to a GPU job slot.
+        assert!(matches!(
+            gpus.try_allocate(Some(2)),
+            GpuAllocation::Granted(_)
+        ));
+
+        drop(first);
+        assert_eq!(gpus.in_use(), 0);
+        assert!(matches!(
+            gpus.try_allocate(Some(4)),
+            GpuAllocation::Granted(_)
+        ));
+    }
+
+    #[test]
+    fn gpu_jobs_are_capped_by_max_parallel_gpu_jobs() {
+        let gpus = allocator(6, 1);
+        let _first = gpus.try_allocate(Some(1));
+        assert!(matches!(gpus.try_allocate(Some(1)), GpuAllocation::Busy));
+        // CPU-only jobs don't take a GPU job slot.
+        assert!(matches!(gpus.try_allocate(None), GpuAllocation::Granted(_)));
+        assert!(matches!(
+            gpus.try_allocate(Some(0)),
+            GpuAllocation::Granted(_)
+        ));
+    }
+
+    #[test]
+    fn requests_beyond_max_gpus_total_are_unsatisfiable() {
+        let gpus = allocator(6, 2);
+        assert!(matches!(
+            gpus.try_allocate(Some(8)),
+            GpuAllocation::Unsatisfiable
+        ));
+        let cpu_only = allocator(0, 0);
+        assert!(matches!(
+            cpu_only.try_allocate(Some(1)),
+            GpuAllocation::Unsatisfiable
+        ));
+        assert!(matches!(
+            cpu_only.try_allocate(None),
+            GpuAllocation::Granted(_)
+        ));
+    }
+}
@@ -1,117 +1,1521 @@
-use integration_lm_eval_harness::{LmEvalRunner, RunnerError};
+mod endpoints;
+mod gpu;
+mod reaper;
+mod webhooks;
+
+use anyhow::Context;
+use chrono::Utc;
+use endpoints::{EndpointAllocation, EndpointLimiter};
+use gpu::{GpuAllocation, GpuAllocator};
+use integration_core::{
+    absolute, check_engine_version, check_installed_version, read_partial_result, read_progress,
+    EvalRunner, RunDirs, RunnerError, PARTIAL_RESULT_FILE,
+};
+use integration_custom::CommandRunner;
+use integration_helm::HelmRunner;
+use integration_lm_eval_harness::LmEvalRunner;
+use integration_openai_evals::OpenAiEvalsRunner;
 use redis::AsyncCommands;
+use serde_json::Value;
+use std::future::Future;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use tokio::time::{sleep, Duration};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, Duration, Instant};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 use unified_domain::db::DbPool;
+use unified_domain::post_processors::PostProcessError;
 use unified_domain::result_store::ResultStoreHandles;
-use unified_domain::runs;
-use unified_shared::eval::{EvalConfig, EvalEngine, EvalErrorKind, EvalErrorPayload, RunStatus};
-use unified_shared::settings::Settings;
+use unified_domain::{datasets, ensemble, runs};
+use unified_shared::error::DomainError;
+use unified_shared::eval::{
+    canary_subset, run_status_channel, DatasetConfig, EvalConfig, EvalEngine, EvalErrorKind,
+    EvalErrorPayload, EvalResult, MetricConfig, MetricRecord, QueuedRun, RunCompletedEvent,
+    RunStatus, RunStatusEvent, SampleRecord, SampleResultLocation,
+};
+use unified_shared::queue::{self, QueueLane, QueueSelector};
+use unified_shared::retry::Backoff;
+use unified_shared::settings::{LogFormat, LoggingSettings, QueueStrategy, Settings};
+use uuid::Uuid;
+use webhooks::WebhookSender;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    init_tracing();
     let settings = Settings::load()?;
+    init_tracing(&settings.logging);
     let redis_pool = deadpool_redis::Config::from_url(settings.redis.url.clone())
         .create_pool(Some(deadpool_redis::Runtime::Tokio1))?;
-    let db = unified_domain::db::init_pool(&settings.database.url).await?;
+    let db = unified_domain::db::init_pool(&settings.database).await?;
     let stores = ResultStoreHandles::new(&settings, db.clone()).await?;
+    for (kind, err) in stores.check_stores().await {
+        tracing::warn!("{kind} is unreachable; runs writing to it will fail: {err:#}");
+    }
     let runners = Runners::new(&settings);
+    runners.probe().await;
+    let run_dirs = RunDirs::new(&settings.integrations);
+    let dataset_cache_dir = absolute(Path::new(&settings.integrations.dataset_cache_dir));
+    let gpus = GpuAllocator::new(&settings.queues);
+    let endpoints = EndpointLimiter::new(&settings.queues);
+    let job_slots = Arc::new(Semaphore::new(
+        settings.queues.max_parallel_jobs.max(1) as usize
+    ));
+    let webhooks = WebhookSender::new(&settings.webhooks)?;
+    let worker_id = settings
+        .queues
+        .worker_id
+        .clone()
+        .unwrap_or_else(|| format!("worker-{}", Uuid::new_v4()));
+    tracing::info!("starting worker {worker_id}");
     let ctx = Arc::new(WorkerContext {
+        worker_id,
         settings,
         db,
+        redis: redis_pool.clone(),
         stores,
         runners,
+        run_dirs,
+        dataset_cache_dir,
+        gpus,
+        endpoints,
+        webhooks,
     });
 
+    if ctx.settings.retention.enabled {
+        tokio::spawn(reaper::run(ctx.clone()));
+    }
+    tokio::spawn(sweep_enqueue_outbox(ctx.clone()));
+
+    let mut selector = QueueSelector::new(ctx.settings.queues.strategy);
+    let mut paused = false;
+    let mut requeues = 0;
+    let mut failures = 0;
     loop {
-        let mut conn = redis_pool.get().await?;
-        let job: Option<(String, String)> = conn
-            .blpop(&ctx.settings.redis.queue_key, 5)
-            .await
-            .map_err(|err| anyhow::anyhow!(err))?;
-
-        if let Some((_key, payload)) = job {
-            tracing::info!("received job payload");
-            match serde_json::from_str::<EvalConfig>(&payload) {
-                Ok(config) => {
-                    if let Err(err) = process_job(ctx.clone(), config).await {
-                        tracing::error!("job failed: {err:?}");
-                    }
+        let slot = job_slots.clone().acquire_owned().await?;
+        let poll = poll_queue(
+            &ctx,
+            &redis_pool,
+            &mut selector,
+            &mut paused,
+            &mut requeues,
+            slot,
+        );
+        if let Some(delay) = after_poll(poll.await, &mut failures)? {
+            sleep(delay).await;
+        }
+    }
+}
+
+/// How the poll loop carries on after a poll: `None` to poll again at once,
+/// or how long to wait after a transient failure, backing off with the
+/// `failures` in a row. Any other error stops the worker.
+fn after_poll(result: anyhow::Result<()>, failures: &mut u32) -> anyhow::Result<Option<Duration>> {
+    match result {
+        Ok(()) => {
+            *failures = 0;
+            Ok(None)
+        }
+        Err(err) if is_transient(&err) => {
+            *failures += 1;
+            let delay = POLL_BACKOFF.delay(*failures);
+            tracing::warn!(
+                "polling the queue failed ({err:#}); retrying in {}ms",
+                delay.as_millis()
+            );
+            Ok(Some(delay))
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// Backoff between failed polls while Redis (or MySQL) is unreachable. The
+/// jitter keeps workers from reconnecting in lockstep after a restart.
+const POLL_BACKOFF: Backoff = Backoff {
+    base_delay: Duration::from_millis(500),
+    max_delay: Duration::from_secs(30),
+    jitter: true,
+};
+
+/// Backoff after requeueing a run whose GPUs are busy, growing while runs
+/// keep being requeued so a worker whose GPUs are all taken doesn't spin on
+/// popping and pushing back the same run.
+const REQUEUE_BACKOFF: Backoff = Backoff {
+    base_delay: Duration::from_secs(1),
+    max_delay: Duration::from_secs(30),
+    jitter: true,
+};
+
+/// How often the enqueue outbox is swept, and how long a released run must
+/// have waited there before the sweep pushes it, so it doesn't race the
+/// worker that released it.
+const OUTBOX_SWEEP_INTERVAL: Duration = Duration::from_secs(30);
+const OUTBOX_SWEEP_BATCH: i64 = 100;
+
+/// Pushes released runs whose enqueue failed, e.g. because Redis was down
+/// when their last dependency finished. A run pushed twice (the push landed
+/// but clearing the outbox didn't) is harmless: only one copy can claim it.
+async fn sweep_enqueue_outbox(ctx: Arc<WorkerContext>) {
+    loop {
+        sleep(OUTBOX_SWEEP_INTERVAL).await;
+        let pending = match runs::pending_enqueues(
+            &ctx.db,
+            OUTBOX_SWEEP_INTERVAL,
+            OUTBOX_SWEEP_BATCH,
+        )
+        .await
+        {
+            Ok(pending) => pending,
+            Err(err) => {
+                tracing::warn!("failed to read the enqueue outbox: {err}");
+                continue;
+            }
+        };
+        for run in pending {
+            match ctx.enqueue_released(&run).await {
+                Ok(()) => tracing::info!("queued released run {} from the outbox", run.id),
+                Err(err) => {
+                    tracing::warn!("failed to queue released run {}: {err:?}", run.id);
+                    break;
                 }
-                Err(err) => tracing::error!("invalid job payload: {err:?}"),
             }
+        }
+    }
+}
+
+/// How often a paused worker checks whether it was resumed.
+const PAUSE_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Re-checks the pause flag after BLPOP took `payload` off `key`: a pause
+/// set while BLPOP was waiting must not let the job start, so it goes back
+/// to the head of `key` and this returns true. If the check fails the job
+/// starts: it is already off the queue.
+async fn return_if_paused<C>(conn: &mut C, pause_key: &str, key: &str, payload: &str) -> bool
+where
+    C: redis::aio::ConnectionLike + Send,
+{
+    let pause_set = match conn.exists(pause_key).await {
+        Ok(set) => set,
+        Err(err) => {
+            tracing::warn!("failed to re-check the pause after popping from {key}: {err}");
+            false
+        }
+    };
+    if pause_set {
+        tracing::info!("queue consumption paused; returning the job popped from {key}");
+        if let Err(err) = conn.lpush::<_, _, ()>(key, payload).await {
+            tracing::error!("failed to return a job to {key} on pause: {err}");
+        }
+    }
+    pause_set
+}
+
+/// Pops one job (waiting up to 5s) and starts it, or requeues it when its
+/// endpoint or GPUs are busy, waiting longer the more runs in a row were
+/// requeued (counted in `requeues`). `slot` is held by the started job. While
+/// `redis.pause_key` is set nothing is popped, and a job popped just as the
+/// pause was set goes back to the head of its list; `paused` tracks the last
+/// state seen so only changes are logged. Failing to requeue or reject the
+/// popped run is logged rather than returned, so it never stops the worker.
+async fn poll_queue(
+    ctx: &Arc<WorkerContext>,
+    redis_pool: &deadpool_redis::Pool,
+    selector: &mut QueueSelector,
+    paused: &mut bool,
+    requeues: &mut u32,
+    slot: OwnedSemaphorePermit,
+) -> anyhow::Result<()> {
+    let mut conn = redis_pool.get().await?;
+    let pause_set: bool = conn
+        .exists(&ctx.settings.redis.pause_key)
+        .await
+        .map_err(|err| anyhow::anyhow!(err))?;
+    if pause_set != *paused {
+        *paused = pause_set;
+        if pause_set {
+            tracing::info!("queue consumption paused; running jobs continue");
         } else {
-            sleep(Duration::from_secs(1)).await;
+            tracing::info!("queue consumption resumed");
+        }
+    }
+    if pause_set {
+        drop(slot);
+        sleep(PAUSE_POLL_INTERVAL).await;
+        return Ok(());
+    }
+    let lanes = queue_lanes(&ctx.settings, &mut conn).await?;
+    let job: Option<(String, String)> = conn
+        .blpop(selector.order(&lanes), 5)
+        .await
+        .map_err(|err| anyhow::anyhow!(err))?;
+
+    if let Some((key, payload)) = job {
+        if return_if_paused(&mut *conn, &ctx.settings.redis.pause_key, &key, &payload).await {
+            *paused = true;
+            return Ok(());
+        }
+        tracing::info!("received job payload from {key}");
+        selector.record_pop(&lanes, &key);
+        match resolve_job(ctx, &payload).await {
+            Ok(None) => {}
+            Ok(Some(config)) => {
+                // The endpoint comes first, so a run waiting for it holds
+                // neither GPUs nor its claim. Members of a multi-model run
+                // each take their own endpoint's permit once it runs.
+                let endpoint_permit = if config.is_multi_model() {
+                    None
+                } else {
+                    match ctx.endpoints.try_acquire(&config.model) {
+                        EndpointAllocation::Granted(permit) => permit,
+                        EndpointAllocation::Busy(endpoint) => {
+                            tracing::info!(
+                                "run {} waits for a free slot on endpoint {endpoint}; requeueing",
+                                config.run_id
+                            );
+                            drop(slot);
+                            requeue(&mut conn, &key, payload, &config.run_id, requeues).await;
+                            return Ok(());
+                        }
+                    }
+                };
+                let num_gpus = admission_gpus(ctx, &config);
+                match ctx.gpus.try_allocate(num_gpus) {
+                    GpuAllocation::Granted(lease) => {
+                        *requeues = 0;
+                        let ctx = ctx.clone();
+                        tokio::spawn(async move {
+                            if let Err(err) = process_job(ctx, config).await {
+                                tracing::error!("job failed: {err:?}");
+                            }
+                            drop(lease);
+                            drop(endpoint_permit);
+                            drop(slot);
+                        });
+                    }
+                    GpuAllocation::Busy => {
+                        tracing::info!(
+                            "run {} needs {:?} GPU(s) but {} are in use; requeueing",
+                            config.run_id,
+                            num_gpus,
+                            ctx.gpus.in_use()
+                        );
+                        drop(endpoint_permit);
+                        drop(slot);
+                        requeue(&mut conn, &key, payload, &config.run_id, requeues).await;
+                    }
+                    GpuAllocation::Unsatisfiable => {
+                        let message = format!(
+                            "run requests {:?} GPU(s) but max_gpus_total is {}",
+                            num_gpus, ctx.settings.queues.max_gpus_total
+                        );
+                        tracing::error!("rejecting run {}: {message}", config.run_id);
+                        let payload = EvalErrorPayload {
+                            kind: EvalErrorKind::Config,
+                            message,
+                            code: None,
+                            engine: None,
+                            details: None,
+                        };
+                        if let Err(err) = ctx
+                            .set_status(&config.run_id, RunStatus::FailedConfig, Some(payload))
+                            .await
+                        {
+                            tracing::error!("failed to reject run {}: {err:?}", config.run_id);
+                        }
+                    }
+                }
+            }
+            Err(err) => tracing::error!("invalid job payload: {err:?}"),
+        }
+    } else {
+        sleep(Duration::from_secs(1)).await;
+    }
+    Ok(())
+}
+
+/// Pushes a popped run that can't start yet back onto `key`, then waits
+/// longer the more runs in a row were requeued. Failing to push it back is
+/// only logged: the run stays queued in the database.
+async fn requeue(
+    conn: &mut deadpool_redis::Connection,
+    key: &str,
+    payload: String,
+    run_id: &Uuid,
+    requeues: &mut u32,
+) {
+    if let Err(err) = conn.rpush::<_, _, ()>(key, payload).await {
+        tracing::error!("failed to requeue run {run_id}; it stays queued but off the queue: {err}");
+    }
+    *requeues += 1;
+    sleep(REQUEUE_BACKOFF.delay(*requeues)).await;
+}
+
+/// GPUs to reserve for a run: what its config asks for, else the runner's
+/// estimate. An estimate beyond `max_gpus_total` is capped rather than
+/// rejected, since it is only a heuristic.
+fn admission_gpus(ctx: &WorkerContext, config: &EvalConfig) -> Option<u8> {
+    if config.resources.num_gpus.is_some() {
+        return config.resources.num_gpus;
+    }
+    let runner = ctx.runners.for_engine(&config.engine)?;
+    let estimate = runner.estimate_resources(config);
+    tracing::info!(
+        "run {} estimated at {} GPU(s), {} GB, {}s",
+        config.run_id,
+        estimate.num_gpus,
+        estimate.memory_gb,
+        estimate.duration_seconds
+    );
+    let max = u8::try_from(ctx.settings.queues.max_gpus_total).unwrap_or(u8::MAX);
+    if estimate.num_gpus > max {
+        tracing::warn!(
+            "run {} is estimated to need {} GPU(s) but max_gpus_total is {max}; reserving {max}",
+            config.run_id,
+            estimate.num_gpus
+        );
+    }
+    Some(estimate.num_gpus.min(max))
+}
+
+/// Errors a poll can recover from by waiting: Redis connection failures and
+/// an unreachable database. Anything else stops the worker.
+fn is_transient(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| {
+        if let Some(err) = cause.downcast_ref::<redis::RedisError>() {
+            // Matching the kind rather than `is_io_error` also catches the
+            // I/O errors redis reports without an underlying `io::Error`.
+            return err.is_connection_refusal()
+                || err.is_connection_dropped()
+                || err.is_timeout()
+                || matches!(
+                    err.kind(),
+                    redis::ErrorKind::IoError
+                        | redis::ErrorKind::BusyLoadingError
+                        | redis::ErrorKind::TryAgain
+                );
+        }
+        cause.is::<deadpool_redis::PoolError>()
+            || matches!(
+                cause.downcast_ref::<DomainError>(),
+                Some(DomainError::Unavailable(_))
+            )
+    })
+}
+
+/// Drops the projects whose list is empty from the registry and returns the
+/// rest. `KEYS[1]` is the registry, `KEYS[i + 1]` the list of `ARGV[i]`.
+/// Each check and removal is atomic, and enqueues push and register in one
+/// `MULTI`, so a project is never dropped while it has a queued run.
+const PRUNE_REGISTRY_SCRIPT: &str = r#"
+local kept = {}
+for i, project_id in ipairs(ARGV) do
+    if redis.call('LLEN', KEYS[i + 1]) == 0 then
+        redis.call('SREM', KEYS[1], project_id)
+    else
+        table.insert(kept, project_id)
+    end
+end
+return kept
+"#;
+
+/// The lists to pop from. The project registry is read on every poll, so a
+/// new project's queue is served without restarting the worker; projects
+/// whose queue has drained are pruned from it so the set and the `BLPOP`
+/// key list don't grow with every project ever queued.
+async fn queue_lanes<C>(settings: &Settings, conn: &mut C) -> anyhow::Result<Vec<QueueLane>>
+where
+    C: redis::aio::ConnectionLike + Send,
+{
+    let project_ids: Vec<String> = match settings.queues.strategy {
+        QueueStrategy::Single => Vec::new(),
+        QueueStrategy::PerProject | QueueStrategy::Weighted => {
+            let registry = queue::registry_key(settings);
+            let registered: Vec<String> = conn
+                .smembers(&registry)
+                .await
+                .map_err(|err| anyhow::anyhow!(err))?;
+            if registered.is_empty() {
+                Vec::new()
+            } else {
+                let script = redis::Script::new(PRUNE_REGISTRY_SCRIPT);
+                let mut invocation = script.prepare_invoke();
+                invocation.key(&registry);
+                for project_id in &registered {
+                    invocation.key(format!("{}:{project_id}", settings.redis.queue_key));
+                    invocation.arg(project_id);
+                }
+                invocation
+                    .invoke_async(conn)
+                    .await
+                    .map_err(|err| anyhow::anyhow!(err))?
+            }
+        }
+    };
+    Ok(queue::lanes(settings, project_ids))
+}
+
+/// Parses a queue payload. Referenced configs are fetched from the object
+/// store; when that fails the run is failed as `infra` and `None` returned.
+async fn resolve_job(ctx: &WorkerContext, payload: &str) -> anyhow::Result<Option<EvalConfig>> {
+    let reference = match serde_json::from_str::<QueuedRun>(payload)? {
+        QueuedRun::Inline(config) => return Ok(Some(*config)),
+        QueuedRun::Reference(reference) => reference,
+    };
+    let fetched = match &ctx.stores.object_store {
+        Some(store) => store.get_uri(&reference.config_uri).await.and_then(|data| {
+            serde_json::from_slice::<EvalConfig>(&data)
+                .with_context(|| format!("invalid config at {}", reference.config_uri))
+        }),
+        None => Err(anyhow::anyhow!(
+            "run config is stored at {} but the object store is not configured",
+            reference.config_uri
+        )),
+    };
+    match fetched {
+        Ok(config) => Ok(Some(config)),
+        Err(err) => {
+            tracing::error!("failed to load config of run {}: {err:#}", reference.run_id);
+            let payload = EvalErrorPayload {
+                kind: EvalErrorKind::Infra,
+                message: format!("failed to load run config: {err:#}"),
+                code: Some("config_fetch_failed".into()),
+                engine: None,
+                details: None,
+            };
+            ctx.set_status(&reference.run_id, RunStatus::FailedInfra, Some(payload))
+                .await?;
+            Ok(None)
         }
     }
 }
 
 struct WorkerContext {
+    worker_id: String,
     settings: Settings,
     db: DbPool,
+    redis: deadpool_redis::Pool,
     stores: ResultStoreHandles,
     runners: Runners,
+    run_dirs: RunDirs,
+    /// `integrations.dataset_cache_dir`, resolved at startup so the cache
+    /// doesn't move with the working directory.
+    dataset_cache_dir: PathBuf,
+    gpus: GpuAllocator,
+    endpoints: EndpointLimiter,
+    webhooks: Option<WebhookSender>,
+}
+
+impl WorkerContext {
+    /// Persists a status change and announces it on the run's status channel.
+    /// Publishing is best effort; subscribers can always fall back to the DB.
+    /// Runs released by the change are pushed onto the run queue, and they
+    /// and any skipped runs are announced too; a release that can't be pushed
+    /// is left in the enqueue outbox for [`sweep_enqueue_outbox`]. A change
+    /// the run's current status doesn't allow, e.g. after it was cancelled
+    /// meanwhile, is logged and dropped.
+    async fn set_status(
+        &self,
+        run_id: &Uuid,
+        status: RunStatus,
+        error: Option<EvalErrorPayload>,
+    ) -> anyhow::Result<()> {
+        let resolution = match runs::update_status(&self.db, run_id, status, error.clone()).await {
+            Ok(resolution) => resolution,
+            Err(DomainError::Conflict(message)) => {
+                tracing::warn!("not recording status of run {run_id}: {message}");
+                return Ok(());
+            }
+            Err(err) => return Err(err.into()),
+        };
+        self.announce(*run_id, status, error).await;
+
+        for run in &resolution.released {
+            tracing::info!("dependencies of run {} completed; queueing it", run.id);
+            self.announce(run.id, RunStatus::Queued, None).await;
+            if let Err(err) = self.enqueue_released(run).await {
+                tracing::warn!(
+                    "failed to queue released run {}; the outbox sweep retries it: {err:?}",
+                    run.id
+                );
+            }
+        }
+        for (skipped, error) in resolution.skipped {
+            tracing::info!("skipping run {skipped}: {}", error.message);
+            self.announce(skipped, RunStatus::Cancelled, Some(error))
+                .await;
+        }
+        Ok(())
+    }
+
+    async fn announce(&self, run_id: Uuid, status: RunStatus, error: Option<EvalErrorPayload>) {
+        let event = RunStatusEvent {
+            run_id,
+            status,
+            error,
+            at: Utc::now(),
+        };
+        if let Err(err) = self.publish_status(&event).await {
+            tracing::warn!("failed to publish status of run {run_id}: {err:?}");
+        }
+    }
+
+    /// Pushes a run onto its queue, as `POST /runs/{id}/enqueue` does.
+    async fn enqueue(&self, run: &runs::Run) -> anyhow::Result<()> {
+        let payload = run.queue_payload()?;
+        let mut conn = self.redis.get().await?;
+        let mut pipe = redis::pipe();
+        pipe.atomic()
+            .rpush(queue::queue_key(&self.settings, &run.project_id), payload)
+            .ignore();
+        if self.settings.queues.strategy != QueueStrategy::Single {
+            pipe.sadd(
+                queue::registry_key(&self.settings),
+                run.project_id.to_string(),
+            )
+            .ignore();
+        }
+        pipe.query_async::<_, ()>(&mut conn).await?;
+        Ok(())
+    }
+
+    /// Pushes a run released from `blocked` and takes it out of the enqueue
+    /// outbox.
+    async fn enqueue_released(&self, run: &runs::Run) -> anyhow::Result<()> {
+        self.enqueue(run).await?;
+        runs::clear_pending_enqueue(&self.db, &run.id).await?;
+        Ok(())
+    }
+
+    fn lease(&self) -> Duration {
+        Duration::from_secs(self.settings.queues.lease_seconds)
+    }
+
+    /// Claims the run for this worker, announcing the switch to `Running`.
+    /// Returns `false` when another worker holds an unexpired lease.
+    async fn claim(&self, run_id: &Uuid) -> anyhow::Result<bool> {
+        if !runs::try_claim(&self.db, run_id, &self.worker_id, self.lease()).await? {
+            return Ok(false);
+        }
+        let event = RunStatusEvent {
+            run_id: *run_id,
+            status: RunStatus::Running,
+            error: None,
+            at: Utc::now(),
+        };
+        if let Err(err) = self.publish_status(&event).await {
+            tracing::warn!("failed to publish status of run {run_id}: {err:?}");
+        }
+        Ok(true)
+    }
+
+    /// Publishes the summary of a finished job on `redis.completion_channel`
+    /// and hands it to the webhook, both best effort. `outcome` is the
+    /// number of metrics stored, or why recording the outcome failed; a run
+    /// left unfinished by such a failure is reported as `failed_infra`.
+    async fn report_completion(
+        &self,
+        run_id: &Uuid,
+        started: Instant,
+        outcome: &anyhow::Result<usize>,
+    ) {
+        let (status, error) = match (runs::get(&self.db, run_id).await, outcome) {
+            (Ok(run), _) if run.status.is_terminal() => (run.status, run.error),
+            (_, Err(err)) => (
+                RunStatus::FailedInfra,
+                Some(EvalErrorPayload {
+                    kind: EvalErrorKind::Infra,
+                    message: format!("failed to record the outcome of the run: {err}"),
+                    code: None,
+                    engine: None,
+                    details: None,
+                }),
+            ),
+            (Ok(run), Ok(_)) => (run.status, run.error),
+            (Err(err), Ok(_)) => {
+                tracing::warn!("failed to load run {run_id} to report its completion: {err}");
+                return;
+            }
+        };
+        let event = RunCompletedEvent {
+            run_id: *run_id,
+            metric_count: match (&status, outcome) {
+                (RunStatus::Completed, Ok(count)) => *count,
+                _ => 0,
+            },
+            status,
+            duration_ms: started.elapsed().as_millis() as u64,
+            error,
+            at: Utc::now(),
+        };
+        tracing::info!(
+            "job {run_id} finished as {:?} in {}ms with {} metrics",
+            event.status,
+            event.duration_ms,
+            event.metric_count
+        );
+        if let Err(err) = self.publish_completion(&event).await {
+            tracing::warn!("failed to publish completion of run {run_id}: {err:?}");
+        }
+        if let Some(webhooks) = &self.webhooks {
+            webhooks.send(&event);
+        }
+    }
+
+    async fn publish_completion(&self, event: &RunCompletedEvent) -> anyhow::Result<()> {
+        let payload = serde_json::to_string(event)?;
+        let mut conn = self.redis.get().await?;
+        conn.publish::<_, _, ()>(&self.settings.redis.completion_channel, payload)
+            .await?;
+        Ok(())
+    }
+
+    async fn publish_status(&self, event: &RunStatusEvent) -> anyhow::Result<()> {
+        let channel = run_status_channel(&self.settings.redis.status_channel_prefix, &event.run_id);
+        let payload = serde_json::to_string(event)?;
+        let mut conn = self.redis.get().await?;
+        conn.publish::<_, _, ()>(channel, payload).await?;
+        Ok(())
+    }
 }
 
 struct Runners {
-    lm_eval: integration_lm_eval_harness::LmEvalRunner,
+    lm_eval: LmEvalRunner,
+    helm: HelmRunner,
+    openai_evals: OpenAiEvalsRunner,
+    custom: CommandRunner,
 }
 
 impl Runners {
     fn new(settings: &Settings) -> Self {
         Self {
-            lm_eval: integration_lm_eval_harness::LmEvalRunner::new(settings),
+            lm_eval: LmEvalRunner::new(settings),
+            helm: HelmRunner::new(settings),
+            openai_evals: OpenAiEvalsRunner::new(settings),
+            custom: CommandRunner::new(settings),
+        }
+    }
+
+    fn all(&self) -> [&dyn EvalRunner; 4] {
+        [&self.lm_eval, &self.helm, &self.openai_evals, &self.custom]
+    }
+
+    /// Logs which engines are usable on this host. Unavailable engines are
+    /// reported but not disabled; their jobs fail with the engine's own error.
+    async fn probe(&self) {
+        let mut available = Vec::new();
+        for runner in self.all() {
+            match runner.health_check().await {
+                Ok(()) => available.push(runner.name()),
+                Err(err) => {
+                    tracing::error!("engine {} is unavailable: {err:#}", runner.name())
+                }
+            }
+        }
+        tracing::info!("available engines: {available:?}");
+    }
+
+    fn for_engine(&self, engine: &EvalEngine) -> Option<&dyn EvalRunner> {
+        match engine {
+            EvalEngine::LmEvalHarness => Some(&self.lm_eval),
+            EvalEngine::Helm => Some(&self.helm),
+            EvalEngine::OpenAiEvals => Some(&self.openai_evals),
+            EvalEngine::Custom(_) => Some(&self.custom),
+            _ => None,
         }
     }
 }
 
+#[tracing::instrument(
+    name = "job",
+    skip_all,
+    fields(run_id = %config.run_id, engine = ?config.engine)
+)]
 async fn process_job(ctx: Arc<WorkerContext>, config: EvalConfig) -> anyhow::Result<()> {
-    runs::update_status(&ctx.db, &config.run_id, RunStatus::Running, None).await?;
+    if !ctx.claim(&config.run_id).await? {
+        tracing::info!(
+            "run {} is claimed by another worker; skipping",
+            config.run_id
+        );
+        return Ok(());
+    }
+    let started = Instant::now();
+    let _renewal = LeaseRenewal::spawn(ctx.clone(), config.run_id);
     tracing::info!("running job {} via {:?}", config.run_id, config.engine);
 
-    let result = match config.engine {
-        EvalEngine::LmEvalHarness => ctx.runners.lm_eval.run(&config).await,
-        other => {
-            tracing::warn!("engine {:?} not supported yet", other);
-            Err(integration_lm_eval_harness::RunnerError::NotSupported)
+    let runner = ctx.runners.for_engine(&config.engine);
+    let result = match runner {
+        Some(runner) => {
+            let local = match check_version_upfront(runner, &config).await {
+                Ok(()) => localize_dataset(&ctx, &config).await,
+                Err(payload) => Err(payload),
+            };
+            match local {
+                Ok(mut local) => {
+                    // Post-processed metrics, composites included, are derived
+                    // from the engine's metrics later; engines never see them.
+                    local.metrics.retain(|metric| engine_metric(&ctx, metric));
+                    if local.is_multi_model() {
+                        run_members(&ctx, runner, &local).await
+                    } else {
+                        run_with_partials(&ctx, runner, &local, local.run_id, None).await
+                    }
+                }
+                Err(payload) => Err(RunnerError::Eval(payload)),
+            }
+        }
+        None => {
+            tracing::warn!("engine {:?} not supported yet", config.engine);
+            Err(RunnerError::NotSupported)
         }
     };
 
-    match result {
-        Ok(eval_result) => {
-            ctx.stores
-                .persist_eval_result(&config, &eval_result)
-                .await?;
-            runs::update_status(&ctx.db, &config.run_id, RunStatus::Completed, None).await?;
+    let result = match result {
+        // An error without any metrics is a plain failure, whatever the
+        // reported status.
+        Ok(EvalResult {
+            error: Some(payload),
+            metrics,
+            ..
+        }) if metrics.is_empty() => Err(RunnerError::Eval(payload)),
+        Ok(mut eval_result) => match runner {
+            Some(runner) => record_engine_version(runner, &config, &mut eval_result)
+                .await
+                .map(|()| eval_result)
+                .map_err(RunnerError::Eval),
+            None => Ok(eval_result),
+        },
+        Err(err) => Err(err),
+    };
+
+    // The run may have been cancelled (e.g. with its experiment) meanwhile;
+    // its outcome must not overwrite that.
+    if matches!(
+        runs::get(&ctx.db, &config.run_id).await?.status,
+        RunStatus::Cancelled
+    ) {
+        tracing::info!(
+            "run {} was cancelled; discarding its outcome",
+            config.run_id
+        );
+        ctx.report_completion(&config.run_id, started, &Ok(0)).await;
+        return Ok(());
+    }
+
+    let outcome = match result {
+        Ok(mut eval_result) => {
+            // Failed sub-tasks of a partial result may report placeholder
+            // values; only finite metrics are kept.
+            let partial_error = eval_result
+                .is_partial()
+                .then(|| eval_result.error.clone())
+                .flatten();
+            if let Some(error) = &partial_error {
+                eval_result
+                    .metrics
+                    .retain(|metric| metric.value.is_finite());
+                tracing::warn!(
+                    "run {} completed partially: {}",
+                    config.run_id,
+                    error.message
+                );
+            }
+            if let (Some(runner), Some(canary)) = (runner, &config.canary) {
+                match run_canary(&ctx, runner, &config, canary).await {
+                    Ok(metrics) => eval_result.metrics.extend(metrics),
+                    Err(message) => {
+                        tracing::warn!("canary of run {} failed: {message}", config.run_id);
+                        let mut metadata = match eval_result.metadata.take() {
+                            Some(Value::Object(map)) => map,
+                            _ => serde_json::Map::new(),
+                        };
+                        metadata.insert("canary_error".into(), Value::String(message));
+                        eval_result.metadata = Some(Value::Object(metadata));
+                    }
+                }
+            }
+            finish_run(&ctx, &config, &mut eval_result, partial_error).await
         }
         Err(err) => {
             let payload = match err {
-                integration_lm_eval_harness::RunnerError::Eval(payload) => payload,
-                integration_lm_eval_harness::RunnerError::Io(io_err) => EvalErrorPayload {
+                RunnerError::Eval(payload) => payload,
+                RunnerError::Io(io_err) => EvalErrorPayload {
                     kind: EvalErrorKind::Infra,
                     message: io_err.to_string(),
                     code: None,
-                    engine: Some("lm_eval_harness".into()),
+                    engine: runner.map(|runner| runner.name().to_string()),
                     details: None,
                 },
-                integration_lm_eval_harness::RunnerError::NotSupported => EvalErrorPayload {
+                RunnerError::NotSupported => EvalErrorPayload {
                     kind: EvalErrorKind::Engine,
                     message: "Engine not supported".into(),
                     code: None,
                     engine: Some(format!("{:?}", config.engine)),
                     details: None,
                 },
+                RunnerError::Cancelled => EvalErrorPayload {
+                    kind: EvalErrorKind::Cancelled,
+                    message: "run was cancelled".into(),
+                    code: None,
+                    engine: runner.map(|runner| runner.name().to_string()),
+                    details: None,
+                },
+            };
+            let status = map_error_to_status(payload.kind.clone());
+            ctx.set_status(&config.run_id, status, Some(payload))
+                .await
+                .map(|()| 0)
+        }
+    };
+
+    // Failing to record the outcome still ends the job for its listeners.
+    ctx.report_completion(&config.run_id, started, &outcome)
+        .await;
+    outcome.map(drop)
+}
+
+/// Persists a successful (possibly partial) result and completes the run,
+/// attaching `partial_error` when some sub-tasks failed, and returns how many
+/// metrics were stored (see [`ResultStoreHandles::persist_eval_result`]). A
+/// post-processor failing on the result fails the run as a config error with
+/// code `post_processor_failed`, and failing to persist it as an infra
+/// error; neither stores any metric.
+async fn finish_run(
+    ctx: &WorkerContext,
+    config: &EvalConfig,
+    eval_result: &mut EvalResult,
+    partial_error: Option<EvalErrorPayload>,
+) -> anyhow::Result<usize> {
+    match ctx.stores.persist_eval_result(config, eval_result).await {
+        Ok(metric_count) => {
+            if let Some(metadata) = &eval_result.metadata {
+                if let Err(err) = runs::set_result_metadata(&ctx.db, &config.run_id, metadata).await
+                {
+                    tracing::warn!(
+                        "failed to record result metadata of run {}: {err}",
+                        config.run_id
+                    );
+                }
+            }
+            if let Err(err) = store_raw_result(ctx, config).await {
+                tracing::warn!(
+                    "failed to store raw result of run {}: {err:?}",
+                    config.run_id
+                );
+            }
+            ctx.set_status(&config.run_id, RunStatus::Completed, partial_error)
+                .await?;
+            if let Err(err) = ctx.run_dirs.cleanup(config.run_id).await {
+                tracing::warn!("failed to clean up run {}: {err:?}", config.run_id);
+            }
+            Ok(metric_count)
+        }
+        Err(err) => {
+            let payload = match err.downcast_ref::<PostProcessError>() {
+                Some(failed) => {
+                    tracing::error!("run {}: {failed}", config.run_id);
+                    EvalErrorPayload {
+                        kind: EvalErrorKind::Config,
+                        message: failed.to_string(),
+                        code: Some("post_processor_failed".into()),
+                        engine: None,
+                        details: None,
+                    }
+                }
+                None => {
+                    tracing::error!("failed to persist results: {err:?}");
+                    EvalErrorPayload {
+                        kind: EvalErrorKind::Infra,
+                        message: format!("failed to persist results: {err}"),
+                        code: None,
+                        engine: None,
+                        details: None,
+                    }
+                }
             };
-            let status = map_error_to_status(payload.kind);
-            runs::update_status(&ctx.db, &config.run_id, status, Some(payload)).await?;
+            let status = map_error_to_status(payload.kind.clone());
+            ctx.set_status(&config.run_id, status, Some(payload))
+                .await?;
+            Ok(0)
         }
     }
+}
 
+/// Copies the runner's raw result file to the object store so
+/// `GET /runs/{id}/raw-result` can serve it after the run directory is gone.
+/// Without an object store, or a runner keeping a raw result, it is skipped.
+async fn store_raw_result(ctx: &WorkerContext, config: &EvalConfig) -> anyhow::Result<()> {
+    let (Some(store), Some(runner)) = (
+        &ctx.stores.object_store,
+        ctx.runners.for_engine(&config.engine),
+    ) else {
+        return Ok(());
+    };
+    let Some(path) = runner.raw_result_path(config) else {
+        return Ok(());
+    };
+    let data = match tokio::fs::read(&path).await {
+        Ok(data) => data,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err).with_context(|| format!("failed to read {}", path.display())),
+    };
+    let uri = store.put_raw_result(config.run_id, &data).await?;
+    tracing::info!("stored raw result of run {} at {uri}", config.run_id);
     Ok(())
 }
 
+/// Renews the lease on a claimed run every third of the lease period until
+/// dropped or the claim is lost.
+struct LeaseRenewal(JoinHandle<()>);
+
+impl LeaseRenewal {
+    fn spawn(ctx: Arc<WorkerContext>, run_id: Uuid) -> Self {
+        Self(tokio::spawn(async move {
+            let lease = ctx.lease();
+            loop {
+                sleep(lease / 3).await;
+                match runs::renew_lease(&ctx.db, &run_id, &ctx.worker_id, lease).await {
+                    Ok(true) => {}
+                    Ok(false) => {
+                        tracing::warn!("lost lease on run {run_id}");
+                        break;
+                    }
+                    Err(err) => tracing::warn!("failed to renew lease on run {run_id}: {err:?}"),
+                }
+            }
+        }))
+    }
+}
+
+impl Drop for LeaseRenewal {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// How often the worker checks a running job's `partial_result.json`.
+const PARTIAL_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Runs `config` on behalf of run `run_id` while persisting the metrics of
+/// its `partial_result.json` whenever the runner rewrites it, so suites show
+/// results per finished sub-task. The final result overwrites them when the
+/// run completes. For a `member` of a multi-model run, `config` carries a
+/// scratch run id and the partials are stored under the member's subsets.
+///
+/// Each check also reads `progress.json`; once its error rate trips the
+/// circuit breaker the run is dropped (stopping the engine) and fails as an
+/// engine error with code `error_rate_exceeded`. A run found cancelled in
+/// the DB is dropped the same way and returns [`RunnerError::Cancelled`].
+async fn run_with_partials(
+    ctx: &WorkerContext,
+    runner: &dyn EvalRunner,
+    config: &EvalConfig,
+    run_id: Uuid,
+    member: Option<&str>,
+) -> Result<EvalResult, RunnerError> {
+    let run = runner.run(config);
+    tokio::pin!(run);
+    let mut ticker = tokio::time::interval(PARTIAL_POLL_INTERVAL);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let mut last_modified = None;
+    let mut breaker_passed = false;
+    loop {
+        tokio::select! {
+            result = &mut run => return result,
+            _ = ticker.tick() => {
+                if was_cancelled(ctx, run_id).await {
+                    tracing::info!("run {run_id} was cancelled; stopping the engine");
+                    return Err(RunnerError::Cancelled);
+                }
+                if let Err(err) =
+                    persist_partials(ctx, config, run_id, member, &mut last_modified).await
+                {
+                    tracing::warn!("failed to persist partial metrics of run {run_id}: {err:?}");
+                }
+                if !breaker_passed {
+                    match check_error_rate(ctx, runner, config).await {
+                        BreakerCheck::Pending => {}
+                        BreakerCheck::Passed => breaker_passed = true,
+                        BreakerCheck::Tripped(payload) => {
+                            tracing::warn!("aborting run {run_id}: {}", payload.message);
+                            return Err(RunnerError::Eval(payload));
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Drives `run`, an evaluation on behalf of the run `run_id` (e.g. one of
+/// its members or its canary), until it finishes or the run is found
+/// cancelled. A cancelled run's future is dropped, which stops the engine
+/// since runners spawn it with `kill_on_drop`.
+async fn until_cancelled(
+    ctx: &WorkerContext,
+    run_id: Uuid,
+    run: impl Future<Output = Result<EvalResult, RunnerError>>,
+) -> Result<EvalResult, RunnerError> {
+    let cancelled = || async move {
+        let cancelled = was_cancelled(ctx, run_id).await;
+        if cancelled {
+            tracing::info!("run {run_id} was cancelled; stopping the engine");
+        }
+        cancelled
+    };
+    stop_when_cancelled(PARTIAL_POLL_INTERVAL, cancelled, run).await
+}
+
+/// Drives `run`, calling `cancelled` every `interval`; once it returns true
+/// `run` is dropped and [`RunnerError::Cancelled`] returned.
+async fn stop_when_cancelled<C, F>(
+    interval: Duration,
+    mut cancelled: C,
+    run: impl Future<Output = Result<EvalResult, RunnerError>>,
+) -> Result<EvalResult, RunnerError>
+where
+    C: FnMut() -> F,
+    F: Future<Output = bool>,
+{
+    tokio::pin!(run);
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        tokio::select! {
+            result = &mut run => return result,
+            _ = ticker.tick() => {
+                if cancelled().await {
+                    return Err(RunnerError::Cancelled);
+                }
+            }
+        }
+    }
+}
+
+/// Whether the run was cancelled, e.g. with its experiment, since it started.
+async fn was_cancelled(ctx: &WorkerContext, run_id: Uuid) -> bool {
+    match runs::get(&ctx.db, &run_id).await {
+        Ok(run) => run.status == RunStatus::Cancelled,
+        Err(err) => {
+            tracing::warn!("failed to check whether run {run_id} was cancelled: {err}");
+            false
+        }
+    }
+}
+
+/// What a circuit-breaker check of a running job concluded.
+enum BreakerCheck {
+    /// No counts yet, or too few samples to judge; check again.
+    Pending,
+    /// The run got through the window without tripping; it isn't checked
+    /// again.
+    Passed,
+    Tripped(EvalErrorPayload),
+}
+
+/// Judges the failed share of the samples in the run's `progress.json`
+/// against `circuit_breaker`.
+async fn check_error_rate(
+    ctx: &WorkerContext,
+    runner: &dyn EvalRunner,
+    config: &EvalConfig,
+) -> BreakerCheck {
+    let breaker = &ctx.settings.circuit_breaker;
+    if !breaker.enabled {
+        return BreakerCheck::Passed;
+    }
+    let progress = match read_progress(&ctx.run_dirs.path(config.run_id)).await {
+        Ok(Some(progress)) => progress,
+        Ok(None) => return BreakerCheck::Pending,
+        Err(err) => {
+            tracing::warn!("failed to read progress of run {}: {err:?}", config.run_id);
+            return BreakerCheck::Pending;
+        }
+    };
+    let (Some(completed), Some(failed)) = (progress.completed_samples, progress.failed_samples)
+    else {
+        tracing::debug!(
+            "progress of run {} has no sample counts; the error rate can't be judged yet",
+            config.run_id
+        );
+        return BreakerCheck::Pending;
+    };
+    if !breaker.trips(completed, failed) {
+        return if breaker.settled(completed) {
+            BreakerCheck::Passed
+        } else {
+            BreakerCheck::Pending
+        };
+    }
+    BreakerCheck::Tripped(EvalErrorPayload {
+        kind: EvalErrorKind::Engine,
+        message: format!(
+            "aborted after {failed} of {completed} samples failed (more than {:.0}%)",
+            breaker.max_error_rate * 100.0
+        ),
+        code: Some("error_rate_exceeded".into()),
+        engine: Some(runner.name().into()),
+        details: Some(serde_json::json!({
+            "completed_samples": completed,
+            "failed_samples": failed,
+            "max_error_rate": breaker.max_error_rate,
+        })),
+    })
+}
+
+/// Stores the metrics of `config`'s `partial_result.json` as run `run_id`'s,
+/// under `member`'s subsets if given, if it changed since `last_modified`.
+async fn persist_partials(
+    ctx: &WorkerContext,
+    config: &EvalConfig,
+    run_id: Uuid,
+    member: Option<&str>,
+    last_modified: &mut Option<std::time::SystemTime>,
+) -> anyhow::Result<()> {
+    let run_dir = ctx.run_dirs.path(config.run_id);
+    let modified = match tokio::fs::metadata(run_dir.join(PARTIAL_RESULT_FILE)).await {
+        Ok(metadata) => metadata.modified()?,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err.into()),
+    };
+    if *last_modified == Some(modified) {
+        return Ok(());
+    }
+    let Some(partial) = read_partial_result(&run_dir).await? else {
+        return Ok(());
+    };
+    let records: Vec<MetricRecord> = partial
+        .metrics
+        .into_iter()
+        .filter(|metric| metric.value.is_finite())
+        .map(|metric| MetricRecord {
+            run_id,
+            subset: match member {
+                Some(member) => Some(ensemble::member_subset(member, metric.subset.as_deref())),
+                None => metric.subset,
+            },
+            ..metric
+        })
+        .collect();
+    ctx.stores
+        .for_output(&config.output)
+        .save_partial_metrics(&records)
+        .await?;
+    *last_modified = Some(modified);
+    tracing::info!("stored {} partial metric(s) of run {run_id}", records.len());
+    Ok(())
+}
+
+/// Records the version of the engine that ran as `engine_version` in the
+/// result metadata, unless the runner already did, and checks it against the
+/// requested one with [`check_engine_version`]. Fails only for a mismatch
+/// under `strict_version`.
+async fn record_engine_version(
+    runner: &dyn EvalRunner,
+    config: &EvalConfig,
+    result: &mut EvalResult,
+) -> Result<(), EvalErrorPayload> {
+    let reported = result
+        .metadata
+        .as_ref()
+        .is_some_and(|metadata| metadata.get("engine_version").is_some());
+    if !reported {
+        if let Some(version) = runner.installed_version().await {
+            let mut metadata = match result.metadata.take() {
+                Some(Value::Object(map)) => map,
+                _ => serde_json::Map::new(),
+            };
+            metadata.insert("engine_version".into(), Value::String(version));
+            result.metadata = Some(Value::Object(metadata));
+        }
+    }
+    check_engine_version(config, result, runner.name())
+}
+
+/// Fails a `strict_version` run before the engine starts when the installed
+/// version doesn't match the requested one. Without `strict_version` the
+/// mismatch is only a warning, recorded once the run finished by
+/// [`record_engine_version`].
+async fn check_version_upfront(
+    runner: &dyn EvalRunner,
+    config: &EvalConfig,
+) -> Result<(), EvalErrorPayload> {
+    if !config.strict_version || config.engine_version.is_none() {
+        return Ok(());
+    }
+    match runner.installed_version().await {
+        Some(installed) => check_installed_version(config, &installed, runner.name()).map(drop),
+        None => Ok(()),
+    }
+}
+
+/// Whether the engine computes `metric` itself rather than the worker.
+fn engine_metric(ctx: &WorkerContext, metric: &MetricConfig) -> bool {
+    !ctx.stores.post_processors.handles(metric)
+}
+
+/// Evaluates each member of a multi-model run with `runner`, one after the
+/// other under scratch run ids through [`run_with_partials`], each holding
+/// its own endpoint's permit, and merges their results with
+/// [`ensemble::combine`]. Samples the runner left in the object store are
+/// read back to be voted on. A member failing outright fails the run; a
+/// partial one keeps its finite metrics and the run completes partially.
+async fn run_members(
+    ctx: &WorkerContext,
+    runner: &dyn EvalRunner,
+    config: &EvalConfig,
+) -> Result<EvalResult, RunnerError> {
+    if let Err(message) = config.check_members() {
+        return Err(RunnerError::Eval(EvalErrorPayload {
+            kind: EvalErrorKind::Config,
+            message,
+            code: None,
+            engine: None,
+            details: None,
+        }));
+    }
+    let started_at = Utc::now();
+    let mut members = Vec::new();
+    let mut error = None;
+    for model in config.members() {
+        let scratch_id = Uuid::new_v4();
+        let mut member_config = config.clone();
+        member_config.run_id = scratch_id;
+        member_config.model = model.clone();
+        member_config.models.clear();
+        tracing::info!(
+            "running model {} of run {} as {scratch_id}",
+            model.logical_name,
+            config.run_id
+        );
+        let outcome = {
+            let _endpoint_permit = ctx.endpoints.acquire(&config.run_id, model).await;
+            run_with_partials(
+                ctx,
+                runner,
+                &member_config,
+                config.run_id,
+                Some(&model.logical_name),
+            )
+            .await
+        };
+        let outcome = match outcome {
+            Ok(mut result) => {
+                let location = std::mem::replace(&mut result.samples, SampleResultLocation::None);
+                match member_samples(ctx, location).await {
+                    Ok(samples) => Ok((result, samples)),
+                    Err(payload) => Err(RunnerError::Eval(payload)),
+                }
+            }
+            Err(err) => Err(err),
+        };
+        if let Err(err) = ctx.run_dirs.remove(scratch_id).await {
+            tracing::warn!(
+                "failed to remove dir of model {} of run {}: {err:?}",
+                model.logical_name,
+                config.run_id
+            );
+        }
+        // Metrics reported only per sample are aggregated when the run's
+        // results are persisted.
+        let (result, samples) = match outcome {
+            Ok((result, samples)) if result.metrics.is_empty() && samples.is_empty() => {
+                let mut payload = result.error.unwrap_or_else(|| EvalErrorPayload {
+                    kind: EvalErrorKind::Engine,
+                    message: "no metrics reported".into(),
+                    code: None,
+                    engine: Some(runner.name().into()),
+                    details: None,
+                });
+                payload.message = format!("model {}: {}", model.logical_name, payload.message);
+                return Err(RunnerError::Eval(payload));
+            }
+            Ok(outcome) => outcome,
+            Err(RunnerError::Eval(mut payload)) => {
+                payload.message = format!("model {}: {}", model.logical_name, payload.message);
+                return Err(RunnerError::Eval(payload));
+            }
+            Err(err) => return Err(err),
+        };
+        if let Some(member_error) = result.error {
+            error.get_or_insert(EvalErrorPayload {
+                message: format!("model {}: {}", model.logical_name, member_error.message),
+                ..member_error
+            });
+        }
+        members.push(ensemble::MemberResult {
+            logical_name: model.logical_name.clone(),
+            metrics: result
+                .metrics
+                .into_iter()
+                .filter(|metric| metric.value.is_finite())
+                .collect(),
+            samples,
+        });
+    }
+
+    let combined = ensemble::combine(
+        config.run_id,
+        config
+            .metrics
+            .iter()
+            .filter(|metric| engine_metric(ctx, metric)),
+        members,
+    );
+    Ok(EvalResult {
+        run_id: config.run_id,
+        status: RunStatus::Completed,
+        started_at,
+        completed_at: Utc::now(),
+        metrics: combined.metrics,
+        samples: if combined.samples.is_empty() {
+            SampleResultLocation::None
+        } else {
+            SampleResultLocation::Inline {
+                samples: combined.samples,
+            }
+        },
+        error,
+        metadata: None,
+    })
+}
+
+/// The samples a member of a multi-model run reported, read back if the
+/// runner wrote them to the object store. Samples in ClickHouse aren't read
+/// back, so they can't be voted on and fail the member.
+async fn member_samples(
+    ctx: &WorkerContext,
+    location: SampleResultLocation,
+) -> Result<Vec<SampleRecord>, EvalErrorPayload> {
+    match location {
+        SampleResultLocation::Inline { samples } => Ok(samples),
+        SampleResultLocation::None => Ok(Vec::new()),
+        SampleResultLocation::ObjectStore { uri, format } => {
+            let Some(store) = &ctx.stores.object_store else {
+                return Err(EvalErrorPayload {
+                    kind: EvalErrorKind::Config,
+                    message: format!("samples at {uri} need an object store to be voted on"),
+                    code: None,
+                    engine: None,
+                    details: None,
+                });
+            };
+            store
+                .read_sample_records(&uri, &format)
+                .await
+                .map_err(|err| EvalErrorPayload {
+                    kind: EvalErrorKind::Infra,
+                    message: format!("failed to read samples at {uri}: {err:#}"),
+                    code: None,
+                    engine: None,
+                    details: None,
+                })
+        }
+        SampleResultLocation::ClickHouse { table } => Err(EvalErrorPayload {
+            kind: EvalErrorKind::Config,
+            message: format!(
+                "samples in ClickHouse table {table} can't be voted on; report them inline"
+            ),
+            code: None,
+            engine: None,
+            details: None,
+        }),
+    }
+}
+
+/// Evaluates the run's canary dataset with the same runner and config and
+/// returns its finite metrics, their subsets per [`canary_subset`]. It runs
+/// under a scratch run id so the run's own directory is left alone; errors
+/// are returned as a message since a failed canary never fails the run.
+async fn run_canary(
+    ctx: &WorkerContext,
+    runner: &dyn EvalRunner,
+    config: &EvalConfig,
+    canary: &DatasetConfig,
+) -> Result<Vec<MetricRecord>, String> {
+    let scratch_id = Uuid::new_v4();
+    let mut canary_config = config.clone();
+    canary_config.run_id = scratch_id;
+    canary_config.dataset = canary.clone();
+    canary_config.canary = None;
+    canary_config.models.clear();
+    canary_config
+        .metrics
+        .retain(|metric| engine_metric(ctx, metric));
+    tracing::info!(
+        "running canary {} of run {} as {scratch_id}",
+        canary.name,
+        config.run_id
+    );
+
+    let outcome = match localize_dataset(ctx, &canary_config).await {
+        Ok(local) => until_cancelled(ctx, config.run_id, runner.run(&local))
+            .await
+            .map_err(|err| match err {
+                RunnerError::Eval(payload) => payload.message,
+                other => other.to_string(),
+            }),
+        Err(payload) => Err(payload.message),
+    };
+    if let Err(err) = ctx.run_dirs.remove(scratch_id).await {
+        tracing::warn!(
+            "failed to remove canary dir of run {}: {err:?}",
+            config.run_id
+        );
+    }
+
+    let result = outcome?;
+    if result.metrics.is_empty() {
+        return Err(result
+            .error
+            .map(|error| error.message)
+            .unwrap_or_else(|| "canary produced no metrics".into()));
+    }
+    Ok(result
+        .metrics
+        .into_iter()
+        .filter(|metric| metric.value.is_finite())
+        .map(|metric| MetricRecord {
+            run_id: config.run_id,
+            subset: Some(canary_subset(metric.subset.as_deref())),
+            ..metric
+        })
+        .collect())
+}
+
+/// Downloads remote datasets into the local cache and points the config's
+/// `dataset.uri` at the cached file, so runners only ever see local paths.
+/// Metrics needing references on a dataset without them are rejected first,
+/// since configs stored by reference skip that check when enqueued.
+async fn localize_dataset(
+    ctx: &WorkerContext,
+    config: &EvalConfig,
+) -> Result<EvalConfig, EvalErrorPayload> {
+    config
+        .check_references()
+        .map_err(|message| EvalErrorPayload {
+            kind: EvalErrorKind::Config,
+            message,
+            code: Some("reference_required".into()),
+            engine: None,
+            details: None,
+        })?;
+    let object_store = ctx.stores.object_store.as_deref();
+    match datasets::ensure_local(&config.dataset, &ctx.dataset_cache_dir, object_store).await {
+        Ok(Some(path)) => {
+            let mut local = config.clone();
+            local.dataset.uri = Some(path.to_string_lossy().into_owned());
+            Ok(local)
+        }
+        Ok(None) => Ok(config.clone()),
+        Err(err) => Err(EvalErrorPayload {
+            kind: EvalErrorKind::Infra,
+            message: format!("failed to fetch dataset {}: {err:#}", config.dataset.name),
+            code: Some("dataset_fetch_failed".into()),
+            engine: None,
+            details: None,
+        }),
+    }
+}
+
 fn map_error_to_status(kind: EvalErrorKind) -> RunStatus {
     match kind {
         EvalErrorKind::Config => RunStatus::FailedConfig,
@@ -123,7 +1527,269 @@ fn map_error_to_status(kind: EvalErrorKind) -> RunStatus {
     }
 }
 
-fn init_tracing() {
-    let fmt_layer = tracing_subscriber::fmt::layer();
-    tracing_subscriber::registry().with(fmt_layer).init();
+fn init_tracing(settings: &LoggingSettings) {
+    let registry = tracing_subscriber::registry();
+    match settings.format {
+        LogFormat::Json => registry
+            .with(tracing_subscriber::fmt::layer().json().with_span_list(true))
+            .init(),
+        LogFormat::Text => registry.with(tracing_subscriber::fmt::layer()).init(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use redis::{RedisResult, Value as RedisValue};
+    use std::cell::Cell;
+    use std::collections::VecDeque;
+    use std::process::Stdio;
+    use tokio::io::AsyncReadExt;
+    use tokio::process::Command;
+
+    /// A Redis connection answering each command with the next of `replies`
+    /// and recording the commands it was sent.
+    struct FakeRedis {
+        replies: VecDeque<RedisResult<RedisValue>>,
+        commands: Vec<Vec<String>>,
+    }
+
+    impl FakeRedis {
+        fn new(replies: impl IntoIterator<Item = RedisResult<RedisValue>>) -> Self {
+            Self {
+                replies: replies.into_iter().collect(),
+                commands: Vec::new(),
+            }
+        }
+    }
+
+    impl redis::aio::ConnectionLike for FakeRedis {
+        fn req_packed_command<'a>(
+            &'a mut self,
+            cmd: &'a redis::Cmd,
+        ) -> redis::RedisFuture<'a, RedisValue> {
+            let args = cmd
+                .args_iter()
+                .map(|arg| match arg {
+                    redis::Arg::Simple(bytes) => String::from_utf8_lossy(bytes).into_owned(),
+                    redis::Arg::Cursor => "<cursor>".into(),
+                })
+                .collect();
+            self.commands.push(args);
+            let reply = self.replies.pop_front().expect("unexpected redis command");
+            Box::pin(async move { reply })
+        }
+
+        fn req_packed_commands<'a>(
+            &'a mut self,
+            _: &'a redis::Pipeline,
+            _: usize,
+            _: usize,
+        ) -> redis::RedisFuture<'a, Vec<RedisValue>> {
+            unimplemented!("pipelines are not faked")
+        }
+
+        fn get_db(&self) -> i64 {
+            0
+        }
+    }
+
+    /// The settings shipped in `config/default.toml`.
+    fn settings() -> Settings {
+        config::Config::builder()
+            .add_source(config::File::from_str(
+                include_str!("../../../config/default.toml"),
+                config::FileFormat::Toml,
+            ))
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap()
+    }
+
+    fn redis_error() -> redis::RedisError {
+        (redis::ErrorKind::IoError, "connection reset").into()
+    }
+
+    #[tokio::test]
+    async fn drained_projects_are_pruned_from_the_lanes() {
+        let mut settings = settings();
+        settings.queues.strategy = QueueStrategy::PerProject;
+        let queue_key = settings.redis.queue_key.clone();
+        let data = |value: &str| RedisValue::Data(value.as_bytes().to_vec());
+        let mut conn = FakeRedis::new([
+            Ok(RedisValue::Bulk(vec![data("a"), data("b"), data("c")])),
+            // The script keeps the projects whose list still holds runs.
+            Ok(RedisValue::Bulk(vec![data("a"), data("c")])),
+        ]);
+        let lanes = queue_lanes(&settings, &mut conn).await.unwrap();
+
+        let keys: Vec<&str> = lanes.iter().map(|lane| lane.key.as_str()).collect();
+        assert_eq!(
+            keys,
+            [
+                format!("{queue_key}:a"),
+                format!("{queue_key}:c"),
+                queue_key.clone()
+            ]
+        );
+        let registry = queue::registry_key(&settings);
+        assert_eq!(conn.commands[0], ["SMEMBERS", registry.as_str()]);
+        let script = &conn.commands[1];
+        assert_eq!(script[0], "EVALSHA");
+        assert_eq!(
+            script[2..],
+            [
+                "4".into(),
+                registry,
+                format!("{queue_key}:a"),
+                format!("{queue_key}:b"),
+                format!("{queue_key}:c"),
+                "a".into(),
+                "b".into(),
+                "c".into(),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn a_job_popped_as_the_pause_is_set_goes_back_to_its_list() {
+        let mut conn = FakeRedis::new([Ok(RedisValue::Int(1)), Ok(RedisValue::Int(1))]);
+        assert!(return_if_paused(&mut conn, "eval:paused", "eval:queue:p1", "job").await);
+        assert_eq!(
+            conn.commands,
+            [
+                vec!["EXISTS", "eval:paused"],
+                vec!["LPUSH", "eval:queue:p1", "job"],
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn a_job_popped_without_a_pause_starts() {
+        let mut conn = FakeRedis::new([Ok(RedisValue::Int(0))]);
+        assert!(!return_if_paused(&mut conn, "eval:paused", "eval:queue:p1", "job").await);
+        assert_eq!(conn.commands, [vec!["EXISTS", "eval:paused"]]);
+
+        // The job is already off the queue, so a failed check lets it start.
+        let mut conn = FakeRedis::new([Err(redis_error())]);
+        assert!(!return_if_paused(&mut conn, "eval:paused", "eval:queue:p1", "job").await);
+        assert_eq!(conn.commands.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn cancelling_a_run_kills_its_engine() {
+        // Stands in for an engine; runners spawn theirs the same way.
+        let mut child = Command::new("sleep")
+            .arg("30")
+            .stdout(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .unwrap();
+        let mut stdout = child.stdout.take().unwrap();
+        let run = async move {
+            child.wait().await.context("engine failed")?;
+            Err(RunnerError::NotSupported)
+        };
+
+        let checks = &Cell::new(0);
+        let cancelled = || async move {
+            checks.set(checks.get() + 1);
+            checks.get() == 3
+        };
+        let result = stop_when_cancelled(Duration::from_millis(10), cancelled, run).await;
+        assert!(matches!(result, Err(RunnerError::Cancelled)));
+        assert_eq!(checks.get(), 3);
+
+        // The pipe only closes once the killed process is gone.
+        let mut rest = Vec::new();
+        tokio::time::timeout(Duration::from_secs(5), stdout.read_to_end(&mut rest))
+            .await
+            .expect("the engine outlived its cancelled run")
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn runs_that_finish_keep_their_result() {
+        let run = async {
+            sleep(Duration::from_millis(30)).await;
+            Err(RunnerError::NotSupported)
+        };
+        let result = stop_when_cancelled(Duration::from_millis(10), || async { false }, run).await;
+        assert!(matches!(result, Err(RunnerError::NotSupported)));
+    }
+
+    #[tokio::test]
+    async fn polling_backs_off_through_intermittent_redis_failures() {
+        let mut settings = settings();
+        settings.queues.strategy = QueueStrategy::PerProject;
+        let no_projects = || Ok(RedisValue::Bulk(Vec::new()));
+        let mut conn = FakeRedis::new([
+            Err(redis_error()),
+            Err(redis_error()),
+            Err(redis_error()),
+            no_projects(),
+            Err(redis_error()),
+            no_projects(),
+        ]);
+
+        let mut failures = 0;
+        let mut delays = Vec::new();
+        for _ in 0..6 {
+            let poll = queue_lanes(&settings, &mut conn).await.map(|_| ());
+            delays.push(after_poll(poll, &mut failures).unwrap());
+        }
+
+        // Each failure in a row waits twice as long, plus up to half again
+        // of jitter; a successful poll starts over.
+        let bounds =
+            |ms: u64| Some(Duration::from_millis(ms))..=Some(Duration::from_millis(ms * 3 / 2));
+        let expected = [bounds(500), bounds(1000), bounds(2000)];
+        for (delay, bounds) in delays[..3].iter().zip(&expected) {
+            assert!(bounds.contains(delay), "{delay:?} not in {bounds:?}");
+        }
+        assert_eq!(delays[3], None);
+        assert!(bounds(500).contains(&delays[4]), "{:?}", delays[4]);
+        assert_eq!(delays[5], None);
+        assert_eq!(failures, 0);
+    }
+
+    #[test]
+    fn long_outages_cap_the_backoff() {
+        let mut failures = 0;
+        for _ in 0..20 {
+            let delay = after_poll(Err(redis_error().into()), &mut failures)
+                .unwrap()
+                .unwrap();
+            assert!(delay <= POLL_BACKOFF.max_delay * 3 / 2, "{delay:?}");
+        }
+        let delay = after_poll(Err(redis_error().into()), &mut failures)
+            .unwrap()
+            .unwrap();
+        assert!(delay >= POLL_BACKOFF.max_delay, "{delay:?}");
+    }
+
+    #[test]
+    fn only_transient_failures_keep_the_worker_polling() {
+        let transient: [anyhow::Error; 3] = [
+            anyhow::anyhow!(redis_error()).context("failed to read the queue registry"),
+            redis::RedisError::from((redis::ErrorKind::TryAgain, "cluster is resharding")).into(),
+            DomainError::Unavailable("mysql is unreachable".into()).into(),
+        ];
+        for err in transient {
+            let mut failures = 0;
+            assert!(after_poll(Err(err), &mut failures).unwrap().is_some());
+            assert_eq!(failures, 1);
+        }
+
+        let fatal: [anyhow::Error; 2] = [
+            redis::RedisError::from((redis::ErrorKind::TypeError, "unexpected reply")).into(),
+            anyhow::anyhow!("invalid queue payload"),
+        ];
+        for err in fatal {
+            let mut failures = 2;
+            assert!(after_poll(Err(err), &mut failures).is_err());
+            assert_eq!(failures, 2);
+        }
+    }
 }
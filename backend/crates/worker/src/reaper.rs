@@ -0,0 +1,105 @@
+use std::sync::Arc;
+
+use chrono::Utc;
+use tokio::time::{sleep, Duration};
+use unified_domain::projects;
+use unified_domain::runs::{self, ProjectScope, Run};
+use unified_shared::eval::{OutputConfig, RunStatus};
+use unified_shared::settings::{ProjectSettings, RetentionSettings};
+use uuid::Uuid;
+
+use crate::WorkerContext;
+
+/// Runs reaped per status in one pass; the rest wait for the next pass.
+const REAP_BATCH_SIZE: i64 = 100;
+
+const FAILED_STATUSES: &[RunStatus] = &[
+    RunStatus::FailedConfig,
+    RunStatus::FailedEngine,
+    RunStatus::FailedInfra,
+    RunStatus::TimedOut,
+];
+
+/// Periodically deletes the run directories and object-store prefixes of
+/// failed and cancelled runs past their retention window. Only terminal runs
+/// are ever considered, and only those this worker claimed or none did, as
+/// another worker's run directories are out of reach. Projects overriding
+/// `retention` in their settings are reaped in passes of their own, with
+/// their windows.
+pub async fn run(ctx: Arc<WorkerContext>) {
+    let interval = Duration::from_secs(ctx.settings.retention.interval_seconds.max(1));
+    loop {
+        match projects::list_settings(&ctx.db).await {
+            Ok(overrides) => reap_all(&ctx, &overrides).await,
+            // Reaping with the global windows could delete what a project
+            // keeps longer.
+            Err(err) => {
+                tracing::warn!("failed to load project settings; skipping reaper pass: {err}")
+            }
+        }
+        sleep(interval).await;
+    }
+}
+
+async fn reap_all(ctx: &WorkerContext, overrides: &[(Uuid, ProjectSettings)]) {
+    let overriding: Vec<(Uuid, RetentionSettings)> = overrides
+        .iter()
+        .filter(|(_, settings)| settings.overrides_retention())
+        .map(|(project_id, settings)| (*project_id, settings.apply(&ctx.settings).retention))
+        .collect();
+    let excluded: Vec<Uuid> = overriding
+        .iter()
+        .map(|(project_id, _)| *project_id)
+        .collect();
+    let passes = std::iter::once((ProjectScope::Except(&excluded), &ctx.settings.retention)).chain(
+        overriding
+            .iter()
+            .map(|(project_id, retention)| (ProjectScope::Only(project_id), retention)),
+    );
+    for (scope, retention) in passes {
+        for (statuses, days) in [
+            (FAILED_STATUSES, retention.failed_days),
+            (&[RunStatus::Cancelled][..], retention.cancelled_days),
+        ] {
+            if let Err(err) = reap(ctx, statuses, scope, days).await {
+                tracing::warn!("artifact reaper pass failed: {err:?}");
+            }
+        }
+    }
+}
+
+async fn reap(
+    ctx: &WorkerContext,
+    statuses: &[RunStatus],
+    scope: ProjectScope<'_>,
+    days: u64,
+) -> anyhow::Result<()> {
+    let cutoff = Utc::now() - chrono::Duration::days(days as i64);
+    let candidates = runs::list_reapable(
+        &ctx.db,
+        &ctx.worker_id,
+        statuses,
+        scope,
+        cutoff,
+        REAP_BATCH_SIZE,
+    )
+    .await?;
+    for run in candidates {
+        match reap_run(ctx, &run).await {
+            Ok(()) => runs::mark_artifacts_reaped(&ctx.db, &run.id).await?,
+            Err(err) => tracing::warn!("failed to reap artifacts of run {}: {err:?}", run.id),
+        }
+    }
+    Ok(())
+}
+
+async fn reap_run(ctx: &WorkerContext, run: &Run) -> anyhow::Result<()> {
+    ctx.run_dirs.remove(run.id).await?;
+    if let (OutputConfig::ObjectStore { .. }, Some(store)) =
+        (run.output(), &ctx.stores.object_store)
+    {
+        let deleted = store.delete_run_prefix(run.id).await?;
+        tracing::info!("deleted {deleted} object(s) of run {}", run.id);
+    }
+    Ok(())
+}
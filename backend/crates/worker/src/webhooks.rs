@@ -0,0 +1,176 @@
+use std::fmt;
+
+use hmac::{Hmac, Mac};
+use reqwest::header::CONTENT_TYPE;
+use sha2::Sha256;
+use tokio::time::Duration;
+use unified_shared::eval::RunCompletedEvent;
+use unified_shared::retry::{retry_with_backoff, Backoff, RetryPolicy};
+use unified_shared::settings::WebhookSettings;
+
+/// Header carrying `sha256=<hex HMAC-SHA256 of the body>` when
+/// `webhooks.secret` is set.
+pub const SIGNATURE_HEADER: &str = "X-Signature-256";
+
+/// POSTs [`RunCompletedEvent`]s to `webhooks.url`. Deliveries run in the
+/// background with retries, so a slow or failing endpoint never holds up a
+/// job slot.
+#[derive(Clone)]
+pub struct WebhookSender {
+    client: reqwest::Client,
+    url: String,
+    secret: Option<String>,
+    policy: RetryPolicy,
+}
+
+impl WebhookSender {
+    /// `None` while no `webhooks.url` is configured.
+    pub fn new(settings: &WebhookSettings) -> anyhow::Result<Option<Self>> {
+        let Some(url) = &settings.url else {
+            return Ok(None);
+        };
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(settings.timeout_seconds))
+            .build()?;
+        Ok(Some(Self {
+            client,
+            url: url.clone(),
+            secret: settings.secret.clone(),
+            policy: RetryPolicy {
+                max_attempts: settings.max_attempts,
+                backoff: Backoff {
+                    base_delay: Duration::from_secs(1),
+                    max_delay: Duration::from_secs(60),
+                    jitter: true,
+                },
+            },
+        }))
+    }
+
+    /// Starts delivering `event` and returns at once; a delivery that still
+    /// fails after the last attempt is logged and dropped.
+    pub fn send(&self, event: &RunCompletedEvent) {
+        let body = match serde_json::to_vec(event) {
+            Ok(body) => body,
+            Err(err) => {
+                tracing::warn!("failed to encode webhook of run {}: {err}", event.run_id);
+                return;
+            }
+        };
+        let sender = self.clone();
+        let run_id = event.run_id;
+        tokio::spawn(async move {
+            let delivery = retry_with_backoff(
+                &sender.policy,
+                |err: &DeliveryError| err.retryable,
+                |_| sender.deliver(&body),
+            )
+            .await;
+            if let Err(err) = delivery {
+                tracing::warn!("failed to deliver webhook of run {run_id}: {err}");
+            }
+        });
+    }
+
+    async fn deliver(&self, body: &[u8]) -> Result<(), DeliveryError> {
+        let mut request = self
+            .client
+            .post(&self.url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(body.to_vec());
+        if let Some(secret) = &self.secret {
+            request = request.header(SIGNATURE_HEADER, sign(secret, body));
+        }
+        let response = request.send().await.map_err(|err| DeliveryError {
+            retryable: true,
+            message: err.to_string(),
+        })?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(());
+        }
+        Err(DeliveryError {
+            retryable: status.is_server_error() || status.as_u16() == 429,
+            message: format!("endpoint answered {status}"),
+        })
+    }
+}
+
+/// The [`SIGNATURE_HEADER`] value for `body`: `sha256=` and the hex
+/// HMAC-SHA256 of the body keyed with `secret`.
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(body);
+    format!("sha256={:x}", mac.finalize().into_bytes())
+}
+
+#[derive(Debug)]
+struct DeliveryError {
+    retryable: bool,
+    message: String,
+}
+
+impl fmt::Display for DeliveryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use unified_shared::eval::{EvalErrorKind, EvalErrorPayload, RunStatus};
+    use uuid::Uuid;
+
+    #[test]
+    fn signatures_are_hex_hmac_sha256() {
+        // RFC 4231, test case 2.
+        assert_eq!(
+            sign("Jefe", b"what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn events_serialize_to_the_documented_body() {
+        let run_id = Uuid::new_v4();
+        let event = RunCompletedEvent {
+            run_id,
+            status: RunStatus::FailedEngine,
+            duration_ms: 1500,
+            metric_count: 0,
+            error: Some(EvalErrorPayload {
+                kind: EvalErrorKind::Engine,
+                message: "harness exited with 1".into(),
+                code: None,
+                engine: Some("lm-eval-harness".into()),
+                details: None,
+            }),
+            at: chrono::DateTime::from_timestamp(1_700_000_000, 0).unwrap(),
+        };
+        let body = serde_json::to_vec(&event).unwrap();
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap(),
+            json!({
+                "run_id": run_id,
+                "status": "FailedEngine",
+                "duration_ms": 1500,
+                "metric_count": 0,
+                "error": {
+                    "kind": "engine",
+                    "message": "harness exited with 1",
+                    "code": null,
+                    "engine": "lm-eval-harness",
+                    "details": null,
+                },
+                "at": "2023-11-14T22:13:20Z",
+            })
+        );
+        // Receivers verify the signature over the exact bytes delivered.
+        let signature = sign("secret", &body);
+        assert_eq!(signature, sign("secret", &body));
+        assert_ne!(signature, sign("other", &body));
+    }
+}
@@ -0,0 +1 @@
+ALTER TABLE runs ADD COLUMN samples_location_json TEXT NULL;
@@ -0,0 +1,16 @@
+-- One row per sample position so re-reported samples (resumed runs) upsert
+-- instead of duplicating. Existing duplicates keep their most recently
+-- inserted row. NULL subset/split are keyed as ''.
+DELETE older FROM sample_outputs older
+JOIN sample_outputs newer
+    ON newer.run_id = older.run_id
+    AND newer.dataset = older.dataset
+    AND IFNULL(newer.subset, '') = IFNULL(older.subset, '')
+    AND IFNULL(newer.split, '') = IFNULL(older.split, '')
+    AND newer.sample_index = older.sample_index
+    AND newer.id > older.id;
+
+ALTER TABLE sample_outputs
+    ADD COLUMN subset_key VARCHAR(255) AS (IFNULL(subset, '')) STORED,
+    ADD COLUMN split_key VARCHAR(64) AS (IFNULL(split, '')) STORED,
+    ADD UNIQUE KEY uq_sample_outputs_position (run_id, dataset, subset_key, split_key, sample_index);
@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS run_status_history (
+    id BIGINT NOT NULL AUTO_INCREMENT,
+    run_id CHAR(36) NOT NULL,
+    from_status VARCHAR(32) NULL,
+    to_status VARCHAR(32) NOT NULL,
+    error_kind VARCHAR(32) NULL,
+    at DATETIME(6) NOT NULL,
+    PRIMARY KEY (id),
+    KEY idx_run_status_history_run (run_id, id)
+);
@@ -0,0 +1,3 @@
+ALTER TABLE runs
+    ADD COLUMN worker_id VARCHAR(64) NULL,
+    ADD COLUMN lease_expires_at DATETIME(6) NULL;
@@ -0,0 +1,3 @@
+ALTER TABLE runs
+    ADD COLUMN artifacts_reaped_at DATETIME(6) NULL,
+    ADD KEY idx_runs_status_finished_at (status, finished_at);
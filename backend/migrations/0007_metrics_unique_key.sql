@@ -0,0 +1,17 @@
+-- One row per metric key so re-persisted results upsert instead of
+-- duplicating. Existing duplicates keep their most recent row. NULL
+-- subset/split are keyed as ''.
+DELETE older FROM metrics older
+JOIN metrics newer
+    ON newer.run_id = older.run_id
+    AND newer.dataset = older.dataset
+    AND IFNULL(newer.subset, '') = IFNULL(older.subset, '')
+    AND IFNULL(newer.split, '') = IFNULL(older.split, '')
+    AND newer.metric_name = older.metric_name
+    AND (newer.timestamp > older.timestamp
+        OR (newer.timestamp = older.timestamp AND newer.id > older.id));
+
+ALTER TABLE metrics
+    ADD COLUMN subset_key VARCHAR(255) AS (IFNULL(subset, '')) STORED,
+    ADD COLUMN split_key VARCHAR(64) AS (IFNULL(split, '')) STORED,
+    ADD UNIQUE KEY uq_metrics_key (run_id, dataset, subset_key, split_key, metric_name);
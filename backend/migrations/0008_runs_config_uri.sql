@@ -0,0 +1,4 @@
+-- Large eval configs live in the object store; `eval_config_json` then only
+-- holds a stub with the run's ids and output config.
+ALTER TABLE runs
+    ADD COLUMN config_uri VARCHAR(1024) NULL AFTER eval_config_json;
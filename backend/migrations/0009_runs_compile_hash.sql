@@ -0,0 +1,5 @@
+-- Hash of the compile request that produced a run, so recompiling an
+-- experiment can skip runs it already created.
+ALTER TABLE runs
+    ADD COLUMN compile_hash CHAR(64) NULL,
+    ADD KEY idx_runs_experiment_compile_hash (experiment_id, compile_hash);
@@ -0,0 +1,12 @@
+-- Code versions a model implementation pointed at over time, so a past run
+-- can be traced to the repo_reference that was current when it ran.
+CREATE TABLE IF NOT EXISTS model_impl_reference_history (
+    id BIGINT NOT NULL AUTO_INCREMENT,
+    model_impl_id CHAR(36) NOT NULL,
+    repo_url VARCHAR(1024) NULL,
+    from_reference VARCHAR(255) NULL,
+    to_reference VARCHAR(255) NULL,
+    changed_at DATETIME(6) NOT NULL,
+    PRIMARY KEY (id),
+    KEY idx_model_impl_reference_history_impl (model_impl_id, id)
+);
@@ -0,0 +1,7 @@
+-- Store eval and experiment configs as native JSON so they can be filtered
+-- with JSON_EXTRACT. Existing rows hold serialized JSON text, which MySQL
+-- parses during the conversion; readers also accept the old TEXT columns, so
+-- the API can roll out before this migration runs.
+ALTER TABLE runs MODIFY COLUMN eval_config_json JSON NOT NULL;
+ALTER TABLE tasks MODIFY COLUMN eval_config_json JSON NOT NULL;
+ALTER TABLE experiments MODIFY COLUMN global_config_json JSON NULL;
@@ -0,0 +1,4 @@
+-- Metadata the engine reported with a run's result, such as the effective
+-- sampling seed and library versions.
+ALTER TABLE runs
+    ADD COLUMN result_metadata_json JSON NULL;
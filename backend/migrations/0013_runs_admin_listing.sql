@@ -0,0 +1,9 @@
+-- Cross-project run listing (GET /admin/runs) filters on status and engine
+-- and pages by (created_at, id). The engine is read from the stored config,
+-- which keeps it even for configs stored by reference.
+ALTER TABLE runs
+    ADD COLUMN engine VARCHAR(64)
+        GENERATED ALWAYS AS (JSON_UNQUOTE(JSON_EXTRACT(eval_config_json, '$.engine'))) VIRTUAL,
+    ADD KEY idx_runs_status_created (status, created_at, id),
+    ADD KEY idx_runs_engine_created (engine, created_at, id),
+    ADD KEY idx_runs_created (created_at, id);
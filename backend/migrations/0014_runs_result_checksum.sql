@@ -0,0 +1,4 @@
+-- SHA-256 of the `result.json` a run's results were read from, for detecting
+-- later corruption.
+ALTER TABLE runs
+    ADD COLUMN result_checksum CHAR(64) NULL;
@@ -0,0 +1,10 @@
+-- `Custom` engines are stored as an object (`{"Custom": {...}}`); the
+-- generated engine column holds just the variant name for them.
+ALTER TABLE runs
+    MODIFY COLUMN engine VARCHAR(64)
+        GENERATED ALWAYS AS (
+            CASE JSON_TYPE(JSON_EXTRACT(eval_config_json, '$.engine'))
+                WHEN 'OBJECT' THEN JSON_UNQUOTE(JSON_EXTRACT(JSON_KEYS(JSON_EXTRACT(eval_config_json, '$.engine')), '$[0]'))
+                ELSE JSON_UNQUOTE(JSON_EXTRACT(eval_config_json, '$.engine'))
+            END
+        ) VIRTUAL;
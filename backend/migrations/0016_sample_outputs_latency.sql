@@ -0,0 +1,4 @@
+-- GET /runs/:id/latency reads percentiles by seeking into the run's samples
+-- ordered by latency instead of loading them.
+ALTER TABLE sample_outputs
+    ADD KEY idx_sample_outputs_latency (run_id, latency_ms);
@@ -0,0 +1,8 @@
+-- Runs that must complete before a run may be queued. A run with
+-- unfinished dependencies waits in status 'blocked'.
+CREATE TABLE IF NOT EXISTS run_dependencies (
+    run_id CHAR(36) NOT NULL,
+    depends_on_run_id CHAR(36) NOT NULL,
+    PRIMARY KEY (run_id, depends_on_run_id),
+    KEY idx_run_dependencies_depends_on (depends_on_run_id)
+);
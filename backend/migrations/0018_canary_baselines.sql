@@ -0,0 +1,7 @@
+-- The run whose canary metrics a project's later runs are compared against
+-- to detect drift.
+CREATE TABLE IF NOT EXISTS canary_baselines (
+    project_id CHAR(36) NOT NULL PRIMARY KEY,
+    run_id CHAR(36) NOT NULL,
+    set_at DATETIME(6) NOT NULL
+);
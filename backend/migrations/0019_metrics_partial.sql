@@ -0,0 +1,5 @@
+-- Metrics persisted from a runner's partial_result.json while the run is in
+-- progress. The final result clears the flag on the metrics it re-emits and
+-- drops the rest.
+ALTER TABLE metrics
+    ADD COLUMN partial BOOLEAN NOT NULL DEFAULT FALSE;
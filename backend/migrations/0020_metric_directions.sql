@@ -0,0 +1,10 @@
+-- Whether each metric improves upward or downward, resolved when it is
+-- persisted, plus per-name overrides of the built-in guess.
+ALTER TABLE metrics
+    ADD COLUMN direction VARCHAR(16) NULL;
+
+CREATE TABLE IF NOT EXISTS metric_directions (
+    metric_name VARCHAR(255) NOT NULL PRIMARY KEY,
+    direction VARCHAR(16) NOT NULL,
+    updated_at DATETIME(6) NOT NULL
+);
@@ -0,0 +1,7 @@
+-- Per-project overrides of the global settings; fields left out fall back
+-- to the global value.
+CREATE TABLE IF NOT EXISTS project_settings (
+    project_id CHAR(36) NOT NULL PRIMARY KEY,
+    settings_json JSON NOT NULL,
+    updated_at DATETIME(6) NOT NULL
+);
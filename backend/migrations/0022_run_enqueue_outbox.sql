@@ -0,0 +1,8 @@
+-- Runs released from 'blocked' to 'queued' that still have to be pushed onto
+-- the run queue. Rows are written in the transaction that releases the run
+-- and deleted once the push succeeded, so a Redis failure in between leaves
+-- the run to be pushed later instead of stranded.
+CREATE TABLE IF NOT EXISTS run_enqueue_outbox (
+    run_id CHAR(36) NOT NULL PRIMARY KEY,
+    created_at DATETIME(6) NOT NULL
+);
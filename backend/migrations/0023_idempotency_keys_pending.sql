@@ -0,0 +1,3 @@
+-- Keys are claimed before their request creates anything; the ids stay NULL
+-- until it finishes.
+ALTER TABLE idempotency_keys MODIFY resource_ids_json TEXT NULL;
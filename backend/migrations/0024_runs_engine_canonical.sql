@@ -0,0 +1,23 @@
+-- Runs compiled before engines were canonicalized on write may store an
+-- alias (`lm_eval_harness`, `{"custom": {...}}`), which the generated engine
+-- column passes through and engine filters never match. Rewrite them to the
+-- variant names.
+UPDATE runs
+SET eval_config_json = JSON_SET(eval_config_json, '$.engine',
+    CASE engine
+        WHEN 'lm_eval_harness' THEN 'LmEvalHarness'
+        WHEN 'open_compass' THEN 'OpenCompass'
+        WHEN 'opencompass' THEN 'OpenCompass'
+        WHEN 'helm' THEN 'Helm'
+        WHEN 'deep_eval' THEN 'DeepEval'
+        WHEN 'deepeval' THEN 'DeepEval'
+        WHEN 'openai_evals' THEN 'OpenAiEvals'
+        WHEN 'open_ai_evals' THEN 'OpenAiEvals'
+    END)
+WHERE engine IN ('lm_eval_harness', 'open_compass', 'opencompass', 'helm', 'deep_eval',
+                 'deepeval', 'openai_evals', 'open_ai_evals');
+
+UPDATE runs
+SET eval_config_json = JSON_SET(eval_config_json, '$.engine',
+    JSON_OBJECT('Custom', JSON_EXTRACT(eval_config_json, '$.engine.custom')))
+WHERE engine = 'custom';